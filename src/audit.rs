@@ -0,0 +1,132 @@
+use crate::providers::rate_limiter::{UsageBand, UsagePercentages};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// What `run` did with the process after a usage check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    /// The process was left running
+    Proceed,
+    /// The process was paused for the backoff
+    Pause,
+    /// The process was resumed after its backoff
+    Resume,
+}
+
+/// One decision, a line of a `run --audit-log` file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    /// Usage of each limit as of the check behind the decision
+    pub percentages: UsagePercentages,
+    pub band: UsageBand,
+    pub action: AuditAction,
+    /// Milliseconds until the next check
+    pub backoff_ms: u64,
+}
+
+impl AuditRecord {
+    /// A record of `action`, taken now
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn new(
+        percentages: UsagePercentages,
+        band: UsageBand,
+        action: AuditAction,
+        backoff: Duration,
+    ) -> Self {
+        Self {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or(Duration::ZERO)
+                .as_millis() as u64,
+            percentages,
+            band,
+            action,
+            backoff_ms: backoff.as_millis() as u64,
+        }
+    }
+}
+
+/// Append-only JSONL file of the decisions taken during a run
+///
+/// Unlike the tracing output, which is meant for people, every line is an
+/// [`AuditRecord`] in a stable format.
+#[derive(Debug)]
+pub struct AuditLog {
+    output: File,
+}
+
+impl AuditLog {
+    /// Open `path` for appending, creating it if needed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened
+    pub fn open(path: &Path) -> Result<Self> {
+        let output = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+        Ok(Self { output })
+    }
+
+    /// Append `record` as one line
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record cannot be written
+    pub fn record(&mut self, record: &AuditRecord) -> Result<()> {
+        let line = serde_json::to_string(record)?;
+        writeln!(self.output, "{line}").context("Failed to write audit log")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_audit_log_appends_json_lines() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("audit.jsonl");
+        let percentages = UsagePercentages {
+            requests: 95,
+            ..UsagePercentages::default()
+        };
+        let pause = AuditRecord::new(
+            percentages,
+            UsageBand::Critical,
+            AuditAction::Pause,
+            Duration::from_secs(5),
+        );
+        AuditLog::open(&path)?.record(&pause)?;
+        // Reopening keeps earlier records
+        let resume = AuditRecord::new(
+            percentages,
+            UsageBand::Critical,
+            AuditAction::Resume,
+            Duration::ZERO,
+        );
+        AuditLog::open(&path)?.record(&resume)?;
+
+        let contents = std::fs::read_to_string(&path)?;
+        let records: Vec<AuditRecord> = contents
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(records, [pause, resume]);
+        assert!(contents.contains(r#""action":"pause""#), "{contents}");
+        assert!(contents.contains(r#""band":"critical""#), "{contents}");
+        assert!(contents.contains(r#""backoff_ms":5000"#), "{contents}");
+        Ok(())
+    }
+}
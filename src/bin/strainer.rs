@@ -1,24 +1,173 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
-use strainer::config::Config;
-use strainer::providers;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::Pid;
+use rand_core::{OsRng, RngCore};
+use strainer::audit::{AuditAction, AuditLog, AuditRecord};
+#[cfg(not(feature = "tui"))]
+use strainer::config::Thresholds;
+use strainer::config::{self, Config, ConfigBuilder, HumanDuration, KeyLock};
+use strainer::credentials::{self, KeyringRef};
+use strainer::exit::{ExitCode, WithExitCode};
+use strainer::gate::GateServer;
+use strainer::lock::RunLock;
 use strainer::providers::config::{AnthropicConfig, MockConfig, OpenAIConfig, ProviderConfig};
-use strainer::providers::rate_limiter::RateLimiter;
-use tracing::info;
+use strainer::providers::proxy::ProxyProvider;
+#[cfg(not(feature = "tui"))]
+use strainer::providers::rate_limiter::UsagePercentages;
+use strainer::providers::rate_limiter::{RateLimiter, UsageBand};
+use strainer::providers::replay::{self, RecordingProvider};
+use strainer::providers::{self, Provider, RateLimitInfo};
+use strainer::proxy::ProxyServer;
+use strainer::signing;
+use strainer::summary::{self, RunSummary};
+#[cfg(feature = "tui")]
+use strainer::tui::Dashboard;
+use tokio::signal::unix::{signal as unix_signal, Signal as UnixSignal, SignalKind};
+use tokio::sync::watch;
+use tracing::{info, info_span, warn, Instrument};
 use tracing_subscriber::{fmt, EnvFilter};
 
-use strainer::cli::{Cli, Commands};
-use strainer::process::ProcessController;
+use strainer::cli::{Cli, Commands, ConfigAction, ExportFormat};
+use strainer::process::{ProcessController, SpawnOptions};
+use strainer::resources::{ResourceExcess, ResourceMonitor};
 use strainer::{initialize_config, InitOptions};
 
 use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io::IsTerminal;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> std::process::ExitCode {
     let cli = Cli::parse();
+    match run(cli).await {
+        Ok(()) => ExitCode::Success.into(),
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::of(&e).into()
+        }
+    }
+}
+
+/// Run the parsed command; errors carry the [`ExitCode`] to report
+async fn run(cli: Cli) -> Result<()> {
+    init_logging(&cli);
+    let run_id = cli.run_id.clone().unwrap_or_else(new_run_id);
+    dispatch(cli)
+        .instrument(info_span!("strainer", run_id = %run_id))
+        .await
+}
+
+/// Run the parsed command within the run's span
+async fn dispatch(cli: Cli) -> Result<()> {
+    // Commands that do not start or watch a process are handled first
+    if let Some(result) = run_standalone(&cli).await {
+        return result;
+    }
+
+    // Check for empty command vector in Run command
+    if let Commands::Run { ref command, .. } = cli.command {
+        if command.is_empty() {
+            eprintln!("Error: No command specified");
+            return Err(anyhow::anyhow!("No command specified")).with_exit_code(ExitCode::Config);
+        }
+    }
+
+    // Load configuration from file and CLI args
+    let base_config = load_base_config(&cli)
+        .await
+        .with_exit_code(ExitCode::Config)?;
+
+    // Named commands take their settings from the config alone
+    let mut final_config = base_config;
+    if !matches!(cli.command, Commands::RunNamed { .. }) {
+        final_config.merge(create_cli_config(&cli.command));
+        // An explicit flag wins even when it asks for the default
+        if let Some(stdio) = cli.command.stdio() {
+            final_config.process.stdio = stdio;
+        }
+    }
+    final_config.validate().with_exit_code(ExitCode::Config)?;
+
+    let result = match cli.command {
+        Commands::Run {
+            command,
+            tui,
+            wait_for_capacity,
+            on_pause_exit,
+            config_check_only,
+            pty,
+            detach,
+            pid_file,
+            log_file,
+            record,
+            summary_file,
+            audit_log,
+            control_file,
+            api,
+            api_key,
+            ..
+        } => {
+            if detach && !config_check_only {
+                return spawn_detached(pid_file.as_deref(), log_file.as_deref());
+            }
+            let _pid_file = pid_file.map(PidFile::create).transpose()?;
+            let opts = RunOptions {
+                tui,
+                wait_for_capacity,
+                on_pause_exit,
+                config_check_only,
+                pty,
+                record,
+                summary_file,
+                audit_log,
+                control_file,
+                cli_provider: api.is_some() || api_key.is_some(),
+            };
+            run_command(command, final_config, &opts).await
+        }
+        Commands::RunNamed { name } => {
+            let (config, command) = final_config
+                .for_command(&name)
+                .with_exit_code(ExitCode::Config)?;
+            config.validate().with_exit_code(ExitCode::Config)?;
+            run_command(command, config, &RunOptions::default()).await
+        }
+        Commands::Watch { pid, pid_file, .. } => match (pid, pid_file) {
+            (Some(pid), _) => watch_process(pid, final_config),
+            (None, Some(pid_file)) => watch_pid_file(&pid_file, final_config),
+            (None, None) => unreachable!("clap requires --pid or --pid-file"),
+        },
+        Commands::Init { .. }
+        | Commands::Config { .. }
+        | Commands::SignConfig { .. }
+        | Commands::Stop { .. }
+        | Commands::Replay { .. }
+        | Commands::SetKey { .. }
+        | Commands::DiffConfig { .. }
+        | Commands::Monitor { .. }
+        | Commands::Gate { .. }
+        | Commands::Bench { .. }
+        | Commands::Providers => {
+            unreachable!() // Already handled above
+        }
+    };
 
+    if let Err(ref e) = result {
+        eprintln!("{e}");
+    }
+    result
+}
+
+/// Set up logging from the CLI options
+fn init_logging(cli: &Cli) {
     // Setup logging based on CLI options, but only if not already initialized
-    if std::env::var("RUST_LOG").is_err() {
+    // and the terminal is not taken over by the dashboard
+    if std::env::var("RUST_LOG").is_err() && !cli.command.tui() {
         let filter = if cli.verbose { "debug" } else { &cli.log_level };
 
         let subscriber = fmt()
@@ -26,7 +175,8 @@ async fn main() -> Result<()> {
             .with_target(false)
             .with_thread_ids(true)
             .with_file(true)
-            .with_line_number(true);
+            .with_line_number(true)
+            .with_ansi(std::io::stdout().is_terminal());
 
         if cli.log_format == "json" {
             let _ = subscriber.json().try_init();
@@ -34,139 +184,1205 @@ async fn main() -> Result<()> {
             let _ = subscriber.try_init();
         }
     }
+}
 
-    // Handle init command early as it doesn't need config loading
-    if let Commands::Init {
-        config,
-        no_prompt,
-        force,
-    } = cli.command
-    {
-        return initialize_config(InitOptions {
-            config_path: config,
-            no_prompt,
-            force,
+/// PID file that is removed again when strainer exits
+struct PidFile(PathBuf);
+
+impl PidFile {
+    fn create(path: PathBuf) -> Result<Self> {
+        write_pid_file(&path, std::process::id())?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn write_pid_file(path: &Path, pid: u32) -> Result<()> {
+    std::fs::write(path, format!("{pid}\n"))
+        .with_context(|| format!("Failed to write PID file {}", path.display()))
+}
+
+/// Arguments for the background copy of a detached run
+///
+/// This is the current command line without `--detach`; anything after `--`
+/// belongs to the child command and is kept as is, even when not UTF-8.
+fn daemon_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut in_command = false;
+    args.into_iter()
+        .skip(1)
+        .filter(|arg| {
+            if in_command {
+                return true;
+            }
+            in_command = arg == OsStr::new("--");
+            arg != OsStr::new("--detach")
         })
-        .await;
+        .collect()
+}
+
+/// Re-run strainer in the background and return immediately
+///
+/// The background copy runs in its own process group so terminal signals
+/// aimed at the shell's foreground job do not reach it.
+fn spawn_detached(pid_file: Option<&Path>, log_file: Option<&Path>) -> Result<()> {
+    let (stdout, stderr) = match log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open log file {}", path.display()))?;
+            (Stdio::from(file.try_clone()?), Stdio::from(file))
+        }
+        None => (Stdio::null(), Stdio::null()),
+    };
+
+    let daemon = std::process::Command::new(std::env::current_exe()?)
+        .args(daemon_args(std::env::args_os()))
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr)
+        .process_group(0)
+        .spawn()
+        .context("Failed to start strainer in the background")?;
+
+    if let Some(path) = pid_file {
+        write_pid_file(path, daemon.id())?;
     }
+    println!(
+        "Started strainer in the background with PID {}",
+        daemon.id()
+    );
+    Ok(())
+}
 
-    // Check for empty command vector in Run command
-    if let Commands::Run { ref command, .. } = cli.command {
-        if command.is_empty() {
-            eprintln!("Error: No command specified");
-            anyhow::bail!("No command specified");
+/// Read a PID written by `--pid-file`
+///
+/// Zero and negative PIDs are rejected, as signalling them would reach a
+/// whole process group or every process strainer may signal.
+fn read_pid_file(path: &Path) -> Result<i32> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read PID file {}", path.display()))?;
+    contents
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&pid: &i32| pid > 0)
+        .ok_or_else(|| anyhow::anyhow!("Invalid PID in {}", path.display()))
+}
+
+/// Ask a detached strainer to stop; it terminates its child before exiting
+fn stop_daemon(pid_file: &Path) -> Result<()> {
+    let pid = read_pid_file(pid_file)?;
+    signal::kill(Pid::from_raw(pid), Signal::SIGTERM)
+        .with_context(|| format!("Failed to signal strainer process {pid}"))?;
+    println!("Sent SIGTERM to strainer process {pid}");
+    Ok(())
+}
+
+/// Run a command that does not start or watch a process, returning `None`
+/// for commands that do
+async fn run_standalone(cli: &Cli) -> Option<Result<()>> {
+    let result = match &cli.command {
+        // Init doesn't need config loading
+        Commands::Init {
+            config,
+            no_prompt,
+            force,
+            wait,
+            strict,
+            dry_run,
+        } => {
+            initialize_config(InitOptions {
+                config_path: config.clone(),
+                no_prompt: *no_prompt,
+                force: *force,
+                wait: *wait,
+                strict: *strict,
+                dry_run: *dry_run,
+                env_prefix: cli.env_prefix.clone(),
+            })
+            .await
         }
+        Commands::SignConfig {
+            config,
+            key,
+            generate_key,
+        } => sign_config_file(config, key, *generate_key),
+        Commands::Stop { pid_file } => stop_daemon(pid_file),
+        Commands::DiffConfig {
+            left,
+            right,
+            show_secrets,
+        } => diff_config(left, right, *show_secrets).with_exit_code(ExitCode::Config),
+        Commands::SetKey { service, account } => set_key(service, account),
+        Commands::Providers => {
+            list_providers();
+            Ok(())
+        }
+        Commands::Config {
+            action: ConfigAction::Set { key, value, file },
+        } => config::set_config_value(file, key, value)
+            .map(|()| println!("Set {key} in {}", file.display()))
+            .with_exit_code(ExitCode::Config),
+        // These only need the resolved file and environment config
+        Commands::Config {
+            action:
+                ConfigAction::Export {
+                    format,
+                    show_secrets,
+                },
+        } => load_base_config(cli)
+            .await
+            .and_then(|config| export_config(&config, *format, *show_secrets)),
+        Commands::Replay { recording } => load_base_config(cli)
+            .await
+            .and_then(|config| replay_recording(recording, config)),
+        Commands::Monitor { interval } => match load_base_config(cli).await {
+            Ok(config) => monitor(config, Duration::from_secs(*interval)).await,
+            Err(e) => Err(e),
+        },
+        Commands::Gate { socket } => match load_base_config(cli).await {
+            Ok(config) => gate(config, socket).await,
+            Err(e) => Err(e),
+        },
+        Commands::Bench {
+            requests_per_minute,
+            command,
+        } => match load_base_config(cli).await {
+            Ok(config) => bench(command, config, requests_per_minute).await,
+            Err(e) => Err(e),
+        },
+        Commands::Run { .. } | Commands::RunNamed { .. } | Commands::Watch { .. } => return None,
+    };
+    Some(result)
+}
+
+/// Print each provider `--api` accepts with the defaults it implies
+fn list_providers() {
+    let show = |value: Option<u32>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+    for provider_config in ProviderConfig::supported() {
+        let limits = provider_config.default_rate_limits();
+        let api = config::ApiConfig {
+            provider_config,
+            ..Default::default()
+        };
+        println!("{}", api.provider_config);
+        println!(
+            "  base_url: {}",
+            api.base_url_default().as_deref().unwrap_or("-")
+        );
+        println!("  model: {}", api.provider_config.model().unwrap_or("-"));
+        println!("  max_tokens: {}", show(api.provider_config.max_tokens()));
+        println!(
+            "  limits: requests_per_minute={} tokens_per_minute={} input_tokens_per_minute={} output_tokens_per_minute={}",
+            show(limits.requests_per_minute),
+            show(limits.tokens_per_minute),
+            show(limits.input_tokens_per_minute),
+            show(limits.output_tokens_per_minute)
+        );
     }
+}
 
-    // Load configuration from file and CLI args
-    let base_config = match Config::load() {
-        Ok(c) => c,
-        Err(e) => {
-            // Allow load to fail if using CLI args
-            if cli.command.api_key().is_none() {
-                return Err(e);
+/// Store an API key in the OS keyring, prompting when run from a terminal
+fn set_key(service: &str, account: &str) -> Result<()> {
+    let reference = KeyringRef::new(service, account)?;
+    let key = if std::io::stdin().is_terminal() {
+        dialoguer::Password::new()
+            .with_prompt(format!("API key for {reference}"))
+            .interact()?
+    } else {
+        let mut line = String::new();
+        std::io::stdin()
+            .read_line(&mut line)
+            .context("Failed to read API key from stdin")?;
+        line.trim().to_string()
+    };
+    if key.is_empty() {
+        anyhow::bail!("No API key given");
+    }
+    credentials::store_key(&reference.entry()?, &key)?;
+    println!("Stored API key; set api_key = \"{reference}\" to use it");
+    Ok(())
+}
+
+/// Print the limiter's decision for each reading in a usage recording
+#[allow(clippy::cast_precision_loss)]
+fn replay_recording(path: &Path, config: Config) -> Result<()> {
+    let records = replay::read_records(path)?;
+    let start = records.first().map_or(0, |record| record.timestamp_ms);
+    let decisions = replay::replay(&records, config.thresholds, config.backoff, config.limits)?;
+    for decision in &decisions {
+        let p = decision.percentages;
+        println!(
+            "+{:.1}s requests {}% tokens {}% input {}% output {}% errors {}% -> {} (backoff {:?})",
+            decision.timestamp_ms.saturating_sub(start) as f64 / 1000.0,
+            p.requests,
+            p.tokens,
+            p.input_tokens,
+            p.output_tokens,
+            p.error_requests,
+            if decision.proceed { "proceed" } else { "pause" },
+            decision.backoff
+        );
+    }
+    println!("Replayed {} readings", decisions.len());
+    Ok(())
+}
+
+/// Generate a short random identifier used to attribute log events to a run
+fn new_run_id() -> String {
+    format!("{:08x}", OsRng.next_u32())
+}
+
+/// Load the file and environment configuration that CLI options are merged into
+///
+/// When `--config` is an `http(s)://` URL the config is fetched from it
+/// instead of the local config files.
+///
+/// When a public key is configured, every config file that would be loaded
+/// must carry a valid signature; verification failures are never downgraded
+/// to defaults.
+async fn load_base_config(cli: &Cli) -> Result<Config> {
+    let url = cli
+        .config
+        .to_str()
+        .filter(|source| config::is_config_url(source));
+    if !cli.no_verify {
+        let public_key = cli.public_key.clone().or_else(|| {
+            std::env::var(format!("{}{}", cli.env_prefix, signing::PUBLIC_KEY_ENV)).ok()
+        });
+        if let Some(public_key) = public_key {
+            if let Some(url) = url {
+                anyhow::bail!(
+                    "Cannot verify the signature of a config fetched from {url}; \
+                     pass --no-verify to load it"
+                );
             }
-            Config::default()
+            verify_config_files(&Config::default_paths()?, &public_key)?;
         }
+    }
+
+    let loaded = match url {
+        Some(url) => Config::load_url(url, config::CONFIG_FETCH_TIMEOUT, &cli.env_prefix).await,
+        None => Config::load_with_prefix(&cli.env_prefix),
     };
+    match loaded {
+        Ok(c) => Ok(c),
+        Err(e) if cli.allow_missing_config => {
+            warn!("Failed to load configuration, using defaults: {e:#}");
+            Ok(Config::default())
+        }
+        // Allow load to fail if using CLI args, unless a URL was asked for
+        Err(_) if url.is_none() && cli.command.api_key().is_some() => Ok(Config::default()),
+        Err(e) => Err(e),
+    }
+}
 
-    let cli_config = create_cli_config(&cli.command);
-    let mut final_config = base_config;
-    final_config.merge(cli_config);
-    final_config.validate()?;
+fn verify_config_files(paths: &[PathBuf], public_key: &str) -> Result<()> {
+    let key = signing::parse_public_key(public_key)?;
+    for path in paths.iter().filter(|path| path.exists()) {
+        // Included files are loaded too, so they must be signed as well
+        for file in Config::files_with_includes(path)? {
+            signing::verify_config(&file, &key)?;
+        }
+    }
+    Ok(())
+}
 
-    let result = match cli.command {
-        Commands::Run { command, .. } => run_command(command, final_config).await,
-        Commands::Watch { pid, .. } => watch_process(pid, final_config),
-        Commands::Init { .. } => unreachable!(), // Already handled above
+fn sign_config_file(config: &Path, key_path: &Path, generate_key: bool) -> Result<()> {
+    let key = if generate_key {
+        if key_path.exists() {
+            anyhow::bail!("Signing key {} already exists", key_path.display());
+        }
+        let key = signing::generate_signing_key();
+        signing::write_signing_key(key_path, &key)?;
+        println!("Wrote signing key to {}", key_path.display());
+        key
+    } else {
+        signing::read_signing_key(key_path)?
     };
 
-    if let Err(ref e) = result {
-        eprintln!("{e}");
+    let sig_path = signing::sign_config(config, &key)?;
+    println!("Wrote signature to {}", sig_path.display());
+    println!("Public key: {}", signing::public_key_hex(&key));
+    Ok(())
+}
+
+fn export_config(config: &Config, format: ExportFormat, show_secrets: bool) -> Result<()> {
+    print!("{}", render_config(config, format, show_secrets)?);
+    Ok(())
+}
+
+fn render_config(config: &Config, format: ExportFormat, show_secrets: bool) -> Result<String> {
+    let mut config = config.clone();
+    if !show_secrets {
+        config.api.api_key = None;
     }
-    result
+    Ok(match format {
+        ExportFormat::Toml => toml::to_string_pretty(&config)?,
+        ExportFormat::Json => serde_json::to_string_pretty(&config)? + "\n",
+        ExportFormat::Env => config.to_env_exports(show_secrets),
+    })
+}
+
+/// Print the fields that differ between the config files at `left` and `right`
+fn diff_config(left: &Path, right: &Path, show_secrets: bool) -> Result<()> {
+    let load = |path: &Path| {
+        Config::builder()
+            .from_file(path)
+            .and_then(ConfigBuilder::build)
+            .with_context(|| format!("Failed to load config {}", path.display()))
+    };
+    let differences = load(left)?.diff(&load(right)?, show_secrets)?;
+    if differences.is_empty() {
+        println!("No differences");
+    }
+    for difference in differences {
+        println!("{difference}");
+    }
+    Ok(())
 }
 
 fn create_cli_config(cli: &Commands) -> Config {
-    let provider_config = match cli.api() {
+    let mut provider_config = match cli.api() {
         "openai" => ProviderConfig::OpenAI(OpenAIConfig::default()),
         "mock" => ProviderConfig::Mock(MockConfig::default()),
         _ => ProviderConfig::Anthropic(AnthropicConfig::default()),
     };
+    // Model and max tokens apply to whichever provider `--api` selected
+    let (model, max_tokens) = match &mut provider_config {
+        ProviderConfig::Anthropic(config) => {
+            (Some(&mut config.model), Some(&mut config.max_tokens))
+        }
+        ProviderConfig::OpenAI(config) => (Some(&mut config.model), Some(&mut config.max_tokens)),
+        ProviderConfig::Mock(_) | ProviderConfig::Aggregate(_) => (None, None),
+    };
+    if let (Some(model), Some(value)) = (model, cli.model()) {
+        *model = value.to_string();
+    }
+    if let (Some(max_tokens), Some(value)) = (max_tokens, cli.max_tokens()) {
+        *max_tokens = value;
+    }
 
     Config {
         limits: strainer::config::RateLimits {
             requests_per_minute: cli.requests_per_minute(),
             tokens_per_minute: cli.tokens_per_minute(),
             input_tokens_per_minute: cli.input_tokens_per_minute(),
+            output_tokens_per_minute: cli.output_tokens_per_minute(),
+            error_requests_per_minute: cli.error_requests_per_minute(),
+            fraction_of_quota: cli.fraction_of_quota(),
+            shared_instances: cli.share_factor(),
+            ramp_up_seconds: None,
+            force: cli.force_limits(),
+            by_model: HashMap::new(),
         },
         thresholds: strainer::config::Thresholds {
             warning: cli.warning_threshold(),
             critical: cli.critical_threshold(),
             resume: cli.resume_threshold(),
+            ..Default::default()
         },
         backoff: strainer::config::BackoffConfig {
-            min_seconds: cli.min_backoff(),
-            max_seconds: cli.max_backoff(),
+            min_seconds: HumanDuration::from_secs(u64::from(cli.min_backoff())),
+            max_seconds: HumanDuration::from_secs(u64::from(cli.max_backoff())),
         },
         process: strainer::config::ProcessConfig {
             pause_on_warning: cli.pause_on_warning(),
             pause_on_critical: cli.pause_on_critical(),
+            warmup_seconds: cli.warmup_seconds(),
+            post_resume_grace_seconds: cli.post_resume_grace_seconds(),
+            kill_timeout_ms: cli
+                .kill_timeout()
+                .map(|timeout| u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX)),
+            key_lock: cli.key_lock(),
+            ..Default::default()
+        },
+        proxy: strainer::config::ProxyConfig {
+            enabled: cli.proxy(),
+            ..Default::default()
         },
         api: strainer::config::ApiConfig {
             provider_config,
             api_key: cli.api_key(),
             base_url: Some(cli.api_base_url().to_string()),
             parameters: HashMap::default(),
+            merge: strainer::config::MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         },
         ..Default::default()
     }
 }
 
-async fn run_command(command: Vec<String>, config: Config) -> Result<()> {
+fn spawn_options(config: &Config) -> SpawnOptions {
+    let mut opts = SpawnOptions {
+        working_dir: config.process.working_dir.clone(),
+        env: config.process.env.clone(),
+        pty: false,
+        stdio: config.process.stdio,
+        retries: config.process.spawn_retries,
+        retry_delay: Duration::from_millis(config.process.spawn_retry_delay_ms),
+    };
+    if let Some(var) = &config.process.api_key_env {
+        if let Some(api_key) = config.api.resolved_api_key() {
+            opts.env.insert(var.clone(), api_key);
+        }
+    }
+    opts
+}
+
+/// Per-invocation options for `run` that are not part of the configuration
+#[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
+struct RunOptions {
+    tui: bool,
+    wait_for_capacity: bool,
+    /// Terminate the command and exit with `EX_TEMPFAIL` instead of pausing
+    on_pause_exit: bool,
+    config_check_only: bool,
+    /// Attach the command to a pseudo-terminal
+    pty: bool,
+    /// File to record usage readings to
+    record: Option<PathBuf>,
+    /// File to write the run summary to on exit
+    summary_file: Option<PathBuf>,
+    /// File to append each pause and resume decision to
+    audit_log: Option<PathBuf>,
+    /// File an external controller writes `pause` or `resume` to
+    control_file: Option<PathBuf>,
+    /// The provider was chosen with `--api` or `--api-key`, so `[[routes]]`
+    /// do not apply
+    cli_provider: bool,
+}
+
+/// Stand-in for the dashboard when built without the `tui` feature
+#[cfg(not(feature = "tui"))]
+#[derive(Debug)]
+struct Dashboard;
+
+#[cfg(not(feature = "tui"))]
+#[allow(clippy::unused_self, clippy::needless_pass_by_ref_mut)]
+impl Dashboard {
+    fn start(_title: String, _thresholds: Thresholds) -> Result<Self> {
+        anyhow::bail!("strainer was built without the `tui` feature")
+    }
+
+    fn update(&mut self, _percentages: UsagePercentages, _paused: bool) -> Result<()> {
+        unreachable!("dashboard cannot be started without the `tui` feature")
+    }
+
+    fn quit_requested(&self) -> Result<bool> {
+        unreachable!("dashboard cannot be started without the `tui` feature")
+    }
+}
+
+/// Sleep for `duration`, returning `false` early if the dashboard user quit
+async fn sleep_unless_quit(duration: Duration, dashboard: Option<&Dashboard>) -> Result<bool> {
+    let Some(dashboard) = dashboard else {
+        tokio::time::sleep(duration).await;
+        return Ok(true);
+    };
+    let deadline = tokio::time::Instant::now() + duration;
+    loop {
+        if dashboard.quit_requested()? {
+            return Ok(false);
+        }
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            return Ok(true);
+        }
+        tokio::time::sleep((deadline - now).min(Duration::from_millis(100))).await;
+    }
+}
+
+/// Wait before the process is started until there is capacity for it
+///
+/// Returns immediately unless usage is already critical; otherwise backs off
+/// until usage drops to the resume threshold so the child starts without an
+/// initial burst.
+async fn wait_for_capacity(rate_limiter: &mut RateLimiter) -> Result<()> {
+    let (proceed, mut backoff) = rate_limiter.check_limits()?;
+    if proceed {
+        return Ok(());
+    }
+
+    info!("Usage is critical, waiting for capacity before starting the process");
+    let resume = u32::from(rate_limiter.thresholds().resume);
+    loop {
+        tokio::time::sleep(backoff).await;
+        let (proceed, next_backoff) = rate_limiter.check_limits()?;
+        if proceed && rate_limiter.last_percentages().max() <= resume {
+            info!("Usage below resume threshold, starting process");
+            return Ok(());
+        }
+        backoff = next_backoff;
+    }
+}
+
+/// Run `command` under the rate limiter, writing the run summary if requested
+///
+/// The provider is the one of the first `[[routes]]` entry matching `command`,
+/// if any, unless `--api` or `--api-key` chose it.
+async fn run_command(command: Vec<String>, config: Config, opts: &RunOptions) -> Result<()> {
+    let config = if opts.cli_provider {
+        if !config.routes.is_empty() {
+            info!("Provider set with --api or --api-key, ignoring [[routes]]");
+        }
+        config
+    } else {
+        config.routed(&command).with_exit_code(ExitCode::Config)?
+    };
+    let mut summary = RunSummary::new(config.api.provider_config.to_string());
+    let result = supervise(command, config, opts, &mut summary).await;
+    if let Some(path) = opts
+        .summary_file
+        .as_deref()
+        .filter(|_| !opts.config_check_only)
+    {
+        summary.finish();
+        if let Err(e) = summary.write(path) {
+            warn!("{e:#}");
+        }
+    }
+    result
+}
+
+/// Run `command` once per requests-per-minute limit and print a table of the
+/// runtime and pauses of each run
+///
+/// A run that fails is reported in the table and does not stop the sweep.
+async fn bench(command: &[String], config: Config, limits: &[u32]) -> Result<()> {
+    let mut results = Vec::with_capacity(limits.len());
+    for &limit in limits {
+        let mut run_config = config.clone();
+        run_config.limits.requests_per_minute = Some(limit);
+        run_config.validate().with_exit_code(ExitCode::Config)?;
+        info!("Benchmarking with {limit} requests per minute");
+        let mut summary = RunSummary::new(run_config.api.provider_config.to_string());
+        let result = supervise(
+            command.to_vec(),
+            run_config,
+            &RunOptions::default(),
+            &mut summary,
+        )
+        .await;
+        summary.finish();
+        if let Err(e) = result {
+            warn!("Run with {limit} requests per minute failed: {e:#}");
+        }
+        results.push((limit, summary));
+    }
+    print!("{}", summary::bench_table(&results));
+    Ok(())
+}
+
+/// Start the local counting proxy and point the child at it
+///
+/// Returns the running server and `provider` wrapped to count the proxied usage.
+async fn start_proxy(
+    config: &Config,
+    spawn_opts: &mut SpawnOptions,
+    provider: Box<dyn Provider>,
+) -> Result<(ProxyServer, Box<dyn Provider>)> {
+    let upstream = config
+        .api
+        .base_url_default()
+        .ok_or_else(|| anyhow::anyhow!("Proxy mode requires an upstream base URL"))?;
+    let client = config.network.forwarding_client()?;
+    let server = ProxyServer::start_with_client(
+        &config.proxy.listen,
+        &upstream,
+        client,
+        config.proxy.estimate_tokens,
+        config.network.request_id_header.clone(),
+    )
+    .await?;
+    let env_var = config.proxy.env_var_for(&config.api.provider_config);
+    info!(
+        "Proxying {upstream} on {} via {env_var}",
+        server.local_url()
+    );
+    spawn_opts.env.insert(env_var, server.local_url());
+    let provider = ProxyProvider::new(server.usage(), provider)
+        .with_missing_metric_policy(config.proxy.missing_metric_policy)
+        .boxed();
+    Ok((server, provider))
+}
+
+/// Route `provider`'s usage through the proxy and recorder when enabled
+///
+/// Returns the running proxy server, if any, and the wrapped provider.
+async fn wrap_provider(
+    config: &Config,
+    opts: &RunOptions,
+    spawn_opts: &mut SpawnOptions,
+    mut provider: Box<dyn Provider>,
+) -> Result<(Option<ProxyServer>, Box<dyn Provider>)> {
+    let proxy = if config.proxy.enabled {
+        let (server, proxied) = start_proxy(config, spawn_opts, provider).await?;
+        provider = proxied;
+        Some(server)
+    } else {
+        None
+    };
+    if let Some(path) = &opts.record {
+        provider = Box::new(RecordingProvider::new(path, provider)?);
+    }
+    Ok((proxy, provider))
+}
+
+/// Lock the API key of `config` for this run, as `process.key_lock` asks
+///
+/// Runs without an API key lock the provider name instead.
+async fn acquire_key_lock(config: &Config) -> Result<Option<RunLock>> {
+    if config.process.key_lock == KeyLock::Off {
+        return Ok(None);
+    }
+    let key = config
+        .api
+        .resolved_api_key()
+        .unwrap_or_else(|| config.api.provider_config.to_string());
+    let path = RunLock::path_for(&key);
+    let mut waiting = false;
+    loop {
+        if let Some(lock) = RunLock::try_acquire(&path)? {
+            return Ok(Some(lock));
+        }
+        let holder = RunLock::holder(&path)
+            .map_or_else(|| "another run".to_string(), |pid| format!("run {pid}"));
+        if config.process.key_lock == KeyLock::Fail {
+            return Err(anyhow::anyhow!(
+                "API key is locked by {holder} ({})",
+                path.display()
+            ))
+            .with_exit_code(ExitCode::TempFail);
+        }
+        if !waiting {
+            info!("API key is locked by {holder}, waiting for it to finish");
+            waiting = true;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Build the rate limiter `config` describes, first waiting for capacity if asked
+///
+/// Also returns the provider's usage stream when it pushes usage.
+async fn start_rate_limiter(
+    config: &Config,
+    provider: Box<dyn Provider>,
+    wait_for_capacity_first: bool,
+) -> Result<(RateLimiter, Option<watch::Receiver<RateLimitInfo>>)> {
+    let mut rate_limiter =
+        RateLimiter::new(config.thresholds.clone(), config.backoff.clone(), provider)
+            .with_limits(config.active_limits())
+            .with_schedule(config.schedule.clone());
+    if wait_for_capacity_first {
+        wait_for_capacity(&mut rate_limiter).await?;
+    }
+    let usage_updates = rate_limiter.subscribe();
+    if usage_updates.is_some() {
+        info!("Provider streams usage, checking limits as it changes");
+    }
+    Ok((rate_limiter, usage_updates))
+}
+
+async fn supervise(
+    command: Vec<String>,
+    mut config: Config,
+    opts: &RunOptions,
+    summary: &mut RunSummary,
+) -> Result<()> {
     // Check for empty command vector
     if command.is_empty() {
         anyhow::bail!("No command specified");
     }
+    info!("Target: {}", config.api.target());
+
+    // Create provider, routing usage through the local proxy if enabled
+    let provider = providers::create_provider_chain(&config.api, &config.fallback_providers)
+        .with_exit_code(ExitCode::Config)?;
+    if opts.config_check_only {
+        println!("config OK");
+        return Ok(());
+    }
+    let _key_lock = acquire_key_lock(&config).await?;
+    let mut spawn_opts = spawn_options(&config);
+    spawn_opts.pty = opts.pty;
+    let (_proxy, provider) = wrap_provider(&config, opts, &mut spawn_opts, provider).await?;
+    let mut dashboard = if opts.tui {
+        Some(Dashboard::start(
+            command.join(" "),
+            config.thresholds.clone(),
+        )?)
+    } else {
+        None
+    };
+    let (mut rate_limiter, mut usage_updates) =
+        start_rate_limiter(&config, provider, opts.wait_for_capacity).await?;
+    let mut audit_log = opts.audit_log.as_deref().map(AuditLog::open).transpose()?;
 
-    // Create provider and rate limiter
-    let provider = providers::create_provider(&config.api)?;
-    let mut rate_limiter = RateLimiter::new(config.thresholds, config.backoff, provider);
+    // A SIGTERM (e.g. from `strainer stop`) or SIGINT terminates the child
+    // cleanly; listen before spawning so the child is never left behind
+    let mut sigterm = unix_signal(SignalKind::terminate())?;
+    let mut sigint = unix_signal(SignalKind::interrupt())?;
 
     // Start the process
-    let (controller, mut child) = ProcessController::from_command(&command)?;
-    info!("Started process with PID {}", child.id());
+    let (controller, mut child) = spawn_child(&command, &spawn_opts, &config).await?;
+    let mut resources = ResourceMonitor::new(controller.pid(), config.process.resource_limits());
+    let started = Instant::now();
+    let mut resumed_at: Option<Instant> = None;
+    // Set while the process is stopped, from when it was paused
+    let mut paused_at: Option<Instant> = None;
 
-    // Monitor process and rate limits
-    loop {
-        // Check if process is still running first
-        if let Some(status) = child.try_wait()? {
-            info!("Process exited with status {status}");
-            // If the process exited with a non-zero status, propagate the error
-            if !status.success() {
-                anyhow::bail!("Process exited with non-zero status: {status}");
+    // Monitor process and rate limits, never leaving the child behind on error
+    let result: Result<()> = async {
+        loop {
+            // Check if process is still running first
+            if let Some(status) = child.try_wait()? {
+                return process_exited(status, summary);
             }
-            return Ok(());
-        }
 
-        // Process is still running, check rate limits
-        let (proceed, backoff) = rate_limiter.check_limits()?;
+            // Process is still running, check rate limits
+            let (proceed, backoff) = check_usage(&mut rate_limiter, usage_updates.as_mut())?;
+            let held = pause_held(proceed, started, resumed_at, &config.process);
+            start_fallback(!proceed && !held, &mut config.process);
+            let critical_pause = !proceed && config.process.pause_on_critical && !held;
+            if opts.on_pause_exit && critical_pause {
+                return exit_instead_of_pause(&controller, &mut child, paused_at, &config, summary);
+            }
+            // Resource ceilings are enforced from the start, even during warmup
+            let excess = resources.check();
+            let control_pause = control_requests_pause(opts.control_file.as_deref());
+            let pausing = critical_pause || excess.is_some() || control_pause;
+            summary.observe(rate_limiter.last_percentages());
+            if let Some(dashboard) = dashboard.as_mut() {
+                dashboard.update(rate_limiter.last_percentages(), pausing)?;
+            }
 
-        if !proceed {
-            if config.process.pause_on_critical {
-                info!("Rate limit critical threshold reached, pausing process");
-                controller.pause()?;
+            let wait = next_wait(!proceed && !held, backoff, excess.is_some(), &config);
+            if !pausing {
+                audit(
+                    audit_log.as_mut(),
+                    &rate_limiter,
+                    AuditAction::Proceed,
+                    wait,
+                )?;
+            } else if paused_at.is_none() {
+                if !pause(&controller, excess.as_ref(), control_pause)? {
+                    return process_gone(&mut child, summary);
+                }
+                // A pause held across checks is audited once, when it starts
+                audit(audit_log.as_mut(), &rate_limiter, AuditAction::Pause, wait)?;
+                paused_at = Some(Instant::now());
+            }
+            let keep_running = wait_for_next_check(
+                wait,
+                dashboard.as_ref(),
+                // Pushed usage ends the wait early, except during a pause's backoff
+                (!pausing).then_some(&mut usage_updates),
+                [&mut sigterm, &mut sigint],
+            )
+            .await?;
+            // A pause the control file still asks for is held until the next
+            // check, unless strainer is stopping
+            let stay_paused = keep_running && control_requests_pause(opts.control_file.as_deref());
+            if let Some(since) = paused_at.take_if(|_| !stay_paused) {
+                if !resume(
+                    &controller,
+                    since,
+                    summary,
+                    audit_log.as_mut(),
+                    &rate_limiter,
+                )? {
+                    return process_gone(&mut child, summary);
+                }
+                resumed_at = Some(Instant::now());
             }
-            tokio::time::sleep(backoff).await;
-            if config.process.pause_on_critical {
-                info!("Resuming process after backoff");
-                controller.resume()?;
+            if !keep_running {
+                info!("Stopping, terminating process");
+                return terminate(&controller, &mut child, &config, summary);
+            }
+        }
+    }
+    .await;
+    if result.is_err() {
+        stop_after_error(&controller, &mut child, &config);
+    }
+    result
+}
+
+/// Spawn the command with the configured pause signal
+///
+/// Spawning runs on a blocking task, since retries sleep between attempts.
+async fn spawn_child(
+    command: &[String],
+    spawn_opts: &SpawnOptions,
+    config: &Config,
+) -> Result<(ProcessController, Child)> {
+    let pause_signal = config.process.pause_signal()?;
+    let (command, spawn_opts) = (command.to_vec(), spawn_opts.clone());
+    let (controller, child) = tokio::task::spawn_blocking(move || {
+        ProcessController::from_command_with_opts(&command, &spawn_opts)
+    })
+    .await??;
+    info!("Started process with PID {}", child.id());
+    Ok((controller.with_pause_signal(pause_signal), child))
+}
+
+/// Resume and terminate a child still running when supervising it failed
+fn stop_after_error(controller: &ProcessController, child: &mut Child, config: &Config) {
+    if !matches!(child.try_wait(), Ok(None)) {
+        return;
+    }
+    warn!("Stopping process {} after an error", child.id());
+    // A paused process must be continued to handle SIGTERM
+    let _ = controller.resume();
+    if let Err(e) = controller.terminate_with_timeout(config.process.kill_timeout()) {
+        warn!("Failed to terminate process: {e}");
+    }
+    let _ = child.wait();
+}
+
+/// Resume a process paused `since` then and audit it, returning `false` if
+/// it had exited
+fn resume(
+    controller: &ProcessController,
+    since: Instant,
+    summary: &mut RunSummary,
+    audit_log: Option<&mut AuditLog>,
+    rate_limiter: &RateLimiter,
+) -> Result<bool> {
+    info!("Resuming process after backoff");
+    let resumed = controller.resume()?;
+    summary.record_pause(since.elapsed());
+    if resumed {
+        audit(audit_log, rate_limiter, AuditAction::Resume, Duration::ZERO)?;
+    }
+    Ok(resumed)
+}
+
+/// Terminate the process, recording its exit code in `summary`
+fn terminate(
+    controller: &ProcessController,
+    child: &mut Child,
+    config: &Config,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    controller.terminate_with_timeout(config.process.kill_timeout())?;
+    summary.exit_code = child.wait().ok().and_then(|status| status.code());
+    Ok(())
+}
+
+/// End the run with `EX_TEMPFAIL` instead of pausing, for `--on-pause-exit`
+///
+/// A process already paused for another reason is resumed first so it can
+/// handle `SIGTERM`. Only the process itself is signalled, so anything it
+/// started, such as the commands of a shell script, keeps running unless it
+/// passes the signal on.
+fn exit_instead_of_pause(
+    controller: &ProcessController,
+    child: &mut Child,
+    paused_at: Option<Instant>,
+    config: &Config,
+    summary: &mut RunSummary,
+) -> Result<()> {
+    info!("Usage is critical, terminating process instead of pausing");
+    if let Some(since) = paused_at {
+        summary.record_pause(since.elapsed());
+        if !controller.resume()? {
+            return process_gone(child, summary);
+        }
+    }
+    terminate(controller, child, config, summary)?;
+    Err(anyhow::anyhow!(
+        "Usage is critical, exited instead of pausing"
+    ))
+    .with_exit_code(ExitCode::TempFail)
+}
+
+/// Start the `on_critical_command` fallback when usage is `critical`, taking
+/// it from `process` so it only ever runs once
+///
+/// It shares strainer's stdio and runs alongside the supervised command; its
+/// exit status is logged but never changes strainer's own.
+fn start_fallback(critical: bool, process: &mut config::ProcessConfig) {
+    let command = critical
+        .then(|| process.on_critical_command.take())
+        .flatten();
+    let Some((program, args)) = command.as_deref().and_then(<[String]>::split_first) else {
+        return;
+    };
+    info!("Usage is critical, starting fallback command {program}");
+    let mut fallback = tokio::process::Command::new(program);
+    fallback.args(args).envs(&process.env);
+    if let Some(dir) = &process.working_dir {
+        fallback.current_dir(dir);
+    }
+    match fallback.spawn() {
+        Ok(mut child) => {
+            tokio::spawn(async move {
+                match child.wait().await {
+                    Ok(status) if status.success() => info!("Fallback command finished"),
+                    Ok(status) => warn!("Fallback command exited with {status}"),
+                    Err(e) => warn!("Failed to wait for fallback command: {e}"),
+                }
+            });
+        }
+        Err(e) => warn!("Failed to start fallback command {program}: {e}"),
+    }
+}
+
+/// Whether critical usage is kept from pausing the process, logging why
+///
+/// Early readings may still reflect earlier work, so none pause during the
+/// warmup after the process `started`, and a process resumed within the
+/// post-resume grace gets to make progress first.
+fn pause_held(
+    proceed: bool,
+    started: Instant,
+    resumed_at: Option<Instant>,
+    process: &config::ProcessConfig,
+) -> bool {
+    let warming_up = started.elapsed() < Duration::from_secs(u64::from(process.warmup_seconds));
+    let grace = Duration::from_secs(u64::from(process.post_resume_grace_seconds));
+    let in_grace = resumed_at.is_some_and(|at| at.elapsed() < grace);
+    if !proceed && warming_up {
+        info!("Usage is critical during warmup, not pausing");
+    } else if !proceed && in_grace {
+        warn!("Usage is critical within {grace:?} of resuming, not pausing yet");
+    }
+    warming_up || in_grace
+}
+
+/// Wait `wait` before the next usage check, or until pushed usage changes
+///
+/// Returns `false` when strainer should stop instead: the dashboard user
+/// quit, or SIGTERM or SIGINT arrived.
+async fn wait_for_next_check(
+    wait: Duration,
+    dashboard: Option<&Dashboard>,
+    usage_updates: Option<&mut Option<watch::Receiver<RateLimitInfo>>>,
+    [sigterm, sigint]: [&mut UnixSignal; 2],
+) -> Result<bool> {
+    let usage_changed = async {
+        match usage_updates {
+            Some(updates) => usage_changed(updates).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        keep_running = sleep_unless_quit(wait, dashboard) => keep_running,
+        () = usage_changed => Ok(true),
+        _ = sigterm.recv() => {
+            info!("Received SIGTERM");
+            Ok(false)
+        }
+        _ = sigint.recv() => {
+            info!("Received SIGINT");
+            Ok(false)
+        }
+    }
+}
+
+/// Pause the process and log why, returning `false` if it had exited since
+/// it was last checked
+fn pause(
+    controller: &ProcessController,
+    excess: Option<&ResourceExcess>,
+    control_pause: bool,
+) -> Result<bool> {
+    if let Some(excess) = excess {
+        warn!("Process {excess}, pausing process");
+    } else if control_pause {
+        info!("Control file requests a pause, pausing process");
+    } else {
+        info!("Rate limit critical threshold reached, pausing process");
+    }
+    controller.pause()
+}
+
+/// How long to wait before the next check: the limiter's backoff when
+/// `limited`, the minimum backoff over a resource ceiling, else a second
+const fn next_wait(
+    limited: bool,
+    backoff: Duration,
+    over_resources: bool,
+    config: &Config,
+) -> Duration {
+    if limited {
+        backoff
+    } else if over_resources {
+        config.backoff.min_backoff()
+    } else {
+        Duration::from_secs(1)
+    }
+}
+
+/// Whether the control file at `path`, if any, asks for the process to be paused
+///
+/// A missing or unreadable file does not pause, so a controller can clear its
+/// request by deleting the file.
+fn control_requests_pause(path: Option<&Path>) -> bool {
+    let Some(contents) = path.and_then(|path| std::fs::read_to_string(path).ok()) else {
+        return false;
+    };
+    match contents.trim() {
+        "pause" => true,
+        "resume" | "" => false,
+        other => {
+            warn!("Ignoring unknown control file contents {other:?}");
+            false
+        }
+    }
+}
+
+/// Append a decision, with the usage of the latest check, to the audit log if any
+fn audit(
+    log: Option<&mut AuditLog>,
+    rate_limiter: &RateLimiter,
+    action: AuditAction,
+    backoff: Duration,
+) -> Result<()> {
+    let Some(log) = log else {
+        return Ok(());
+    };
+    log.record(&AuditRecord::new(
+        rate_limiter.last_percentages(),
+        rate_limiter.last_band(),
+        action,
+        backoff,
+    ))
+}
+
+/// Check limits against newly pushed usage, or poll the provider
+///
+/// Usage already seen is polled again rather than reused, since a quiet
+/// stream does not announce its usage window ending.
+fn check_usage(
+    rate_limiter: &mut RateLimiter,
+    updates: Option<&mut watch::Receiver<RateLimitInfo>>,
+) -> Result<(bool, Duration)> {
+    match updates {
+        Some(updates) if updates.has_changed().unwrap_or(false) => {
+            let pushed = updates.borrow_and_update().clone();
+            rate_limiter.check_pushed_limits(&pushed)
+        }
+        _ => rate_limiter.check_limits(),
+    }
+}
+
+/// Wait for the provider to push new usage, never finishing without a stream
+///
+/// A closed stream is dropped, so later checks poll the provider instead.
+async fn usage_changed(updates: &mut Option<watch::Receiver<RateLimitInfo>>) {
+    let Some(receiver) = updates else {
+        return std::future::pending().await;
+    };
+    if receiver.changed().await.is_err() {
+        warn!("Usage stream closed, polling the provider instead");
+        *updates = None;
+    }
+}
+
+/// Finish a run whose process exited with `status`
+fn process_exited(status: ExitStatus, summary: &mut RunSummary) -> Result<()> {
+    info!("Process exited with status {status}");
+    summary.exit_code = status.code();
+    // If the process exited with a non-zero status, propagate the error
+    if !status.success() {
+        anyhow::bail!("Process exited with non-zero status: {status}");
+    }
+    Ok(())
+}
+
+/// Finish a run whose process was gone when it was signalled
+fn process_gone(child: &mut Child, summary: &mut RunSummary) -> Result<()> {
+    info!("Process exited before it could be signalled");
+    match child.try_wait() {
+        Ok(Some(status)) => process_exited(status, summary),
+        _ => Ok(()),
+    }
+}
+
+/// Check usage every `interval`, logging band changes, until SIGINT or SIGTERM
+async fn monitor(config: Config, interval: Duration) -> Result<()> {
+    config.validate().with_exit_code(ExitCode::Config)?;
+    let provider = providers::create_provider_chain(&config.api, &config.fallback_providers)
+        .with_exit_code(ExitCode::Config)?;
+    let (mut rate_limiter, _) = start_rate_limiter(&config, provider, false).await?;
+    let mut sigterm = unix_signal(SignalKind::terminate())?;
+    let mut sigint = unix_signal(SignalKind::interrupt())?;
+    info!("Monitoring usage every {interval:?}");
+
+    let mut band = None;
+    loop {
+        // Failed checks are reported but do not end monitoring
+        match rate_limiter.check_limits() {
+            Ok(_) => {
+                let current = rate_limiter.last_band();
+                if let Some(message) = band_change(band, &rate_limiter) {
+                    if current > band.unwrap_or_default() {
+                        warn!("{message}");
+                    } else {
+                        info!("{message}");
+                    }
+                    band = Some(current);
+                }
             }
-            continue;
+            Err(e) => warn!("Usage check failed: {e:#}"),
         }
+        tokio::select! {
+            () = tokio::time::sleep(interval) => {}
+            _ = sigterm.recv() => break,
+            _ = sigint.recv() => break,
+        }
+    }
+    info!("Stopped monitoring");
+    Ok(())
+}
+
+/// Describe the move from `previous` to the band of the limiter's latest
+/// check, or `None` if the band is unchanged
+fn band_change(previous: Option<UsageBand>, rate_limiter: &RateLimiter) -> Option<String> {
+    let current = rate_limiter.last_band();
+    if previous == Some(current) {
+        return None;
+    }
+    let change = previous.map_or_else(
+        || format!("Usage band is {current}"),
+        |from| format!("Usage band changed from {from} to {current}"),
+    );
+    let p = rate_limiter.last_percentages();
+    Some(format!(
+        "{change} (requests {}%, tokens {}%, input {}%, output {}%, errors {}%)",
+        p.requests, p.tokens, p.input_tokens, p.output_tokens, p.error_requests
+    ))
+}
+
+async fn gate(config: Config, socket: &Path) -> Result<()> {
+    config.validate().with_exit_code(ExitCode::Config)?;
+    let provider = providers::create_provider_chain(&config.api, &config.fallback_providers)
+        .with_exit_code(ExitCode::Config)?;
+    let (rate_limiter, _) = start_rate_limiter(&config, provider, false).await?;
+    let mut sigterm = unix_signal(SignalKind::terminate())?;
+    let mut sigint = unix_signal(SignalKind::interrupt())?;
+    let server = GateServer::start(socket, rate_limiter)?;
+    info!("Gating requests on {}", server.path().display());
 
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = sigint.recv() => {}
     }
+    drop(server);
+    info!("Stopped gating");
+    Ok(())
 }
 
 fn watch_process(pid: u32, _config: Config) -> Result<()> {
@@ -179,10 +1395,30 @@ fn watch_process(pid: u32, _config: Config) -> Result<()> {
         println!("Process {pid} is running");
         Ok(())
     } else {
-        anyhow::bail!("Process {} is not running", pid);
+        anyhow::bail!("Process {pid} is not running");
     }
 }
 
+/// Watch the process whose ID is in `pid_file`
+///
+/// A service that restarted may have replaced the process and rewritten
+/// the file since it was read, so the file is read again before the
+/// process counts as not running.
+fn watch_pid_file(pid_file: &Path, config: Config) -> Result<()> {
+    let pid = read_pid_file(pid_file)?;
+    if ProcessController::new(pid).is_running() {
+        println!("Process {pid} is running");
+        return Ok(());
+    }
+    let reread = read_pid_file(pid_file)?;
+    if reread == pid {
+        anyhow::bail!("Process {pid} from {} is not running", pid_file.display());
+    }
+    info!("{} now holds PID {reread}", pid_file.display());
+    let reread = u32::try_from(reread).context("PID out of range")?;
+    watch_process(reread, config)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -194,7 +1430,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_run_command_empty() {
-        let result = run_command(vec![], Config::default()).await;
+        let result = run_command(vec![], Config::default(), &RunOptions::default()).await;
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
@@ -207,7 +1443,7 @@ mod tests {
         let mut config = Config::default();
         config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
 
-        let result = run_command(vec!["true".to_string()], config).await;
+        let result = run_command(vec!["true".to_string()], config, &RunOptions::default()).await;
         assert!(result.is_ok());
     }
 
@@ -216,7 +1452,7 @@ mod tests {
         let mut config = Config::default();
         config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
 
-        let result = run_command(vec!["false".to_string()], config).await;
+        let result = run_command(vec!["false".to_string()], config, &RunOptions::default()).await;
         assert!(result.is_err()); // The command should fail because 'false' exits with non-zero
     }
 
@@ -227,8 +1463,8 @@ mod tests {
         config.limits.requests_per_minute = Some(1);
         config.thresholds.critical = 50;
         config.process.pause_on_critical = true;
-        config.backoff.min_seconds = 1;
-        config.backoff.max_seconds = 2;
+        config.backoff.min_seconds = HumanDuration::from_secs(1);
+        config.backoff.max_seconds = HumanDuration::from_secs(2);
 
         // Start a long-running process that we can control
         let mut child = Command::new("sleep")
@@ -239,7 +1475,12 @@ mod tests {
         // Run the command in a separate task so we can kill it after our test
         let config_clone = config.clone();
         let handle = tokio::spawn(async move {
-            run_command(vec!["sleep".to_string(), "10".to_string()], config_clone).await
+            run_command(
+                vec!["sleep".to_string(), "10".to_string()],
+                config_clone,
+                &RunOptions::default(),
+            )
+            .await
         });
 
         // Give it some time to start
@@ -254,6 +1495,244 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_run_command_with_proxy() {
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        config.api.base_url = Some("http://127.0.0.1:9".to_string());
+        config.proxy.enabled = true;
+
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "test -n \"$STRAINER_PROXY_URL\"".to_string(),
+        ];
+        let result = run_command(command, config, &RunOptions::default()).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_run_command_with_env_and_working_dir() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        config.api.api_key = Some("child-key".to_string());
+        config.process.working_dir = Some(dir.path().to_path_buf());
+        config
+            .process
+            .env
+            .insert("STRAINER_CHILD_VAR".to_string(), "set".to_string());
+        config.process.api_key_env = Some("CHILD_API_KEY".to_string());
+
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "test \"$STRAINER_CHILD_VAR\" = set && test \"$CHILD_API_KEY\" = child-key && touch marker"
+                .to_string(),
+        ];
+        let result = run_command(command, config, &RunOptions::default()).await;
+        assert!(result.is_ok());
+        assert!(dir.path().join("marker").exists());
+    }
+
+    #[tokio::test]
+    async fn test_run_command_child_exits_before_pause() {
+        let mut config = Config::default();
+        // The mock has used its whole request quota, so the first check pauses
+        // the child
+        config.api.provider_config = ProviderConfig::Mock(MockConfig {
+            requests_per_minute: Some(100),
+            requests_used: 100,
+            ..MockConfig::default()
+        });
+        config.process.pause_on_critical = true;
+        config.backoff.min_seconds = HumanDuration::from_millis(50);
+        config.backoff.max_seconds = HumanDuration::from_millis(100);
+
+        let result = run_command(vec!["true".to_string()], config, &RunOptions::default()).await;
+        assert!(result.is_ok(), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn test_warmup_prevents_pausing_at_start() {
+        let config_with_warmup = |warmup_seconds| {
+            let mut config = Config::default();
+            // The mock has used its whole request quota, as if an earlier job had
+            config.api.provider_config = ProviderConfig::Mock(MockConfig {
+                requests_per_minute: Some(100),
+                requests_used: 100,
+                ..MockConfig::default()
+            });
+            config.backoff.min_seconds = HumanDuration::from_millis(50);
+            config.backoff.max_seconds = HumanDuration::from_millis(100);
+            config.process.warmup_seconds = warmup_seconds;
+            config
+        };
+        let command = || vec!["sleep".to_string(), "0.5".to_string()];
+
+        let mut summary = RunSummary::new("mock");
+        let opts = RunOptions::default();
+        supervise(command(), config_with_warmup(60), &opts, &mut summary)
+            .await
+            .unwrap();
+        assert_eq!(summary.pause_count, 0);
+        assert_eq!(summary.exit_code, Some(0));
+
+        let mut summary = RunSummary::new("mock");
+        supervise(command(), config_with_warmup(0), &opts, &mut summary)
+            .await
+            .unwrap();
+        assert!(summary.pause_count > 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_provider_error_stops_process() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        config.process.working_dir = Some(dir.path().to_path_buf());
+        // The relay of piped output only ends once the command is gone
+        config.process.stdio = config::StdioMode::Pipe;
+        let command = vec!["sleep".to_string(), "30".to_string()];
+        // Recording a reading fails, so the first check after spawning fails
+        let opts = RunOptions {
+            record: Some(PathBuf::from("/dev/full")),
+            ..RunOptions::default()
+        };
+
+        let mut summary = RunSummary::new("mock");
+        let result = tokio::time::timeout(
+            Duration::from_secs(10),
+            supervise(command, config, &opts, &mut summary),
+        )
+        .await
+        .expect("supervise hung after the provider failed");
+        assert!(result.is_err());
+
+        // No process is left running in the command's directory
+        let dir = dir.path().canonicalize().unwrap();
+        let left_behind = std::fs::read_dir("/proc")
+            .unwrap()
+            .filter_map(|entry| std::fs::read_link(entry.ok()?.path().join("cwd")).ok())
+            .any(|cwd| cwd == dir);
+        assert!(!left_behind);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_resource_excess_pauses_process() {
+        let dir = tempdir().unwrap();
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        // Any running process is above a one-byte ceiling
+        config.process.max_memory = Some(1);
+        // The command records the pause signal, then is stopped by SIGSTOP
+        config.process.pause_signal = Some("SIGTSTP".to_string());
+        config.process.working_dir = Some(dir.path().to_path_buf());
+        config.backoff.min_seconds = HumanDuration::from_millis(50);
+        config.backoff.max_seconds = HumanDuration::from_millis(100);
+        let command = [
+            "sh",
+            "-c",
+            "trap 'touch paused' TSTP; for i in 1 2 3 4 5 6 7 8 9 10; do sleep 0.1; done",
+        ]
+        .map(str::to_string)
+        .to_vec();
+
+        let mut summary = RunSummary::new("mock");
+        supervise(command, config, &RunOptions::default(), &mut summary)
+            .await
+            .unwrap();
+        assert!(summary.pause_count > 0);
+        assert!(dir.path().join("paused").exists());
+        assert_eq!(summary.exit_code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_post_resume_grace_spaces_pauses() {
+        let config_with_grace = |grace_seconds| {
+            let mut config = Config::default();
+            // The mock's quota stays used up, so usage is critical for the whole run
+            config.api.provider_config = ProviderConfig::Mock(MockConfig {
+                requests_per_minute: Some(100),
+                requests_used: 100,
+                ..MockConfig::default()
+            });
+            config.backoff.min_seconds = HumanDuration::from_millis(50);
+            config.backoff.max_seconds = HumanDuration::from_millis(100);
+            config.process.post_resume_grace_seconds = grace_seconds;
+            config
+        };
+        let command = || vec!["sleep".to_string(), "2.5".to_string()];
+        let opts = RunOptions::default();
+
+        // Consecutive pauses start at least the grace apart, so only a few
+        // fit in the run
+        let mut summary = RunSummary::new("mock");
+        let started = Instant::now();
+        supervise(command(), config_with_grace(1), &opts, &mut summary)
+            .await
+            .unwrap();
+        let runtime = started.elapsed();
+        assert!(summary.pause_count > 1, "{summary:?}");
+        assert!(
+            Duration::from_secs(1) * (summary.pause_count - 1) <= runtime,
+            "{summary:?} in {runtime:?}"
+        );
+
+        // Without a grace the process is paused again right after each resume
+        let mut summary = RunSummary::new("mock");
+        let started = Instant::now();
+        supervise(command(), config_with_grace(0), &opts, &mut summary)
+            .await
+            .unwrap();
+        let runtime = started.elapsed();
+        assert!(
+            Duration::from_secs(1) * (summary.pause_count - 1) > runtime,
+            "{summary:?} in {runtime:?}"
+        );
+    }
+
+    #[test]
+    fn test_process_gone_after_child_was_reaped() {
+        let (controller, mut child) =
+            ProcessController::from_command(&["true".to_string()]).unwrap();
+        child.wait().unwrap();
+
+        assert!(!controller.pause().unwrap());
+        let mut summary = RunSummary::new("mock");
+        assert!(process_gone(&mut child, &mut summary).is_ok());
+        assert_eq!(summary.exit_code, Some(0));
+    }
+
+    #[test]
+    fn test_render_config_redacts_secrets() {
+        let mut config = Config::default();
+        config.api.api_key = Some("secret-key".to_string());
+
+        for format in [ExportFormat::Toml, ExportFormat::Json, ExportFormat::Env] {
+            let output = render_config(&config, format, false).unwrap();
+            assert!(!output.contains("secret-key"), "{format:?} leaked the key");
+            let output = render_config(&config, format, true).unwrap();
+            assert!(output.contains("secret-key"), "{format:?} omitted the key");
+        }
+    }
+
+    #[test]
+    fn test_pid_file_rejects_non_positive_pids() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("strainer.pid");
+        for contents in ["0", "-1", "-4242", "nope"] {
+            std::fs::write(&path, contents).unwrap();
+            let err = read_pid_file(&path).unwrap_err();
+            assert!(err.to_string().contains("Invalid PID"), "{contents}: {err}");
+            assert!(stop_daemon(&path).is_err());
+        }
+        std::fs::write(&path, "4242\n").unwrap();
+        assert_eq!(read_pid_file(&path).unwrap(), 4242);
+    }
+
     #[test]
     fn test_watch_process_not_running() {
         let result = watch_process(1, Config::default());
@@ -274,6 +1753,27 @@ mod tests {
         let _ = child.wait_with_output();
     }
 
+    #[tokio::test]
+    async fn test_closed_usage_stream_falls_back_to_polling() {
+        let info = RateLimitInfo {
+            requests_used: 1,
+            tokens_used: 0,
+            input_tokens_used: 0,
+            output_tokens_used: 0,
+            error_requests_used: 0,
+        };
+        let (sender, receiver) = watch::channel(info);
+        let mut updates = Some(receiver);
+
+        sender.send_modify(|info| info.requests_used += 1);
+        usage_changed(&mut updates).await;
+        assert!(updates.is_some());
+
+        drop(sender);
+        usage_changed(&mut updates).await;
+        assert!(updates.is_none());
+    }
+
     #[tokio::test]
     async fn test_main_init_command() {
         let temp_dir = tempdir().unwrap();
@@ -292,12 +1792,19 @@ mod tests {
             config,
             no_prompt,
             force,
+            wait,
+            strict,
+            dry_run,
         } = cli.command
         {
             let result = strainer::initialize_config(strainer::InitOptions {
                 config_path: config,
                 no_prompt,
                 force,
+                wait,
+                strict,
+                dry_run,
+                env_prefix: cli.env_prefix,
             })
             .await;
             assert!(result.is_ok());
@@ -307,6 +1814,72 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_config_applies_model_and_max_tokens() {
+        let cli = Cli::parse_from([
+            "strainer",
+            "run",
+            "--api",
+            "anthropic",
+            "--model",
+            "claude-3-opus",
+            "--max-tokens",
+            "4096",
+            "--",
+            "true",
+        ]);
+        let ProviderConfig::Anthropic(config) = create_cli_config(&cli.command).api.provider_config
+        else {
+            panic!("Expected Anthropic provider");
+        };
+        assert_eq!(config.model, "claude-3-opus");
+        assert_eq!(config.max_tokens, 4096);
+
+        let cli = Cli::parse_from([
+            "strainer", "watch", "--pid", "1", "--api", "openai", "--model", "gpt-4o",
+        ]);
+        let ProviderConfig::OpenAI(config) = create_cli_config(&cli.command).api.provider_config
+        else {
+            panic!("Expected OpenAI provider");
+        };
+        assert_eq!(config.model, "gpt-4o");
+        assert_eq!(config.max_tokens, OpenAIConfig::default().max_tokens);
+    }
+
+    #[test]
+    fn test_cli_config_without_model_keeps_provider_defaults() {
+        let cli = Cli::parse_from(["strainer", "run", "--", "true"]);
+        let ProviderConfig::Anthropic(config) = create_cli_config(&cli.command).api.provider_config
+        else {
+            panic!("Expected Anthropic provider");
+        };
+        let defaults = AnthropicConfig::default();
+        assert_eq!(config.model, defaults.model);
+        assert_eq!(config.max_tokens, defaults.max_tokens);
+    }
+
+    #[test]
+    fn test_cli_config_without_model_keeps_file_model() {
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Anthropic(AnthropicConfig {
+            model: "claude-3-opus".to_string(),
+            max_tokens: 4096,
+            ..AnthropicConfig::default()
+        });
+        let model = |config: &Config| match &config.api.provider_config {
+            ProviderConfig::Anthropic(config) => (config.model.clone(), config.max_tokens),
+            _ => panic!("Expected Anthropic provider"),
+        };
+
+        let cli = Cli::parse_from(["strainer", "run", "--", "true"]);
+        config.merge(create_cli_config(&cli.command));
+        assert_eq!(model(&config), ("claude-3-opus".to_string(), 4096));
+
+        let cli = Cli::parse_from(["strainer", "run", "--model", "claude-3-haiku", "--", "true"]);
+        config.merge(create_cli_config(&cli.command));
+        assert_eq!(model(&config), ("claude-3-haiku".to_string(), 4096));
+    }
+
     #[tokio::test]
     async fn test_main_run_command() {
         let args = vec!["strainer", "run", "--api", "mock", "--", "true"];
@@ -314,7 +1887,7 @@ mod tests {
         match cli.command {
             Commands::Run { ref command, .. } => {
                 let config = create_cli_config(&cli.command);
-                let result = run_command(command.clone(), config).await;
+                let result = run_command(command.clone(), config, &RunOptions::default()).await;
                 assert!(result.is_ok());
             }
             _ => panic!("Expected Run command"),
@@ -333,7 +1906,7 @@ mod tests {
 
         let cli = Cli::parse_from(args.clone());
         let pid = match &cli.command {
-            Commands::Watch { pid, .. } => *pid,
+            Commands::Watch { pid: Some(pid), .. } => *pid,
             _ => panic!("Expected Watch command"),
         };
 
@@ -343,6 +1916,8 @@ mod tests {
                 api_key: None,
                 base_url: None,
                 parameters: HashMap::default(),
+                merge: strainer::config::MergeStrategy::Extend,
+                remove_parameters: Vec::new(),
             },
             ..Default::default()
         };
@@ -352,4 +1927,167 @@ mod tests {
 
         let _ = child.wait_with_output();
     }
+
+    /// Provider that reports a scripted sequence of request counts
+    #[derive(Debug)]
+    struct ScriptedProvider {
+        requests: std::sync::Mutex<std::collections::VecDeque<u32>>,
+    }
+
+    impl providers::Provider for ScriptedProvider {
+        fn get_rate_limits(&self) -> Result<providers::RateLimitInfo> {
+            let mut requests = self.requests.lock().unwrap();
+            let requests_used = if requests.len() > 1 {
+                requests.pop_front().unwrap()
+            } else {
+                requests[0]
+            };
+            Ok(providers::RateLimitInfo {
+                requests_used,
+                tokens_used: 0,
+                input_tokens_used: 0,
+                output_tokens_used: 0,
+                error_requests_used: 0,
+            })
+        }
+
+        fn get_rate_limits_config(&self) -> Result<providers::RateLimitsConfig> {
+            Ok(providers::RateLimitsConfig {
+                requests_per_minute: Some(100),
+                tokens_per_minute: None,
+                input_tokens_per_minute: None,
+                output_tokens_per_minute: None,
+            })
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    fn scripted_limiter(requests: &[u32]) -> RateLimiter {
+        let provider = ScriptedProvider {
+            requests: std::sync::Mutex::new(requests.iter().copied().collect()),
+        };
+        RateLimiter::new(
+            strainer::config::Thresholds {
+                warning: 30,
+                critical: 50,
+                resume: 25,
+                forecast_horizon_seconds: 300,
+                threshold_inclusive: true,
+                gating: strainer::config::Gating::Any,
+                rounding: strainer::config::RoundingMode::Floor,
+                strict_usage: false,
+                warning_sustained_seconds: 0,
+                pause_on_sustained_warning: false,
+                reset_on_resume: false,
+                latency_backoff_ms: 0,
+                percent_precision: 0,
+            },
+            strainer::config::BackoffConfig {
+                min_seconds: HumanDuration::from_secs(0),
+                max_seconds: HumanDuration::from_secs(1),
+            },
+            Box::new(provider),
+        )
+    }
+
+    #[test]
+    fn test_monitor_reports_each_band_change_once() {
+        // Normal, warning, critical and back to normal, each for two checks
+        let mut limiter = scripted_limiter(&[0, 0, 40, 40, 60, 60, 10, 10]);
+        let mut band = None;
+        let mut changes = Vec::new();
+        for _ in 0..8 {
+            limiter.check_limits().unwrap();
+            if let Some(message) = band_change(band, &limiter) {
+                changes.push(message);
+                band = Some(limiter.last_band());
+            }
+        }
+        let percent =
+            |requests| format!("(requests {requests}%, tokens 0%, input 0%, output 0%, errors 0%)");
+        assert_eq!(
+            changes,
+            [
+                format!("Usage band is normal {}", percent(0)),
+                format!("Usage band changed from normal to warning {}", percent(40)),
+                format!(
+                    "Usage band changed from warning to critical {}",
+                    percent(60)
+                ),
+                format!("Usage band changed from critical to normal {}", percent(10)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_capacity_holds_until_below_resume() {
+        // Critical, then warning (still above resume), then below resume
+        let mut limiter = scripted_limiter(&[90, 40, 10]);
+        let start = std::time::Instant::now();
+
+        wait_for_capacity(&mut limiter).await.unwrap();
+
+        assert_eq!(limiter.last_percentages().requests, 10);
+        assert!(
+            start.elapsed() >= Duration::from_secs(1),
+            "Should have backed off while usage was critical"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_capacity_starts_immediately_when_available() {
+        let mut limiter = scripted_limiter(&[10]);
+        let start = std::time::Instant::now();
+
+        wait_for_capacity(&mut limiter).await.unwrap();
+
+        assert_eq!(limiter.last_percentages().requests, 10);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_daemon_args_strip_detach_before_command() {
+        let args = [
+            "strainer",
+            "run",
+            "--detach",
+            "--pid-file",
+            "p",
+            "--",
+            "tool",
+            "--detach",
+        ]
+        .map(OsString::from);
+        assert_eq!(
+            daemon_args(args),
+            ["run", "--pid-file", "p", "--", "tool", "--detach"].map(OsString::from)
+        );
+    }
+
+    #[test]
+    fn test_daemon_args_keep_non_utf8_arguments() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let file = OsString::from_vec(b"report-\xff.txt".to_vec());
+        let args = [
+            OsString::from("strainer"),
+            OsString::from("run"),
+            OsString::from("--detach"),
+            OsString::from("--"),
+            OsString::from("cat"),
+            file.clone(),
+        ];
+        assert_eq!(
+            daemon_args(args),
+            [
+                OsString::from("run"),
+                OsString::from("--"),
+                OsString::from("cat"),
+                file,
+            ]
+        );
+    }
 }
@@ -1,10 +1,22 @@
-use clap::{Parser, Subcommand};
+use crate::config::{KeyLock, StdioMode, DEFAULT_ENV_PREFIX};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
+use std::time::Duration;
+
+/// Parse a rate option such as `50k`, see [`crate::config::parse_rate`]
+fn parse_rate(rate: &str) -> Result<u32, String> {
+    crate::config::parse_rate(rate).map_err(|e| e.to_string())
+}
+
+/// Parse a duration option such as `5s`, see [`crate::config::parse_duration`]
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    crate::config::parse_duration(duration).map_err(|e| e.to_string())
+}
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// Path to config file
+    /// Path to config file, or an `http(s)://` URL to fetch the config from
     #[arg(long, default_value = "~/.config/strainer/config.toml")]
     pub config: PathBuf,
 
@@ -20,6 +32,28 @@ pub struct Cli {
     #[arg(short, long)]
     pub verbose: bool,
 
+    /// Fall back to defaults with a warning if the config cannot be loaded
+    #[arg(long, global = true)]
+    pub allow_missing_config: bool,
+
+    /// Prefix of the environment variables that override the config, e.g.
+    /// `MYAPP_` to read `MYAPP_API_KEY`
+    #[arg(long, global = true, default_value = DEFAULT_ENV_PREFIX)]
+    pub env_prefix: String,
+
+    /// Hex-encoded public key used to verify config file signatures
+    /// (defaults to `STRAINER_CONFIG_PUBLIC_KEY`, named with `--env-prefix`)
+    #[arg(long, global = true)]
+    pub public_key: Option<String>,
+
+    /// Skip config signature verification
+    #[arg(long, global = true)]
+    pub no_verify: bool,
+
+    /// Identifier attached to every log event of this run (random by default)
+    #[arg(long, global = true)]
+    pub run_id: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -39,22 +73,55 @@ pub enum Commands {
         /// Force overwrite if config file exists
         #[arg(long)]
         force: bool,
+
+        /// Retry the API key check with backoff if the provider is briefly
+        /// unreachable
+        #[arg(long)]
+        wait: bool,
+
+        /// Fail instead of writing a config that does not pass validation
+        #[arg(long)]
+        strict: bool,
+
+        /// Print the config that would be written instead of writing it
+        #[arg(long, visible_alias = "stdout")]
+        dry_run: bool,
     },
 
     /// Run a command with rate limiting
     Run {
-        /// Maximum requests per minute
-        #[arg(long)]
+        /// Maximum requests per minute (e.g. 50000, 50k or 1.5M)
+        #[arg(long, value_parser = parse_rate)]
         requests_per_minute: Option<u32>,
 
-        /// Maximum tokens per minute
-        #[arg(long)]
+        /// Maximum tokens per minute (e.g. 50000, 50k or 1.5M)
+        #[arg(long, value_parser = parse_rate)]
         tokens_per_minute: Option<u32>,
 
-        /// Maximum input tokens per minute
-        #[arg(long)]
+        /// Maximum input tokens per minute (e.g. 50000, 50k or 1.5M)
+        #[arg(long, value_parser = parse_rate)]
         input_tokens_per_minute: Option<u32>,
 
+        /// Maximum output tokens per minute (e.g. 50000, 50k or 1.5M)
+        #[arg(long, value_parser = parse_rate)]
+        output_tokens_per_minute: Option<u32>,
+
+        /// Maximum failed requests per minute before usage counts as critical
+        #[arg(long, value_parser = parse_rate)]
+        error_requests_per_minute: Option<u32>,
+
+        /// Use at most this fraction of the provider's reported quota (0-1]
+        #[arg(long)]
+        fraction_of_quota: Option<f64>,
+
+        /// Divide every limit by this many instances sharing one quota
+        #[arg(long)]
+        share_factor: Option<u32>,
+
+        /// Enforce only the configured limits, ignoring those the provider reports
+        #[arg(long)]
+        force_limits: bool,
+
         /// Percentage at which to start warning
         #[arg(long, default_value = "30")]
         warning_threshold: u8,
@@ -71,9 +138,19 @@ pub enum Commands {
         #[arg(long, default_value = "60")]
         max_backoff: u32,
 
-        /// API provider
-        #[arg(long, default_value = "anthropic")]
-        api: String,
+        /// API provider [default: anthropic]
+        ///
+        /// Takes precedence over any `[[routes]]` entry matching the command.
+        #[arg(long)]
+        api: Option<String>,
+
+        /// Model to use with the provider selected by `--api`
+        #[arg(long)]
+        model: Option<String>,
+
+        /// Maximum tokens per response for the provider selected by `--api`
+        #[arg(long)]
+        max_tokens: Option<u32>,
 
         /// API key
         #[arg(long)]
@@ -95,6 +172,99 @@ pub enum Commands {
         #[arg(long, default_value = "25")]
         resume_threshold: u8,
 
+        /// Route the command's API calls through a local counting proxy
+        #[arg(long)]
+        proxy: bool,
+
+        /// Show a live usage dashboard instead of log output; the command's
+        /// output is discarded, since the dashboard takes over the terminal
+        #[arg(long)]
+        tui: bool,
+
+        /// Hold off starting the command while usage is critical, until it
+        /// drops below the resume threshold
+        #[arg(long)]
+        wait_for_capacity: bool,
+
+        /// Terminate the command and exit with 75 (`EX_TEMPFAIL`) the first
+        /// time usage is critical, instead of pausing it
+        #[arg(long)]
+        on_pause_exit: bool,
+
+        /// Only log usage, never pausing the command, for this many seconds
+        /// after it starts
+        #[arg(long, default_value = "0")]
+        warmup_seconds: u32,
+
+        /// Only log critical usage, never pausing the command again, for this
+        /// many seconds after it is resumed
+        #[arg(long, default_value = "0")]
+        post_resume_grace_seconds: u32,
+
+        /// Time the command gets to exit after SIGTERM when strainer shuts
+        /// down, e.g. `10s`, before it is killed (default 100ms)
+        #[arg(long, value_parser = parse_duration)]
+        kill_timeout: Option<Duration>,
+
+        /// Validate the config and construct the provider, then exit without
+        /// running the command
+        #[arg(long)]
+        config_check_only: bool,
+
+        /// Run the command in a pseudo-terminal, for tools that buffer output
+        /// or drop colors when not attached to a TTY
+        #[arg(long, conflicts_with = "tui")]
+        pty: bool,
+
+        /// Run in the background, returning control to the shell immediately
+        #[arg(long, conflicts_with = "tui")]
+        detach: bool,
+
+        /// File to write the process ID of strainer to (removed on exit)
+        #[arg(long)]
+        pid_file: Option<PathBuf>,
+
+        /// File to append strainer's own output to when detached
+        #[arg(long)]
+        log_file: Option<PathBuf>,
+
+        /// Record every usage reading to a JSONL file for `strainer replay`
+        #[arg(long)]
+        record: Option<PathBuf>,
+
+        /// Write run statistics as JSON to this file when strainer exits
+        #[arg(long)]
+        summary_file: Option<PathBuf>,
+
+        /// Append every proceed, pause and resume decision as a JSON line
+        /// to this file
+        #[arg(long)]
+        audit_log: Option<PathBuf>,
+
+        /// Pause the command while this file contains `pause`, whatever the
+        /// usage; `resume`, any other contents or no file let it run
+        #[arg(long)]
+        control_file: Option<PathBuf>,
+
+        /// Lock the API key for this run, so a second run with the same key
+        /// fails (`fail`) or waits for this one (`wait`) instead of starting
+        #[arg(long, default_value = "off")]
+        key_lock: KeyLock,
+
+        /// Share strainer's stdin, stdout and stderr with the command (the
+        /// default, unless the config sets `process.stdio`)
+        #[arg(long, group = "stdio", conflicts_with = "tui")]
+        inherit_stdio: bool,
+
+        /// Read the command's stdout and stderr through pipes and forward
+        /// them to strainer's, so output keeps draining while it is paused
+        #[arg(long, group = "stdio", conflicts_with_all = ["pty", "tui"])]
+        pipe_stdio: bool,
+
+        /// Discard the command's output and give it no input
+        #[arg(long, group = "stdio", conflicts_with = "pty")]
+        null_stdio: bool,
+
         /// Command to run
         #[arg(last = true)]
         command: Vec<String>,
@@ -103,22 +273,47 @@ pub enum Commands {
     /// Watch an existing process
     Watch {
         /// Process ID to watch
-        #[arg(long)]
-        pid: u32,
+        #[arg(long, required_unless_present = "pid_file")]
+        pid: Option<u32>,
+
+        /// File to read the process ID to watch from, e.g. one a service
+        /// writes on start
+        #[arg(long, alias = "pidfile", conflicts_with = "pid")]
+        pid_file: Option<PathBuf>,
 
         // Include all the same options as Run except for command
-        /// Maximum requests per minute
-        #[arg(long)]
+        /// Maximum requests per minute (e.g. 50000, 50k or 1.5M)
+        #[arg(long, value_parser = parse_rate)]
         requests_per_minute: Option<u32>,
 
-        /// Maximum tokens per minute
-        #[arg(long)]
+        /// Maximum tokens per minute (e.g. 50000, 50k or 1.5M)
+        #[arg(long, value_parser = parse_rate)]
         tokens_per_minute: Option<u32>,
 
-        /// Maximum input tokens per minute
-        #[arg(long)]
+        /// Maximum input tokens per minute (e.g. 50000, 50k or 1.5M)
+        #[arg(long, value_parser = parse_rate)]
         input_tokens_per_minute: Option<u32>,
 
+        /// Maximum output tokens per minute (e.g. 50000, 50k or 1.5M)
+        #[arg(long, value_parser = parse_rate)]
+        output_tokens_per_minute: Option<u32>,
+
+        /// Maximum failed requests per minute before usage counts as critical
+        #[arg(long, value_parser = parse_rate)]
+        error_requests_per_minute: Option<u32>,
+
+        /// Use at most this fraction of the provider's reported quota (0-1]
+        #[arg(long)]
+        fraction_of_quota: Option<f64>,
+
+        /// Divide every limit by this many instances sharing one quota
+        #[arg(long)]
+        share_factor: Option<u32>,
+
+        /// Enforce only the configured limits, ignoring those the provider reports
+        #[arg(long)]
+        force_limits: bool,
+
         /// Percentage at which to start warning
         #[arg(long, default_value = "30")]
         warning_threshold: u8,
@@ -135,9 +330,19 @@ pub enum Commands {
         #[arg(long, default_value = "60")]
         max_backoff: u32,
 
-        /// API provider
-        #[arg(long, default_value = "anthropic")]
-        api: String,
+        /// API provider [default: anthropic]
+        ///
+        /// Takes precedence over any `[[routes]]` entry matching the command.
+        #[arg(long)]
+        api: Option<String>,
+
+        /// Model to use with the provider selected by `--api`
+        #[arg(long)]
+        model: Option<String>,
+
+        /// Maximum tokens per response for the provider selected by `--api`
+        #[arg(long)]
+        max_tokens: Option<u32>,
 
         /// API key
         #[arg(long)]
@@ -159,6 +364,139 @@ pub enum Commands {
         #[arg(long, default_value = "25")]
         resume_threshold: u8,
     },
+
+    /// Run a command defined under `[commands.<name>]` in the config
+    RunNamed {
+        /// Name of the configured command
+        name: String,
+    },
+
+    /// Stop a strainer started with `run --detach`
+    Stop {
+        /// PID file written by the detached run
+        #[arg(long)]
+        pid_file: PathBuf,
+    },
+
+    /// Feed a usage recording from `run --record` through the limiter and
+    /// print each decision, using the thresholds and limits in the config
+    Replay {
+        /// Recording written by `run --record`
+        recording: PathBuf,
+    },
+
+    /// Check usage periodically and log changes between the normal, warning
+    /// and critical bands until interrupted, without controlling a process
+    Monitor {
+        /// Seconds between usage checks
+        #[arg(long, default_value = "10")]
+        interval: u64,
+    },
+
+    /// Listen on a unix socket and answer each connection with `go` or
+    /// `wait N` based on current usage, without controlling a process
+    Gate {
+        /// Path of the unix socket to listen on
+        #[arg(long)]
+        socket: PathBuf,
+    },
+
+    /// Run a command once per requests-per-minute limit and print the runtime
+    /// and pauses of each run, using the provider and settings in the config
+    Bench {
+        /// Limits to sweep, comma separated (e.g. 30,60,1.5k)
+        #[arg(long, value_parser = parse_rate, value_delimiter = ',', required = true)]
+        requests_per_minute: Vec<u32>,
+
+        /// Command to run
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Store an API key in the OS keyring, read from the terminal or stdin,
+    /// for use as `api_key = "keyring:<service>/<account>"`
+    SetKey {
+        /// Keyring service name
+        #[arg(long, default_value = "strainer")]
+        service: String,
+
+        /// Keyring account name, e.g. the provider the key belongs to
+        #[arg(long, default_value = "api-key")]
+        account: String,
+    },
+
+    /// Inspect or edit the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Write a detached signature for a config file
+    SignConfig {
+        /// Config file to sign
+        #[arg(default_value = "strainer.toml")]
+        config: PathBuf,
+
+        /// File holding the hex-encoded signing key
+        #[arg(long)]
+        key: PathBuf,
+
+        /// Create a new signing key at the key path first
+        #[arg(long)]
+        generate_key: bool,
+    },
+
+    /// Print the fields that differ between two config files
+    DiffConfig {
+        /// First config file
+        left: PathBuf,
+
+        /// Second config file
+        right: PathBuf,
+
+        /// Show API keys instead of redacting them
+        #[arg(long)]
+        show_secrets: bool,
+    },
+
+    /// List the providers `--api` accepts, with the base URL, model, max
+    /// tokens and rate limits each uses by default
+    Providers,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigAction {
+    /// Print the resolved configuration
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value = "toml")]
+        format: ExportFormat,
+
+        /// Include the API key in the output
+        #[arg(long)]
+        show_secrets: bool,
+    },
+
+    /// Set one value in a config file, keeping its comments and layout
+    Set {
+        /// Dotted key to set, e.g. `limits.requests_per_minute`
+        key: String,
+
+        /// New value, as TOML (`120`, `true`, `"text"`) or a plain string
+        value: String,
+
+        /// Config file to edit
+        #[arg(long, default_value = "strainer.toml")]
+        file: PathBuf,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Toml,
+    Json,
+    /// Shell `export` statements using the `STRAINER_*` variables
+    Env,
 }
 
 impl Commands {
@@ -173,7 +511,18 @@ impl Commands {
                 requests_per_minute,
                 ..
             } => *requests_per_minute,
-            Self::Init { .. } => None,
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
         }
     }
 
@@ -186,7 +535,18 @@ impl Commands {
             | Self::Watch {
                 tokens_per_minute, ..
             } => *tokens_per_minute,
-            Self::Init { .. } => None,
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
         }
     }
 
@@ -201,7 +561,132 @@ impl Commands {
                 input_tokens_per_minute,
                 ..
             } => *input_tokens_per_minute,
-            Self::Init { .. } => None,
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn output_tokens_per_minute(&self) -> Option<u32> {
+        match self {
+            Self::Run {
+                output_tokens_per_minute,
+                ..
+            }
+            | Self::Watch {
+                output_tokens_per_minute,
+                ..
+            } => *output_tokens_per_minute,
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn error_requests_per_minute(&self) -> Option<u32> {
+        match self {
+            Self::Run {
+                error_requests_per_minute,
+                ..
+            }
+            | Self::Watch {
+                error_requests_per_minute,
+                ..
+            } => *error_requests_per_minute,
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn fraction_of_quota(&self) -> Option<f64> {
+        match self {
+            Self::Run {
+                fraction_of_quota, ..
+            }
+            | Self::Watch {
+                fraction_of_quota, ..
+            } => *fraction_of_quota,
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn share_factor(&self) -> Option<u32> {
+        match self {
+            Self::Run { share_factor, .. } | Self::Watch { share_factor, .. } => *share_factor,
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn force_limits(&self) -> bool {
+        match self {
+            Self::Run { force_limits, .. } | Self::Watch { force_limits, .. } => *force_limits,
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => false,
         }
     }
 
@@ -214,7 +699,18 @@ impl Commands {
             | Self::Watch {
                 warning_threshold, ..
             } => *warning_threshold,
-            Self::Init { .. } => 30, // Default value
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => 30, // Default value
         }
     }
 
@@ -227,7 +723,18 @@ impl Commands {
             | Self::Watch {
                 critical_threshold, ..
             } => *critical_threshold,
-            Self::Init { .. } => 50, // Default value
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => 50, // Default value
         }
     }
 
@@ -240,7 +747,18 @@ impl Commands {
             | Self::Watch {
                 resume_threshold, ..
             } => *resume_threshold,
-            Self::Init { .. } => 25, // Default value
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => 25, // Default value
         }
     }
 
@@ -248,7 +766,18 @@ impl Commands {
     pub const fn min_backoff(&self) -> u32 {
         match self {
             Self::Run { min_backoff, .. } | Self::Watch { min_backoff, .. } => *min_backoff,
-            Self::Init { .. } => 5, // Default value
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => 5, // Default value
         }
     }
 
@@ -256,15 +785,196 @@ impl Commands {
     pub const fn max_backoff(&self) -> u32 {
         match self {
             Self::Run { max_backoff, .. } | Self::Watch { max_backoff, .. } => *max_backoff,
-            Self::Init { .. } => 60, // Default value
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => 60, // Default value
         }
     }
 
     #[must_use]
     pub fn api(&self) -> &str {
         match self {
-            Self::Run { api, .. } | Self::Watch { api, .. } => api,
-            Self::Init { .. } => "anthropic", // Default value
+            Self::Run { api, .. } | Self::Watch { api, .. } => {
+                api.as_deref().unwrap_or("anthropic")
+            }
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => "anthropic", // Default value
+        }
+    }
+
+    #[must_use]
+    pub const fn warmup_seconds(&self) -> u32 {
+        match self {
+            Self::Run { warmup_seconds, .. } => *warmup_seconds,
+            Self::Watch { .. }
+            | Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => 0,
+        }
+    }
+
+    #[must_use]
+    pub const fn post_resume_grace_seconds(&self) -> u32 {
+        match self {
+            Self::Run {
+                post_resume_grace_seconds,
+                ..
+            } => *post_resume_grace_seconds,
+            Self::Watch { .. }
+            | Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => 0,
+        }
+    }
+
+    #[must_use]
+    pub const fn kill_timeout(&self) -> Option<Duration> {
+        match self {
+            Self::Run { kill_timeout, .. } => *kill_timeout,
+            Self::Watch { .. }
+            | Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
+        }
+    }
+
+    /// Stdio mode chosen with `--inherit-stdio`, `--pipe-stdio` or
+    /// `--null-stdio`, if any
+    ///
+    /// `--tui` discards the command's output, so it cannot draw over the
+    /// dashboard or read from the terminal it has taken over.
+    #[must_use]
+    pub const fn stdio(&self) -> Option<StdioMode> {
+        match self {
+            Self::Run {
+                inherit_stdio: true,
+                ..
+            } => Some(StdioMode::Inherit),
+            Self::Run {
+                pipe_stdio: true, ..
+            } => Some(StdioMode::Pipe),
+            Self::Run {
+                null_stdio: true, ..
+            }
+            | Self::Run { tui: true, .. } => Some(StdioMode::Null),
+            Self::Run { .. }
+            | Self::Watch { .. }
+            | Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn key_lock(&self) -> KeyLock {
+        match self {
+            Self::Run { key_lock, .. } => *key_lock,
+            Self::Watch { .. }
+            | Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => KeyLock::Off,
+        }
+    }
+
+    #[must_use]
+    pub fn model(&self) -> Option<&str> {
+        match self {
+            Self::Run { model, .. } | Self::Watch { model, .. } => model.as_deref(),
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn max_tokens(&self) -> Option<u32> {
+        match self {
+            Self::Run { max_tokens, .. } | Self::Watch { max_tokens, .. } => *max_tokens,
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
         }
     }
 
@@ -272,7 +982,18 @@ impl Commands {
     pub fn api_key(&self) -> Option<String> {
         match self {
             Self::Run { api_key, .. } | Self::Watch { api_key, .. } => api_key.clone(),
-            Self::Init { .. } => None,
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => None,
         }
     }
 
@@ -280,7 +1001,18 @@ impl Commands {
     pub fn api_base_url(&self) -> &str {
         match self {
             Self::Run { api_base_url, .. } | Self::Watch { api_base_url, .. } => api_base_url,
-            Self::Init { .. } => "https://api.anthropic.com/v1", // Default value
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => "https://api.anthropic.com/v1", // Default value
         }
     }
 
@@ -293,7 +1025,18 @@ impl Commands {
             | Self::Watch {
                 pause_on_warning, ..
             } => *pause_on_warning,
-            Self::Init { .. } => false, // Default value
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => false, // Default value
         }
     }
 
@@ -306,7 +1049,58 @@ impl Commands {
             | Self::Watch {
                 pause_on_critical, ..
             } => *pause_on_critical,
-            Self::Init { .. } => true, // Default value
+            Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => true, // Default value
+        }
+    }
+
+    #[must_use]
+    pub const fn tui(&self) -> bool {
+        match self {
+            Self::Run { tui, .. } => *tui,
+            Self::Watch { .. }
+            | Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => false,
+        }
+    }
+
+    #[must_use]
+    pub const fn proxy(&self) -> bool {
+        match self {
+            Self::Run { proxy, .. } => *proxy,
+            Self::Watch { .. }
+            | Self::Init { .. }
+            | Self::Config { .. }
+            | Self::SignConfig { .. }
+            | Self::RunNamed { .. }
+            | Self::Stop { .. }
+            | Self::Replay { .. }
+            | Self::SetKey { .. }
+            | Self::DiffConfig { .. }
+            | Self::Monitor { .. }
+            | Self::Gate { .. }
+            | Self::Bench { .. }
+            | Self::Providers => false,
         }
     }
 }
@@ -322,6 +1116,26 @@ mod tests {
         assert!(cli.to_string().contains("Usage: strainer"));
     }
 
+    #[test]
+    fn test_cli_allow_missing_config() {
+        let cli = Cli::try_parse_from(["strainer", "run", "--allow-missing-config", "--", "true"])
+            .unwrap();
+        assert!(cli.allow_missing_config);
+
+        let cli = Cli::try_parse_from(["strainer", "run", "--", "true"]).unwrap();
+        assert!(!cli.allow_missing_config);
+    }
+
+    #[test]
+    fn test_cli_env_prefix() {
+        let cli = Cli::try_parse_from(["strainer", "run", "--", "true"]).unwrap();
+        assert_eq!(cli.env_prefix, "STRAINER_");
+
+        let cli = Cli::try_parse_from(["strainer", "run", "--env-prefix", "MYAPP_", "--", "true"])
+            .unwrap();
+        assert_eq!(cli.env_prefix, "MYAPP_");
+    }
+
     #[test]
     fn test_cli_init_command() {
         let cli = Cli::try_parse_from(["strainer", "init"]).unwrap();
@@ -330,7 +1144,10 @@ mod tests {
             Commands::Init {
                 config: None,
                 no_prompt: false,
-                force: false
+                force: false,
+                wait: false,
+                strict: false,
+                dry_run: false
             }
         ));
     }
@@ -344,6 +1161,9 @@ mod tests {
             "test.toml",
             "--no-prompt",
             "--force",
+            "--wait",
+            "--strict",
+            "--dry-run",
         ])
         .unwrap();
         assert!(matches!(
@@ -351,7 +1171,10 @@ mod tests {
             Commands::Init {
                 config: Some(_),
                 no_prompt: true,
-                force: true
+                force: true,
+                wait: true,
+                strict: true,
+                dry_run: true
             }
         ));
     }
@@ -377,6 +1200,10 @@ mod tests {
             "1000",
             "--input-tokens-per-minute",
             "500",
+            "--output-tokens-per-minute",
+            "250",
+            "--error-requests-per-minute",
+            "5",
             "--warning-threshold",
             "40",
             "--critical-threshold",
@@ -402,6 +1229,8 @@ mod tests {
             requests_per_minute,
             tokens_per_minute,
             input_tokens_per_minute,
+            output_tokens_per_minute,
+            error_requests_per_minute,
             warning_threshold,
             critical_threshold,
             min_backoff,
@@ -417,11 +1246,13 @@ mod tests {
             assert_eq!(requests_per_minute, Some(100));
             assert_eq!(tokens_per_minute, Some(1000));
             assert_eq!(input_tokens_per_minute, Some(500));
+            assert_eq!(output_tokens_per_minute, Some(250));
+            assert_eq!(error_requests_per_minute, Some(5));
             assert_eq!(warning_threshold, 40);
             assert_eq!(critical_threshold, 80);
             assert_eq!(min_backoff, 10);
             assert_eq!(max_backoff, 120);
-            assert_eq!(api, "test-provider");
+            assert_eq!(api.as_deref(), Some("test-provider"));
             assert_eq!(api_key, Some("test-key".to_string()));
             assert_eq!(api_base_url, "http://test.local");
             assert!(pause_on_warning);
@@ -431,16 +1262,173 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cli_rate_options_accept_suffixes() {
+        let cli = Cli::try_parse_from([
+            "strainer",
+            "run",
+            "--requests-per-minute",
+            "100/min",
+            "--tokens-per-minute",
+            "1.5M",
+            "--input-tokens-per-minute",
+            "50k",
+            "--",
+            "true",
+        ])
+        .unwrap();
+        assert_eq!(cli.command.requests_per_minute(), Some(100));
+        assert_eq!(cli.command.tokens_per_minute(), Some(1_500_000));
+        assert_eq!(cli.command.input_tokens_per_minute(), Some(50_000));
+
+        let err = Cli::try_parse_from([
+            "strainer",
+            "watch",
+            "--pid",
+            "1",
+            "--tokens-per-minute",
+            "5m",
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("ambiguous"), "{err}");
+    }
+
     #[test]
     fn test_cli_watch_command() {
         let cli = Cli::try_parse_from(["strainer", "watch", "--pid", "1234"]).unwrap();
         if let Commands::Watch { pid, .. } = cli.command {
-            assert_eq!(pid, 1234);
+            assert_eq!(pid, Some(1234));
         } else {
             panic!("Expected Watch command");
         }
     }
 
+    #[test]
+    fn test_cli_watch_pid_file() {
+        let cli = Cli::try_parse_from(["strainer", "watch", "--pidfile", "/run/app.pid"]).unwrap();
+        if let Commands::Watch { pid, pid_file, .. } = cli.command {
+            assert_eq!(pid, None);
+            assert_eq!(pid_file, Some(PathBuf::from("/run/app.pid")));
+        } else {
+            panic!("Expected Watch command");
+        }
+
+        // Exactly one of --pid and --pid-file is needed
+        assert!(Cli::try_parse_from(["strainer", "watch"]).is_err());
+        assert!(Cli::try_parse_from([
+            "strainer",
+            "watch",
+            "--pid",
+            "1",
+            "--pid-file",
+            "/run/app.pid"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_cli_bench_command() {
+        let cli = Cli::try_parse_from([
+            "strainer",
+            "bench",
+            "--requests-per-minute",
+            "30,1.5k",
+            "--",
+            "sleep",
+            "1",
+        ])
+        .unwrap();
+        if let Commands::Bench {
+            requests_per_minute,
+            command,
+        } = cli.command
+        {
+            assert_eq!(requests_per_minute, [30, 1500]);
+            assert_eq!(command, ["sleep", "1"]);
+        } else {
+            panic!("Expected Bench command");
+        }
+
+        assert!(Cli::try_parse_from(["strainer", "bench", "--", "true"]).is_err());
+        assert!(Cli::try_parse_from(["strainer", "bench", "--requests-per-minute", "30"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_stdio_flags() {
+        let stdio = |flags: &[&str]| {
+            let args = ["strainer", "run"]
+                .iter()
+                .chain(flags)
+                .chain(&["--", "true"]);
+            Cli::try_parse_from(args).map(|cli| cli.command.stdio())
+        };
+        assert_eq!(stdio(&[]).unwrap(), None);
+        assert_eq!(
+            stdio(&["--inherit-stdio"]).unwrap(),
+            Some(StdioMode::Inherit)
+        );
+        assert_eq!(stdio(&["--pipe-stdio"]).unwrap(), Some(StdioMode::Pipe));
+        assert_eq!(stdio(&["--null-stdio"]).unwrap(), Some(StdioMode::Null));
+
+        // Only one mode at a time, and a PTY replaces all of them
+        assert!(stdio(&["--pipe-stdio", "--null-stdio"]).is_err());
+        assert!(stdio(&["--inherit-stdio", "--pipe-stdio"]).is_err());
+        assert!(stdio(&["--pty", "--pipe-stdio"]).is_err());
+        assert!(stdio(&["--pty", "--null-stdio"]).is_err());
+
+        // The dashboard owns the terminal, so the command cannot share it
+        assert_eq!(stdio(&["--tui"]).unwrap(), Some(StdioMode::Null));
+        assert_eq!(
+            stdio(&["--tui", "--null-stdio"]).unwrap(),
+            Some(StdioMode::Null)
+        );
+        assert!(stdio(&["--tui", "--inherit-stdio"]).is_err());
+        assert!(stdio(&["--tui", "--pipe-stdio"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_config_export_command() {
+        let cli = Cli::try_parse_from([
+            "strainer",
+            "config",
+            "export",
+            "--format",
+            "env",
+            "--show-secrets",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Config {
+                action: ConfigAction::Export {
+                    format: ExportFormat::Env,
+                    show_secrets: true
+                }
+            }
+        ));
+    }
+
+    #[test]
+    fn test_cli_config_set() {
+        let cli = Cli::try_parse_from([
+            "strainer",
+            "config",
+            "set",
+            "limits.requests_per_minute",
+            "120",
+        ])
+        .unwrap();
+        let Commands::Config {
+            action: ConfigAction::Set { key, value, file },
+        } = cli.command
+        else {
+            panic!("Expected config set");
+        };
+        assert_eq!(key, "limits.requests_per_minute");
+        assert_eq!(value, "120");
+        assert_eq!(file, PathBuf::from("strainer.toml"));
+    }
+
     #[test]
     fn test_commands_accessors() {
         test_run_command_accessors();
@@ -453,32 +1441,73 @@ mod tests {
             requests_per_minute: Some(100),
             tokens_per_minute: Some(1000),
             input_tokens_per_minute: Some(500),
+            output_tokens_per_minute: Some(250),
+            error_requests_per_minute: Some(5),
+            fraction_of_quota: Some(0.5),
+            share_factor: Some(4),
+            force_limits: true,
             warning_threshold: 40,
             critical_threshold: 80,
             min_backoff: 10,
             max_backoff: 120,
-            api: "test-provider".to_string(),
+            api: Some("test-provider".to_string()),
+            model: Some("test-model".to_string()),
+            max_tokens: Some(4096),
             api_key: Some("test-key".to_string()),
             api_base_url: "http://test.local".to_string(),
             pause_on_warning: true,
             pause_on_critical: true,
             resume_threshold: 20,
+            proxy: true,
+            tui: false,
+            wait_for_capacity: false,
+            on_pause_exit: false,
+            warmup_seconds: 15,
+            post_resume_grace_seconds: 3,
+            kill_timeout: Some(Duration::from_secs(5)),
+            config_check_only: false,
+            pty: false,
+            detach: false,
+            pid_file: None,
+            log_file: None,
+            record: None,
+            summary_file: None,
+            audit_log: None,
+            control_file: None,
+            key_lock: KeyLock::Wait,
+            inherit_stdio: false,
+            pipe_stdio: true,
+            null_stdio: false,
             command: vec!["test".to_string()],
         };
 
         assert_eq!(run_cmd.requests_per_minute(), Some(100));
         assert_eq!(run_cmd.tokens_per_minute(), Some(1000));
         assert_eq!(run_cmd.input_tokens_per_minute(), Some(500));
+        assert_eq!(run_cmd.output_tokens_per_minute(), Some(250));
+        assert_eq!(run_cmd.error_requests_per_minute(), Some(5));
+        assert_eq!(run_cmd.fraction_of_quota(), Some(0.5));
+        assert_eq!(run_cmd.share_factor(), Some(4));
+        assert!(run_cmd.force_limits());
         assert_eq!(run_cmd.warning_threshold(), 40);
         assert_eq!(run_cmd.critical_threshold(), 80);
         assert_eq!(run_cmd.min_backoff(), 10);
         assert_eq!(run_cmd.max_backoff(), 120);
         assert_eq!(run_cmd.api(), "test-provider");
+        assert_eq!(run_cmd.model(), Some("test-model"));
+        assert_eq!(run_cmd.max_tokens(), Some(4096));
         assert_eq!(run_cmd.api_key(), Some("test-key".to_string()));
         assert_eq!(run_cmd.api_base_url(), "http://test.local");
         assert!(run_cmd.pause_on_warning());
         assert!(run_cmd.pause_on_critical());
         assert_eq!(run_cmd.resume_threshold(), 20);
+        assert!(run_cmd.proxy());
+        assert!(!run_cmd.tui());
+        assert_eq!(run_cmd.warmup_seconds(), 15);
+        assert_eq!(run_cmd.post_resume_grace_seconds(), 3);
+        assert_eq!(run_cmd.kill_timeout(), Some(Duration::from_secs(5)));
+        assert_eq!(run_cmd.key_lock(), KeyLock::Wait);
+        assert_eq!(run_cmd.stdio(), Some(StdioMode::Pipe));
     }
 
     #[test]
@@ -487,20 +1516,31 @@ mod tests {
             config: None,
             no_prompt: false,
             force: false,
+            wait: false,
+            strict: false,
+            dry_run: false,
         };
 
         assert_eq!(init_cmd.requests_per_minute(), None);
         assert_eq!(init_cmd.tokens_per_minute(), None);
         assert_eq!(init_cmd.input_tokens_per_minute(), None);
+        assert_eq!(init_cmd.output_tokens_per_minute(), None);
+        assert_eq!(init_cmd.error_requests_per_minute(), None);
+        assert_eq!(init_cmd.fraction_of_quota(), None);
+        assert_eq!(init_cmd.share_factor(), None);
+        assert!(!init_cmd.force_limits());
         assert_eq!(init_cmd.warning_threshold(), 30);
         assert_eq!(init_cmd.critical_threshold(), 50);
         assert_eq!(init_cmd.min_backoff(), 5);
         assert_eq!(init_cmd.max_backoff(), 60);
         assert_eq!(init_cmd.api(), "anthropic");
+        assert_eq!(init_cmd.model(), None);
+        assert_eq!(init_cmd.max_tokens(), None);
         assert_eq!(init_cmd.api_key(), None);
         assert_eq!(init_cmd.api_base_url(), "https://api.anthropic.com/v1");
         assert!(!init_cmd.pause_on_warning());
         assert!(init_cmd.pause_on_critical());
         assert_eq!(init_cmd.resume_threshold(), 25);
+        assert!(!init_cmd.proxy());
     }
 }
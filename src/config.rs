@@ -1,11 +1,21 @@
+use crate::credentials::{self, KeyringRef, KEYRING_PREFIX};
+use crate::process::TERMINATE_GRACE;
 use crate::providers::config::{AnthropicConfig, MockConfig, OpenAIConfig, ProviderConfig};
-use anyhow::{anyhow, Result};
+use crate::resources::ResourceLimits;
+use anyhow::{anyhow, Context, Result};
 use dirs;
+use nix::sys::signal::Signal;
+use regex::Regex;
 use serde::de::Deserializer;
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Serialize, Serializer};
-use std::collections::HashMap;
-use std::{env, path::PathBuf};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
 
 #[derive(Debug, Clone)]
 pub struct ApiConfig {
@@ -13,6 +23,43 @@ pub struct ApiConfig {
     pub api_key: Option<String>,
     pub base_url: Option<String>,
     pub parameters: HashMap<String, String>,
+    /// How this config's provider `parameters` combine with the config it is
+    /// merged into
+    pub merge: MergeStrategy,
+    /// Provider parameters to delete from the config this one is merged into
+    pub remove_parameters: Vec<String>,
+}
+
+/// How a map in an overlay config is combined with the config it is merged into
+///
+/// Scalars set in the overlay always replace the current value; the strategy
+/// only decides what happens to entries the overlay does not mention.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeStrategy {
+    /// Keep existing entries, overwriting those the overlay also sets
+    #[default]
+    Extend,
+    /// Drop existing entries and use only the overlay's
+    Replace,
+}
+
+impl MergeStrategy {
+    /// Merge `overlay` into `target`, then delete the keys in `remove`
+    pub fn apply<V: Clone>(
+        self,
+        target: &mut HashMap<String, V>,
+        overlay: &HashMap<String, V>,
+        remove: &[String],
+    ) {
+        if self == Self::Replace {
+            target.clear();
+        }
+        target.extend(overlay.iter().map(|(k, v)| (k.clone(), v.clone())));
+        for key in remove {
+            target.remove(key);
+        }
+    }
 }
 
 impl Default for ApiConfig {
@@ -22,6 +69,8 @@ impl Default for ApiConfig {
             api_key: None,
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::default(),
+            remove_parameters: Vec::new(),
         }
     }
 }
@@ -55,6 +104,27 @@ impl Serialize for ApiConfig {
                 if !cfg.parameters.is_empty() {
                     map.serialize_entry("parameters", &cfg.parameters)?;
                 }
+                let limits = [
+                    ("requests_per_minute", cfg.requests_per_minute),
+                    ("tokens_per_minute", cfg.tokens_per_minute),
+                    ("input_tokens_per_minute", cfg.input_tokens_per_minute),
+                    ("output_tokens_per_minute", cfg.output_tokens_per_minute),
+                ];
+                for (key, limit) in limits {
+                    if let Some(limit) = limit {
+                        map.serialize_entry(key, &limit)?;
+                    }
+                }
+                if cfg.requests_used > 0 {
+                    map.serialize_entry("requests_used", &cfg.requests_used)?;
+                }
+                if let Some(delay_ms) = cfg.delay_ms {
+                    map.serialize_entry("delay_ms", &delay_ms)?;
+                }
+            }
+            ProviderConfig::Aggregate(cfg) => {
+                map.serialize_entry("type", "aggregate")?;
+                map.serialize_entry("providers", &cfg.providers)?;
             }
         }
         if let Some(api_key) = &self.api_key {
@@ -63,6 +133,12 @@ impl Serialize for ApiConfig {
         if let Some(base_url) = &self.base_url {
             map.serialize_entry("base_url", base_url)?;
         }
+        if self.merge != MergeStrategy::default() {
+            map.serialize_entry("merge", &self.merge)?;
+        }
+        if !self.remove_parameters.is_empty() {
+            map.serialize_entry("remove_parameters", &self.remove_parameters)?;
+        }
         map.end()
     }
 }
@@ -80,6 +156,18 @@ impl<'de> Deserialize<'de> for ApiConfig {
             let base_url = obj
                 .remove("base_url")
                 .and_then(|v| v.as_str().map(ToString::to_string));
+            let merge = obj
+                .remove("merge")
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(serde::de::Error::custom)?
+                .unwrap_or_default();
+            let remove_parameters = obj
+                .remove("remove_parameters")
+                .map(serde_json::from_value)
+                .transpose()
+                .map_err(serde::de::Error::custom)?
+                .unwrap_or_default();
             let provider_config: ProviderConfig =
                 serde_json::from_value(serde_json::Value::Object(obj))
                     .map_err(serde::de::Error::custom)?;
@@ -88,6 +176,8 @@ impl<'de> Deserialize<'de> for ApiConfig {
                 api_key,
                 base_url,
                 parameters: HashMap::default(),
+                merge,
+                remove_parameters,
             })
         } else {
             Err(serde::de::Error::custom("Expected a map for ApiConfig"))
@@ -96,17 +186,53 @@ impl<'de> Deserialize<'de> for ApiConfig {
 }
 
 impl ApiConfig {
+    /// Get the API key, expanding a `${VAR}` placeholder from the environment
+    /// or reading a `keyring:service/account` reference from the OS keyring
+    #[must_use]
+    pub fn resolved_api_key(&self) -> Option<String> {
+        let api_key = self.api_key.as_ref()?;
+        if let Some(reference) = api_key.strip_prefix(KEYRING_PREFIX) {
+            return match reference
+                .parse::<KeyringRef>()
+                .and_then(|reference| credentials::read_key(&reference.entry()?))
+            {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    warn!("Could not resolve API key {api_key}: {e:#}");
+                    None
+                }
+            };
+        }
+        api_key
+            .strip_prefix("${")
+            .and_then(|s| s.strip_suffix('}'))
+            .map_or_else(|| Some(api_key.clone()), |var| env::var(var).ok())
+    }
+
     #[must_use]
     pub fn base_url_default(&self) -> Option<String> {
         self.base_url.as_ref().map_or_else(
             || match &self.provider_config {
                 ProviderConfig::Anthropic(_) => Some("https://api.anthropic.com/v1".to_string()),
                 ProviderConfig::OpenAI(_) => Some("https://api.openai.com/v1".to_string()),
-                ProviderConfig::Mock(_) => None,
+                ProviderConfig::Mock(_) | ProviderConfig::Aggregate(_) => None,
             },
             |url| Some(url.clone()),
         )
     }
+
+    /// Describe the provider, model and base URL requests go to, for logging
+    ///
+    /// Never includes the API key.
+    #[must_use]
+    pub fn target(&self) -> String {
+        format!(
+            "provider={} model={} base_url={}",
+            self.provider_config,
+            self.provider_config.model().unwrap_or("-"),
+            self.base_url_default().as_deref().unwrap_or("-")
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -132,26 +258,534 @@ pub struct Config {
     pub backoff: BackoffConfig,
     pub process: ProcessConfig,
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    /// Providers to fall back to, in order, when the `[api]` provider fails
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_providers: Vec<ApiConfig>,
+    /// Named commands runnable with `strainer run-named <name>`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub commands: HashMap<String, NamedCommand>,
+    /// Limits and thresholds for parts of the day, under `[[schedule]]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<ScheduleWindow>,
+    /// Providers for particular commands, under `[[routes]]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub routes: Vec<Route>,
+}
+
+/// A rule choosing the provider for the commands it matches
+///
+/// `program` matches the command's first argument, either as given or by
+/// its file name, so `program = "summarize.py"` matches
+/// `./tools/summarize.py`. `pattern` is a regex searched for in the command
+/// line with its arguments joined by spaces. When both are set, both must
+/// match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Route {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Provider used in place of `[api]` for matching commands
+    pub api: ApiConfig,
+    /// `pattern`, compiled the first time it is needed
+    #[serde(skip)]
+    regex: OnceLock<Regex>,
+}
+
+impl Route {
+    /// The compiled `pattern`, if set
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pattern` is not a valid regex
+    fn regex(&self) -> Result<Option<&Regex>> {
+        let Some(pattern) = &self.pattern else {
+            return Ok(None);
+        };
+        if let Some(regex) = self.regex.get() {
+            return Ok(Some(regex));
+        }
+        let regex =
+            Regex::new(pattern).with_context(|| format!("Invalid route pattern '{pattern}'"))?;
+        Ok(Some(self.regex.get_or_init(|| regex)))
+    }
+
+    /// Whether this route applies to `command`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pattern` is not a valid regex
+    pub fn matches(&self, command: &[String]) -> Result<bool> {
+        if let Some(program) = &self.program {
+            let Some(first) = command.first() else {
+                return Ok(false);
+            };
+            let file_name = Path::new(first).file_name();
+            if first != program && file_name.is_none_or(|name| name != program.as_str()) {
+                return Ok(false);
+            }
+        }
+        if let Some(regex) = self.regex()? {
+            if !regex.is_match(&command.join(" ")) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Limits and thresholds that apply during part of the day
+///
+/// Hours are UTC. The window starts at `start_hour` and ends before
+/// `end_hour`, wrapping past midnight when `end_hour` is the smaller, so
+/// `start_hour = 22, end_hour = 6` covers the night. Limits set here override
+/// the base ones; thresholds given here replace the base thresholds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleWindow {
+    pub start_hour: u8,
+    pub end_hour: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<RateLimits>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thresholds: Option<Thresholds>,
+}
+
+impl ScheduleWindow {
+    /// Whether the window covers `hour` (0-23)
+    #[must_use]
+    pub const fn contains(&self, hour: u8) -> bool {
+        if self.start_hour < self.end_hour {
+            hour >= self.start_hour && hour < self.end_hour
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+}
+
+impl std::fmt::Display for ScheduleWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:00-{:02}:00 UTC", self.start_hour, self.end_hour)
+    }
+}
+
+/// Index of the first window in `schedule` that covers the UTC hour of `at`
+#[must_use]
+pub fn active_window(schedule: &[ScheduleWindow], at: SystemTime) -> Option<usize> {
+    let seconds = at
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    #[allow(clippy::cast_possible_truncation)] // always below 24
+    let hour = (seconds / 3600 % 24) as u8;
+    schedule.iter().position(|window| window.contains(hour))
 }
 
+/// A command defined in config under `[commands.<name>]`
+///
+/// Any limits, thresholds or backoff given here are applied over the base
+/// config when the command is run.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NamedCommand {
+    pub command: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<RateLimits>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thresholds: Option<Thresholds>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backoff: Option<BackoffConfig>,
+}
+
+/// Largest number of digits accepted on either side of a rate's decimal point
+const MAX_RATE_DIGITS: usize = 12;
+
+/// Parse a per-minute rate such as `50000`, `50k`, `1.5M` or `100/min`
+///
+/// `k` and `K` multiply by a thousand and `M` by a million. Lowercase `m` is
+/// rejected as ambiguous, as is any unit other than `/min` or `/minute`.
+///
+/// # Errors
+///
+/// Returns an error if the rate is malformed, is not a whole number once its
+/// suffix is applied, or does not fit in a `u32`
+pub fn parse_rate(rate: &str) -> Result<u32> {
+    let invalid =
+        || anyhow!("Invalid rate '{rate}': expected a number such as 50000, 50k, 1.5M or 100/min");
+    let number = match rate.trim().split_once('/') {
+        Some((number, unit)) if matches!(unit.trim(), "min" | "minute") => number.trim_end(),
+        Some((_, unit)) => {
+            return Err(anyhow!(
+                "Invalid rate '{rate}': only per-minute rates are supported, not '/{}'",
+                unit.trim()
+            ))
+        }
+        None => rate.trim(),
+    };
+    let (digits, multiplier) = match number.char_indices().last() {
+        Some((index, 'k' | 'K')) => (&number[..index], 1_000),
+        Some((index, 'M')) => (&number[..index], 1_000_000),
+        Some((_, 'm')) => {
+            return Err(anyhow!(
+                "Invalid rate '{rate}': use 'M' for millions, 'm' is ambiguous"
+            ))
+        }
+        _ => (number, 1),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    let all_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if whole.is_empty()
+        || !all_digits(whole)
+        || !all_digits(fraction)
+        || (digits.contains('.') && fraction.is_empty())
+        || fraction.len() > MAX_RATE_DIGITS
+    {
+        return Err(invalid());
+    }
+    let too_large = || anyhow!("Invalid rate '{rate}': exceeds the maximum of {}", u32::MAX);
+    if whole.trim_start_matches('0').len() > MAX_RATE_DIGITS {
+        return Err(too_large());
+    }
+
+    // Work in integers scaled by the fraction's digits so 1.5M is exact
+    #[allow(clippy::cast_possible_truncation)] // at most MAX_RATE_DIGITS
+    let scale = 10_u128.pow(fraction.len() as u32);
+    let parse = |part: &str| part.parse::<u128>().map_err(|_| invalid());
+    let fraction_value = if fraction.is_empty() {
+        0
+    } else {
+        parse(fraction)?
+    };
+    let scaled = (parse(whole)? * scale + fraction_value) * multiplier;
+    if !scaled.is_multiple_of(scale) {
+        return Err(anyhow!("Invalid rate '{rate}': must be a whole number"));
+    }
+    u32::try_from(scaled / scale).map_err(|_| too_large())
+}
+
+/// Deserialize an optional rate given as a number or a string for [`parse_rate`]
+fn deserialize_rate<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Rate {
+        Number(u32),
+        Text(String),
+    }
+
+    match Option::<Rate>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Rate::Number(rate)) => Ok(Some(rate)),
+        Some(Rate::Text(rate)) => parse_rate(&rate)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+/// Parse a memory size such as `536870912`, `512M`, `512MiB` or `2G`
+///
+/// `K`, `M` and `G` are binary multiples (1024, 1024² and 1024³) and may be
+/// followed by `B` or `iB`.
+///
+/// # Errors
+///
+/// Returns an error if the size is malformed or does not fit in a `u64`
+pub fn parse_memory(size: &str) -> Result<u64> {
+    let invalid =
+        || anyhow!("Invalid memory size '{size}': expected a number such as 536870912, 512M or 2G");
+    let trimmed = size.trim();
+    let number = trimmed
+        .strip_suffix("iB")
+        .or_else(|| trimmed.strip_suffix('B'))
+        .unwrap_or(trimmed);
+    let (digits, shift) = match number.char_indices().last() {
+        Some((index, 'k' | 'K')) => (&number[..index], 10),
+        Some((index, 'M')) => (&number[..index], 20),
+        Some((index, 'G')) => (&number[..index], 30),
+        _ => (number, 0),
+    };
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(1 << shift))
+        .ok_or_else(|| anyhow!("Invalid memory size '{size}': too large"))
+}
+
+/// Parse a duration such as `500ms`, `5s` or `2m`; a bare number is seconds
+///
+/// # Errors
+///
+/// Returns an error if the duration is malformed, has an unknown unit or is
+/// too large
+pub fn parse_duration(duration: &str) -> Result<Duration> {
+    let trimmed = duration.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (digits, unit) = trimmed.split_at(split);
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| anyhow!("Invalid duration '{duration}': expected e.g. 500ms, 5s or 2m"))?;
+    let millis = match unit.trim() {
+        "ms" => Some(value),
+        "" | "s" => value.checked_mul(1000),
+        "m" => value.checked_mul(60_000),
+        other => {
+            return Err(anyhow!(
+                "Invalid duration '{duration}': unknown unit '{other}', expected ms, s or m"
+            ))
+        }
+    };
+    millis
+        .map(Duration::from_millis)
+        .ok_or_else(|| anyhow!("Invalid duration '{duration}': too large"))
+}
+
+/// A duration written for people, such as `500ms`, `5s` or `2m`
+///
+/// Deserializes from a string for [`parse_duration`] or, as older configs
+/// have it, from a whole number of seconds. Serializes back to a string in
+/// the largest unit that keeps it exact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct HumanDuration(Duration);
+
+impl HumanDuration {
+    #[must_use]
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(Duration::from_secs(secs))
+    }
+
+    #[must_use]
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(Duration::from_millis(millis))
+    }
+
+    #[must_use]
+    pub const fn as_duration(self) -> Duration {
+        self.0
+    }
+}
+
+impl From<Duration> for HumanDuration {
+    fn from(duration: Duration) -> Self {
+        Self(duration)
+    }
+}
+
+impl From<HumanDuration> for Duration {
+    fn from(duration: HumanDuration) -> Self {
+        duration.0
+    }
+}
+
+impl std::str::FromStr for HumanDuration {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_duration(s).map(Self)
+    }
+}
+
+impl std::fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let millis = self.0.as_millis();
+        if millis != 0 && millis.is_multiple_of(60_000) {
+            write!(f, "{}m", millis / 60_000)
+        } else if millis.is_multiple_of(1000) {
+            write!(f, "{}s", millis / 1000)
+        } else {
+            write!(f, "{millis}ms")
+        }
+    }
+}
+
+impl Serialize for HumanDuration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HumanDuration {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Seconds(u64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Seconds(secs) => Ok(Self::from_secs(secs)),
+            Raw::Text(duration) => duration.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// Deserialize an optional memory size given in bytes or as a string for [`parse_memory`]
+fn deserialize_memory<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Memory {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Option::<Memory>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Memory::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Memory::Text(size)) => parse_memory(&size)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct RateLimits {
+    #[serde(default, deserialize_with = "deserialize_rate")]
+    pub requests_per_minute: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_rate")]
+    pub tokens_per_minute: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_rate")]
+    pub input_tokens_per_minute: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_rate")]
+    pub output_tokens_per_minute: Option<u32>,
+    /// Failed requests per minute above which usage counts as critical,
+    /// as a burst of errors usually means an outage rather than load
+    #[serde(
+        default,
+        deserialize_with = "deserialize_rate",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub error_requests_per_minute: Option<u32>,
+    /// Fraction of the provider's reported quota to use (e.g. 0.5 for half)
+    ///
+    /// Applied to each limit the provider reports; when an absolute limit is
+    /// also set, the stricter of the two wins.
+    #[serde(default)]
+    pub fraction_of_quota: Option<f64>,
+    /// Number of instances sharing one quota, each enforcing its share
+    ///
+    /// Every effective limit is divided by this. Meant for usage each
+    /// instance counts itself; usage reported by the provider already
+    /// covers every instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared_instances: Option<u32>,
+    /// Seconds over which every limit ramps up linearly from a tenth to its
+    /// full value after the limiter starts, so work starting after an idle
+    /// period does not burst (unset or 0 disables)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ramp_up_seconds: Option<u32>,
+    /// Enforce only these limits, ignoring the ones the provider reports,
+    /// e.g. when its defaults do not match the account's tier
+    #[serde(default)]
+    pub force: bool,
+    /// Per-minute limits for specific models, keyed by model name
+    ///
+    /// The entry for the configured model replaces the limits it sets; the
+    /// others, and every limit for models without an entry, come from above.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub by_model: HashMap<String, ModelLimits>,
+}
+
+/// Per-minute limits that apply to one model, under `[limits.by_model]`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelLimits {
+    #[serde(
+        default,
+        deserialize_with = "deserialize_rate",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub requests_per_minute: Option<u32>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_rate",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub tokens_per_minute: Option<u32>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_rate",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub input_tokens_per_minute: Option<u32>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_rate",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub output_tokens_per_minute: Option<u32>,
 }
 
-impl Default for RateLimits {
-    fn default() -> Self {
-        Self {
-            requests_per_minute: Some(30),
-            tokens_per_minute: Some(50000),
-            input_tokens_per_minute: None,
+impl RateLimits {
+    /// These limits with the `by_model` entry for `model`, if any, applied
+    #[must_use]
+    pub fn for_model(&self, model: Option<&str>) -> Self {
+        let mut limits = self.clone();
+        if let Some(entry) = model.and_then(|model| self.by_model.get(model)) {
+            limits.requests_per_minute = entry.requests_per_minute.or(self.requests_per_minute);
+            limits.tokens_per_minute = entry.tokens_per_minute.or(self.tokens_per_minute);
+            limits.input_tokens_per_minute = entry
+                .input_tokens_per_minute
+                .or(self.input_tokens_per_minute);
+            limits.output_tokens_per_minute = entry
+                .output_tokens_per_minute
+                .or(self.output_tokens_per_minute);
+        }
+        limits
+    }
+
+    /// Override these limits with any that are set in `other`
+    ///
+    /// `by_model` entries in `other` replace those for the same model.
+    pub fn merge(&mut self, other: &Self) {
+        if let Some(rpm) = other.requests_per_minute {
+            self.requests_per_minute = Some(rpm);
+        }
+        if let Some(tpm) = other.tokens_per_minute {
+            self.tokens_per_minute = Some(tpm);
+        }
+        if let Some(itpm) = other.input_tokens_per_minute {
+            self.input_tokens_per_minute = Some(itpm);
+        }
+        if let Some(otpm) = other.output_tokens_per_minute {
+            self.output_tokens_per_minute = Some(otpm);
         }
+        if let Some(erpm) = other.error_requests_per_minute {
+            self.error_requests_per_minute = Some(erpm);
+        }
+        if let Some(fraction) = other.fraction_of_quota {
+            self.fraction_of_quota = Some(fraction);
+        }
+        if let Some(instances) = other.shared_instances {
+            self.shared_instances = Some(instances);
+        }
+        if let Some(seconds) = other.ramp_up_seconds {
+            self.ramp_up_seconds = Some(seconds);
+        }
+        if other.force {
+            self.force = true;
+        }
+        self.by_model.extend(
+            other
+                .by_model
+                .iter()
+                .map(|(model, limits)| (model.clone(), limits.clone())),
+        );
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Thresholds {
     #[serde(default = "default_warning_threshold")]
     pub warning: u8,
@@ -159,6 +793,246 @@ pub struct Thresholds {
     pub critical: u8,
     #[serde(default = "default_resume_threshold")]
     pub resume: u8,
+    /// Log a forecast when usage is projected to reach critical within this
+    /// many seconds (0 disables forecasting)
+    #[serde(default = "default_forecast_horizon")]
+    pub forecast_horizon_seconds: u32,
+    /// Whether usage exactly at the warning or critical threshold counts as
+    /// reaching it (`>=`) rather than only usage above it (`>`)
+    ///
+    /// With the default floor rounding, `false` means the critical threshold
+    /// trips only once usage reaches at least one whole percent above it.
+    #[serde(default = "default_threshold_inclusive")]
+    pub threshold_inclusive: bool,
+    /// How usage of the individual limits combines into the critical decision
+    #[serde(default)]
+    pub gating: Gating,
+    /// How fractional usage percentages are rounded before comparing them
+    /// with the thresholds
+    #[serde(default)]
+    pub rounding: RoundingMode,
+    /// Treat usage above 100% of a limit as an error instead of pausing
+    ///
+    /// Usage over the limit usually means stale data or a misconfigured
+    /// limit, which backing off would never resolve.
+    #[serde(default)]
+    pub strict_usage: bool,
+    /// Raise an error-level alert once usage has stayed in the warning band
+    /// for this many seconds (0 disables)
+    #[serde(default)]
+    pub warning_sustained_seconds: u32,
+    /// Also pause the process once the warning band has been sustained,
+    /// until usage drops below the warning threshold
+    #[serde(default)]
+    pub pause_on_sustained_warning: bool,
+    /// Zero the limiter's recorded usage once usage drops to the resume
+    /// threshold
+    ///
+    /// Usage is re-read from the provider on every check, so the zeroed
+    /// figures only last until the next one; this exists for configs written
+    /// when strainer always reset.
+    #[serde(default)]
+    pub reset_on_resume: bool,
+    /// Back off once the provider's API calls have taken longer than this
+    /// many milliseconds for several consecutive checks (0 disables)
+    ///
+    /// Rising API latency often comes just before rate limiting.
+    #[serde(default)]
+    pub latency_backoff_ms: u64,
+    /// Decimal places shown in logged usage percentages (0 logs whole
+    /// percents); display only, thresholds still compare rounded percents
+    #[serde(default)]
+    pub percent_precision: u8,
+}
+
+/// How a fractional usage percentage is rounded to a whole percent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoundingMode {
+    /// Round down, so 75.5% counts as 75%
+    #[default]
+    Floor,
+    /// Round up, so any usage past a whole percent counts as the next one;
+    /// the conservative choice, pausing at the earliest moment
+    Ceil,
+    /// Round to the nearest whole percent, with halves rounding up
+    Nearest,
+}
+
+impl RoundingMode {
+    /// Divide `numerator` by a non-zero `denominator`, rounding the quotient
+    ///
+    /// Never overflows, even for values near `u64::MAX`.
+    #[must_use]
+    pub const fn divide(self, numerator: u64, denominator: u64) -> u64 {
+        match self {
+            Self::Floor => numerator / denominator,
+            Self::Ceil => numerator.div_ceil(denominator),
+            Self::Nearest => {
+                let round_up = numerator % denominator >= denominator.div_ceil(2);
+                numerator / denominator + round_up as u64
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RoundingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Floor => write!(f, "floor"),
+            Self::Ceil => write!(f, "ceil"),
+            Self::Nearest => write!(f, "nearest"),
+        }
+    }
+}
+
+impl std::str::FromStr for RoundingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "floor" => Ok(Self::Floor),
+            "ceil" => Ok(Self::Ceil),
+            "nearest" => Ok(Self::Nearest),
+            other => Err(anyhow!(
+                "Unknown rounding '{other}', expected 'floor', 'ceil' or 'nearest'"
+            )),
+        }
+    }
+}
+
+/// How per-limit usage combines into the decision to pause at critical
+///
+/// Only limits that are configured (or reported by the provider) take part.
+/// Warnings and the resume check always look at the highest usage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Gating {
+    /// Pause when any limit reaches critical
+    #[default]
+    Any,
+    /// Pause only when every limit has reached critical at the same time,
+    /// so a spike in a single metric does not pause the job
+    All,
+}
+
+impl std::fmt::Display for Gating {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Any => write!(f, "any"),
+            Self::All => write!(f, "all"),
+        }
+    }
+}
+
+impl std::str::FromStr for Gating {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "any" => Ok(Self::Any),
+            "all" => Ok(Self::All),
+            other => Err(anyhow!("Unknown gating '{other}', expected 'any' or 'all'")),
+        }
+    }
+}
+
+impl Thresholds {
+    /// Check that resume, warning and critical are in ascending order
+    ///
+    /// Equal warning and critical thresholds are allowed and mean there is no
+    /// warning band.
+    fn validate_order(&self) -> Result<()> {
+        if self.warning > self.critical {
+            return Err(anyhow!(
+                "Warning threshold must not exceed critical threshold"
+            ));
+        }
+        if self.resume >= self.warning {
+            return Err(anyhow!(
+                "Resume threshold must be less than warning threshold"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Pass the `STRAINER_*` variables for these thresholds to `push`
+    fn push_env_vars(&self, push: &mut impl FnMut(&str, String)) {
+        push("STRAINER_WARNING_THRESHOLD", self.warning.to_string());
+        push("STRAINER_CRITICAL_THRESHOLD", self.critical.to_string());
+        push("STRAINER_RESUME_THRESHOLD", self.resume.to_string());
+        push(
+            "STRAINER_FORECAST_HORIZON",
+            self.forecast_horizon_seconds.to_string(),
+        );
+        push(
+            "STRAINER_THRESHOLD_INCLUSIVE",
+            self.threshold_inclusive.to_string(),
+        );
+        push("STRAINER_GATING", self.gating.to_string());
+        push("STRAINER_ROUNDING", self.rounding.to_string());
+        push("STRAINER_STRICT_USAGE", self.strict_usage.to_string());
+        push(
+            "STRAINER_WARNING_SUSTAINED_SECONDS",
+            self.warning_sustained_seconds.to_string(),
+        );
+        push(
+            "STRAINER_PAUSE_ON_SUSTAINED_WARNING",
+            self.pause_on_sustained_warning.to_string(),
+        );
+        push("STRAINER_RESET_ON_RESUME", self.reset_on_resume.to_string());
+        push(
+            "STRAINER_LATENCY_BACKOFF_MS",
+            self.latency_backoff_ms.to_string(),
+        );
+        push(
+            "STRAINER_PERCENT_PRECISION",
+            self.percent_precision.to_string(),
+        );
+    }
+
+    /// Merge the thresholds in `other` that differ from the defaults
+    pub fn merge(&mut self, other: &Self) {
+        if other.warning != default_warning_threshold() {
+            self.warning = other.warning;
+        }
+        if other.critical != default_critical_threshold() {
+            self.critical = other.critical;
+        }
+        if other.resume != default_resume_threshold() {
+            self.resume = other.resume;
+        }
+        if other.forecast_horizon_seconds != default_forecast_horizon() {
+            self.forecast_horizon_seconds = other.forecast_horizon_seconds;
+        }
+        if other.threshold_inclusive != default_threshold_inclusive() {
+            self.threshold_inclusive = other.threshold_inclusive;
+        }
+        if other.gating != Gating::default() {
+            self.gating = other.gating;
+        }
+        if other.rounding != RoundingMode::default() {
+            self.rounding = other.rounding;
+        }
+        if other.strict_usage {
+            self.strict_usage = true;
+        }
+        if other.warning_sustained_seconds != 0 {
+            self.warning_sustained_seconds = other.warning_sustained_seconds;
+        }
+        if other.pause_on_sustained_warning {
+            self.pause_on_sustained_warning = true;
+        }
+        if other.reset_on_resume {
+            self.reset_on_resume = true;
+        }
+        if other.latency_backoff_ms != 0 {
+            self.latency_backoff_ms = other.latency_backoff_ms;
+        }
+        if other.percent_precision != 0 {
+            self.percent_precision = other.percent_precision;
+        }
+    }
 }
 
 impl Default for Thresholds {
@@ -167,10 +1041,26 @@ impl Default for Thresholds {
             warning: default_warning_threshold(),
             critical: default_critical_threshold(),
             resume: default_resume_threshold(),
+            forecast_horizon_seconds: default_forecast_horizon(),
+            threshold_inclusive: default_threshold_inclusive(),
+            gating: Gating::default(),
+            rounding: RoundingMode::default(),
+            strict_usage: false,
+            warning_sustained_seconds: 0,
+            pause_on_sustained_warning: false,
+            reset_on_resume: false,
+            latency_backoff_ms: 0,
+            percent_precision: 0,
         }
     }
 }
 
+const fn default_forecast_horizon() -> u32 {
+    300
+}
+const fn default_threshold_inclusive() -> bool {
+    true
+}
 const fn default_warning_threshold() -> u8 {
     80
 }
@@ -183,10 +1073,14 @@ const fn default_resume_threshold() -> u8 {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BackoffConfig {
+    /// Pause between checks while usage is below critical, in whole seconds
+    /// or as a duration such as `"500ms"`
     #[serde(default = "default_min_backoff")]
-    pub min_seconds: u32,
+    pub min_seconds: HumanDuration,
+    /// Pause after usage reaches critical, in whole seconds or as a duration
+    /// such as `"2m"`
     #[serde(default = "default_max_backoff")]
-    pub max_seconds: u32,
+    pub max_seconds: HumanDuration,
 }
 
 impl Default for BackoffConfig {
@@ -198,11 +1092,25 @@ impl Default for BackoffConfig {
     }
 }
 
-const fn default_min_backoff() -> u32 {
-    1
+impl BackoffConfig {
+    /// Pause between checks while usage is below critical
+    #[must_use]
+    pub const fn min_backoff(&self) -> Duration {
+        self.min_seconds.as_duration()
+    }
+
+    /// Pause after usage reaches critical
+    #[must_use]
+    pub const fn max_backoff(&self) -> Duration {
+        self.max_seconds.as_duration()
+    }
+}
+
+const fn default_min_backoff() -> HumanDuration {
+    HumanDuration::from_secs(1)
 }
-const fn default_max_backoff() -> u32 {
-    60
+const fn default_max_backoff() -> HumanDuration {
+    HumanDuration::from_secs(60)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -211,51 +1119,648 @@ pub struct ProcessConfig {
     pub pause_on_warning: bool,
     #[serde(default = "default_pause_on_critical")]
     pub pause_on_critical: bool,
+    /// Working directory for the child process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<PathBuf>,
+    /// Extra environment variables for the child process
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+    /// Environment variable under which the resolved API key is passed to the child
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    /// Seconds after the child starts during which usage is only logged and
+    /// never pauses it, while the provider's figures catch up with the new run
+    #[serde(default)]
+    pub warmup_seconds: u32,
+    /// Seconds after the child is resumed during which critical usage only
+    /// logs a warning, so it can make progress before it is paused again
+    #[serde(default)]
+    pub post_resume_grace_seconds: u32,
+    /// Catchable signal, e.g. `SIGTSTP`, sent to pause the child so it can
+    /// clean up and stop itself; `SIGSTOP` follows if it does not stop
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pause_signal: Option<String>,
+    /// Pause the child while its resident memory is above this many bytes;
+    /// also accepts sizes such as `"512M"` (Linux only)
+    #[serde(
+        default,
+        deserialize_with = "deserialize_memory",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_memory: Option<u64>,
+    /// Pause the child while it uses more CPU than this percentage of one
+    /// core, throttling it to roughly that share (Linux only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cpu_percent: Option<f64>,
+    /// Whether a run holds a lock on its API key, keeping a second run with
+    /// the same key from starting alongside it
+    #[serde(default)]
+    pub key_lock: KeyLock,
+    /// Where the child's stdin, stdout and stderr are connected
+    #[serde(default)]
+    pub stdio: StdioMode,
+    /// Times to retry starting the child when it cannot be spawned, e.g.
+    /// while its binary is being redeployed
+    #[serde(default)]
+    pub spawn_retries: u32,
+    /// Milliseconds before the first spawn retry, doubling for each retry after
+    #[serde(default = "default_spawn_retry_delay_ms")]
+    pub spawn_retry_delay_ms: u64,
+    /// Milliseconds the child gets to exit after `SIGTERM` when strainer
+    /// shuts down, before it is killed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kill_timeout_ms: Option<u64>,
+    /// Fallback command, e.g. one using a local model, started once the
+    /// first time usage turns critical; it runs alongside the child, which is
+    /// paused as usual, and its exit status is only logged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_critical_command: Option<Vec<String>>,
 }
 
-impl Default for ProcessConfig {
-    fn default() -> Self {
-        Self {
-            pause_on_warning: default_pause_on_warning(),
-            pause_on_critical: default_pause_on_critical(),
+/// What `run` does when another run already holds the lock on its API key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyLock {
+    /// Take no lock
+    #[default]
+    Off,
+    /// Exit without starting the command
+    Fail,
+    /// Wait for the other run to finish, then start
+    Wait,
+}
+
+impl std::fmt::Display for KeyLock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Fail => write!(f, "fail"),
+            Self::Wait => write!(f, "wait"),
         }
     }
 }
 
-const fn default_pause_on_warning() -> bool {
-    false
+impl std::str::FromStr for KeyLock {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "off" => Ok(Self::Off),
+            "fail" => Ok(Self::Fail),
+            "wait" => Ok(Self::Wait),
+            other => Err(anyhow!(
+                "Unknown key lock '{other}', expected 'off', 'fail' or 'wait'"
+            )),
+        }
+    }
 }
-const fn default_pause_on_critical() -> bool {
-    true
+
+/// Where the child process's standard streams are connected
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StdioMode {
+    /// Share strainer's stdin, stdout and stderr
+    #[default]
+    Inherit,
+    /// Read the child's stdout and stderr through pipes and forward them to
+    /// strainer's own, draining them even while the child is paused
+    Pipe,
+    /// Connect all three streams to the null device, discarding output
+    Null,
 }
 
-impl Config {
-    /// Create a new configuration builder
-    #[must_use]
-    pub fn builder() -> ConfigBuilder {
-        ConfigBuilder::new()
+impl std::fmt::Display for StdioMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Inherit => write!(f, "inherit"),
+            Self::Pipe => write!(f, "pipe"),
+            Self::Null => write!(f, "null"),
+        }
     }
+}
 
-    /// Load configuration from default locations and environment variables
-    ///
-    /// # Errors
-    ///
-    /// This function will return an error if:
-    /// - Configuration validation fails
+impl std::str::FromStr for StdioMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "inherit" => Ok(Self::Inherit),
+            "pipe" => Ok(Self::Pipe),
+            "null" => Ok(Self::Null),
+            other => Err(anyhow!(
+                "Unknown stdio mode '{other}', expected 'inherit', 'pipe' or 'null'"
+            )),
+        }
+    }
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        Self {
+            pause_on_warning: default_pause_on_warning(),
+            pause_on_critical: default_pause_on_critical(),
+            working_dir: None,
+            env: HashMap::default(),
+            api_key_env: None,
+            warmup_seconds: 0,
+            post_resume_grace_seconds: 0,
+            pause_signal: None,
+            max_memory: None,
+            max_cpu_percent: None,
+            key_lock: KeyLock::default(),
+            stdio: StdioMode::default(),
+            spawn_retries: 0,
+            spawn_retry_delay_ms: default_spawn_retry_delay_ms(),
+            kill_timeout_ms: None,
+            on_critical_command: None,
+        }
+    }
+}
+
+impl ProcessConfig {
+    /// Merge `other` over these settings, taking the values it sets that
+    /// differ from the defaults
+    fn merge(&mut self, other: Self) {
+        if other.pause_on_warning != default_pause_on_warning() {
+            self.pause_on_warning = other.pause_on_warning;
+        }
+        if other.pause_on_critical != default_pause_on_critical() {
+            self.pause_on_critical = other.pause_on_critical;
+        }
+        if let Some(working_dir) = other.working_dir {
+            self.working_dir = Some(working_dir);
+        }
+        self.env.extend(other.env);
+        if let Some(api_key_env) = other.api_key_env {
+            self.api_key_env = Some(api_key_env);
+        }
+        if other.warmup_seconds != 0 {
+            self.warmup_seconds = other.warmup_seconds;
+        }
+        if other.post_resume_grace_seconds != 0 {
+            self.post_resume_grace_seconds = other.post_resume_grace_seconds;
+        }
+        if let Some(pause_signal) = other.pause_signal {
+            self.pause_signal = Some(pause_signal);
+        }
+        if let Some(max_memory) = other.max_memory {
+            self.max_memory = Some(max_memory);
+        }
+        if let Some(max_cpu_percent) = other.max_cpu_percent {
+            self.max_cpu_percent = Some(max_cpu_percent);
+        }
+        if other.key_lock != KeyLock::default() {
+            self.key_lock = other.key_lock;
+        }
+        if other.stdio != StdioMode::default() {
+            self.stdio = other.stdio;
+        }
+        if other.spawn_retries != 0 {
+            self.spawn_retries = other.spawn_retries;
+        }
+        if other.spawn_retry_delay_ms != default_spawn_retry_delay_ms() {
+            self.spawn_retry_delay_ms = other.spawn_retry_delay_ms;
+        }
+        if let Some(kill_timeout_ms) = other.kill_timeout_ms {
+            self.kill_timeout_ms = Some(kill_timeout_ms);
+        }
+        if let Some(command) = other.on_critical_command {
+            self.on_critical_command = Some(command);
+        }
+    }
+
+    /// The configured pause signal, parsed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the signal is unknown or is `SIGKILL`
+    pub fn pause_signal(&self) -> Result<Option<Signal>> {
+        let Some(name) = &self.pause_signal else {
+            return Ok(None);
+        };
+        let signal = name
+            .parse::<Signal>()
+            .map_err(|_| anyhow!("Unknown pause signal '{name}'"))?;
+        if signal == Signal::SIGKILL {
+            return Err(anyhow!("Pause signal must not be SIGKILL"));
+        }
+        Ok(Some(signal))
+    }
+
+    /// Override these settings from environment variables named with `prefix`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `<prefix>MAX_MEMORY` is not a valid size
+    fn read_env(&mut self, prefix: &str) -> Result<()> {
+        if let Some(value) = parse_env(prefix, "PAUSE_ON_WARNING") {
+            self.pause_on_warning = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "PAUSE_ON_CRITICAL") {
+            self.pause_on_critical = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "WARMUP_SECONDS") {
+            self.warmup_seconds = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "POST_RESUME_GRACE_SECONDS") {
+            self.post_resume_grace_seconds = value;
+        }
+
+        if let Some(signal) = env_var(prefix, "PAUSE_SIGNAL") {
+            self.pause_signal = Some(signal);
+        }
+
+        if let Some(size) = env_var(prefix, "MAX_MEMORY") {
+            self.max_memory = Some(parse_memory(&size)?);
+        }
+
+        if let Some(value) = parse_env(prefix, "MAX_CPU_PERCENT") {
+            self.max_cpu_percent = Some(value);
+        }
+
+        if let Some(value) = parse_env(prefix, "KEY_LOCK") {
+            self.key_lock = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "STDIO") {
+            self.stdio = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "SPAWN_RETRIES") {
+            self.spawn_retries = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "SPAWN_RETRY_DELAY_MS") {
+            self.spawn_retry_delay_ms = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "KILL_TIMEOUT_MS") {
+            self.kill_timeout_ms = Some(value);
+        }
+
+        Ok(())
+    }
+
+    /// Pass the `STRAINER_*` variables for these settings to `push`
+    fn push_env_vars(&self, push: &mut impl FnMut(&str, String)) {
+        push(
+            "STRAINER_PAUSE_ON_WARNING",
+            self.pause_on_warning.to_string(),
+        );
+        push(
+            "STRAINER_PAUSE_ON_CRITICAL",
+            self.pause_on_critical.to_string(),
+        );
+        push("STRAINER_WARMUP_SECONDS", self.warmup_seconds.to_string());
+        push(
+            "STRAINER_POST_RESUME_GRACE_SECONDS",
+            self.post_resume_grace_seconds.to_string(),
+        );
+        if let Some(signal) = &self.pause_signal {
+            push("STRAINER_PAUSE_SIGNAL", signal.clone());
+        }
+        if let Some(max_memory) = self.max_memory {
+            push("STRAINER_MAX_MEMORY", max_memory.to_string());
+        }
+        if let Some(max_cpu_percent) = self.max_cpu_percent {
+            push("STRAINER_MAX_CPU_PERCENT", max_cpu_percent.to_string());
+        }
+        push("STRAINER_KEY_LOCK", self.key_lock.to_string());
+        push("STRAINER_STDIO", self.stdio.to_string());
+        push("STRAINER_SPAWN_RETRIES", self.spawn_retries.to_string());
+        push(
+            "STRAINER_SPAWN_RETRY_DELAY_MS",
+            self.spawn_retry_delay_ms.to_string(),
+        );
+        if let Some(kill_timeout_ms) = self.kill_timeout_ms {
+            push("STRAINER_KILL_TIMEOUT_MS", kill_timeout_ms.to_string());
+        }
+    }
+
+    /// How long the child gets to exit after `SIGTERM` when strainer shuts down
+    #[must_use]
+    pub fn kill_timeout(&self) -> Duration {
+        self.kill_timeout_ms
+            .map_or(TERMINATE_GRACE, Duration::from_millis)
+    }
+
+    /// The memory and CPU ceilings for the child
+    #[must_use]
+    pub const fn resource_limits(&self) -> ResourceLimits {
+        ResourceLimits {
+            max_memory: self.max_memory,
+            max_cpu_percent: self.max_cpu_percent,
+        }
+    }
+}
+
+/// Settings for the local proxy that counts the child's API usage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_proxy_listen")]
+    pub listen: String,
+    /// Environment variable through which the child learns the proxy URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env_var: Option<String>,
+    /// Estimate the tokens of proxied calls whose responses report no usage
+    #[serde(default)]
+    pub estimate_tokens: bool,
+    /// How successful responses that leave out a token count are counted
+    #[serde(default)]
+    pub missing_metric_policy: MissingMetricPolicy,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: default_proxy_listen(),
+            env_var: None,
+            estimate_tokens: false,
+            missing_metric_policy: MissingMetricPolicy::default(),
+        }
+    }
+}
+
+/// How a token count missing from a proxied response is counted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingMetricPolicy {
+    /// Count the missing metric as zero
+    #[default]
+    Zero,
+    /// Count the missing metric as its full limit for the rest of the window
+    AssumeFull,
+    /// Fail the usage check
+    Error,
+}
+
+impl std::fmt::Display for MissingMetricPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Zero => write!(f, "zero"),
+            Self::AssumeFull => write!(f, "assume_full"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl std::str::FromStr for MissingMetricPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "zero" => Ok(Self::Zero),
+            "assume_full" => Ok(Self::AssumeFull),
+            "error" => Ok(Self::Error),
+            other => Err(anyhow!(
+                "Unknown missing metric policy '{other}', expected 'zero', 'assume_full' or 'error'"
+            )),
+        }
+    }
+}
+
+impl ProxyConfig {
+    /// Replace these settings with `other` when it is enabled, keeping token
+    /// estimation if either side asks for it and any non-default missing
+    /// metric policy
+    fn merge(&mut self, other: Self) {
+        let estimate_tokens = self.estimate_tokens || other.estimate_tokens;
+        let missing_metric_policy = if other.missing_metric_policy == MissingMetricPolicy::default()
+        {
+            self.missing_metric_policy
+        } else {
+            other.missing_metric_policy
+        };
+        if other.enabled {
+            *self = other;
+        }
+        self.estimate_tokens = estimate_tokens;
+        self.missing_metric_policy = missing_metric_policy;
+    }
+
+    /// Override these settings from environment variables named with `prefix`
+    fn read_env(&mut self, prefix: &str) {
+        if let Some(value) = parse_env(prefix, "PROXY") {
+            self.enabled = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "PROXY_ESTIMATE_TOKENS") {
+            self.estimate_tokens = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "MISSING_METRIC_POLICY") {
+            self.missing_metric_policy = value;
+        }
+    }
+
+    /// Get the environment variable to set for the given provider
+    #[must_use]
+    pub fn env_var_for(&self, provider: &ProviderConfig) -> String {
+        self.env_var.clone().unwrap_or_else(|| {
+            match provider {
+                ProviderConfig::Anthropic(_) => "ANTHROPIC_BASE_URL",
+                ProviderConfig::OpenAI(_) => "OPENAI_BASE_URL",
+                ProviderConfig::Mock(_) | ProviderConfig::Aggregate(_) => "STRAINER_PROXY_URL",
+            }
+            .to_string()
+        })
+    }
+}
+
+/// How strainer reaches provider APIs, for networks that require an
+/// outbound proxy or a private certificate authority
+///
+/// Without an explicit `http_proxy`, the standard `HTTPS_PROXY`, `HTTP_PROXY`
+/// and `NO_PROXY` environment variables are honored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Proxy URL for all provider requests (e.g. `http://proxy.corp:3128`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_proxy: Option<String>,
+    /// PEM file with an additional CA certificate to trust
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_cert_path: Option<PathBuf>,
+    /// Seconds a provider request may take, including reading the response,
+    /// before it fails (0 disables); requests forwarded by the proxy are only
+    /// limited in the time to connect
+    #[serde(default = "default_request_timeout")]
+    pub request_timeout_seconds: u64,
+    /// User-Agent sent with provider requests, `strainer/<version>` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Header (e.g. `X-Request-ID`) set to a new random identifier on each
+    /// proxied provider request, to find it in the provider's logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id_header: Option<String>,
+}
+
+/// User-Agent sent with provider requests unless `user_agent` is set
+pub const DEFAULT_USER_AGENT: &str = concat!("strainer/", env!("CARGO_PKG_VERSION"));
+
+/// How long a provider request may take unless `request_timeout_seconds` is set
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const fn default_request_timeout() -> u64 {
+    DEFAULT_REQUEST_TIMEOUT.as_secs()
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            http_proxy: None,
+            ca_cert_path: None,
+            request_timeout_seconds: default_request_timeout(),
+            user_agent: None,
+            request_id_header: None,
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Merge the settings in `other` that are set or differ from the defaults
+    fn merge(&mut self, other: Self) {
+        if other.http_proxy.is_some() {
+            self.http_proxy = other.http_proxy;
+        }
+        if other.ca_cert_path.is_some() {
+            self.ca_cert_path = other.ca_cert_path;
+        }
+        if other.request_timeout_seconds != default_request_timeout() {
+            self.request_timeout_seconds = other.request_timeout_seconds;
+        }
+        if other.user_agent.is_some() {
+            self.user_agent = other.user_agent;
+        }
+        if other.request_id_header.is_some() {
+            self.request_id_header = other.request_id_header;
+        }
+    }
+
+    /// The request timeout, `None` if disabled
+    #[must_use]
+    pub const fn request_timeout(&self) -> Option<Duration> {
+        match self.request_timeout_seconds {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        }
+    }
+
+    /// The User-Agent sent with provider requests
+    #[must_use]
+    pub fn user_agent(&self) -> &str {
+        self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT)
+    }
+
+    /// Build an HTTP client for provider requests using these settings
+    ///
+    /// A request that outlasts the timeout fails like any other network error.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the proxy URL or request ID header is invalid, the
+    /// CA certificate cannot be read or parsed, or the client cannot be
+    /// constructed
+    pub fn http_client(&self) -> Result<reqwest::Client> {
+        let mut builder = self.client_builder()?;
+        if let Some(timeout) = self.request_timeout() {
+            builder = builder.timeout(timeout);
+        }
+        builder.build().context("Failed to build HTTP client")
+    }
+
+    /// Build an HTTP client for the requests the proxy forwards upstream
+    ///
+    /// Only connecting is bounded by the timeout: a streamed response may
+    /// legitimately run for minutes, and cutting it off would fail the
+    /// child's request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in the same cases as [`NetworkConfig::http_client`]
+    pub fn forwarding_client(&self) -> Result<reqwest::Client> {
+        let mut builder = self.client_builder()?;
+        if let Some(timeout) = self.request_timeout() {
+            builder = builder.connect_timeout(timeout);
+        }
+        builder.build().context("Failed to build HTTP client")
+    }
+
+    /// A client builder with every setting but the timeout applied
+    fn client_builder(&self) -> Result<reqwest::ClientBuilder> {
+        if let Some(name) = &self.request_id_header {
+            reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid request ID header: {name}"))?;
+        }
+        let mut builder = reqwest::Client::builder().user_agent(self.user_agent());
+        if let Some(url) = &self.http_proxy {
+            let proxy = reqwest::Proxy::all(url)
+                .with_context(|| format!("Invalid HTTP proxy URL: {url}"))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &self.ca_cert_path {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Failed to read CA certificate {}", path.display()))?;
+            let cert = reqwest::Certificate::from_pem(&pem)
+                .with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        Ok(builder)
+    }
+}
+
+fn default_proxy_listen() -> String {
+    "127.0.0.1:0".to_string()
+}
+
+const fn default_pause_on_warning() -> bool {
+    false
+}
+const fn default_pause_on_critical() -> bool {
+    true
+}
+
+const fn default_spawn_retry_delay_ms() -> u64 {
+    1000
+}
+
+impl Config {
+    /// Create a new configuration builder
+    #[must_use]
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
+    /// Load configuration from default locations and `STRAINER_*`
+    /// environment variables
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Configuration validation fails
     pub fn load() -> Result<Self> {
-        let home_dir = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
-        let current_dir = env::current_dir()?;
+        Self::load_with_prefix(DEFAULT_ENV_PREFIX)
+    }
 
-        let config_paths = [
-            current_dir.join("strainer.toml"),
-            home_dir.join(".config/strainer/config.toml"),
-            home_dir.join(".strainer.toml"),
-        ];
+    /// Load configuration from default locations and environment variables
+    /// named with `env_prefix`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - Configuration validation fails
+    pub fn load_with_prefix(env_prefix: &str) -> Result<Self> {
+        let config_paths = Self::default_paths()?;
 
         // Try to load from file first
         let builder = config_paths.iter().try_fold(
             Self::builder(),
-            |builder, path: &std::path::PathBuf| -> Result<ConfigBuilder, anyhow::Error> {
+            |builder, path: &PathBuf| -> Result<ConfigBuilder, anyhow::Error> {
                 if path.exists() {
                     builder.from_file(path)
                 } else {
@@ -265,7 +1770,63 @@ impl Config {
         )?;
 
         // Then load from environment, which will override file settings
-        builder.from_env()?.build()
+        builder.from_env_with_prefix(env_prefix)?.build()
+    }
+
+    /// Fetch configuration from an `http(s)://` URL, then apply environment
+    /// variables named with `env_prefix`
+    ///
+    /// The local config files are not read; the fetched config replaces them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config cannot be fetched or parsed, or
+    /// validation fails
+    pub async fn load_url(url: &str, timeout: Duration, env_prefix: &str) -> Result<Self> {
+        Self::builder()
+            .from_url(url, timeout)
+            .await?
+            .from_env_with_prefix(env_prefix)?
+            .build()
+    }
+
+    /// The config file at `path` followed by every file it includes, directly
+    /// or through other includes
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file cannot be read or parsed, or the includes
+    /// form a cycle
+    pub fn files_with_includes(path: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        read_config_table(path, &mut Vec::new(), &mut files)?;
+        Ok(files)
+    }
+
+    /// Config file locations checked by [`Config::load`], in load order
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the current directory cannot be determined
+    pub fn default_paths() -> Result<Vec<PathBuf>> {
+        let home_dir = dirs::home_dir();
+        if home_dir.is_none() {
+            debug!("No home directory found, skipping home config locations");
+        }
+        Ok(Self::paths_in(&env::current_dir()?, home_dir.as_deref()))
+    }
+
+    /// Config file locations for the given working and home directories
+    ///
+    /// Home-based locations are skipped entirely when there is no home directory.
+    #[must_use]
+    pub fn paths_in(current_dir: &Path, home_dir: Option<&Path>) -> Vec<PathBuf> {
+        let mut paths = vec![current_dir.join("strainer.toml")];
+        if let Some(home_dir) = home_dir {
+            paths.push(home_dir.join(".config/strainer/config.toml"));
+            paths.push(home_dir.join(".strainer.toml"));
+        }
+        paths
     }
 
     /// Validate the configuration
@@ -280,37 +1841,113 @@ impl Config {
         // Validate API configuration
         match &self.api.provider_config {
             ProviderConfig::Mock(_) => {}
+            // Each aggregated provider brings its own key
+            ProviderConfig::Aggregate(cfg) => {
+                if cfg.providers.iter().any(|api| {
+                    api.api_key.is_none() && !matches!(api.provider_config, ProviderConfig::Mock(_))
+                }) {
+                    return Err(anyhow!(
+                        "API key is required for each non-mock aggregated provider"
+                    ));
+                }
+            }
             _ => {
                 if self.api.api_key.is_none() {
                     return Err(anyhow!("API key is required for non-mock provider"));
                 }
             }
         }
+        if let Some(reference) = self
+            .api
+            .api_key
+            .as_deref()
+            .and_then(|key| key.strip_prefix(KEYRING_PREFIX))
+        {
+            reference.parse::<KeyringRef>()?;
+        }
 
         // Validate provider-specific configuration
         self.api.provider_config.validate()?;
 
-        // Validate thresholds
-        if self.thresholds.warning >= self.thresholds.critical {
-            return Err(anyhow!(
-                "Warning threshold must be less than critical threshold"
-            ));
+        self.thresholds.validate_order()?;
+
+        // Validate quota fraction
+        if let Some(fraction) = self.limits.fraction_of_quota {
+            if !(fraction > 0.0 && fraction <= 1.0) {
+                return Err(anyhow!(
+                    "Fraction of quota must be greater than 0 and at most 1"
+                ));
+            }
         }
-        if self.thresholds.resume >= self.thresholds.warning {
-            return Err(anyhow!(
-                "Resume threshold must be less than warning threshold"
-            ));
+        if self.limits.shared_instances == Some(0) {
+            return Err(anyhow!("Shared instances must be at least 1"));
+        }
+        if self
+            .process
+            .on_critical_command
+            .as_ref()
+            .is_some_and(Vec::is_empty)
+        {
+            return Err(anyhow!("On-critical command must not be empty"));
         }
 
         // Validate backoff configuration
-        if self.backoff.min_seconds >= self.backoff.max_seconds {
+        if self.backoff.min_backoff() >= self.backoff.max_backoff() {
             return Err(anyhow!("Minimum backoff must be less than maximum backoff"));
         }
 
+        self.process.pause_signal()?;
+        if self.process.max_memory == Some(0) {
+            return Err(anyhow!("Maximum memory must be greater than 0"));
+        }
+        if let Some(cpu) = self.process.max_cpu_percent {
+            if !(cpu > 0.0 && cpu.is_finite()) {
+                return Err(anyhow!("Maximum CPU percentage must be greater than 0"));
+            }
+        }
+
+        for window in &self.schedule {
+            if window.start_hour > 23 || window.end_hour > 23 {
+                return Err(anyhow!("Schedule hours must be between 0 and 23"));
+            }
+            if window.start_hour == window.end_hour {
+                return Err(anyhow!(
+                    "Schedule window {window} is empty: start and end hours must differ"
+                ));
+            }
+            if let Some(thresholds) = &window.thresholds {
+                thresholds
+                    .validate_order()
+                    .with_context(|| format!("Invalid thresholds for schedule window {window}"))?;
+            }
+        }
+
+        for (index, route) in self.routes.iter().enumerate() {
+            if route.program.is_none() && route.pattern.is_none() {
+                return Err(anyhow!("Route {index} must set a program or a pattern"));
+            }
+            route
+                .regex()
+                .with_context(|| format!("Invalid pattern for route {index}"))?;
+            if route.api.api_key.is_none()
+                && !matches!(route.api.provider_config, ProviderConfig::Mock(_))
+            {
+                return Err(anyhow!(
+                    "API key is required for the non-mock provider of route {index}"
+                ));
+            }
+            route.api.provider_config.validate()?;
+        }
+
         Ok(())
     }
 
     /// Merge another configuration into this one
+    ///
+    /// Values set in `other` override this config's. Provider `parameters`
+    /// follow `other.api.merge` and lose any keys listed in
+    /// `other.api.remove_parameters`; environment variables and named
+    /// commands are extended.
     pub fn merge(&mut self, other: Self) {
         // API configuration is merged
         if let Some(key) = &other.api.api_key {
@@ -324,32 +1961,47 @@ impl Config {
             self.api.base_url = Some(base_url);
         }
 
-        // Provider configuration is merged
+        // Provider configuration is merged, with provider parameters combined
+        // according to the overlay's merge strategy
+        let (merge, remove) = (other.api.merge, other.api.remove_parameters.as_slice());
         match (&mut self.api.provider_config, &other.api.provider_config) {
             (ProviderConfig::Anthropic(self_config), ProviderConfig::Anthropic(other_config)) => {
-                // Merge direct fields
-                self_config.model.clone_from(&other_config.model);
-                self_config.max_tokens = other_config.max_tokens;
-                // Merge parameters
-                self_config
-                    .parameters
-                    .extend(other_config.parameters.clone());
+                // Direct fields are merged if they differ from defaults
+                let defaults = AnthropicConfig::default();
+                if other_config.model != defaults.model {
+                    self_config.model.clone_from(&other_config.model);
+                }
+                if other_config.max_tokens != defaults.max_tokens {
+                    self_config.max_tokens = other_config.max_tokens;
+                }
+                merge.apply(
+                    &mut self_config.parameters,
+                    &other_config.parameters,
+                    remove,
+                );
             }
             (ProviderConfig::OpenAI(self_config), ProviderConfig::OpenAI(other_config)) => {
-                // Merge direct fields
-                self_config.model.clone_from(&other_config.model);
-                self_config.max_tokens = other_config.max_tokens;
-
-                // Merge parameters
-                self_config
-                    .parameters
-                    .extend(other_config.parameters.clone());
+                // Direct fields are merged if they differ from defaults
+                let defaults = OpenAIConfig::default();
+                if other_config.model != defaults.model {
+                    self_config.model.clone_from(&other_config.model);
+                }
+                if other_config.max_tokens != defaults.max_tokens {
+                    self_config.max_tokens = other_config.max_tokens;
+                }
+                merge.apply(
+                    &mut self_config.parameters,
+                    &other_config.parameters,
+                    remove,
+                );
             }
             (ProviderConfig::Mock(self_config), ProviderConfig::Mock(other_config)) => {
                 // For mock, just merge parameters
-                self_config
-                    .parameters
-                    .extend(other_config.parameters.clone());
+                merge.apply(
+                    &mut self_config.parameters,
+                    &other_config.parameters,
+                    remove,
+                );
             }
             _ => {
                 // Different provider types - replace entirely
@@ -362,34 +2014,213 @@ impl Config {
         }
 
         // Rate limits are merged if set
-        if let Some(rpm) = other.limits.requests_per_minute {
-            self.limits.requests_per_minute = Some(rpm);
-        }
-        if let Some(tpm) = other.limits.tokens_per_minute {
-            self.limits.tokens_per_minute = Some(tpm);
-        }
-        if let Some(itpm) = other.limits.input_tokens_per_minute {
-            self.limits.input_tokens_per_minute = Some(itpm);
-        }
+        self.limits.merge(&other.limits);
 
         // Thresholds are merged if they differ from defaults
-        if other.thresholds.warning != default_warning_threshold() {
-            self.thresholds.warning = other.thresholds.warning;
-        }
-        if other.thresholds.critical != default_critical_threshold() {
-            self.thresholds.critical = other.thresholds.critical;
-        }
-        if other.thresholds.resume != default_resume_threshold() {
-            self.thresholds.resume = other.thresholds.resume;
-        }
+        self.thresholds.merge(&other.thresholds);
 
         // Process settings are merged if they differ from defaults
-        if other.process.pause_on_warning != ProcessConfig::default().pause_on_warning {
-            self.process.pause_on_warning = other.process.pause_on_warning;
+        self.process.merge(other.process);
+
+        self.proxy.merge(other.proxy);
+        self.network.merge(other.network);
+
+        if !other.fallback_providers.is_empty() {
+            self.fallback_providers = other.fallback_providers;
+        }
+
+        self.commands.extend(other.commands);
+        if !other.schedule.is_empty() {
+            self.schedule = other.schedule;
+        }
+        if !other.routes.is_empty() {
+            self.routes = other.routes;
+        }
+    }
+
+    /// The config to run `command` with, using the provider of the first
+    /// route that matches it in place of `[api]`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a route's pattern is not a valid regex
+    pub fn routed(&self, command: &[String]) -> Result<Self> {
+        let mut config = self.clone();
+        for (index, route) in self.routes.iter().enumerate() {
+            if route.matches(command)? {
+                debug!("Command matches route {index}");
+                config.api = route.api.clone();
+                break;
+            }
+        }
+        Ok(config)
+    }
+
+    /// The rate limits to enforce, with those set for the provider's model
+    /// under `[limits.by_model]` applied
+    #[must_use]
+    pub fn active_limits(&self) -> RateLimits {
+        self.limits.for_model(self.api.provider_config.model())
+    }
+
+    /// Resolve a named command, returning its command line and the config to
+    /// run it with
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing the available names if `name` is not defined
+    pub fn for_command(&self, name: &str) -> Result<(Self, Vec<String>)> {
+        let Some(named) = self.commands.get(name) else {
+            let mut available: Vec<&str> = self.commands.keys().map(String::as_str).collect();
+            available.sort_unstable();
+            return Err(if available.is_empty() {
+                anyhow!("Unknown command '{name}': no commands are defined in the config")
+            } else {
+                anyhow!(
+                    "Unknown command '{name}', available commands: {}",
+                    available.join(", ")
+                )
+            });
+        };
+
+        let mut config = self.clone();
+        if let Some(limits) = &named.limits {
+            config.limits.merge(limits);
+        }
+        if let Some(thresholds) = &named.thresholds {
+            config.thresholds.clone_from(thresholds);
+        }
+        if let Some(backoff) = &named.backoff {
+            config.backoff.clone_from(backoff);
+        }
+        Ok((config, named.command.clone()))
+    }
+
+    /// Fields that differ between this config and `other`, sorted by path
+    ///
+    /// Both configs are compared through their serialized form, so only
+    /// fields that are written to a config file take part. API keys are shown
+    /// as `<redacted>` unless `show_secrets` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either config cannot be serialized
+    pub fn diff(&self, other: &Self, show_secrets: bool) -> Result<Vec<ConfigDifference>> {
+        let (mut left, mut right) = (BTreeMap::new(), BTreeMap::new());
+        flatten_value(String::new(), serde_json::to_value(self)?, &mut left);
+        flatten_value(String::new(), serde_json::to_value(other)?, &mut right);
+
+        let paths: std::collections::BTreeSet<_> =
+            left.keys().chain(right.keys()).cloned().collect();
+        Ok(paths
+            .into_iter()
+            .filter_map(|path| {
+                let (before, after) = (left.remove(&path), right.remove(&path));
+                if before == after {
+                    return None;
+                }
+                let redact = !show_secrets && path.rsplit('.').next() == Some("api_key");
+                let render = |value: Option<serde_json::Value>| {
+                    value.map(|value| {
+                        if redact {
+                            "<redacted>".to_string()
+                        } else {
+                            value.to_string()
+                        }
+                    })
+                };
+                Some(ConfigDifference {
+                    left: render(before),
+                    right: render(after),
+                    path,
+                })
+            })
+            .collect())
+    }
+
+    /// Map the configuration back to the `STRAINER_*` variables read by `from_env`
+    ///
+    /// The API key is only included when `show_secrets` is set. Fields that
+    /// `from_env` does not read are omitted.
+    #[must_use]
+    pub fn to_env_vars(&self, show_secrets: bool) -> Vec<(String, String)> {
+        let mut vars = Vec::new();
+        let mut push = |name: &str, value: String| vars.push((name.to_string(), value));
+
+        push(
+            "STRAINER_PROVIDER_TYPE",
+            self.api.provider_config.to_string(),
+        );
+        match &self.api.provider_config {
+            ProviderConfig::Anthropic(cfg) => {
+                push("STRAINER_MODEL", cfg.model.clone());
+                push("STRAINER_MAX_TOKENS", cfg.max_tokens.to_string());
+            }
+            ProviderConfig::OpenAI(cfg) => {
+                push("STRAINER_MODEL", cfg.model.clone());
+                push("STRAINER_MAX_TOKENS", cfg.max_tokens.to_string());
+            }
+            ProviderConfig::Mock(_) | ProviderConfig::Aggregate(_) => {}
         }
-        if other.process.pause_on_critical != default_pause_on_critical() {
-            self.process.pause_on_critical = other.process.pause_on_critical;
+        if show_secrets {
+            if let Some(api_key) = &self.api.api_key {
+                push("STRAINER_API_KEY", api_key.clone());
+            }
+        }
+        if let Some(base_url) = &self.api.base_url {
+            push("STRAINER_BASE_URL", base_url.clone());
+        }
+
+        if let Some(rpm) = self.limits.requests_per_minute {
+            push("STRAINER_REQUESTS_PER_MINUTE", rpm.to_string());
+        }
+        if let Some(tpm) = self.limits.tokens_per_minute {
+            push("STRAINER_TOKENS_PER_MINUTE", tpm.to_string());
+        }
+        if let Some(itpm) = self.limits.input_tokens_per_minute {
+            push("STRAINER_INPUT_TOKENS_PER_MINUTE", itpm.to_string());
+        }
+        if let Some(otpm) = self.limits.output_tokens_per_minute {
+            push("STRAINER_OUTPUT_TOKENS_PER_MINUTE", otpm.to_string());
+        }
+        if let Some(erpm) = self.limits.error_requests_per_minute {
+            push("STRAINER_ERROR_REQUESTS_PER_MINUTE", erpm.to_string());
         }
+        if let Some(fraction) = self.limits.fraction_of_quota {
+            push("STRAINER_FRACTION_OF_QUOTA", fraction.to_string());
+        }
+        if let Some(instances) = self.limits.shared_instances {
+            push("STRAINER_SHARED_INSTANCES", instances.to_string());
+        }
+        if let Some(seconds) = self.limits.ramp_up_seconds {
+            push("STRAINER_RAMP_UP_SECONDS", seconds.to_string());
+        }
+        push("STRAINER_FORCE_LIMITS", self.limits.force.to_string());
+
+        self.thresholds.push_env_vars(&mut push);
+        self.process.push_env_vars(&mut push);
+        push("STRAINER_PROXY", self.proxy.enabled.to_string());
+        push(
+            "STRAINER_PROXY_ESTIMATE_TOKENS",
+            self.proxy.estimate_tokens.to_string(),
+        );
+        push(
+            "STRAINER_MISSING_METRIC_POLICY",
+            self.proxy.missing_metric_policy.to_string(),
+        );
+
+        vars
+    }
+
+    /// Render the configuration as `export` statements for a POSIX shell
+    #[must_use]
+    pub fn to_env_exports(&self, show_secrets: bool) -> String {
+        self.to_env_vars(show_secrets)
+            .into_iter()
+            .fold(String::new(), |mut out, (name, value)| {
+                let _ = writeln!(out, "export {name}='{}'", value.replace('\'', r"'\''"));
+                out
+            })
     }
 
     #[must_use]
@@ -401,6 +2232,279 @@ impl Config {
             backoff: BackoffConfig::default(),
             process: ProcessConfig::default(),
             logging: LoggingConfig::default(),
+            proxy: ProxyConfig::default(),
+            network: NetworkConfig::default(),
+            fallback_providers: Vec::new(),
+            commands: HashMap::new(),
+            schedule: Vec::new(),
+            routes: Vec::new(),
+        }
+    }
+}
+
+/// A field that differs between two configs, as found by [`Config::diff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDifference {
+    /// Dotted path of the field, e.g. `limits.requests_per_minute`
+    pub path: String,
+    /// Value in the first config, `None` if it is not set there
+    pub left: Option<String>,
+    /// Value in the second config, `None` if it is not set there
+    pub right: Option<String>,
+}
+
+impl std::fmt::Display for ConfigDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "(unset)".to_string());
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.path,
+            show(&self.left),
+            show(&self.right)
+        )
+    }
+}
+
+/// Collect the leaf values of `value` by dotted path, skipping nulls
+fn flatten_value(
+    path: String,
+    value: serde_json::Value,
+    out: &mut BTreeMap<String, serde_json::Value>,
+) {
+    let join = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+    match value {
+        serde_json::Value::Null => {}
+        serde_json::Value::Object(fields) => {
+            for (key, field) in fields {
+                flatten_value(join(&key), field, out);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for (index, item) in items.into_iter().enumerate() {
+                flatten_value(format!("{path}[{index}]"), item, out);
+            }
+        }
+        leaf => {
+            out.insert(path, leaf);
+        }
+    }
+}
+
+/// Prefix of the environment variables read by [`ConfigBuilder::from_env`]
+pub const DEFAULT_ENV_PREFIX: &str = "STRAINER_";
+
+/// How long fetching a config from a URL may take before giving up
+pub const CONFIG_FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether `source` is an `http(s)://` URL rather than a local path
+#[must_use]
+pub fn is_config_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Parse a fetched config as JSON or TOML
+///
+/// The content type decides when it names either format, otherwise the
+/// extension of the URL path does, defaulting to TOML.
+fn parse_fetched_config(url: &str, content_type: Option<&str>, body: &str) -> Result<Config> {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let is_json = match content_type {
+        Some(kind) if kind.contains("json") => true,
+        Some(kind) if kind.contains("toml") => false,
+        _ => Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json")),
+    };
+    if is_json {
+        serde_json::from_str(body).with_context(|| format!("Invalid JSON config from {url}"))
+    } else {
+        toml::from_str(body).with_context(|| format!("Invalid TOML config from {url}"))
+    }
+}
+
+/// Set the dotted `key` (e.g. `limits.requests_per_minute`) in the config file
+/// at `path` to `value`, keeping the file's comments and layout
+///
+/// `value` is read as a TOML value (`120`, `true`, `"text"`, `[1, 2]`), or as
+/// a string when it is not one. The file is only replaced once the edited
+/// config loads and validates.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read, parsed or written, `key` is
+/// not a config key or passes through a value that is not a table, or the
+/// edited config is invalid
+pub fn set_config_value(path: &Path, key: &str, value: &str) -> Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config {}", path.display()))?;
+    let mut document: toml_edit::DocumentMut = contents
+        .parse()
+        .with_context(|| format!("Invalid TOML config in {}", path.display()))?;
+    let value = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| toml_edit::Value::from(value));
+    set_document_value(&mut document, key, value)?;
+
+    // Load the edited file the way `run` would, includes and all, before replacing it
+    let edited = path.with_file_name(format!(
+        ".{}.edit",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    write_with_permissions_of(&edited, path, &document.to_string())
+        .with_context(|| format!("Failed to write config {}", edited.display()))?;
+    let checked = Config::builder()
+        .from_file(&edited)
+        .and_then(|builder| {
+            let config = builder.build()?;
+            let known = toml::Value::try_from(&config)?;
+            if lookup_key(&known, key).is_none() {
+                return Err(anyhow!("Unknown config key `{key}`"));
+            }
+            Ok(())
+        })
+        .and_then(|()| {
+            std::fs::rename(&edited, path)
+                .with_context(|| format!("Failed to write config {}", path.display()))
+        });
+    if checked.is_err() {
+        let _ = std::fs::remove_file(&edited);
+    }
+    checked
+}
+
+/// Write `contents` to a new file at `path` with the permissions of `original`
+///
+/// The file never has looser permissions than `original`, so a config kept
+/// private because it holds an API key stays private when replaced.
+fn write_with_permissions_of(path: &Path, original: &Path, contents: &str) -> Result<()> {
+    use std::io::Write as _;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let permissions = std::fs::metadata(original)?.permissions();
+    // A leftover from an interrupted edit would keep its own permissions
+    let _ = std::fs::remove_file(path);
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.set_permissions(permissions)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+/// Set the dotted `key` in `document`, creating missing tables and keeping
+/// the comments around a value it replaces
+fn set_document_value(
+    document: &mut toml_edit::DocumentMut,
+    key: &str,
+    mut value: toml_edit::Value,
+) -> Result<()> {
+    let segments: Vec<&str> = key.split('.').collect();
+    let Some((last, parents)) = segments.split_last() else {
+        return Err(anyhow!("Empty config key"));
+    };
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(anyhow!("Invalid config key `{key}`"));
+    }
+    let mut table: &mut dyn toml_edit::TableLike = document.as_table_mut();
+    for (depth, segment) in parents.iter().enumerate() {
+        table = table
+            .entry(segment)
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("`{}` is not a table", segments[..=depth].join(".")))?;
+    }
+    match table.get_mut(last) {
+        // Replacing the item in place keeps the comments on its key
+        Some(item) => {
+            if let Some(current) = item.as_value() {
+                *value.decor_mut() = current.decor().clone();
+            }
+            *item = toml_edit::Item::Value(value);
+        }
+        None => {
+            table.insert(last, toml_edit::Item::Value(value));
+        }
+    }
+    Ok(())
+}
+
+/// The value at the dotted `key` in `value`, if any
+fn lookup_key<'a>(value: &'a toml::Value, key: &str) -> Option<&'a toml::Value> {
+    key.split('.')
+        .try_fold(value, |value, segment| value.as_table()?.get(segment))
+}
+
+/// Key of a config file listing other config files to merge into it
+const INCLUDE_KEY: &str = "include";
+
+/// Read the config file at `path` as TOML with its includes merged in
+///
+/// Includes are resolved relative to the including file and merged over it in
+/// order, so a later file wins: tables are merged key by key, while any other
+/// value, including an array, is replaced. `chain` holds the files being read
+/// by the includes that led here, so a file including itself is an error.
+/// Every file read is added to `files`.
+fn read_config_table(
+    path: &Path,
+    chain: &mut Vec<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<toml::Table> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("Failed to read config {}", path.display()))?;
+    if let Some(start) = chain.iter().position(|seen| *seen == canonical) {
+        let cycle: Vec<String> = chain[start..]
+            .iter()
+            .chain([&canonical])
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(anyhow!("Config include cycle: {}", cycle.join(" -> ")));
+    }
+
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config {}", path.display()))?;
+    if !files.contains(&canonical) {
+        files.push(canonical.clone());
+    }
+    let mut table: toml::Table = toml::from_str(&contents)
+        .with_context(|| format!("Invalid TOML config in {}", path.display()))?;
+    let includes: Vec<PathBuf> = table
+        .remove(INCLUDE_KEY)
+        .map(toml::Value::try_into)
+        .transpose()
+        .with_context(|| format!("`include` in {} must be a list of paths", path.display()))?
+        .unwrap_or_default();
+
+    let dir = canonical.parent().unwrap_or_else(|| Path::new("."));
+    chain.push(canonical.clone());
+    for include in includes {
+        let overlay = read_config_table(&dir.join(&include), chain, files)
+            .with_context(|| format!("Failed to include config from {}", path.display()))?;
+        merge_tables(&mut table, overlay);
+    }
+    chain.pop();
+    Ok(table)
+}
+
+/// Merge `overlay` into `base`, recursing into tables present in both
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_tables(base, overlay);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
         }
     }
 }
@@ -411,6 +2515,69 @@ pub struct ConfigBuilder {
     config: Config,
 }
 
+/// Read the environment variable `name` under `prefix`, if set
+fn env_var(prefix: &str, name: &str) -> Option<String> {
+    env::var(format!("{prefix}{name}")).ok()
+}
+
+/// Parse the environment variable `name` under `prefix`, ignoring it if
+/// unset or invalid
+fn parse_env<T: std::str::FromStr>(prefix: &str, name: &str) -> Option<T> {
+    env_var(prefix, name)?.parse().ok()
+}
+
+/// Parse the environment variable `name` under `prefix` as a count that must
+/// be at least 1, failing with the variable's name if it is 0
+fn parse_positive_env(prefix: &str, name: &str) -> Result<Option<u32>> {
+    match parse_env(prefix, name) {
+        Some(0) => Err(anyhow!("{prefix}{name} must be greater than 0")),
+        value => Ok(value),
+    }
+}
+
+/// Parse the environment variable `name` under `prefix` as a rate such as
+/// `50k`, failing with the variable's name if it is invalid
+fn parse_rate_env(prefix: &str, name: &str) -> Result<Option<u32>> {
+    env_var(prefix, name)
+        .map(|value| parse_rate(&value).with_context(|| format!("Invalid {prefix}{name}")))
+        .transpose()
+}
+
+/// Check the threshold ordering `validate` enforces once any threshold has
+/// been set from the environment, so the error names the variables involved
+fn check_env_thresholds(prefix: &str, thresholds: &Thresholds) -> Result<()> {
+    let set: Vec<String> = [
+        "WARNING_THRESHOLD",
+        "CRITICAL_THRESHOLD",
+        "RESUME_THRESHOLD",
+    ]
+    .into_iter()
+    .filter(|name| env_var(prefix, name).is_some())
+    .map(|name| format!("{prefix}{name}"))
+    .collect();
+    if set.is_empty() {
+        return Ok(());
+    }
+    let set = set.join(", ");
+    let Thresholds {
+        warning,
+        critical,
+        resume,
+        ..
+    } = *thresholds;
+    if warning > critical {
+        return Err(anyhow!(
+            "Warning threshold {warning} must not exceed critical threshold {critical} (set by {set})"
+        ));
+    }
+    if resume >= warning {
+        return Err(anyhow!(
+            "Resume threshold {resume} must be less than warning threshold {warning} (set by {set})"
+        ));
+    }
+    Ok(())
+}
+
 impl ConfigBuilder {
     /// Create a new configuration builder with default values
     #[must_use]
@@ -422,50 +2589,107 @@ impl ConfigBuilder {
                     api_key: None,
                     base_url: None,
                     parameters: HashMap::default(),
+                    merge: MergeStrategy::Extend,
+                    remove_parameters: Vec::new(),
                 },
                 limits: RateLimits::default(),
                 thresholds: Thresholds::default(),
                 backoff: BackoffConfig::default(),
                 process: ProcessConfig::default(),
                 logging: LoggingConfig::default(),
+                proxy: ProxyConfig::default(),
+                network: NetworkConfig::default(),
+                fallback_providers: Vec::new(),
+                commands: HashMap::new(),
+                schedule: Vec::new(),
+                routes: Vec::new(),
             },
         }
     }
 
     /// Load configuration from a file
     ///
+    /// A file may pull in others with `include = ["limits.toml", ...]`, see
+    /// [`read_config_table`] for how they are resolved and merged.
+    ///
     /// # Errors
     ///
     /// Returns an error if:
-    /// - The file cannot be read
-    /// - The file contains invalid TOML
+    /// - The file or one of its includes cannot be read
+    /// - A file contains invalid TOML
+    /// - The includes form a cycle
     /// - The configuration is invalid
-    pub fn from_file(mut self, path: &PathBuf) -> Result<Self> {
-        let contents = std::fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&contents)?;
-        self.config = config;
+    pub fn from_file(mut self, path: &Path) -> Result<Self> {
+        let table = read_config_table(path, &mut Vec::new(), &mut Vec::new())?;
+        self.config = table
+            .try_into()
+            .with_context(|| format!("Invalid config in {}", path.display()))?;
+        Ok(self)
+    }
+
+    /// Load configuration from an `http(s)://` URL
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the URL if:
+    /// - The request fails or takes longer than `timeout`
+    /// - The server responds with an error status
+    /// - The response is not a valid JSON or TOML config
+    pub async fn from_url(mut self, url: &str, timeout: Duration) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .context("Failed to build HTTP client")?;
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| format!("Failed to fetch config from {url}"))?;
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = response
+            .text()
+            .await
+            .with_context(|| format!("Failed to read config from {url}"))?;
+        self.config = parse_fetched_config(url, content_type.as_deref(), &body)?;
         Ok(self)
     }
 
-    /// Load configuration from environment variables
+    /// Load configuration from `STRAINER_*` environment variables
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The environment variables contain invalid values
+    /// - The configuration is invalid
+    pub fn from_env(self) -> Result<Self> {
+        self.from_env_with_prefix(DEFAULT_ENV_PREFIX)
+    }
+
+    /// Load configuration from environment variables named with `prefix`
+    /// instead of `STRAINER_`, e.g. `MYAPP_API_KEY` for `MYAPP_`
     ///
     /// # Errors
     ///
     /// Returns an error if:
     /// - The environment variables contain invalid values
     /// - The configuration is invalid
-    pub fn from_env(mut self) -> Result<Self> {
+    pub fn from_env_with_prefix(mut self, prefix: &str) -> Result<Self> {
         // API Configuration
-        if let Ok(api_key) = env::var("STRAINER_API_KEY") {
+        if let Some(api_key) = env_var(prefix, "API_KEY") {
             self.config.api.api_key = Some(api_key);
         }
 
-        if let Ok(base_url) = env::var("STRAINER_BASE_URL") {
+        if let Some(base_url) = env_var(prefix, "BASE_URL") {
             self.config.api.base_url = Some(base_url);
         }
 
         // Provider Configuration
-        if let Ok(provider_type) = env::var("STRAINER_PROVIDER_TYPE") {
+        if let Some(provider_type) = env_var(prefix, "PROVIDER_TYPE") {
             self.config.api.provider_config = match provider_type.to_lowercase().as_str() {
                 "openai" => ProviderConfig::OpenAI(OpenAIConfig::default()),
                 "mock" => ProviderConfig::Mock(MockConfig::default()),
@@ -473,67 +2697,110 @@ impl ConfigBuilder {
             };
         }
 
-        if let Ok(model) = env::var("STRAINER_MODEL") {
+        if let Some(model) = env_var(prefix, "MODEL") {
             self = self.with_model(model);
         }
 
-        if let Ok(max_tokens) = env::var("STRAINER_MAX_TOKENS") {
-            if let Ok(tokens) = max_tokens.parse() {
-                self = self.with_max_tokens(tokens);
-            }
+        if let Some(tokens) = parse_positive_env(prefix, "MAX_TOKENS")? {
+            self = self.with_max_tokens(tokens);
         }
 
         // Rate Limits
-        if let Ok(rpm) = env::var("STRAINER_REQUESTS_PER_MINUTE") {
-            if let Ok(value) = rpm.parse() {
-                self.config.limits.requests_per_minute = Some(value);
-            }
+        if let Some(value) = parse_rate_env(prefix, "REQUESTS_PER_MINUTE")? {
+            self.config.limits.requests_per_minute = Some(value);
         }
 
-        if let Ok(tpm) = env::var("STRAINER_TOKENS_PER_MINUTE") {
-            if let Ok(value) = tpm.parse() {
-                self.config.limits.tokens_per_minute = Some(value);
-            }
+        if let Some(value) = parse_rate_env(prefix, "TOKENS_PER_MINUTE")? {
+            self.config.limits.tokens_per_minute = Some(value);
         }
 
-        if let Ok(itpm) = env::var("STRAINER_INPUT_TOKENS_PER_MINUTE") {
-            if let Ok(value) = itpm.parse() {
-                self.config.limits.input_tokens_per_minute = Some(value);
-            }
+        if let Some(value) = parse_rate_env(prefix, "INPUT_TOKENS_PER_MINUTE")? {
+            self.config.limits.input_tokens_per_minute = Some(value);
+        }
+
+        if let Some(value) = parse_rate_env(prefix, "OUTPUT_TOKENS_PER_MINUTE")? {
+            self.config.limits.output_tokens_per_minute = Some(value);
+        }
+
+        if let Some(value) = parse_rate_env(prefix, "ERROR_REQUESTS_PER_MINUTE")? {
+            self.config.limits.error_requests_per_minute = Some(value);
+        }
+
+        if let Some(value) = parse_env(prefix, "FRACTION_OF_QUOTA") {
+            self.config.limits.fraction_of_quota = Some(value);
+        }
+
+        if let Some(value) = parse_positive_env(prefix, "SHARED_INSTANCES")? {
+            self.config.limits.shared_instances = Some(value);
+        }
+
+        if let Some(value) = parse_env(prefix, "RAMP_UP_SECONDS") {
+            self.config.limits.ramp_up_seconds = Some(value);
+        }
+
+        if let Some(value) = parse_env(prefix, "FORCE_LIMITS") {
+            self.config.limits.force = value;
         }
 
         // Thresholds
-        if let Ok(warning) = env::var("STRAINER_WARNING_THRESHOLD") {
-            if let Ok(value) = warning.parse() {
-                self.config.thresholds.warning = value;
-            }
+        if let Some(value) = parse_env(prefix, "WARNING_THRESHOLD") {
+            self.config.thresholds.warning = value;
         }
 
-        if let Ok(critical) = env::var("STRAINER_CRITICAL_THRESHOLD") {
-            if let Ok(value) = critical.parse() {
-                self.config.thresholds.critical = value;
-            }
+        if let Some(value) = parse_env(prefix, "CRITICAL_THRESHOLD") {
+            self.config.thresholds.critical = value;
         }
 
-        if let Ok(resume) = env::var("STRAINER_RESUME_THRESHOLD") {
-            if let Ok(value) = resume.parse() {
-                self.config.thresholds.resume = value;
-            }
+        if let Some(value) = parse_env(prefix, "RESUME_THRESHOLD") {
+            self.config.thresholds.resume = value;
         }
+        check_env_thresholds(prefix, &self.config.thresholds)?;
 
-        // Process Control
-        if let Ok(pause_warning) = env::var("STRAINER_PAUSE_ON_WARNING") {
-            if let Ok(value) = pause_warning.parse() {
-                self.config.process.pause_on_warning = value;
-            }
+        if let Some(value) = parse_env(prefix, "FORECAST_HORIZON") {
+            self.config.thresholds.forecast_horizon_seconds = value;
         }
 
-        if let Ok(pause_critical) = env::var("STRAINER_PAUSE_ON_CRITICAL") {
-            if let Ok(value) = pause_critical.parse() {
-                self.config.process.pause_on_critical = value;
-            }
+        if let Some(value) = parse_env(prefix, "THRESHOLD_INCLUSIVE") {
+            self.config.thresholds.threshold_inclusive = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "GATING") {
+            self.config.thresholds.gating = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "ROUNDING") {
+            self.config.thresholds.rounding = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "STRICT_USAGE") {
+            self.config.thresholds.strict_usage = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "WARNING_SUSTAINED_SECONDS") {
+            self.config.thresholds.warning_sustained_seconds = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "PAUSE_ON_SUSTAINED_WARNING") {
+            self.config.thresholds.pause_on_sustained_warning = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "RESET_ON_RESUME") {
+            self.config.thresholds.reset_on_resume = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "LATENCY_BACKOFF_MS") {
+            self.config.thresholds.latency_backoff_ms = value;
+        }
+
+        if let Some(value) = parse_env(prefix, "PERCENT_PRECISION") {
+            self.config.thresholds.percent_precision = value;
         }
 
+        // Process Control
+        self.config.process.read_env(prefix)?;
+
+        self.config.proxy.read_env(prefix);
+
         Ok(self)
     }
 
@@ -550,18 +2817,19 @@ impl ConfigBuilder {
         match &mut self.config.api.provider_config {
             ProviderConfig::Anthropic(config) => config.model = model,
             ProviderConfig::OpenAI(config) => config.model = model,
-            ProviderConfig::Mock(_) => {}
+            ProviderConfig::Mock(_) | ProviderConfig::Aggregate(_) => {}
         }
         self
     }
 
     /// Set the maximum number of tokens
     #[must_use]
+    #[allow(clippy::missing_const_for_fn)] // the builder cannot be dropped in a const fn
     pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
         match &mut self.config.api.provider_config {
             ProviderConfig::Anthropic(config) => config.max_tokens = max_tokens,
             ProviderConfig::OpenAI(config) => config.max_tokens = max_tokens,
-            ProviderConfig::Mock(_) => {}
+            ProviderConfig::Mock(_) | ProviderConfig::Aggregate(_) => {}
         }
         self
     }
@@ -601,6 +2869,97 @@ impl ConfigBuilder {
         self
     }
 
+    /// Set output tokens per minute limit
+    #[must_use]
+    pub const fn with_output_tokens_per_minute(mut self, otpm: u32) -> Self {
+        self.config.limits.output_tokens_per_minute = Some(otpm);
+        self
+    }
+
+    /// Set failed requests per minute limit
+    #[must_use]
+    pub const fn with_error_requests_per_minute(mut self, erpm: u32) -> Self {
+        self.config.limits.error_requests_per_minute = Some(erpm);
+        self
+    }
+
+    /// Set the fraction of the provider's reported quota to use
+    #[must_use]
+    pub const fn with_fraction_of_quota(mut self, fraction: f64) -> Self {
+        self.config.limits.fraction_of_quota = Some(fraction);
+        self
+    }
+
+    /// Set the number of instances sharing the quota
+    #[must_use]
+    pub const fn with_shared_instances(mut self, instances: u32) -> Self {
+        self.config.limits.shared_instances = Some(instances);
+        self
+    }
+
+    /// Ramp every limit up from a tenth to its full value over `seconds`
+    #[must_use]
+    pub const fn with_ramp_up_seconds(mut self, seconds: u32) -> Self {
+        self.config.limits.ramp_up_seconds = Some(seconds);
+        self
+    }
+
+    /// Enforce only the configured limits, ignoring the provider's reported ones
+    #[must_use]
+    pub const fn with_force_limits(mut self, force: bool) -> Self {
+        self.config.limits.force = force;
+        self
+    }
+
+    /// Set how usage percentages are rounded
+    #[must_use]
+    pub const fn with_rounding(mut self, rounding: RoundingMode) -> Self {
+        self.config.thresholds.rounding = rounding;
+        self
+    }
+
+    /// Fail checks that see usage above 100% of a limit
+    #[must_use]
+    pub const fn with_strict_usage(mut self, strict: bool) -> Self {
+        self.config.thresholds.strict_usage = strict;
+        self
+    }
+
+    /// Alert once usage stays in the warning band for `seconds` (0 disables)
+    #[must_use]
+    pub const fn with_warning_sustained_seconds(mut self, seconds: u32) -> Self {
+        self.config.thresholds.warning_sustained_seconds = seconds;
+        self
+    }
+
+    /// Pause the process once the warning band has been sustained
+    #[must_use]
+    pub const fn with_pause_on_sustained_warning(mut self, pause: bool) -> Self {
+        self.config.thresholds.pause_on_sustained_warning = pause;
+        self
+    }
+
+    /// Zero recorded usage when usage drops to the resume threshold
+    #[must_use]
+    pub const fn with_reset_on_resume(mut self, reset: bool) -> Self {
+        self.config.thresholds.reset_on_resume = reset;
+        self
+    }
+
+    /// Back off while polling the provider takes longer than `millis` (0 disables)
+    #[must_use]
+    pub const fn with_latency_backoff_ms(mut self, millis: u64) -> Self {
+        self.config.thresholds.latency_backoff_ms = millis;
+        self
+    }
+
+    /// Log usage percentages with `places` decimal places
+    #[must_use]
+    pub const fn with_percent_precision(mut self, places: u8) -> Self {
+        self.config.thresholds.percent_precision = places;
+        self
+    }
+
     /// Set warning threshold
     #[must_use]
     pub const fn with_warning_threshold(mut self, threshold: u8) -> Self {
@@ -636,14 +2995,84 @@ impl ConfigBuilder {
         self
     }
 
-    /// Build and validate the final configuration
-    ///
-    /// # Errors
-    ///
-    /// This function will return an error if:
-    /// - The configuration is invalid
-    pub fn build(self) -> Result<Config> {
-        let config = self.config;
+    /// Set how long after start usage never pauses the process
+    #[must_use]
+    pub const fn with_warmup_seconds(mut self, seconds: u32) -> Self {
+        self.config.process.warmup_seconds = seconds;
+        self
+    }
+
+    /// Set how long after a resume usage never pauses the process again
+    #[must_use]
+    pub const fn with_post_resume_grace_seconds(mut self, seconds: u32) -> Self {
+        self.config.process.post_resume_grace_seconds = seconds;
+        self
+    }
+
+    /// Set the catchable signal tried before `SIGSTOP` when pausing
+    #[must_use]
+    pub fn with_pause_signal(mut self, signal: String) -> Self {
+        self.config.process.pause_signal = Some(signal);
+        self
+    }
+
+    /// Set the resident memory in bytes above which the process is paused
+    #[must_use]
+    pub const fn with_max_memory(mut self, bytes: u64) -> Self {
+        self.config.process.max_memory = Some(bytes);
+        self
+    }
+
+    /// Set the share of one core above which the process is paused
+    #[must_use]
+    pub const fn with_max_cpu_percent(mut self, percent: f64) -> Self {
+        self.config.process.max_cpu_percent = Some(percent);
+        self
+    }
+
+    /// Set what a run does when another run holds the lock on its API key
+    #[must_use]
+    pub const fn with_key_lock(mut self, key_lock: KeyLock) -> Self {
+        self.config.process.key_lock = key_lock;
+        self
+    }
+
+    /// Set where the child's standard streams are connected
+    #[must_use]
+    pub const fn with_stdio(mut self, stdio: StdioMode) -> Self {
+        self.config.process.stdio = stdio;
+        self
+    }
+
+    /// Enable or disable the local counting proxy
+    #[must_use]
+    pub const fn with_proxy(mut self, enabled: bool) -> Self {
+        self.config.proxy.enabled = enabled;
+        self
+    }
+
+    /// Estimate tokens for proxied calls that report no usage
+    #[must_use]
+    pub const fn with_estimate_tokens(mut self, estimate: bool) -> Self {
+        self.config.proxy.estimate_tokens = estimate;
+        self
+    }
+
+    /// Set how proxied responses missing a token count are counted
+    #[must_use]
+    pub const fn with_missing_metric_policy(mut self, policy: MissingMetricPolicy) -> Self {
+        self.config.proxy.missing_metric_policy = policy;
+        self
+    }
+
+    /// Build and validate the final configuration
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// - The configuration is invalid
+    pub fn build(self) -> Result<Config> {
+        let config = self.config;
         config.validate()?;
         Ok(config)
     }
@@ -660,6 +3089,331 @@ mod tests {
     use super::*;
     use crate::providers::config::{MockConfig, OpenAIConfig};
 
+    #[tokio::test]
+    async fn test_load_config_from_url() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        config.limits.requests_per_minute = Some(1000);
+        let toml_body = toml::to_string(&config).unwrap();
+        config.limits.tokens_per_minute = Some(500);
+        let json_body = serde_json::to_string(&config).unwrap();
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/strainer/config"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(toml_body, "application/toml"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/strainer/config.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(json_body))
+            .mount(&server)
+            .await;
+
+        let url = format!("{}/strainer/config", server.uri());
+        let config = ConfigBuilder::new()
+            .from_url(&url, CONFIG_FETCH_TIMEOUT)
+            .await
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(matches!(
+            config.api.provider_config,
+            ProviderConfig::Mock(_)
+        ));
+        assert_eq!(config.limits.requests_per_minute, Some(1000));
+
+        // Without a content type naming the format, the extension decides
+        let url = format!("{}/strainer/config.json", server.uri());
+        let config = ConfigBuilder::new()
+            .from_url(&url, CONFIG_FETCH_TIMEOUT)
+            .await
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(config.limits.tokens_per_minute, Some(500));
+
+        let url = format!("{}/missing.toml", server.uri());
+        let err = ConfigBuilder::new()
+            .from_url(&url, CONFIG_FETCH_TIMEOUT)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains(&url), "{err:#}");
+        assert!(format!("{err:#}").contains("404"), "{err:#}");
+    }
+
+    #[test]
+    fn test_diff_reports_only_changed_fields() {
+        let mut left = Config::default();
+        left.limits.requests_per_minute = Some(30);
+        let mut right = left.clone();
+        assert!(left.diff(&right, false).unwrap().is_empty());
+
+        right.limits.requests_per_minute = Some(60);
+        let lines: Vec<String> = left
+            .diff(&right, false)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(lines, ["limits.requests_per_minute: 30 -> 60"]);
+    }
+
+    #[test]
+    fn test_diff_redacts_api_keys() {
+        let mut left = Config::default();
+        left.api.api_key = Some("sk-left".to_string());
+        let mut right = left.clone();
+        right.api.api_key = Some("sk-right".to_string());
+        right.limits.input_tokens_per_minute = Some(100);
+
+        let diff = left.diff(&right, false).unwrap();
+        let lines: Vec<String> = diff.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "api.api_key: <redacted> -> <redacted>",
+                "limits.input_tokens_per_minute: (unset) -> 100",
+            ]
+        );
+
+        let diff = left.diff(&right, true).unwrap();
+        assert_eq!(diff[0].left.as_deref(), Some("\"sk-left\""));
+        assert_eq!(diff[0].right.as_deref(), Some("\"sk-right\""));
+    }
+
+    #[test]
+    fn test_is_config_url() {
+        assert!(is_config_url("https://config.internal/strainer.toml"));
+        assert!(is_config_url("http://10.0.0.1:8080/config"));
+        assert!(!is_config_url("strainer.toml"));
+        assert!(!is_config_url("/etc/strainer/https://x"));
+    }
+
+    #[tokio::test]
+    async fn test_network_client_uses_configured_proxy() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        // The mock server plays the corporate proxy; the upstream host does
+        // not resolve, so the request only succeeds if it goes via the proxy
+        let proxy = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/ping"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&proxy)
+            .await;
+
+        let network = NetworkConfig {
+            http_proxy: Some(proxy.uri()),
+            ..NetworkConfig::default()
+        };
+        let response = network
+            .http_client()
+            .unwrap()
+            .get("http://provider.invalid/v1/ping")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    /// A complete config file with a comment above the request limit
+    fn commented_config_file(dir: &Path) -> PathBuf {
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        config.limits.requests_per_minute = Some(30);
+        let contents = toml::to_string(&config).unwrap().replacen(
+            "requests_per_minute",
+            "# Shared with the nightly job\nrequests_per_minute",
+            1,
+        );
+        let path = dir.join("strainer.toml");
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_set_config_value_keeps_comments() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = commented_config_file(dir.path());
+
+        set_config_value(&path, "limits.requests_per_minute", "120")?;
+        set_config_value(&path, "network.user_agent", "my-pipeline/2")?;
+
+        let contents = std::fs::read_to_string(&path)?;
+        assert!(
+            contents.contains("# Shared with the nightly job\nrequests_per_minute = 120"),
+            "{contents}"
+        );
+        let config = Config::builder().from_file(&path)?.build()?;
+        assert_eq!(config.limits.requests_per_minute, Some(120));
+        assert_eq!(config.network.user_agent.as_deref(), Some("my-pipeline/2"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_config_value_keeps_permissions() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let path = commented_config_file(dir.path());
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+        set_config_value(&path, "limits.requests_per_minute", "120")?;
+
+        let mode = std::fs::metadata(&path)?.permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600, "{mode:o}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_config_value_rejects_unknown_and_invalid() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = commented_config_file(dir.path());
+        let before = std::fs::read_to_string(&path)?;
+
+        let err = set_config_value(&path, "limits.request_per_minute", "120").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Unknown config key `limits.request_per_minute`"),
+            "{err:#}"
+        );
+        assert!(set_config_value(&path, "thresholds.warning", "101").is_err());
+        assert!(set_config_value(&path, "limits.requests_per_minute.x", "1").is_err());
+        assert!(set_config_value(&path, "limits..x", "1").is_err());
+
+        assert_eq!(
+            std::fs::read_to_string(&path)?,
+            before,
+            "A rejected edit must not be written"
+        );
+        let leftovers: Vec<_> = std::fs::read_dir(dir.path())?.collect();
+        assert_eq!(leftovers.len(), 1, "{leftovers:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_network_client_rejects_bad_settings() {
+        let bad_proxy = NetworkConfig {
+            http_proxy: Some("not a url".to_string()),
+            ..NetworkConfig::default()
+        };
+        assert!(bad_proxy.http_client().is_err());
+
+        let missing_ca = NetworkConfig {
+            ca_cert_path: Some(PathBuf::from("/nonexistent/ca.pem")),
+            ..NetworkConfig::default()
+        };
+        let err = missing_ca.http_client().unwrap_err();
+        assert!(err.to_string().contains("Failed to read CA certificate"));
+
+        let bad_header = NetworkConfig {
+            request_id_header: Some("X Request ID".to_string()),
+            ..NetworkConfig::default()
+        };
+        let err = bad_header.http_client().unwrap_err();
+        assert!(
+            err.to_string().contains("Invalid request ID header"),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_network_client_sends_user_agent() {
+        use wiremock::matchers::header;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(header("user-agent", DEFAULT_USER_AGENT))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(header("user-agent", "my-pipeline/2"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(
+            DEFAULT_USER_AGENT,
+            format!("strainer/{}", env!("CARGO_PKG_VERSION"))
+        );
+        let response = NetworkConfig::default()
+            .http_client()
+            .unwrap()
+            .get(server.uri())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        let custom = NetworkConfig {
+            user_agent: Some("my-pipeline/2".to_string()),
+            ..NetworkConfig::default()
+        };
+        let response = custom
+            .http_client()
+            .unwrap()
+            .get(server.uri())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 204);
+    }
+
+    #[test]
+    fn test_network_section_parses() {
+        let mut config: Config =
+            toml::from_str(&toml::to_string(&Config::default()).unwrap()).unwrap();
+        assert!(config.network.http_proxy.is_none());
+        assert_eq!(
+            config.network.request_timeout(),
+            Some(DEFAULT_REQUEST_TIMEOUT)
+        );
+
+        config.network.request_timeout_seconds = 0;
+        config.network.http_proxy = Some("http://proxy.corp:3128".to_string());
+        config.network.ca_cert_path = Some(PathBuf::from("/etc/ssl/corp.pem"));
+        let parsed: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(
+            parsed.network.http_proxy.as_deref(),
+            Some("http://proxy.corp:3128")
+        );
+        assert_eq!(
+            parsed.network.ca_cert_path,
+            Some(PathBuf::from("/etc/ssl/corp.pem"))
+        );
+        assert_eq!(parsed.network.request_timeout(), None);
+    }
+
+    #[test]
+    fn test_paths_in_without_home_only_uses_cwd() {
+        let cwd = Path::new("/work/project");
+        assert_eq!(
+            Config::paths_in(cwd, None),
+            vec![PathBuf::from("/work/project/strainer.toml")]
+        );
+    }
+
+    #[test]
+    fn test_paths_in_with_home() {
+        let paths = Config::paths_in(Path::new("/work"), Some(Path::new("/home/user")));
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("/work/strainer.toml"),
+                PathBuf::from("/home/user/.config/strainer/config.toml"),
+                PathBuf::from("/home/user/.strainer.toml"),
+            ]
+        );
+    }
+
     #[test]
     fn test_config_validation() {
         // Test valid config
@@ -673,12 +3427,20 @@ mod tests {
                 api_key: Some("test-key".to_string()),
                 base_url: Some("https://api.openai.com/v1".to_string()),
                 parameters: HashMap::default(),
+                merge: MergeStrategy::Extend,
+                remove_parameters: Vec::new(),
             },
             limits: RateLimits::default(),
             thresholds: Thresholds::default(),
             backoff: BackoffConfig::default(),
             process: ProcessConfig::default(),
             logging: LoggingConfig::default(),
+            proxy: ProxyConfig::default(),
+            network: NetworkConfig::default(),
+            fallback_providers: Vec::new(),
+            commands: HashMap::new(),
+            schedule: Vec::new(),
+            routes: Vec::new(),
         };
 
         assert!(config.validate().is_ok());
@@ -688,6 +3450,314 @@ mod tests {
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_rounding_parses() {
+        let thresholds: Thresholds = toml::from_str("rounding = \"ceil\"").unwrap();
+        assert_eq!(thresholds.rounding, RoundingMode::Ceil);
+        let thresholds: Thresholds = toml::from_str("").unwrap();
+        assert_eq!(thresholds.rounding, RoundingMode::Floor);
+        assert_eq!(
+            "nearest".parse::<RoundingMode>().unwrap(),
+            RoundingMode::Nearest
+        );
+        assert!("up".parse::<RoundingMode>().is_err());
+    }
+
+    #[test]
+    fn test_gating_parses() {
+        let thresholds: Thresholds = toml::from_str("gating = \"all\"").unwrap();
+        assert_eq!(thresholds.gating, Gating::All);
+        let thresholds: Thresholds = toml::from_str("").unwrap();
+        assert_eq!(thresholds.gating, Gating::Any);
+        assert!(toml::from_str::<Thresholds>("gating = \"most\"").is_err());
+
+        assert_eq!("all".parse::<Gating>().unwrap(), Gating::All);
+        assert_eq!(Gating::Any.to_string(), "any");
+        assert!("most".parse::<Gating>().is_err());
+    }
+
+    #[test]
+    fn test_rounding_divide_near_max() {
+        for mode in [
+            RoundingMode::Floor,
+            RoundingMode::Ceil,
+            RoundingMode::Nearest,
+        ] {
+            assert_eq!(mode.divide(u64::MAX, u64::MAX), 1);
+            assert_eq!(mode.divide(u64::MAX, 1), u64::MAX);
+        }
+        assert_eq!(RoundingMode::Floor.divide(u64::MAX, 2), u64::MAX / 2);
+        assert_eq!(RoundingMode::Ceil.divide(u64::MAX, 2), u64::MAX / 2 + 1);
+        assert_eq!(RoundingMode::Nearest.divide(u64::MAX, 2), u64::MAX / 2 + 1);
+        assert_eq!(RoundingMode::Nearest.divide(u64::MAX - 1, 3), u64::MAX / 3);
+        assert_eq!(RoundingMode::Nearest.divide(7, 3), 2);
+        assert_eq!(RoundingMode::Nearest.divide(8, 3), 3);
+    }
+
+    #[test]
+    fn test_key_lock_parses() {
+        let process: ProcessConfig = toml::from_str("key_lock = \"wait\"").unwrap();
+        assert_eq!(process.key_lock, KeyLock::Wait);
+        let process: ProcessConfig = toml::from_str("").unwrap();
+        assert_eq!(process.key_lock, KeyLock::Off);
+        assert!(toml::from_str::<ProcessConfig>("key_lock = \"block\"").is_err());
+
+        assert_eq!("fail".parse::<KeyLock>().unwrap(), KeyLock::Fail);
+        assert_eq!(KeyLock::Wait.to_string(), "wait");
+        assert!("block".parse::<KeyLock>().is_err());
+    }
+
+    #[test]
+    fn test_stdio_mode_parses() {
+        let process: ProcessConfig = toml::from_str("stdio = \"null\"").unwrap();
+        assert_eq!(process.stdio, StdioMode::Null);
+        let process: ProcessConfig = toml::from_str("").unwrap();
+        assert_eq!(process.stdio, StdioMode::Inherit);
+        assert!(toml::from_str::<ProcessConfig>("stdio = \"file\"").is_err());
+
+        assert_eq!("pipe".parse::<StdioMode>().unwrap(), StdioMode::Pipe);
+        assert_eq!(StdioMode::Null.to_string(), "null");
+        assert!("file".parse::<StdioMode>().is_err());
+    }
+
+    #[test]
+    fn test_missing_metric_policy_parses() {
+        let proxy: ProxyConfig = toml::from_str("missing_metric_policy = \"assume_full\"").unwrap();
+        assert_eq!(proxy.missing_metric_policy, MissingMetricPolicy::AssumeFull);
+        let proxy: ProxyConfig = toml::from_str("").unwrap();
+        assert_eq!(proxy.missing_metric_policy, MissingMetricPolicy::Zero);
+        assert!(toml::from_str::<ProxyConfig>("missing_metric_policy = \"full\"").is_err());
+
+        assert_eq!(
+            "error".parse::<MissingMetricPolicy>().unwrap(),
+            MissingMetricPolicy::Error
+        );
+        assert_eq!(MissingMetricPolicy::AssumeFull.to_string(), "assume_full");
+        assert!("full".parse::<MissingMetricPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_human_duration_round_trips() {
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper {
+            duration: HumanDuration,
+        }
+
+        for (text, expected) in [
+            ("500ms", Duration::from_millis(500)),
+            ("1500ms", Duration::from_millis(1500)),
+            ("5s", Duration::from_secs(5)),
+            ("90s", Duration::from_secs(90)),
+            ("2m", Duration::from_mins(2)),
+            ("0s", Duration::ZERO),
+        ] {
+            let toml_text = format!("duration = \"{text}\"\n");
+            let parsed: Wrapper = toml::from_str(&toml_text).unwrap();
+            assert_eq!(parsed.duration.as_duration(), expected, "{text}");
+            assert_eq!(toml::to_string(&parsed).unwrap(), toml_text);
+        }
+
+        // Written in the largest exact unit, whatever the input
+        let parsed: Wrapper = toml::from_str("duration = \"60000ms\"").unwrap();
+        assert_eq!(parsed.duration.to_string(), "1m");
+
+        assert!(toml::from_str::<Wrapper>("duration = \"5h\"").is_err());
+        assert!(toml::from_str::<Wrapper>("duration = -1").is_err());
+    }
+
+    #[test]
+    fn test_backoff_accepts_legacy_integer_seconds() {
+        let legacy: BackoffConfig = toml::from_str("min_seconds = 2\nmax_seconds = 120").unwrap();
+        assert_eq!(legacy.min_backoff(), Duration::from_secs(2));
+        assert_eq!(legacy.max_backoff(), Duration::from_mins(2));
+
+        // Saved configs use the duration form, which reads back the same
+        let written = toml::to_string(&legacy).unwrap();
+        assert!(written.contains("max_seconds = \"2m\""), "{written}");
+        let reread: BackoffConfig = toml::from_str(&written).unwrap();
+        assert_eq!(reread.min_backoff(), legacy.min_backoff());
+        assert_eq!(reread.max_backoff(), legacy.max_backoff());
+
+        let human: BackoffConfig =
+            toml::from_str("min_seconds = \"250ms\"\nmax_seconds = \"2m\"").unwrap();
+        assert_eq!(human.min_backoff(), Duration::from_millis(250));
+        assert_eq!(human.max_backoff(), Duration::from_mins(2));
+    }
+
+    #[test]
+    fn test_validate_compares_sub_second_backoff() {
+        let millis: BackoffConfig =
+            toml::from_str("min_seconds = \"200ms\"\nmax_seconds = \"1500ms\"").unwrap();
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        config.backoff = millis;
+        assert!(config.validate().is_ok());
+        config.backoff.max_seconds = HumanDuration::from_millis(100);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_zero_shared_instances() {
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        config.limits.shared_instances = Some(1);
+        assert!(config.validate().is_ok());
+
+        config.limits.shared_instances = Some(0);
+        let err = config.validate().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Shared instances must be at least 1"));
+    }
+
+    #[test]
+    fn test_validate_allows_warning_equal_to_critical() {
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        config.thresholds.warning = 90;
+        config.thresholds.critical = 90;
+        assert!(config.validate().is_ok());
+
+        config.thresholds.warning = 91;
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("must not exceed critical"));
+    }
+
+    fn named_commands_config() -> Config {
+        let toml = r#"
+            [api]
+            type = "mock"
+
+            [limits]
+            requests_per_minute = 100
+
+            [thresholds]
+            [backoff]
+            [process]
+            [logging]
+            level = "info"
+            format = "text"
+
+            [commands.nightly-sync]
+            command = ["sync.sh", "--all"]
+            limits = { requests_per_minute = 10 }
+
+            [commands.report]
+            command = ["report.sh"]
+            thresholds = { warning = 40, critical = 60, resume = 20 }
+        "#;
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn test_for_command_applies_overrides() {
+        let config = named_commands_config();
+
+        let (sync, command) = config.for_command("nightly-sync").unwrap();
+        assert_eq!(command, vec!["sync.sh", "--all"]);
+        assert_eq!(sync.limits.requests_per_minute, Some(10));
+        assert_eq!(sync.thresholds.critical, default_critical_threshold());
+
+        let (report, command) = config.for_command("report").unwrap();
+        assert_eq!(command, vec!["report.sh"]);
+        assert_eq!(report.limits.requests_per_minute, Some(100));
+        assert_eq!(report.thresholds.critical, 60);
+        assert_eq!(report.thresholds.resume, 20);
+    }
+
+    #[test]
+    fn test_for_command_unknown_lists_available() {
+        let err = named_commands_config()
+            .for_command("weekly")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Unknown command 'weekly'"));
+        assert!(err.contains("nightly-sync, report"));
+
+        let err = Config::default().for_command("weekly").unwrap_err();
+        assert!(err.to_string().contains("no commands are defined"));
+    }
+
+    fn routed_config() -> Config {
+        let mut config = named_commands_config();
+        config.routes = toml::from_str::<HashMap<String, Vec<Route>>>(
+            r#"
+            [[routes]]
+            program = "summarize.py"
+            api = { type = "anthropic", api_key = "anthropic-key", model = "claude-route" }
+
+            [[routes]]
+            pattern = "--model gpt-"
+            api = { type = "openai", api_key = "${OPENAI_ROUTE_KEY}", model = "gpt-route" }
+            "#,
+        )
+        .unwrap()
+        .remove("routes")
+        .unwrap();
+        config
+    }
+
+    #[test]
+    fn test_routes_pick_provider_by_command() {
+        let config = routed_config();
+        assert!(config.validate().is_ok());
+        let provider = |command: &[&str]| {
+            let command: Vec<String> = command.iter().map(ToString::to_string).collect();
+            config.routed(&command).unwrap().api.target()
+        };
+
+        assert!(provider(&["./tools/summarize.py", "in.txt"]).contains("model=claude-route"));
+        assert!(provider(&["summarize.py"]).contains("provider=anthropic"));
+        assert!(provider(&["python", "chat.py", "--model", "gpt-4o"]).contains("model=gpt-route"));
+        // Unmatched commands keep the base provider
+        assert!(provider(&["python", "summarize.py"]).contains("provider=mock"));
+        assert!(provider(&[]).contains("provider=mock"));
+
+        // The first matching route wins
+        let both = ["summarize.py", "--model", "gpt-4o"].map(String::from);
+        let routed = config.routed(&both).unwrap();
+        assert_eq!(routed.api.api_key.as_deref(), Some("anthropic-key"));
+        assert_eq!(routed.limits.requests_per_minute, Some(100));
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_routes() {
+        let mut config = routed_config();
+        config.routes[1].pattern = Some("(unclosed".to_string());
+        let err = config.validate().unwrap_err();
+        assert!(
+            format!("{err:#}").contains("Invalid pattern for route 1"),
+            "{err:#}"
+        );
+
+        let mut config = routed_config();
+        config.routes[0].program = None;
+        let err = config.validate().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Route 0 must set a program or a pattern"));
+
+        let mut config = routed_config();
+        config.routes[0].api.api_key = None;
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("API key is required"), "{err}");
+    }
+
+    #[test]
+    fn test_fraction_of_quota_validation() {
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+
+        for valid in [0.5, 1.0] {
+            config.limits.fraction_of_quota = Some(valid);
+            assert!(config.validate().is_ok(), "{valid} should be accepted");
+        }
+        for invalid in [0.0, -0.5, 1.5, f64::NAN] {
+            config.limits.fraction_of_quota = Some(invalid);
+            assert!(config.validate().is_err(), "{invalid} should be rejected");
+        }
+    }
+
     #[test]
     fn test_config_merge() {
         let mut base = Config::default();
@@ -701,11 +3771,20 @@ mod tests {
                 api_key: Some("other-key".to_string()),
                 base_url: Some("http://test.local".to_string()),
                 parameters: HashMap::default(),
+                merge: MergeStrategy::Extend,
+                remove_parameters: Vec::new(),
             },
             limits: RateLimits {
                 requests_per_minute: Some(120),
                 tokens_per_minute: Some(100_000),
                 input_tokens_per_minute: Some(50_000),
+                output_tokens_per_minute: Some(20_000),
+                error_requests_per_minute: Some(10),
+                fraction_of_quota: Some(0.5),
+                shared_instances: None,
+                ramp_up_seconds: None,
+                force: false,
+                by_model: HashMap::new(),
             },
             ..Default::default()
         };
@@ -735,6 +3814,519 @@ mod tests {
         assert_eq!(base.limits.requests_per_minute, Some(120));
         assert_eq!(base.limits.tokens_per_minute, Some(100_000));
         assert_eq!(base.limits.input_tokens_per_minute, Some(50_000));
+        assert_eq!(base.limits.output_tokens_per_minute, Some(20_000));
+        assert_eq!(base.limits.error_requests_per_minute, Some(10));
+        assert_eq!(base.limits.fraction_of_quota, Some(0.5));
+    }
+
+    #[test]
+    fn test_resolved_api_key() {
+        let mut api = ApiConfig {
+            api_key: Some("plain-key".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(api.resolved_api_key(), Some("plain-key".to_string()));
+
+        api.api_key = Some("${STRAINER_TEST_RESOLVED_KEY}".to_string());
+        env::set_var("STRAINER_TEST_RESOLVED_KEY", "from-env");
+        assert_eq!(api.resolved_api_key(), Some("from-env".to_string()));
+        env::remove_var("STRAINER_TEST_RESOLVED_KEY");
+        assert_eq!(api.resolved_api_key(), None);
+    }
+
+    #[test]
+    fn test_resolved_api_key_from_keyring() {
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+        let mut api = ApiConfig {
+            api_key: Some("keyring:strainer/missing".to_string()),
+            ..Default::default()
+        };
+        // Mock entries start empty, so a missing key resolves to nothing
+        assert_eq!(api.resolved_api_key(), None);
+
+        api.api_key = Some("keyring:no-account".to_string());
+        assert_eq!(api.resolved_api_key(), None);
+    }
+
+    #[test]
+    fn test_target_names_provider_model_and_base_url() {
+        let mut api = ApiConfig {
+            provider_config: ProviderConfig::Anthropic(AnthropicConfig {
+                model: "claude-test".to_string(),
+                ..Default::default()
+            }),
+            api_key: Some("sk-secret".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            api.target(),
+            "provider=anthropic model=claude-test base_url=https://api.anthropic.com/v1"
+        );
+
+        api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        api.base_url = Some("http://localhost:8080".to_string());
+        assert_eq!(
+            api.target(),
+            "provider=mock model=- base_url=http://localhost:8080"
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_malformed_keyring_reference() {
+        let mut config = Config::default();
+        config.api.api_key = Some("keyring:strainer".to_string());
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("service/account"), "{err}");
+
+        config.api.api_key = Some("keyring:strainer/anthropic".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_pause_signal() {
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        config.process.pause_signal = Some("SIGTSTP".to_string());
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.process.pause_signal().unwrap(),
+            Some(Signal::SIGTSTP)
+        );
+
+        config.process.pause_signal = Some("SIGNOPE".to_string());
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("Unknown pause signal"), "{err}");
+
+        config.process.pause_signal = Some("SIGKILL".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_merge_process_spawn_settings() {
+        let mut base = Config::default();
+        base.process.env.insert("A".to_string(), "1".to_string());
+
+        let mut other = Config::default();
+        other.process.working_dir = Some(PathBuf::from("/tmp"));
+        other.process.env.insert("B".to_string(), "2".to_string());
+        other.process.api_key_env = Some("ANTHROPIC_API_KEY".to_string());
+
+        base.merge(other);
+        assert_eq!(base.process.working_dir, Some(PathBuf::from("/tmp")));
+        assert_eq!(base.process.env.len(), 2);
+        assert_eq!(
+            base.process.api_key_env,
+            Some("ANTHROPIC_API_KEY".to_string())
+        );
+    }
+
+    #[test]
+    fn test_spawn_retry_settings() {
+        let process: ProcessConfig = toml::from_str("spawn_retries = 3").unwrap();
+        assert_eq!(process.spawn_retries, 3);
+        assert_eq!(process.spawn_retry_delay_ms, 1000);
+
+        let mut base = Config::default();
+        let mut other = Config::default();
+        other.process.spawn_retries = 5;
+        other.process.spawn_retry_delay_ms = 250;
+        base.merge(other);
+        assert_eq!(base.process.spawn_retries, 5);
+        assert_eq!(base.process.spawn_retry_delay_ms, 250);
+    }
+
+    #[test]
+    fn test_on_critical_command() {
+        let process: ProcessConfig =
+            toml::from_str(r#"on_critical_command = ["touch", "fallback"]"#).unwrap();
+        let command = vec!["touch".to_string(), "fallback".to_string()];
+        assert_eq!(process.on_critical_command.as_ref(), Some(&command));
+
+        let mut config = Config::default();
+        config.merge(Config {
+            process,
+            ..Config::default()
+        });
+        assert_eq!(config.process.on_critical_command, Some(command));
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        assert!(config.validate().is_ok());
+
+        config.process.on_critical_command = Some(Vec::new());
+        let err = config.validate().unwrap_err();
+        assert_eq!(err.to_string(), "On-critical command must not be empty");
+    }
+
+    /// A mock config whose provider parameters are `pairs`
+    fn with_parameters(pairs: &[(&str, &str)]) -> Config {
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::Mock(MockConfig {
+            parameters: pairs
+                .iter()
+                .map(|(k, v)| ((*k).to_string(), serde_json::json!(v)))
+                .collect(),
+            ..MockConfig::default()
+        });
+        config
+    }
+
+    fn parameters(config: &Config) -> Vec<(String, String)> {
+        let ProviderConfig::Mock(mock) = &config.api.provider_config else {
+            panic!("expected a mock provider");
+        };
+        let mut pairs: Vec<_> = mock
+            .parameters
+            .iter()
+            .map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string()))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_extends_parameters() {
+        let mut base = with_parameters(&[("a", "1"), ("b", "2")]);
+        base.merge(with_parameters(&[("b", "3"), ("c", "4")]));
+        assert_eq!(
+            parameters(&base),
+            pairs(&[("a", "1"), ("b", "3"), ("c", "4")])
+        );
+    }
+
+    #[test]
+    fn test_merge_replaces_parameters() {
+        let mut base = with_parameters(&[("a", "1"), ("b", "2")]);
+        let mut overlay = with_parameters(&[("c", "4")]);
+        overlay.api.merge = MergeStrategy::Replace;
+        base.merge(overlay);
+        assert_eq!(parameters(&base), pairs(&[("c", "4")]));
+    }
+
+    #[test]
+    fn test_merge_removes_parameters() {
+        let mut base = with_parameters(&[("a", "1"), ("b", "2")]);
+        let mut overlay = with_parameters(&[("c", "4")]);
+        overlay.api.remove_parameters = vec!["a".to_string(), "missing".to_string()];
+        base.merge(overlay);
+        assert_eq!(parameters(&base), pairs(&[("b", "2"), ("c", "4")]));
+    }
+
+    #[test]
+    fn test_aggregate_provider_parses_and_round_trips() {
+        let toml_str = r#"
+[api]
+type = "aggregate"
+
+[[api.providers]]
+type = "anthropic"
+api_key = "${KEY_A}"
+
+[[api.providers]]
+type = "mock"
+"#;
+        let config = Config {
+            api: toml::from_str::<HashMap<String, ApiConfig>>(toml_str)
+                .unwrap()
+                .remove("api")
+                .unwrap(),
+            ..Config::default()
+        };
+        let ProviderConfig::Aggregate(aggregate) = &config.api.provider_config else {
+            panic!("expected an aggregate provider");
+        };
+        assert_eq!(aggregate.providers.len(), 2);
+        assert_eq!(aggregate.providers[0].api_key.as_deref(), Some("${KEY_A}"));
+        assert!(config.validate().is_ok());
+
+        let parsed: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        let ProviderConfig::Aggregate(parsed) = parsed.api.provider_config else {
+            panic!("expected an aggregate provider");
+        };
+        assert_eq!(parsed.providers.len(), 2);
+
+        let mut missing_key = config;
+        if let ProviderConfig::Aggregate(aggregate) = &mut missing_key.api.provider_config {
+            aggregate.providers[0].api_key = None;
+        }
+        assert!(missing_key.validate().is_err());
+    }
+
+    #[test]
+    fn test_merge_policy_parses_and_round_trips() {
+        let config: Config = toml::from_str(&toml::to_string(&Config::default()).unwrap().replace(
+            "[api]\n",
+            "[api]\nmerge = \"replace\"\nremove_parameters = [\"temperature\"]\n",
+        ))
+        .unwrap();
+        assert_eq!(config.api.merge, MergeStrategy::Replace);
+        assert_eq!(
+            config.api.remove_parameters,
+            vec!["temperature".to_string()]
+        );
+
+        let parsed: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(parsed.api.merge, MergeStrategy::Replace);
+        assert_eq!(parsed.api.remove_parameters, config.api.remove_parameters);
+
+        let default = toml::to_string(&Config::default()).unwrap();
+        assert!(!default.contains("merge"), "{default}");
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("50000").unwrap(), 50_000);
+        assert_eq!(parse_rate("50k").unwrap(), 50_000);
+        assert_eq!(parse_rate("50K").unwrap(), 50_000);
+        assert_eq!(parse_rate("1.5M").unwrap(), 1_500_000);
+        assert_eq!(parse_rate("2.25k").unwrap(), 2_250);
+        assert_eq!(parse_rate("100/min").unwrap(), 100);
+        assert_eq!(parse_rate(" 10k / minute ").unwrap(), 10_000);
+        assert_eq!(parse_rate("4294967295").unwrap(), u32::MAX);
+
+        for invalid in [
+            "",
+            "k",
+            "abc",
+            "-5",
+            "1.",
+            ".5",
+            "1.2.3",
+            "1.2345k",
+            "0.5",
+            "5m",
+            "5G",
+            "10/s",
+            "1e3",
+            "4294967296",
+            "5000M",
+        ] {
+            assert!(
+                parse_rate(invalid).is_err(),
+                "{invalid:?} should be rejected"
+            );
+        }
+        let err = parse_rate("5m").unwrap_err();
+        assert!(err.to_string().contains("ambiguous"), "{err}");
+        let err = parse_rate("10/hour").unwrap_err();
+        assert!(err.to_string().contains("per-minute"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(parse_memory("536870912").unwrap(), 536_870_912);
+        assert_eq!(parse_memory("512M").unwrap(), 512 << 20);
+        assert_eq!(parse_memory("512MiB").unwrap(), 512 << 20);
+        assert_eq!(parse_memory(" 2GB ").unwrap(), 2 << 30);
+        assert_eq!(parse_memory("64k").unwrap(), 64 << 10);
+        for invalid in ["", "M", "1.5G", "-1", "5T", "99999999999999999999G"] {
+            assert!(
+                parse_memory(invalid).is_err(),
+                "{invalid:?} should be rejected"
+            );
+        }
+
+        let config: ProcessConfig = toml::from_str("max_memory = \"1G\"").unwrap();
+        assert_eq!(config.max_memory, Some(1 << 30));
+        let config: ProcessConfig = toml::from_str("max_memory = 4096").unwrap();
+        assert_eq!(config.resource_limits().max_memory, Some(4096));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("5s").unwrap(), Duration::from_secs(5));
+        assert_eq!(parse_duration(" 7 ").unwrap(), Duration::from_secs(7));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_mins(2));
+        for invalid in ["", "s", "1.5s", "-1s", "5h", "99999999999999999999m"] {
+            assert!(
+                parse_duration(invalid).is_err(),
+                "{invalid:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_kill_timeout_defaults_to_terminate_grace() {
+        let mut process = ProcessConfig::default();
+        assert_eq!(process.kill_timeout(), TERMINATE_GRACE);
+        process.kill_timeout_ms = Some(2500);
+        assert_eq!(process.kill_timeout(), Duration::from_millis(2500));
+    }
+
+    #[test]
+    fn test_rate_limits_accept_rate_strings() {
+        let limits: RateLimits = toml::from_str(
+            r#"
+requests_per_minute = "100/min"
+tokens_per_minute = "1.5M"
+input_tokens_per_minute = 50000
+"#,
+        )
+        .unwrap();
+        assert_eq!(limits.requests_per_minute, Some(100));
+        assert_eq!(limits.tokens_per_minute, Some(1_500_000));
+        assert_eq!(limits.input_tokens_per_minute, Some(50_000));
+        assert_eq!(limits.output_tokens_per_minute, None);
+
+        let err = toml::from_str::<RateLimits>("tokens_per_minute = \"lots\"").unwrap_err();
+        assert!(err.to_string().contains("Invalid rate 'lots'"), "{err}");
+    }
+
+    #[test]
+    fn test_limits_by_model() {
+        let limits = toml::from_str(
+            r#"
+requests_per_minute = 100
+tokens_per_minute = 5000
+
+[by_model."claude-3-opus"]
+requests_per_minute = 10
+
+[by_model."claude-3-haiku"]
+requests_per_minute = "1k"
+tokens_per_minute = 90000
+"#,
+        )
+        .unwrap();
+        let mut config = Config {
+            limits,
+            ..Config::default()
+        };
+        let active = |config: &Config, model: &str| {
+            let mut config = config.clone();
+            config.api.provider_config = ProviderConfig::Anthropic(AnthropicConfig {
+                model: model.to_string(),
+                ..AnthropicConfig::default()
+            });
+            let limits = config.active_limits();
+            (limits.requests_per_minute, limits.tokens_per_minute)
+        };
+        assert_eq!(active(&config, "claude-3-opus"), (Some(10), Some(5000)));
+        assert_eq!(active(&config, "claude-3-haiku"), (Some(1000), Some(90000)));
+        assert_eq!(active(&config, "claude-2"), (Some(100), Some(5000)));
+
+        let parsed: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(parsed.limits.by_model, config.limits.by_model);
+
+        // An overlay's entry replaces the whole entry for its model
+        let mut overlay = Config::default();
+        overlay.limits.by_model.insert(
+            "claude-3-opus".to_string(),
+            ModelLimits {
+                tokens_per_minute: Some(2000),
+                ..ModelLimits::default()
+            },
+        );
+        config.merge(overlay);
+        assert_eq!(active(&config, "claude-3-opus"), (Some(100), Some(2000)));
+        assert_eq!(active(&config, "claude-3-haiku"), (Some(1000), Some(90000)));
+    }
+
+    #[test]
+    fn test_schedule_parses_and_validates() {
+        let mut config: Config = toml::from_str(&format!(
+            "{}{}",
+            toml::to_string(&Config::default()).unwrap(),
+            r"
+[[schedule]]
+start_hour = 22
+end_hour = 6
+
+[schedule.limits]
+requests_per_minute = 200
+
+[[schedule]]
+start_hour = 9
+end_hour = 17
+
+[schedule.thresholds]
+warning = 50
+critical = 60
+resume = 40
+"
+        ))
+        .unwrap();
+        assert_eq!(config.schedule.len(), 2);
+        assert_eq!(
+            config.schedule[0]
+                .limits
+                .as_ref()
+                .unwrap()
+                .requests_per_minute,
+            Some(200)
+        );
+        assert_eq!(config.schedule[1].thresholds.as_ref().unwrap().critical, 60);
+        assert_eq!(config.schedule[1].to_string(), "09:00-17:00 UTC");
+        config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+        assert!(config.validate().is_ok());
+
+        let parsed: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(parsed.schedule.len(), 2);
+
+        config.schedule[1].end_hour = 9;
+        assert!(config.validate().is_err());
+        config.schedule[1].end_hour = 24;
+        assert!(config.validate().is_err());
+
+        // A window's thresholds replace the base ones, so they must be in order
+        config.schedule[1].end_hour = 17;
+        config.schedule[1].thresholds.as_mut().unwrap().warning = 70;
+        let err = config.validate().unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Invalid thresholds for schedule window 09:00-17:00 UTC: \
+             Warning threshold must not exceed critical threshold"
+        );
+    }
+
+    #[test]
+    fn test_active_window_wraps_midnight() {
+        let window = |start_hour, end_hour| ScheduleWindow {
+            start_hour,
+            end_hour,
+            limits: None,
+            thresholds: None,
+        };
+        let at_hour = |hour: u64| UNIX_EPOCH + Duration::from_secs(hour * 3600 + 59);
+        let schedule = vec![window(22, 6), window(0, 12)];
+
+        assert!(schedule[0].contains(23));
+        assert!(schedule[0].contains(0));
+        assert!(!schedule[0].contains(6));
+        assert!(!schedule[0].contains(21));
+        // The first matching window wins where windows overlap
+        assert_eq!(active_window(&schedule, at_hour(2)), Some(0));
+        assert_eq!(active_window(&schedule, at_hour(24 + 8)), Some(1));
+        assert_eq!(active_window(&schedule, at_hour(15)), None);
+        assert_eq!(active_window(&[], at_hour(2)), None);
+    }
+
+    #[test]
+    fn test_to_env_vars_redacts_api_key() {
+        let mut config = Config::default();
+        config.api.api_key = Some("secret".to_string());
+
+        let vars = config.to_env_vars(false);
+        assert!(!vars.iter().any(|(name, _)| name == "STRAINER_API_KEY"));
+
+        let vars = config.to_env_vars(true);
+        assert!(vars.contains(&("STRAINER_API_KEY".to_string(), "secret".to_string())));
+    }
+
+    #[test]
+    fn test_to_env_exports_quotes_values() {
+        let mut config = Config::default();
+        config.api.base_url = Some("http://it's.local".to_string());
+        config.limits.requests_per_minute = Some(30);
+        let exports = config.to_env_exports(false);
+        assert!(exports.contains("export STRAINER_BASE_URL='http://it'\\''s.local'\n"));
+        assert!(exports.contains("export STRAINER_REQUESTS_PER_MINUTE='30'\n"));
     }
 
     #[test]
@@ -752,19 +4344,30 @@ mod tests {
             api: ApiConfig {
                 provider_config: ProviderConfig::Mock(MockConfig {
                     parameters: HashMap::default(),
-                    requests_per_minute: 100,
-                    tokens_per_minute: 1000,
-                    input_tokens_per_minute: 500,
+                    requests_per_minute: Some(100),
+                    tokens_per_minute: Some(1000),
+                    input_tokens_per_minute: Some(500),
+                    output_tokens_per_minute: None,
+                    requests_used: 0,
+                    delay_ms: None,
                 }),
                 api_key: None,
                 base_url: None,
                 parameters: HashMap::default(),
+                merge: MergeStrategy::Extend,
+                remove_parameters: Vec::new(),
             },
             limits: RateLimits::default(),
             thresholds: Thresholds::default(),
             backoff: BackoffConfig::default(),
             process: ProcessConfig::default(),
             logging: LoggingConfig::default(),
+            proxy: ProxyConfig::default(),
+            network: NetworkConfig::default(),
+            fallback_providers: Vec::new(),
+            commands: HashMap::new(),
+            schedule: Vec::new(),
+            routes: Vec::new(),
         };
         assert!(matches!(
             config.api.provider_config,
@@ -0,0 +1,120 @@
+use anyhow::{anyhow, Context, Result};
+use keyring::Entry;
+use std::fmt;
+use std::str::FromStr;
+
+/// Prefix marking an `api_key` stored in the OS keyring, as in `keyring:service/account`
+pub const KEYRING_PREFIX: &str = "keyring:";
+
+/// Service and account naming a secret in the OS keyring
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyringRef {
+    pub service: String,
+    pub account: String,
+}
+
+impl KeyringRef {
+    /// Create a reference, rejecting names that would not round-trip through `service/account`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either name is empty or the service contains `/`
+    pub fn new(service: &str, account: &str) -> Result<Self> {
+        if service.is_empty() || account.is_empty() {
+            return Err(anyhow!("Keyring service and account must not be empty"));
+        }
+        if service.contains('/') {
+            return Err(anyhow!("Keyring service must not contain '/'"));
+        }
+        Ok(Self {
+            service: service.to_string(),
+            account: account.to_string(),
+        })
+    }
+
+    /// Open the keyring entry this reference names
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the platform keyring rejects the entry
+    pub fn entry(&self) -> Result<Entry> {
+        Entry::new(&self.service, &self.account)
+            .with_context(|| format!("Failed to open keyring entry {self}"))
+    }
+}
+
+impl FromStr for KeyringRef {
+    type Err = anyhow::Error;
+
+    /// Parse `service/account`, without the `keyring:` prefix
+    fn from_str(s: &str) -> Result<Self> {
+        let (service, account) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Keyring reference must be service/account, got '{s}'"))?;
+        Self::new(service, account)
+    }
+}
+
+impl fmt::Display for KeyringRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{KEYRING_PREFIX}{}/{}", self.service, self.account)
+    }
+}
+
+/// Read a key from a keyring entry
+///
+/// # Errors
+///
+/// Returns an error if the entry does not exist or the keyring is unavailable
+pub fn read_key(entry: &Entry) -> Result<String> {
+    entry
+        .get_password()
+        .context("Failed to read API key from the keyring")
+}
+
+/// Store a key in a keyring entry, replacing any existing key
+///
+/// # Errors
+///
+/// Returns an error if the keyring is unavailable
+pub fn store_key(entry: &Entry, key: &str) -> Result<()> {
+    entry
+        .set_password(key)
+        .context("Failed to store API key in the keyring")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_entry(reference: &KeyringRef) -> Entry {
+        let credential = keyring::mock::default_credential_builder()
+            .build(None, &reference.service, &reference.account)
+            .unwrap();
+        Entry::new_with_credential(credential)
+    }
+
+    #[test]
+    fn test_parse_keyring_reference() {
+        let reference: KeyringRef = "strainer/anthropic/work".parse().unwrap();
+        assert_eq!(reference.service, "strainer");
+        assert_eq!(reference.account, "anthropic/work");
+        assert_eq!(reference.to_string(), "keyring:strainer/anthropic/work");
+
+        assert!("strainer".parse::<KeyringRef>().is_err());
+        assert!("/account".parse::<KeyringRef>().is_err());
+        assert!("strainer/".parse::<KeyringRef>().is_err());
+        assert!(KeyringRef::new("a/b", "c").is_err());
+    }
+
+    #[test]
+    fn test_store_and_read_key() {
+        let entry = mock_entry(&KeyringRef::new("strainer", "anthropic").unwrap());
+        assert!(read_key(&entry).is_err());
+
+        store_key(&entry, "sk-first").unwrap();
+        assert_eq!(read_key(&entry).unwrap(), "sk-first");
+        store_key(&entry, "sk-second").unwrap();
+        assert_eq!(read_key(&entry).unwrap(), "sk-second");
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt;
+use std::io;
+
+/// Exit codes reported by the `strainer` binary
+///
+/// The values follow shell and `sysexits.h` conventions so orchestrators can
+/// tell a bad config or a missing command from a transient rate-limit failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    /// Everything succeeded (0)
+    Success,
+    /// Any failure without a more specific code (1)
+    Error,
+    /// The configuration or command line is invalid (2)
+    Config,
+    /// Rate limits could not be satisfied in time; retrying later may succeed (75, `EX_TEMPFAIL`)
+    TempFail,
+    /// The command exists but could not be executed (126)
+    NotExecutable,
+    /// The command was not found (127)
+    NotFound,
+}
+
+impl ExitCode {
+    /// The numeric exit status
+    #[must_use]
+    pub const fn code(self) -> u8 {
+        match self {
+            Self::Success => 0,
+            Self::Error => 1,
+            Self::Config => 2,
+            Self::TempFail => 75,
+            Self::NotExecutable => 126,
+            Self::NotFound => 127,
+        }
+    }
+
+    /// Classify an error returned when spawning a command
+    #[must_use]
+    pub fn for_spawn_error(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => Self::NotFound,
+            io::ErrorKind::PermissionDenied => Self::NotExecutable,
+            _ => Self::Error,
+        }
+    }
+
+    /// The code attached to `error` or any error it wraps, [`ExitCode::Error`] if none
+    #[must_use]
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<ExitError>())
+            .map_or(Self::Error, |exit| exit.code)
+    }
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        Self::from(code.code())
+    }
+}
+
+/// An error carrying the exit code the binary should report for it
+///
+/// Displays as the wrapped error, so attaching a code never changes messages.
+#[derive(Debug)]
+pub struct ExitError {
+    code: ExitCode,
+    error: anyhow::Error,
+}
+
+impl ExitError {
+    #[must_use]
+    pub const fn new(code: ExitCode, error: anyhow::Error) -> Self {
+        Self { code, error }
+    }
+
+    #[must_use]
+    pub const fn code(&self) -> ExitCode {
+        self.code
+    }
+}
+
+impl fmt::Display for ExitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "{:#}", self.error)
+        } else {
+            write!(f, "{}", self.error)
+        }
+    }
+}
+
+impl std::error::Error for ExitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// Attach an [`ExitCode`] to the error of a `Result`
+pub trait WithExitCode<T> {
+    /// Report `code` if this is an error
+    ///
+    /// # Errors
+    ///
+    /// Returns the original error, wrapped to carry `code`
+    fn with_exit_code(self, code: ExitCode) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> WithExitCode<T> for Result<T, E> {
+    fn with_exit_code(self, code: ExitCode) -> anyhow::Result<T> {
+        self.map_err(|error| ExitError::new(code, error.into()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn test_exit_code_values() {
+        let codes = [
+            ExitCode::Success,
+            ExitCode::Error,
+            ExitCode::Config,
+            ExitCode::TempFail,
+            ExitCode::NotExecutable,
+            ExitCode::NotFound,
+        ]
+        .map(ExitCode::code);
+        assert_eq!(codes, [0, 1, 2, 75, 126, 127]);
+    }
+
+    #[test]
+    fn test_spawn_errors_are_classified() {
+        let code = |kind| ExitCode::for_spawn_error(&io::Error::from(kind));
+        assert_eq!(code(io::ErrorKind::NotFound), ExitCode::NotFound);
+        assert_eq!(
+            code(io::ErrorKind::PermissionDenied),
+            ExitCode::NotExecutable
+        );
+        assert_eq!(code(io::ErrorKind::Interrupted), ExitCode::Error);
+    }
+
+    #[test]
+    fn test_code_is_found_through_context() {
+        let error = Err::<(), _>(anyhow!("limit wait exceeded"))
+            .with_exit_code(ExitCode::TempFail)
+            .context("Run failed")
+            .unwrap_err();
+        assert_eq!(ExitCode::of(&error), ExitCode::TempFail);
+        assert_eq!(format!("{error:#}"), "Run failed: limit wait exceeded");
+
+        assert_eq!(ExitCode::of(&anyhow!("plain")), ExitCode::Error);
+    }
+
+    #[test]
+    fn test_exit_error_keeps_message_and_source() {
+        let error = Err::<(), _>(io::Error::from(io::ErrorKind::NotFound))
+            .context("Failed to spawn command: nope")
+            .with_exit_code(ExitCode::NotFound)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Failed to spawn command: nope");
+        assert_eq!(error.chain().count(), 2);
+        assert!(error.chain().last().unwrap().is::<io::Error>());
+    }
+}
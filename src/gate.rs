@@ -0,0 +1,207 @@
+use crate::providers::rate_limiter::RateLimiter;
+use anyhow::{Context, Result};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Pause before accepting again after `accept` fails, e.g. when out of file
+/// descriptors, so the accept loop does not spin
+pub(crate) const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Unix socket server that tells clients whether to make a request
+///
+/// Each connection runs one limit check and gets a single line back:
+/// - `go` when usage allows the request
+/// - `wait N` when the client should back off for N seconds first
+/// - `error MESSAGE` when usage could not be checked
+///
+/// The connection is closed after the reply, so a client can gate each
+/// request with e.g. `nc -U <socket>`.
+#[derive(Debug)]
+pub struct GateServer {
+    path: PathBuf,
+    handle: JoinHandle<()>,
+}
+
+impl GateServer {
+    /// Start answering gate requests on the unix socket at `path`
+    ///
+    /// A stale socket left at `path` by an earlier run is replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - `path` exists and is not a socket
+    /// - The socket cannot be bound
+    pub fn start(path: &Path, rate_limiter: RateLimiter) -> Result<Self> {
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                anyhow::bail!("{} exists and is not a socket", path.display());
+            }
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind gate socket {}", path.display()))?;
+        let rate_limiter = Arc::new(Mutex::new(rate_limiter));
+
+        let handle = tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        warn!("Gate failed to accept a connection: {e}");
+                        tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                        continue;
+                    }
+                };
+                let rate_limiter = Arc::clone(&rate_limiter);
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &rate_limiter).await {
+                        warn!("Gate connection failed: {e}");
+                    }
+                });
+            }
+        });
+
+        debug!("Gate listening on {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            handle,
+        })
+    }
+
+    /// The path of the socket the gate is listening on
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for GateServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+async fn handle_connection(
+    mut stream: UnixStream,
+    rate_limiter: &Mutex<RateLimiter>,
+) -> Result<()> {
+    let check = rate_limiter
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .check_limits();
+    let reply = match check {
+        Ok((true, _)) => "go\n".to_string(),
+        Ok((false, backoff)) => format!("wait {}\n", wait_seconds(backoff)),
+        Err(e) => format!("error {e:#}\n"),
+    };
+    stream.write_all(reply.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Whole seconds to wait for `backoff`, rounded up so clients never wait
+/// less than asked and never told to wait zero seconds
+fn wait_seconds(backoff: Duration) -> u64 {
+    let seconds = backoff.as_secs() + u64::from(backoff.subsec_nanos() > 0);
+    seconds.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BackoffConfig, Thresholds};
+    use crate::providers::RateLimitInfo;
+    use crate::test_utils::MockProvider;
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+    use tokio::io::AsyncReadExt;
+
+    fn usage(requests_used: u32) -> RateLimitInfo {
+        RateLimitInfo {
+            requests_used,
+            tokens_used: 0,
+            input_tokens_used: 0,
+            output_tokens_used: 0,
+            error_requests_used: 0,
+        }
+    }
+
+    async fn ask(path: &Path) -> String {
+        let mut stream = UnixStream::connect(path).await.unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.unwrap();
+        reply
+    }
+
+    #[tokio::test]
+    async fn test_gate_replies_track_provider_usage() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("gate.sock");
+        let response = Arc::new(Mutex::new(Some(usage(0))));
+        let provider = Box::new(MockProvider {
+            calls: Arc::new(Mutex::new(Vec::new())),
+            responses: Arc::new(Mutex::new(HashMap::new())),
+            default_response: Arc::clone(&response),
+        });
+        let limiter = RateLimiter::new(Thresholds::default(), BackoffConfig::default(), provider);
+        let gate = GateServer::start(&path, limiter).unwrap();
+
+        assert_eq!(ask(&path).await, "go\n");
+
+        // The mock allows 100 requests per minute, so 95 is critical
+        *response.lock().unwrap() = Some(usage(95));
+        let reply = ask(&path).await;
+        let seconds: u64 = reply
+            .strip_prefix("wait ")
+            .and_then(|s| s.trim_end().parse().ok())
+            .unwrap_or_else(|| panic!("unexpected reply {reply:?}"));
+        assert!(seconds >= 1);
+
+        *response.lock().unwrap() = Some(usage(0));
+        assert_eq!(ask(&path).await, "go\n");
+
+        drop(gate);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_gate_replaces_stale_socket_only() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("not-a-socket");
+        std::fs::write(&file, "").unwrap();
+        let limiter = RateLimiter::new(
+            Thresholds::default(),
+            BackoffConfig::default(),
+            MockProvider::new(),
+        );
+        let err = GateServer::start(&file, limiter).unwrap_err();
+        assert!(err.to_string().contains("is not a socket"));
+
+        let path = dir.path().join("gate.sock");
+        let stale = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        drop(stale);
+        let limiter = RateLimiter::new(
+            Thresholds::default(),
+            BackoffConfig::default(),
+            MockProvider::new(),
+        );
+        let _gate = GateServer::start(&path, limiter).unwrap();
+        assert_eq!(ask(&path).await, "go\n");
+    }
+
+    #[test]
+    fn test_wait_seconds_rounds_up() {
+        assert_eq!(wait_seconds(Duration::ZERO), 1);
+        assert_eq!(wait_seconds(Duration::from_millis(1500)), 2);
+        assert_eq!(wait_seconds(Duration::from_secs(30)), 30);
+    }
+}
@@ -1,19 +1,112 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use dialoguer::{Input, Select};
-use reqwest::Client;
 use serde_json::json;
 use std::path::PathBuf;
 use std::time::Duration;
+use thiserror::Error;
 
+use crate::config::{NetworkConfig, DEFAULT_REQUEST_TIMEOUT};
 use crate::providers::config::{AnthropicConfig, MockConfig, OpenAIConfig, ProviderConfig};
 use crate::Config;
 
 const ANTHROPIC_TEST_PROMPT: &str = "Say hello";
 
+/// Connectivity checks made by `init --wait` before giving up
+const API_TEST_ATTEMPTS: u32 = 5;
+
+/// Delay before the first retry of the connectivity check, doubled after each
+const API_TEST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Why the connectivity test during `init` was rejected by the API
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ApiTestError {
+    #[error(
+        "API test failed with status 401 (unauthorized): check your API key. Response: {body}"
+    )]
+    Unauthorized { body: String },
+    #[error(
+        "API test failed with status 403 (forbidden): check that your API key has access to the model. Response: {body}"
+    )]
+    Forbidden { body: String },
+    #[error(
+        "API test failed with status 429 (rate limited): the key was accepted but is over its rate limit, try again later"
+    )]
+    RateLimited,
+    #[error("API test failed with status {status}: {body}")]
+    Status { status: u16, body: String },
+}
+
+/// Whether a failed connectivity check may succeed if tried again
+///
+/// Requests that never got a response, rate limiting and server errors are
+/// transient; a rejected key or any other client error is not.
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<ApiTestError>().map_or_else(
+        || error.is::<reqwest::Error>(),
+        |error| error.status() == 429 || error.status() >= 500,
+    )
+}
+
+impl ApiTestError {
+    /// Classify a failed response by its status code
+    #[must_use]
+    pub fn from_response(status: u16, body: String) -> Self {
+        match status {
+            401 => Self::Unauthorized { body },
+            403 => Self::Forbidden { body },
+            429 => Self::RateLimited,
+            _ => Self::Status { status, body },
+        }
+    }
+
+    /// The HTTP status code the API returned
+    #[must_use]
+    pub const fn status(&self) -> u16 {
+        match self {
+            Self::Unauthorized { .. } => 401,
+            Self::Forbidden { .. } => 403,
+            Self::RateLimited => 429,
+            Self::Status { status, .. } => *status,
+        }
+    }
+}
+
+#[allow(clippy::struct_excessive_bools)]
 pub struct InitOptions {
     pub config_path: Option<PathBuf>,
     pub no_prompt: bool,
     pub force: bool,
+    /// Retry the API connectivity check with backoff while it fails transiently
+    pub wait: bool,
+    /// Refuse to write a config that fails validation, instead of warning
+    pub strict: bool,
+    /// Print the config to stdout instead of writing it
+    pub dry_run: bool,
+    /// Prefix of the environment variables read with `no_prompt`, e.g.
+    /// `MYAPP_` to read `MYAPP_PROVIDER`
+    pub env_prefix: String,
+}
+
+/// Request body for the connectivity test, using the configured model
+///
+/// Provider `parameters` are passed through with their configured type; they
+/// cannot replace the model, token limit or prompt.
+fn anthropic_test_body(config: &AnthropicConfig) -> serde_json::Value {
+    let mut body: serde_json::Map<String, serde_json::Value> = config
+        .parameters
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    body.insert("model".to_string(), json!(config.model));
+    body.insert("max_tokens".to_string(), json!(config.max_tokens));
+    body.insert(
+        "messages".to_string(),
+        json!([{
+            "role": "user",
+            "content": ANTHROPIC_TEST_PROMPT
+        }]),
+    );
+    serde_json::Value::Object(body)
 }
 
 /// Test the Anthropic API connection with the provided credentials
@@ -21,53 +114,78 @@ pub struct InitOptions {
 /// # Arguments
 /// * `api_key` - The API key to test
 /// * `base_url` - The base URL of the Anthropic API
+/// * `config` - The model, token limit and parameters to test with
 ///
 /// # Errors
 /// Returns an error if:
 /// * The API request fails to send
-/// * The API returns a non-success status code
-async fn test_anthropic_api(api_key: &str, base_url: &str) -> Result<()> {
-    let client = Client::new();
+/// * The API returns a non-success status code, as an [`ApiTestError`]
+async fn test_anthropic_api(api_key: &str, base_url: &str, config: &AnthropicConfig) -> Result<()> {
+    let client = NetworkConfig::default().http_client()?;
 
     let response = client
         .post(format!("{base_url}/messages"))
         .header("x-api-key", api_key)
         .header("anthropic-version", "2023-06-01")
-        .json(&json!({
-            "model": "claude-2",
-            "max_tokens": 10,
-            "messages": [{
-                "role": "user",
-                "content": ANTHROPIC_TEST_PROMPT
-            }]
-        }))
-        .timeout(Duration::from_secs(10))
+        .json(&anthropic_test_body(config))
+        .timeout(DEFAULT_REQUEST_TIMEOUT)
         .send()
         .await?;
 
-    if !response.status().is_success() {
-        let error = response.text().await?;
-        return Err(anyhow!("API test failed: {}", error));
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await?;
+        return Err(ApiTestError::from_response(status.as_u16(), body).into());
     }
 
     Ok(())
 }
 
+/// Test the Anthropic API connection, retrying transient failures
+///
+/// Makes up to `attempts` checks, waiting `delay` before the first retry and
+/// twice as long before each one after that.
+///
+/// # Errors
+/// Returns the error of the last attempt, or of the first one that is not
+/// transient
+async fn test_anthropic_api_with_retries(
+    api_key: &str,
+    base_url: &str,
+    config: &AnthropicConfig,
+    attempts: u32,
+    mut delay: Duration,
+) -> Result<()> {
+    let mut attempt = 1;
+    loop {
+        match test_anthropic_api(api_key, base_url, config).await {
+            Err(e) if attempt < attempts && is_transient(&e) => {
+                println!("attempt {attempt} failed ({e}), retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
 /// Initialize the configuration file for the Strainer tool
 ///
 /// # Arguments
 /// * `opts` - The initialization options
 ///
+/// With `dry_run`, the config is printed to stdout and nothing is written.
+///
 /// # Errors
 /// Returns an error if:
-/// * The configuration file already exists and `force` is not set
+/// * The configuration file already exists and neither `force` nor `dry_run`
+///   is set
 /// * Failed to create the configuration directory
 /// * Failed to write the configuration file
 /// * API validation fails when testing credentials
-///
-/// # Panics
-/// This function will panic if:
-/// * Converting the `max_tokens` value to a JSON number fails
+/// * An interactive answer is invalid, e.g. a provider other than Anthropic
+///   or a maximum token count that is not a number
 pub async fn initialize_config(opts: InitOptions) -> Result<()> {
     // Default path if none specified
     let config_dir = dirs::config_dir()
@@ -78,47 +196,61 @@ pub async fn initialize_config(opts: InitOptions) -> Result<()> {
         .unwrap_or_else(|| config_dir.join("config.toml"));
 
     // Check if config exists
-    if config_path.exists() && !opts.force {
+    if config_path.exists() && !opts.force && !opts.dry_run {
         return Err(anyhow!(
             "Config file already exists at {}. Use --force to overwrite.",
             config_path.display()
         ));
     }
 
+    let config = if opts.no_prompt {
+        create_non_interactive_config(&opts.env_prefix)
+    } else {
+        create_interactive_config(opts.wait).await?
+    };
+
+    // A config that fails validation would only be rejected later by `run`
+    if let Err(e) = config.validate() {
+        if opts.strict {
+            return Err(e.context("Refusing to write an invalid config"));
+        }
+        eprintln!("Warning: the config is invalid and `run` will reject it: {e:#}");
+    }
+
+    let toml = toml::to_string_pretty(&config)?;
+    if opts.dry_run {
+        print!("{toml}");
+        return Ok(());
+    }
+
     // Create config directory if it doesn't exist
     if let Some(parent) = config_path.parent() {
         std::fs::create_dir_all(parent)?;
     }
 
-    let config = if opts.no_prompt {
-        create_non_interactive_config()
-    } else {
-        create_interactive_config().await?
-    };
-
     // Write the config file
-    let toml = toml::to_string_pretty(&config)?;
     std::fs::write(&config_path, toml)?;
 
     println!("\nConfiguration created at: {}", config_path.display());
     Ok(())
 }
 
-/// Create configuration in non-interactive mode
-fn create_non_interactive_config() -> Config {
+/// Create configuration in non-interactive mode from the environment
+/// variables named with `env_prefix`
+fn create_non_interactive_config(env_prefix: &str) -> Config {
     let mut config = Config::default();
+    let env_var = |name: &str| std::env::var(format!("{env_prefix}{name}"));
 
     // Get environment variables first
-    let provider_type =
-        std::env::var("STRAINER_PROVIDER").unwrap_or_else(|_| "anthropic".to_string());
-    let model = std::env::var("STRAINER_MODEL");
+    let provider_type = env_var("PROVIDER").unwrap_or_else(|_| "anthropic".to_string());
+    let model = env_var("MODEL");
 
     // Set provider based on environment variable or default to Anthropic
     config.api.provider_config = match provider_type.to_lowercase().as_str() {
         "openai" => {
             let mut cfg = OpenAIConfig::default();
             if let Ok(model_val) = &model {
-                cfg.model = model_val.to_string();
+                cfg.model.clone_from(model_val);
             }
 
             ProviderConfig::OpenAI(cfg)
@@ -134,15 +266,28 @@ fn create_non_interactive_config() -> Config {
     };
 
     // In non-interactive mode, use environment variable placeholder
-    if std::env::var("STRAINER_API_KEY").is_ok() {
-        config.api.api_key = Some("${STRAINER_API_KEY}".to_string());
+    if env_var("API_KEY").is_ok() {
+        config.api.api_key = Some(format!("${{{env_prefix}API_KEY}}"));
     }
 
+    for (var, threshold) in [
+        ("WARNING_THRESHOLD", &mut config.thresholds.warning),
+        ("CRITICAL_THRESHOLD", &mut config.thresholds.critical),
+        ("RESUME_THRESHOLD", &mut config.thresholds.resume),
+    ] {
+        if let Some(value) = env_var(var).ok().and_then(|v| v.parse().ok()) {
+            *threshold = value;
+        }
+    }
+
+    apply_default_limits(&mut config);
     config
 }
 
 /// Create configuration in interactive mode
-async fn create_interactive_config() -> Result<Config> {
+///
+/// With `wait`, the API key check is retried while it fails transiently.
+async fn create_interactive_config(wait: bool) -> Result<Config> {
     let mut config = Config::default();
 
     println!("Initializing strainer configuration...\n");
@@ -169,6 +314,21 @@ async fn create_interactive_config() -> Result<Config> {
 
     config.api.provider_config = providers[selected].1.clone();
 
+    // Provider specific settings, asked first so the key test uses them
+    let cfg = interactive_anthropic_config(&mut config.api.provider_config)?;
+    let model: String = Input::new()
+        .with_prompt("Enter model name")
+        .with_initial_text("claude-2")
+        .interact_text()?;
+    cfg.model = model;
+
+    let max_tokens: String = Input::new()
+        .with_prompt("Maximum tokens per response")
+        .with_initial_text("100000")
+        .interact_text()?;
+    cfg.max_tokens = parse_max_tokens(&max_tokens)?;
+    let anthropic = cfg.clone();
+
     // API key
     let api_key: String = Input::new()
         .with_prompt("Enter API key (or environment variable name)")
@@ -184,72 +344,102 @@ async fn create_interactive_config() -> Result<Config> {
     // Test API key if available
     if let Some(key) = api_key_value {
         print!("Testing API key... ");
-        match test_anthropic_api(
+        let base_url = config
+            .api
+            .base_url
+            .clone()
+            .unwrap_or_else(|| "https://api.anthropic.com/v1".to_string());
+        let attempts = if wait { API_TEST_ATTEMPTS } else { 1 };
+        match test_anthropic_api_with_retries(
             &key,
-            &config
-                .api
-                .base_url
-                .clone()
-                .unwrap_or_else(|| "https://api.anthropic.com/v1".to_string()),
+            &base_url,
+            &anthropic,
+            attempts,
+            API_TEST_RETRY_DELAY,
         )
         .await
         {
             Ok(()) => println!("✓ Success"),
             Err(e) => {
                 println!("✗ Failed");
-                return Err(anyhow!("API key validation failed: {}", e));
+                return Err(anyhow!("API key validation failed: {e}"));
             }
         }
     }
 
     config.api.api_key = Some(api_key);
 
-    // Provider specific settings
-    match &mut config.api.provider_config {
-        ProviderConfig::Anthropic(cfg) => {
-            let model: String = Input::new()
-                .with_prompt("Enter model name")
-                .with_initial_text("claude-2")
-                .interact_text()?;
-            cfg.model = model;
-
-            let max_tokens: String = Input::new()
-                .with_prompt("Maximum tokens per response")
-                .with_initial_text("100000")
-                .interact_text()?;
-            cfg.max_tokens = max_tokens.parse()?;
-        }
-        _ => unreachable!("Only Anthropic provider is supported"),
-    }
+    // Rate limits, starting from the provider's defaults
+    let defaults = config.api.provider_config.default_rate_limits();
+    config.limits.requests_per_minute = prompt_limit(
+        "Requests per minute (leave empty for no limit)",
+        defaults.requests_per_minute,
+    )?;
+    config.limits.tokens_per_minute = prompt_limit(
+        "Tokens per minute (leave empty for no limit)",
+        defaults.tokens_per_minute,
+    )?;
 
-    // Rate limits
-    let rpm: String = Input::new()
-        .with_prompt("Requests per minute (leave empty for no limit)")
+    Ok(config)
+}
+
+/// Ask for a per-minute limit, pre-filled with `default`
+fn prompt_limit(prompt: &str, default: Option<u32>) -> Result<Option<u32>> {
+    let limit: String = Input::new()
+        .with_prompt(prompt)
+        .with_initial_text(default.map(|limit| limit.to_string()).unwrap_or_default())
         .allow_empty(true)
         .interact_text()?;
 
-    if !rpm.is_empty() {
-        config.limits.requests_per_minute = Some(rpm.parse()?);
+    if limit.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(limit.parse()?))
     }
+}
 
-    let tpm: String = Input::new()
-        .with_prompt("Tokens per minute (leave empty for no limit)")
-        .allow_empty(true)
-        .interact_text()?;
-
-    if !tpm.is_empty() {
-        config.limits.tokens_per_minute = Some(tpm.parse()?);
+/// The Anthropic settings of `provider`, the only provider the interactive
+/// prompts can configure
+///
+/// # Errors
+///
+/// Returns an error naming the non-interactive alternative for any other provider
+fn interactive_anthropic_config(provider: &mut ProviderConfig) -> Result<&mut AnthropicConfig> {
+    match provider {
+        ProviderConfig::Anthropic(cfg) => Ok(cfg),
+        other => Err(anyhow!(
+            "Interactive setup only supports the anthropic provider, not {other}; \
+             run `strainer init --no-prompt` with STRAINER_PROVIDER={other} instead"
+        )),
     }
+}
 
-    Ok(config)
+/// Parse the maximum tokens per response entered at the prompt
+///
+/// # Errors
+///
+/// Returns an error if `input` is not a whole number that fits in a `u32`
+fn parse_max_tokens(input: &str) -> Result<u32> {
+    input
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid maximum tokens per response '{input}'"))
+}
+
+/// Set the limits the interactive prompts offer to the provider's defaults
+fn apply_default_limits(config: &mut Config) {
+    let defaults = config.api.provider_config.default_rate_limits();
+    config.limits.requests_per_minute = defaults.requests_per_minute;
+    config.limits.tokens_per_minute = defaults.tokens_per_minute;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::DEFAULT_ENV_PREFIX;
     use tempfile::tempdir;
     use wiremock::{
-        matchers::{header, method, path},
+        matchers::{body_partial_json, header, method, path},
         Mock, MockServer, ResponseTemplate,
     };
 
@@ -258,12 +448,79 @@ mod tests {
         let dir = tempdir().unwrap();
         let config_path = dir.path().join("config.toml");
 
-        let config = create_non_interactive_config();
+        let config = create_non_interactive_config(DEFAULT_ENV_PREFIX);
         let result = std::fs::write(&config_path, toml::to_string(&config).unwrap());
         assert!(result.is_ok());
         assert!(config_path.exists());
     }
 
+    #[test]
+    fn test_non_interactive_config_reads_env_prefix() {
+        // A prefix of its own keeps this test clear of the default variables
+        let prefix = "STRAINER_INIT_PREFIX_TEST_";
+        std::env::set_var(format!("{prefix}PROVIDER"), "openai");
+        std::env::set_var(format!("{prefix}MODEL"), "gpt-4o");
+        std::env::set_var(format!("{prefix}API_KEY"), "test-key");
+        std::env::set_var(format!("{prefix}WARNING_THRESHOLD"), "50");
+
+        let config = create_non_interactive_config(prefix);
+        match &config.api.provider_config {
+            ProviderConfig::OpenAI(cfg) => assert_eq!(cfg.model, "gpt-4o"),
+            other => panic!("Expected the openai provider, got {other}"),
+        }
+        assert_eq!(
+            config.api.api_key.as_deref(),
+            Some("${STRAINER_INIT_PREFIX_TEST_API_KEY}")
+        );
+        assert_eq!(config.thresholds.warning, 50);
+    }
+
+    #[test]
+    fn test_interactive_setup_rejects_other_providers() {
+        let mut anthropic = ProviderConfig::Anthropic(AnthropicConfig::default());
+        assert!(interactive_anthropic_config(&mut anthropic).is_ok());
+
+        for mut provider in [
+            ProviderConfig::OpenAI(OpenAIConfig::default()),
+            ProviderConfig::Mock(MockConfig::default()),
+        ] {
+            let name = provider.to_string();
+            let error = interactive_anthropic_config(&mut provider).unwrap_err();
+            let message = error.to_string();
+            assert!(
+                message.contains("only supports the anthropic provider"),
+                "{message}"
+            );
+            assert!(
+                message.contains(&format!("STRAINER_PROVIDER={name}")),
+                "{message}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_max_tokens() {
+        assert_eq!(parse_max_tokens("100000").unwrap(), 100_000);
+        assert_eq!(parse_max_tokens(" 4096 ").unwrap(), 4096);
+
+        for input in ["", "lots", "-1", "1.5", "99999999999"] {
+            let error = parse_max_tokens(input).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("Invalid maximum tokens per response '{input}'")
+            );
+        }
+    }
+
+    #[test]
+    fn test_default_limits_come_from_provider() {
+        let mut config = Config::default();
+        config.api.provider_config = ProviderConfig::OpenAI(OpenAIConfig::default());
+        apply_default_limits(&mut config);
+        assert_eq!(config.limits.requests_per_minute, Some(3500));
+        assert_eq!(config.limits.tokens_per_minute, Some(90000));
+    }
+
     #[tokio::test]
     async fn test_anthropic_api_success() {
         let mock_server = MockServer::start().await;
@@ -272,6 +529,7 @@ mod tests {
             .and(path("/messages"))
             .and(header("x-api-key", "test-key"))
             .and(header("anthropic-version", "2023-06-01"))
+            .and(header("user-agent", crate::config::DEFAULT_USER_AGENT))
             .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
                 "id": "test",
                 "content": "Hello"
@@ -279,28 +537,154 @@ mod tests {
             .mount(&mock_server)
             .await;
 
-        let result = test_anthropic_api("test-key", &mock_server.uri()).await;
+        let result =
+            test_anthropic_api("test-key", &mock_server.uri(), &AnthropicConfig::default()).await;
         assert!(result.is_ok());
     }
 
     #[tokio::test]
-    async fn test_anthropic_api_failure() {
+    async fn test_anthropic_api_uses_configured_model() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/messages"))
+            .and(body_partial_json(serde_json::json!({
+                "model": "claude-3-haiku-20240307",
+                "max_tokens": 64,
+                "temperature": 0.5,
+                "metadata": {"user_id": "init"}
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = AnthropicConfig {
+            model: "claude-3-haiku-20240307".to_string(),
+            max_tokens: 64,
+            parameters: [
+                ("temperature", json!(0.5)),
+                ("metadata", json!({"user_id": "init"})),
+                ("model", json!("claude-2")),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+        };
+        test_anthropic_api("test-key", &mock_server.uri(), &config)
+            .await
+            .unwrap();
+    }
+
+    async fn api_test_error(status: u16, body: &str) -> ApiTestError {
         let mock_server = MockServer::start().await;
 
         Mock::given(method("POST"))
             .and(path("/messages"))
             .and(header("x-api-key", "test-key"))
             .and(header("anthropic-version", "2023-06-01"))
-            .respond_with(ResponseTemplate::new(401).set_body_string("Unauthorized"))
+            .respond_with(ResponseTemplate::new(status).set_body_string(body))
             .mount(&mock_server)
             .await;
 
-        let result = test_anthropic_api("test-key", &mock_server.uri()).await;
-        assert!(result.is_err());
-        assert!(result
+        test_anthropic_api("test-key", &mock_server.uri(), &AnthropicConfig::default())
+            .await
             .unwrap_err()
-            .to_string()
-            .contains("API test failed: Unauthorized"));
+            .downcast::<ApiTestError>()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_api_unauthorized() {
+        let error = api_test_error(401, "Unauthorized").await;
+        assert_eq!(
+            error,
+            ApiTestError::Unauthorized {
+                body: "Unauthorized".to_string()
+            }
+        );
+        let message = error.to_string();
+        assert!(message.contains("status 401"), "{message}");
+        assert!(message.contains("check your API key"), "{message}");
+        assert!(message.contains("Unauthorized"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_api_forbidden() {
+        let error = api_test_error(403, "Forbidden").await;
+        assert_eq!(error.status(), 403);
+        let message = error.to_string();
+        assert!(message.contains("status 403"), "{message}");
+        assert!(message.contains("access to the model"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_api_rate_limited() {
+        let error = api_test_error(429, "Too Many Requests").await;
+        assert_eq!(error, ApiTestError::RateLimited);
+        let message = error.to_string();
+        assert!(message.contains("status 429"), "{message}");
+        assert!(message.contains("try again later"), "{message}");
+        assert!(!message.contains("check your API key"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_api_other_status() {
+        let error = api_test_error(500, "Internal error").await;
+        assert_eq!(error.status(), 500);
+        assert_eq!(
+            error.to_string(),
+            "API test failed with status 500: Internal error"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_check_retries_until_success() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/messages"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("Unavailable"))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/messages"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let config = AnthropicConfig::default();
+        let delay = Duration::from_millis(10);
+        let uri = mock_server.uri();
+        test_anthropic_api_with_retries("test-key", &uri, &config, 1, delay)
+            .await
+            .unwrap_err();
+        test_anthropic_api_with_retries("test-key", &uri, &config, 3, delay)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_api_check_does_not_retry_rejected_key() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/messages"))
+            .respond_with(ResponseTemplate::new(401).set_body_string("Unauthorized"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let error = test_anthropic_api_with_retries(
+            "test-key",
+            &mock_server.uri(),
+            &AnthropicConfig::default(),
+            5,
+            Duration::from_millis(10),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.downcast_ref::<ApiTestError>().unwrap().status(), 401);
     }
 
     #[tokio::test]
@@ -315,6 +699,10 @@ mod tests {
             config_path: Some(config_path.clone()),
             no_prompt: true,
             force: true,
+            wait: false,
+            strict: false,
+            dry_run: false,
+            env_prefix: DEFAULT_ENV_PREFIX.to_string(),
         };
 
         let result = initialize_config(opts).await;
@@ -334,6 +722,10 @@ mod tests {
             config_path: Some(config_path.clone()),
             no_prompt: true,
             force: false,
+            wait: false,
+            strict: false,
+            dry_run: false,
+            env_prefix: DEFAULT_ENV_PREFIX.to_string(),
         };
 
         let result = initialize_config(opts).await;
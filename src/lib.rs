@@ -1,11 +1,23 @@
+pub mod audit;
 pub mod cli;
 pub mod config;
+pub mod credentials;
+pub mod exit;
+pub mod gate;
 pub mod init;
+pub mod lock;
 pub mod process;
 pub mod providers;
+pub mod proxy;
+pub mod resources;
+pub mod signing;
+pub mod summary;
+pub mod tokens;
+#[cfg(feature = "tui")]
+pub mod tui;
 
 // Re-export key types for convenience
-pub use config::{BackoffConfig, Config, RateLimits, Thresholds};
+pub use config::{BackoffConfig, Config, ProxyConfig, RateLimits, Thresholds};
 pub use init::{initialize_config, InitOptions};
 pub use providers::rate_limiter::RateLimiter;
 pub use providers::{Provider, RateLimitInfo};
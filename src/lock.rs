@@ -0,0 +1,114 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+
+/// Hex digits of the key hash used in lockfile names
+const KEY_HASH_LEN: usize = 16;
+
+/// An advisory lock held by the run using an API key
+///
+/// Runs sharing a key each see only their own usage, so the lock keeps a
+/// second run from starting alongside the first. The lock is released when
+/// this value is dropped or the process exits; the file itself is left in
+/// place, since removing it could let two runs lock different files.
+#[derive(Debug)]
+pub struct RunLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl RunLock {
+    /// The lockfile for runs using `key`, named by a hash so the key never
+    /// appears on disk
+    ///
+    /// Lives in the user's runtime directory, or the temp directory if there
+    /// is none.
+    #[must_use]
+    pub fn path_for(key: &str) -> PathBuf {
+        let hash = hex::encode(Sha256::digest(key.as_bytes()));
+        dirs::runtime_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join(format!("strainer-{}.lock", &hash[..KEY_HASH_LEN]))
+    }
+
+    /// Lock `path` without waiting, writing this process's ID into it
+    ///
+    /// Returns `None` if another process holds the lock.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened, locked or written
+    pub fn try_acquire(path: &Path) -> Result<Option<Self>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open lockfile {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+            }
+        }
+        let mut lock = Self {
+            file,
+            path: path.to_path_buf(),
+        };
+        lock.write_pid()
+            .with_context(|| format!("Failed to write lockfile {}", path.display()))?;
+        Ok(Some(lock))
+    }
+
+    /// The process ID written by the current holder of `path`, if readable
+    #[must_use]
+    pub fn holder(path: &Path) -> Option<u32> {
+        std::fs::read_to_string(path).ok()?.trim().parse().ok()
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn write_pid(&mut self) -> std::io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.rewind()?;
+        writeln!(self.file, "{}", std::process::id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_path_is_keyed_by_hash() {
+        let first = RunLock::path_for("sk-first");
+        assert_eq!(first, RunLock::path_for("sk-first"));
+        assert_ne!(first, RunLock::path_for("sk-second"));
+
+        let name = first.file_name().unwrap().to_str().unwrap();
+        assert!(!name.contains("sk-first"));
+        assert_eq!(name.len(), "strainer-.lock".len() + KEY_HASH_LEN);
+    }
+
+    #[test]
+    fn test_second_acquire_fails_until_released() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("strainer.lock");
+
+        let lock = RunLock::try_acquire(&path).unwrap().unwrap();
+        assert_eq!(lock.path(), path);
+        assert_eq!(RunLock::holder(&path), Some(std::process::id()));
+        assert!(RunLock::try_acquire(&path).unwrap().is_none());
+
+        drop(lock);
+        assert!(RunLock::try_acquire(&path).unwrap().is_some());
+    }
+}
@@ -1,11 +1,74 @@
+use crate::config::StdioMode;
+use crate::exit::{ExitCode, ExitError};
 use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::pty::openpty;
 use nix::sys::signal::{self, Signal};
+use nix::sys::termios::{self, OutputFlags, SetArg};
 use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
 use std::process::Child;
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// How long a process may take to stop after a catchable pause signal before
+/// it is stopped with `SIGSTOP`
+pub const PAUSE_SIGNAL_GRACE: Duration = Duration::from_millis(500);
+
+/// How long [`ProcessController::terminate`] gives a process to exit after
+/// `SIGTERM` before killing it
+pub const TERMINATE_GRACE: Duration = Duration::from_millis(100);
+
+/// How often the process state is polled while waiting for it to stop
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long dropping a [`ProcessController`] waits for the child's remaining
+/// output; a grandchild still holding the output open is not waited for
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Scheduling state of a process, as reported by the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    /// Running or waiting, i.e. not stopped
+    Running,
+    /// Stopped by a signal or a tracer
+    Stopped,
+    /// Exited but not yet reaped by its parent
+    Zombie,
+    /// No process has this ID
+    Gone,
+}
+
+/// Options applied when spawning a child process
+#[derive(Debug, Clone, Default)]
+pub struct SpawnOptions {
+    /// Directory to run the child in, defaults to strainer's own
+    pub working_dir: Option<PathBuf>,
+    /// Variables added to the child's inherited environment
+    pub env: HashMap<String, String>,
+    /// Attach the child's stdin, stdout and stderr to a pseudo-terminal
+    /// instead of inheriting strainer's; takes precedence over `stdio`
+    pub pty: bool,
+    /// Where the child's standard streams are connected
+    pub stdio: StdioMode,
+    /// Times to retry a command that cannot be spawned before giving up
+    pub retries: u32,
+    /// Wait before the first retry, doubling for each retry after
+    pub retry_delay: Duration,
+}
 
 pub struct ProcessController {
     pid: Pid,
+    /// Threads copying the child's output to strainer's, when using a PTY or pipes
+    output: Vec<JoinHandle<()>>,
+    /// Catchable signal sent to pause the process before falling back to `SIGSTOP`
+    pause_signal: Option<Signal>,
 }
 
 #[allow(dead_code)]
@@ -14,9 +77,21 @@ impl ProcessController {
     pub const fn new(pid: i32) -> Self {
         Self {
             pid: Pid::from_raw(pid),
+            output: Vec::new(),
+            pause_signal: None,
         }
     }
 
+    /// Pause with `signal` first, so the process can clean up and stop itself
+    ///
+    /// A process that is not stopped [`PAUSE_SIGNAL_GRACE`] later, or whose
+    /// state cannot be read, is stopped with `SIGSTOP`.
+    #[must_use]
+    pub const fn with_pause_signal(mut self, signal: Option<Signal>) -> Self {
+        self.pause_signal = signal;
+        self
+    }
+
     /// Creates a new process from a command
     ///
     /// # Errors
@@ -26,47 +101,196 @@ impl ProcessController {
     /// - The command is not found
     /// - The command has insufficient permissions
     pub fn from_command(command: &[String]) -> Result<(Self, Child)> {
+        Self::from_command_with_opts(command, &SpawnOptions::default())
+    }
+
+    /// Creates a new process from a command with a working directory and environment
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The command fails to start
+    /// - The command is not found
+    /// - The command has insufficient permissions
+    /// - The working directory does not exist
+    ///
+    /// Errors for a command that is not found or not executable carry
+    /// [`ExitCode::NotFound`] or [`ExitCode::NotExecutable`].
+    ///
+    /// A command that cannot be spawned is retried `opts.retries` times. A
+    /// busy executable is expected to clear up; a missing or non-executable
+    /// one is retried too, in case it is being replaced, but with a warning.
+    /// Retries block the calling thread while they wait, so async callers
+    /// should spawn from a blocking task.
+    pub fn from_command_with_opts(
+        command: &[String],
+        opts: &SpawnOptions,
+    ) -> Result<(Self, Child)> {
         if command.is_empty() {
             anyhow::bail!("Empty command provided");
         }
 
-        let child = Command::new(&command[0])
-            .args(&command[1..])
-            .spawn()
-            .with_context(|| format!("Failed to spawn command: {}", command[0]))?;
+        let mut retries = 0;
+        let mut delay = opts.retry_delay;
+        let (cmd, master, mut child) = loop {
+            let (mut cmd, master) = build_command(command, opts)?;
+            let e = match cmd.spawn() {
+                Ok(child) => break (cmd, master, child),
+                Err(e) => e,
+            };
+            // A missing working directory is also reported as not found
+            let missing_dir = opts.working_dir.as_ref().is_some_and(|dir| !dir.is_dir());
+            if missing_dir || retries >= opts.retries {
+                let code = if missing_dir {
+                    ExitCode::Error
+                } else {
+                    ExitCode::for_spawn_error(&e)
+                };
+                let message = if retries > 0 {
+                    format!(
+                        "Failed to spawn command: {} after {retries} retries",
+                        command[0]
+                    )
+                } else {
+                    format!("Failed to spawn command: {}", command[0])
+                };
+                return Err(ExitError::new(code, anyhow::Error::new(e).context(message)).into());
+            }
+            retries += 1;
+            if is_transient_spawn_error(&e) {
+                debug!("Command {} is busy, retrying in {delay:?}", command[0]);
+            } else {
+                warn!(
+                    "Failed to spawn command {}: {e}; retrying in {delay:?} ({retries} of {})",
+                    command[0], opts.retries
+                );
+            }
+            thread::sleep(delay);
+            delay = delay.saturating_mul(2);
+        };
 
         // SAFETY: Process IDs on Unix systems are always positive and within i32 range
         // If this assumption is violated, we want to panic as it indicates a serious system issue
         #[allow(clippy::cast_possible_wrap)]
         let pid = child.id() as i32;
 
-        Ok((Self::new(pid), child))
+        let mut controller = Self::new(pid);
+        // Drop the command's copies of the terminal so output ends when the child exits
+        drop(cmd);
+        if let Some(master) = master {
+            controller.output.push(relay_pty(master)?);
+        }
+        if let Some(stdout) = child.stdout.take() {
+            controller.output.push(relay_output(stdout, io::stdout));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            controller.output.push(relay_output(stderr, io::stderr));
+        }
+        Ok((controller, child))
     }
 
     /// Pauses the process
     ///
+    /// Returns `false` if the process no longer exists, e.g. because it exited
+    /// after it was last checked.
+    ///
     /// # Errors
     ///
-    /// Returns an error if:
-    /// - The process is not running
-    /// - The process cannot be paused
-    pub fn pause(&self) -> Result<()> {
-        signal::kill(self.pid, Some(Signal::SIGSTOP))
+    /// Returns an error if the process cannot be paused
+    pub fn pause(&self) -> Result<bool> {
+        if let Some(signal) = self.pause_signal {
+            let sent = self
+                .signal_if_alive(signal)
+                .with_context(|| format!("Failed to send {signal} to process {}", self.pid))?;
+            if !sent {
+                return Ok(false);
+            }
+            match self.wait_until_stopped(PAUSE_SIGNAL_GRACE) {
+                Some(ProcessState::Stopped) => return Ok(true),
+                Some(ProcessState::Zombie | ProcessState::Gone) => return Ok(false),
+                Some(ProcessState::Running) | None => warn!(
+                    "Process {} did not stop after {signal}, sending SIGSTOP",
+                    self.pid
+                ),
+            }
+        }
+        self.signal_if_alive(Signal::SIGSTOP)
             .with_context(|| format!("Failed to pause process {}", self.pid))
     }
 
+    /// Poll the process state until it has exited or `timeout` passes
+    ///
+    /// An exited child stays a zombie until it is reaped, which counts as
+    /// exited. Without procfs a zombie cannot be told apart from a running
+    /// process, so the full `timeout` passes unless the process was reaped.
+    fn wait_until_exited(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        while !matches!(
+            self.process_state(),
+            Some(ProcessState::Zombie | ProcessState::Gone)
+        ) && Instant::now() < deadline
+        {
+            thread::sleep(STOP_POLL_INTERVAL.min(deadline - Instant::now()));
+        }
+    }
+
+    /// Poll the process state until it is no longer running or `timeout` passes
+    ///
+    /// Returns the last state seen, `None` if it cannot be read.
+    fn wait_until_stopped(&self, timeout: Duration) -> Option<ProcessState> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let state = self.process_state();
+            if state != Some(ProcessState::Running) || Instant::now() >= deadline {
+                return state;
+            }
+            thread::sleep(STOP_POLL_INTERVAL);
+        }
+    }
+
+    /// The kernel's view of the process, from `/proc/<pid>/stat`
+    ///
+    /// Returns `None` where procfs is unavailable, e.g. on macOS, unless the
+    /// process is known to be gone.
+    #[must_use]
+    pub fn process_state(&self) -> Option<ProcessState> {
+        let Some(stat) = stat_fields(self.pid) else {
+            return (!self.is_running()).then_some(ProcessState::Gone);
+        };
+        Some(match stat.first()?.as_str() {
+            "T" | "t" => ProcessState::Stopped,
+            "Z" | "X" => ProcessState::Zombie,
+            _ => ProcessState::Running,
+        })
+    }
+
     /// Resumes the process
     ///
+    /// Returns `false` if the process no longer exists.
+    ///
     /// # Errors
     ///
-    /// Returns an error if:
-    /// - The process is not paused
-    /// - The process cannot be resumed
-    pub fn resume(&self) -> Result<()> {
-        signal::kill(self.pid, Some(Signal::SIGCONT))
+    /// Returns an error if the process cannot be resumed
+    pub fn resume(&self) -> Result<bool> {
+        self.signal_if_alive(Signal::SIGCONT)
             .with_context(|| format!("Failed to resume process {}", self.pid))
     }
 
+    /// Send `signal`, treating "no such process" as the process having exited
+    fn signal_if_alive(&self, signal: Signal) -> nix::Result<bool> {
+        match signal::kill(self.pid, Some(signal)) {
+            Ok(()) => Ok(true),
+            Err(Errno::ESRCH) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The process ID
+    #[must_use]
+    pub const fn pid(&self) -> i32 {
+        self.pid.as_raw()
+    }
+
     #[must_use]
     pub fn is_running(&self) -> bool {
         signal::kill(self.pid, None).is_ok()
@@ -80,10 +304,25 @@ impl ProcessController {
     /// - The process is not running
     /// - The process cannot be terminated
     pub fn terminate(&self) -> Result<()> {
+        self.terminate_with_timeout(TERMINATE_GRACE)
+    }
+
+    /// Terminates the process, giving it up to `timeout` to exit after `SIGTERM`
+    ///
+    /// Returns as soon as the process exits; one still running after
+    /// `timeout` is killed with `SIGKILL`. Only this process is signalled,
+    /// not the children it started.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The process is not running
+    /// - The process cannot be terminated
+    pub fn terminate_with_timeout(&self, timeout: Duration) -> Result<()> {
         // First try SIGTERM for graceful shutdown
         if signal::kill(self.pid, Some(Signal::SIGTERM)).is_ok() {
             // Give the process a chance to clean up
-            std::thread::sleep(std::time::Duration::from_millis(100));
+            self.wait_until_exited(timeout);
             // If still running, force kill
             if self.is_running() {
                 signal::kill(self.pid, Some(Signal::SIGKILL))
@@ -98,6 +337,151 @@ impl ProcessController {
     }
 }
 
+/// Fields of `/proc/<pid>/stat` after the command name, starting with the state
+///
+/// Returns `None` if the file cannot be read, e.g. because the process is gone
+/// or procfs is unavailable.
+pub(crate) fn stat_fields(pid: Pid) -> Option<Vec<String>> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name is in parentheses and may itself contain spaces or
+    // parentheses
+    let fields = stat.rsplit_once(')')?.1;
+    Some(fields.split_whitespace().map(str::to_string).collect())
+}
+
+impl Drop for ProcessController {
+    /// Wait up to [`OUTPUT_DRAIN_TIMEOUT`] for the child's remaining output to
+    /// be written, leaving any relay still running behind
+    fn drop(&mut self) {
+        let deadline = Instant::now() + OUTPUT_DRAIN_TIMEOUT;
+        while self.output.iter().any(|output| !output.is_finished()) && Instant::now() < deadline {
+            thread::sleep(STOP_POLL_INTERVAL);
+        }
+        for output in self.output.drain(..) {
+            if output.is_finished() {
+                let _ = output.join();
+            } else {
+                debug!("Output is still held open, not waiting for it");
+            }
+        }
+    }
+}
+
+/// Set up the command to spawn, with its working directory, environment and streams
+///
+/// Returns the PTY master when the child is attached to a pseudo-terminal.
+fn build_command(command: &[String], opts: &SpawnOptions) -> Result<(Command, Option<OwnedFd>)> {
+    let mut cmd = Command::new(&command[0]);
+    cmd.args(&command[1..]).envs(&opts.env);
+    if let Some(dir) = &opts.working_dir {
+        cmd.current_dir(dir);
+    }
+    let master = if opts.pty {
+        Some(attach_pty(&mut cmd)?)
+    } else {
+        attach_stdio(&mut cmd, opts.stdio);
+        None
+    };
+    Ok((cmd, master))
+}
+
+/// Whether a spawn failure is likely to clear up by itself, like an
+/// executable that is still open for writing
+fn is_transient_spawn_error(error: &io::Error) -> bool {
+    error.raw_os_error() == Some(Errno::ETXTBSY as i32)
+}
+
+/// Open a pseudo-terminal and make it the stdio of `cmd`, returning the master side
+fn attach_pty(cmd: &mut Command) -> Result<OwnedFd> {
+    let pty = openpty(None, None).context("Failed to open a pseudo-terminal")?;
+    // Keep newlines as they are; strainer's own terminal, if any, translates them
+    let mut settings = termios::tcgetattr(&pty.slave).context("Failed to read PTY settings")?;
+    settings.output_flags.remove(OutputFlags::ONLCR);
+    termios::tcsetattr(&pty.slave, SetArg::TCSANOW, &settings)
+        .context("Failed to configure the PTY")?;
+
+    let stdio = || -> Result<Stdio> { Ok(Stdio::from(pty.slave.try_clone()?)) };
+    cmd.stdin(stdio()?).stdout(stdio()?).stderr(stdio()?);
+    Ok(pty.master)
+}
+
+/// Connect the standard streams of `cmd` as `mode` asks
+fn attach_stdio(cmd: &mut Command, mode: StdioMode) {
+    match mode {
+        StdioMode::Inherit => {}
+        StdioMode::Pipe => {
+            cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        StdioMode::Null => {
+            cmd.stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null());
+        }
+    }
+}
+
+/// Copy a piped output stream of the child to one of strainer's, chunk by chunk
+///
+/// Chunks are raw bytes, never decoded or split into lines, so binary output
+/// and lines of any length pass through unchanged with a fixed-size buffer.
+///
+/// The pipe is drained on its own thread whatever state the child or the
+/// supervisor is in, so a child writing more than the pipe holds never
+/// blocks on a full pipe. The thread ends once every process holding the
+/// write side has exited; if `sink` fails, the rest of the output is still
+/// read and discarded.
+fn relay_output<W: Write + 'static>(
+    mut source: impl Read + Send + 'static,
+    sink: fn() -> W,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        let mut sink = Some(sink());
+        loop {
+            let n = match source.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            let written = sink
+                .as_mut()
+                .map(|sink| sink.write_all(&buf[..n]).and_then(|()| sink.flush()));
+            if matches!(written, Some(Err(_))) {
+                sink = None;
+            }
+        }
+    })
+}
+
+/// Forward stdin to the child's terminal and its output to stdout
+///
+/// Input is forwarded in the background for as long as strainer runs. The
+/// returned thread ends once every process holding the terminal has exited.
+fn relay_pty(master: OwnedFd) -> Result<JoinHandle<()>> {
+    let mut input = File::from(master.try_clone().context("Failed to clone the PTY")?);
+    thread::spawn(move || {
+        let _ = io::copy(&mut io::stdin().lock(), &mut input);
+    });
+
+    let mut output = File::from(master);
+    Ok(thread::spawn(move || {
+        let mut buf = [0; 4096];
+        let mut stdout = io::stdout();
+        // Reading fails with EIO once the child side is closed
+        while let Ok(n) = output.read(&mut buf) {
+            if n == 0
+                || stdout
+                    .write_all(&buf[..n])
+                    .and_then(|()| stdout.flush())
+                    .is_err()
+            {
+                break;
+            }
+        }
+    }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -126,11 +510,11 @@ mod tests {
         let (controller, mut child) = ProcessController::from_command(&command).unwrap();
 
         // Test pause
-        assert!(controller.pause().is_ok());
+        assert!(controller.pause().unwrap());
         assert!(controller.is_running()); // Process should still be running, just paused
 
         // Test resume
-        assert!(controller.resume().is_ok());
+        assert!(controller.resume().unwrap());
         assert!(controller.is_running());
 
         // Cleanup
@@ -161,13 +545,305 @@ mod tests {
         // as it exceeds system limits on all Unix platforms
         let controller = ProcessController::new(i32::MAX);
         assert!(!controller.is_running());
-        assert!(controller.pause().is_err());
-        assert!(controller.resume().is_err());
+        assert!(!controller.pause().unwrap());
+        assert!(!controller.resume().unwrap());
+    }
+
+    #[test]
+    fn test_pause_after_exit_reports_gone() {
+        let command = spawn_test_process();
+        let (controller, mut child) = ProcessController::from_command(&command).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        // The reaped process no longer exists, which is not an error
+        assert!(!controller.pause().unwrap());
+        assert!(!controller.resume().unwrap());
+    }
+
+    #[test]
+    fn test_process_state() {
+        let command = spawn_test_process();
+        let (controller, mut child) = ProcessController::from_command(&command).unwrap();
+        assert_eq!(controller.process_state(), Some(ProcessState::Running));
+
+        // SIGSTOP is delivered asynchronously
+        assert!(controller.pause().unwrap());
+        assert_eq!(
+            controller.wait_until_stopped(Duration::from_secs(1)),
+            Some(ProcessState::Stopped)
+        );
+        assert!(controller.resume().unwrap());
+
+        child.kill().unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(controller.process_state(), Some(ProcessState::Zombie));
+        child.wait().unwrap();
+        assert_eq!(controller.process_state(), Some(ProcessState::Gone));
+    }
+
+    #[test]
+    fn test_pause_signal_lets_process_stop_itself() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("cleaned-up");
+        let command = [
+            "sh".to_string(),
+            "-c".to_string(),
+            format!(
+                "trap 'touch {}; kill -STOP $$' TSTP; while :; do sleep 0.05; done",
+                marker.display()
+            ),
+        ];
+        let (controller, mut child) = ProcessController::from_command(&command).unwrap();
+        let controller = controller.with_pause_signal(Some(Signal::SIGTSTP));
+        // Let the shell install its trap
+        thread::sleep(Duration::from_millis(200));
+
+        assert!(controller.pause().unwrap());
+        assert_eq!(controller.process_state(), Some(ProcessState::Stopped));
+        assert!(marker.exists(), "the SIGTSTP handler did not run");
+
+        assert!(controller.resume().unwrap());
+        controller.terminate().unwrap();
+        let _ = child.wait();
+    }
+
+    #[test]
+    fn test_pause_signal_falls_back_to_sigstop() {
+        // The ignored SIGTSTP is inherited by sleep, which keeps running
+        let command = [
+            "sh".to_string(),
+            "-c".to_string(),
+            "trap '' TSTP; exec sleep 10".to_string(),
+        ];
+        let (controller, mut child) = ProcessController::from_command(&command).unwrap();
+        let controller = controller.with_pause_signal(Some(Signal::SIGTSTP));
+        thread::sleep(Duration::from_millis(100));
+
+        let started = Instant::now();
+        assert!(controller.pause().unwrap());
+        assert!(started.elapsed() >= PAUSE_SIGNAL_GRACE);
+        assert_eq!(
+            controller.wait_until_stopped(Duration::from_secs(1)),
+            Some(ProcessState::Stopped)
+        );
+
+        assert!(controller.resume().unwrap());
+        controller.terminate().unwrap();
+        let _ = child.wait();
+    }
+
+    #[test]
+    fn test_process_with_env() {
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "test \"$STRAINER_TEST_VAR\" = expected".to_string(),
+        ];
+        let mut opts = SpawnOptions::default();
+        opts.env
+            .insert("STRAINER_TEST_VAR".to_string(), "expected".to_string());
+        let (_, mut child) = ProcessController::from_command_with_opts(&command, &opts).unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_process_with_pty() {
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "test -t 0 && test -t 1 && test -t 2".to_string(),
+        ];
+        let opts = SpawnOptions {
+            pty: true,
+            ..Default::default()
+        };
+        let (controller, mut child) =
+            ProcessController::from_command_with_opts(&command, &opts).unwrap();
+        assert!(child.wait().unwrap().success());
+        drop(controller);
+
+        // Signals still reach a child running in a PTY
+        let (controller, mut child) =
+            ProcessController::from_command_with_opts(&spawn_test_process(), &opts).unwrap();
+        assert!(controller.pause().unwrap());
+        assert!(controller.resume().unwrap());
+        controller.terminate().unwrap();
+        assert!(!child.wait().unwrap().success());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_process_stdio_modes() {
+        let fd_target = |pid: &str, fd: u8| {
+            std::fs::read_link(format!("/proc/{pid}/fd/{fd}"))
+                .unwrap()
+                .display()
+                .to_string()
+        };
+        let spawn = |stdio| {
+            let opts = SpawnOptions {
+                stdio,
+                ..Default::default()
+            };
+            let (controller, child) =
+                ProcessController::from_command_with_opts(&spawn_test_process(), &opts).unwrap();
+            let targets = [0, 1, 2].map(|fd| fd_target(&child.id().to_string(), fd));
+            (controller, child, targets)
+        };
+        let own = [0, 1, 2].map(|fd| fd_target("self", fd));
+
+        let (inherit, mut inherit_child, targets) = spawn(StdioMode::Inherit);
+        assert_eq!(targets, own);
+
+        let (piped, mut piped_child, targets) = spawn(StdioMode::Pipe);
+        assert_eq!(targets[0], own[0]);
+        for target in &targets[1..] {
+            assert!(target.starts_with("pipe:"), "{target}");
+            assert!(!own.contains(target), "{target}");
+        }
+        assert_ne!(targets[1], targets[2]);
+        assert_eq!(piped.output.len(), 2);
+
+        let (null, mut null_child, targets) = spawn(StdioMode::Null);
+        assert_eq!(targets, ["/dev/null"; 3].map(str::to_string));
+
+        for (controller, child) in [
+            (inherit, &mut inherit_child),
+            (piped, &mut piped_child),
+            (null, &mut null_child),
+        ] {
+            controller.terminate().unwrap();
+            let _ = child.wait();
+            // Joins the relay threads, which end with the child
+            drop(controller);
+        }
+    }
+
+    #[test]
+    fn test_pty_takes_precedence_over_stdio() {
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "test -t 0 && test -t 1 && test -t 2".to_string(),
+        ];
+        let opts = SpawnOptions {
+            pty: true,
+            stdio: StdioMode::Null,
+            ..Default::default()
+        };
+        let (_, mut child) = ProcessController::from_command_with_opts(&command, &opts).unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_process_with_working_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let expected = dir.path().canonicalize().unwrap();
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!("test \"$(pwd -P)\" = \"{}\"", expected.display()),
+        ];
+        let opts = SpawnOptions {
+            working_dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let (_, mut child) = ProcessController::from_command_with_opts(&command, &opts).unwrap();
+        assert!(child.wait().unwrap().success());
     }
 
     #[test]
     fn test_invalid_command() {
         let command = vec!["nonexistent_command".to_string()];
-        assert!(ProcessController::from_command(&command).is_err());
+        let Err(err) = ProcessController::from_command(&command) else {
+            panic!("Expected spawn to fail");
+        };
+        assert_eq!(ExitCode::of(&err), ExitCode::NotFound);
+        assert!(err.to_string().contains("nonexistent_command"), "{err}");
+    }
+
+    #[test]
+    fn test_non_executable_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("script.sh");
+        std::fs::write(&script, "#!/bin/sh\n").unwrap();
+        let command = vec![script.display().to_string()];
+        let Err(err) = ProcessController::from_command(&command) else {
+            panic!("Expected spawn to fail");
+        };
+        assert_eq!(ExitCode::of(&err), ExitCode::NotExecutable);
+    }
+
+    #[test]
+    fn test_missing_command_fails_after_retries() {
+        let opts = SpawnOptions {
+            retries: 2,
+            retry_delay: Duration::from_millis(20),
+            ..SpawnOptions::default()
+        };
+        let started = Instant::now();
+        let Err(err) =
+            ProcessController::from_command_with_opts(&["nonexistent_command".to_string()], &opts)
+        else {
+            panic!("Expected spawn to fail");
+        };
+        // Waits 20ms, then 40ms
+        assert!(started.elapsed() >= Duration::from_millis(60));
+        assert_eq!(ExitCode::of(&err), ExitCode::NotFound);
+        assert!(err.to_string().contains("after 2 retries"), "{err}");
+    }
+
+    #[test]
+    fn test_command_appearing_during_retries_is_spawned() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("deployed.sh");
+        let deploy = thread::spawn({
+            let dir = dir.path().to_path_buf();
+            let script = script.clone();
+            move || {
+                use std::os::unix::fs::PermissionsExt;
+                thread::sleep(Duration::from_millis(100));
+                let staged = dir.join("staged.sh");
+                std::fs::write(&staged, "#!/bin/sh\nexit 0\n").unwrap();
+                std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755)).unwrap();
+                std::fs::rename(&staged, &script).unwrap();
+            }
+        });
+        let opts = SpawnOptions {
+            retries: 8,
+            retry_delay: Duration::from_millis(20),
+            ..SpawnOptions::default()
+        };
+        let (_, mut child) =
+            ProcessController::from_command_with_opts(&[script.display().to_string()], &opts)
+                .unwrap();
+        assert!(child.wait().unwrap().success());
+        deploy.join().unwrap();
+    }
+
+    #[test]
+    fn test_busy_executable_is_transient() {
+        let busy = io::Error::from_raw_os_error(Errno::ETXTBSY as i32);
+        assert!(is_transient_spawn_error(&busy));
+        assert!(!is_transient_spawn_error(&io::Error::from(
+            io::ErrorKind::NotFound
+        )));
+    }
+
+    #[test]
+    fn test_missing_working_dir_is_not_command_not_found() {
+        let opts = SpawnOptions {
+            working_dir: Some(PathBuf::from("/nonexistent/strainer-dir")),
+            // Never retried, or this would take over a minute
+            retries: 3,
+            retry_delay: Duration::from_secs(10),
+            ..SpawnOptions::default()
+        };
+        let Err(err) = ProcessController::from_command_with_opts(&["true".to_string()], &opts)
+        else {
+            panic!("Expected spawn to fail");
+        };
+        assert_eq!(ExitCode::of(&err), ExitCode::Error);
     }
 }
@@ -0,0 +1,306 @@
+use crate::providers::{Provider, ProviderCallError, RateLimitInfo, RateLimitsConfig};
+use anyhow::{anyhow, Result};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Provider that treats several providers as one shared budget
+///
+/// Usage is the sum of every provider's usage. Each limit is the sum of the
+/// providers' limits, and is unset if any provider leaves it unset, since an
+/// unlimited share makes the whole budget unlimited.
+#[derive(Debug)]
+pub struct AggregateProvider {
+    providers: Vec<Box<dyn Provider>>,
+}
+
+impl AggregateProvider {
+    /// Combine `providers` into one budget
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `providers` is empty
+    pub fn new(providers: Vec<Box<dyn Provider>>) -> Result<Self> {
+        if providers.is_empty() {
+            return Err(anyhow!("An aggregate provider needs at least one provider"));
+        }
+        Ok(Self { providers })
+    }
+}
+
+/// Sum two optional limits, where an unset limit means unlimited
+pub(super) fn sum_limits(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    Some(a?.saturating_add(b?))
+}
+
+/// Add one provider's usage to a running total
+const fn add_usage(total: &RateLimitInfo, usage: &RateLimitInfo) -> RateLimitInfo {
+    RateLimitInfo {
+        requests_used: total.requests_used.saturating_add(usage.requests_used),
+        tokens_used: total.tokens_used.saturating_add(usage.tokens_used),
+        input_tokens_used: total
+            .input_tokens_used
+            .saturating_add(usage.input_tokens_used),
+        output_tokens_used: total
+            .output_tokens_used
+            .saturating_add(usage.output_tokens_used),
+        error_requests_used: total
+            .error_requests_used
+            .saturating_add(usage.error_requests_used),
+    }
+}
+
+const NO_USAGE: RateLimitInfo = RateLimitInfo {
+    requests_used: 0,
+    tokens_used: 0,
+    input_tokens_used: 0,
+    output_tokens_used: 0,
+    error_requests_used: 0,
+};
+
+impl Provider for AggregateProvider {
+    fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+        // Partial usage would under-report the budget, so any failure fails the whole
+        self.providers
+            .iter()
+            .enumerate()
+            .try_fold(NO_USAGE, |total, (index, provider)| {
+                let usage = provider
+                    .get_rate_limits()
+                    .map_err(|e| e.context(format!("Provider {index} in the aggregate failed")))?;
+                Ok(add_usage(&total, &usage))
+            })
+    }
+
+    fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
+        let mut configs = self.providers.iter().map(Provider::get_rate_limits_config);
+        let first = configs
+            .next()
+            .ok_or_else(|| anyhow!("An aggregate provider needs at least one provider"))??;
+        configs.try_fold(first, |total, config| {
+            let config = config?;
+            Ok(RateLimitsConfig {
+                requests_per_minute: sum_limits(
+                    total.requests_per_minute,
+                    config.requests_per_minute,
+                ),
+                tokens_per_minute: sum_limits(total.tokens_per_minute, config.tokens_per_minute),
+                input_tokens_per_minute: sum_limits(
+                    total.input_tokens_per_minute,
+                    config.input_tokens_per_minute,
+                ),
+                output_tokens_per_minute: sum_limits(
+                    total.output_tokens_per_minute,
+                    config.output_tokens_per_minute,
+                ),
+            })
+        })
+    }
+
+    /// Take every provider's last error so none goes stale, reporting the first
+    fn take_last_error(&self) -> Option<ProviderCallError> {
+        self.providers
+            .iter()
+            .filter_map(Provider::take_last_error)
+            .reduce(|first, _| first)
+    }
+
+    /// Take every provider's latency, reporting the slowest
+    fn take_latency(&self) -> Option<Duration> {
+        self.providers
+            .iter()
+            .filter_map(Provider::take_latency)
+            .reduce(Duration::max)
+    }
+
+    /// The latest reset of any provider, when the whole budget is free again
+    fn reset_at(&self) -> Option<Instant> {
+        self.providers.iter().filter_map(Provider::reset_at).max()
+    }
+
+    fn reset_usage(&mut self) {
+        for provider in &mut self.providers {
+            provider.reset_usage();
+        }
+    }
+
+    /// The summed usage, pushed whenever any provider pushes
+    ///
+    /// Only available when every provider pushes its usage, and from within
+    /// a Tokio runtime, which runs one forwarding task per provider.
+    fn subscribe(&self) -> Option<watch::Receiver<RateLimitInfo>> {
+        let receivers = self
+            .providers
+            .iter()
+            .map(Provider::subscribe)
+            .collect::<Option<Vec<_>>>()?;
+        let runtime = tokio::runtime::Handle::try_current().ok()?;
+
+        let latest: Vec<RateLimitInfo> = receivers.iter().map(|r| r.borrow().clone()).collect();
+        let total = latest
+            .iter()
+            .fold(NO_USAGE, |total, u| add_usage(&total, u));
+        let (sender, receiver) = watch::channel(total);
+        let latest = Arc::new(Mutex::new(latest));
+        for (index, mut updates) in receivers.into_iter().enumerate() {
+            let (sender, latest) = (sender.clone(), Arc::clone(&latest));
+            runtime.spawn(async move {
+                while updates.changed().await.is_ok() {
+                    let mut latest = latest.lock().unwrap();
+                    latest[index] = updates.borrow_and_update().clone();
+                    let total = latest
+                        .iter()
+                        .fold(NO_USAGE, |total, u| add_usage(&total, u));
+                    drop(latest);
+                    if sender.send(total).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Some(receiver)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ApiConfig, BackoffConfig, RateLimits, Thresholds};
+    use crate::providers::config::{MockConfig, ProviderConfig};
+    use crate::providers::mock::MockProvider;
+    use crate::providers::rate_limiter::RateLimiter;
+    use std::collections::HashMap;
+
+    fn mock(requests_used: u32, requests_per_minute: Option<u32>) -> Box<dyn Provider> {
+        let config = ApiConfig {
+            provider_config: ProviderConfig::Mock(MockConfig {
+                requests_per_minute,
+                tokens_per_minute: Some(1000),
+                input_tokens_per_minute: None,
+                ..MockConfig::default()
+            }),
+            ..ApiConfig::default()
+        };
+        let mut provider = MockProvider::new(&config).unwrap();
+        provider.set_usage(requests_used, 100, 0);
+        Box::new(provider)
+    }
+
+    fn limiter(provider: AggregateProvider) -> RateLimiter {
+        let thresholds = Thresholds {
+            warning: 30,
+            critical: 50,
+            resume: 25,
+            ..Thresholds::default()
+        };
+        RateLimiter::new(thresholds, BackoffConfig::default(), Box::new(provider))
+    }
+
+    #[test]
+    fn test_sums_usage_and_limits() -> Result<()> {
+        let aggregate = AggregateProvider::new(vec![mock(30, Some(100)), mock(50, Some(300))])?;
+
+        let usage = aggregate.get_rate_limits()?;
+        assert_eq!(usage.requests_used, 80);
+        assert_eq!(usage.tokens_used, 200);
+
+        let limits = aggregate.get_rate_limits_config()?;
+        assert_eq!(limits.requests_per_minute, Some(400));
+        assert_eq!(limits.tokens_per_minute, Some(2000));
+        assert_eq!(limits.input_tokens_per_minute, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unset_limit_makes_the_budget_unlimited() -> Result<()> {
+        let aggregate = AggregateProvider::new(vec![mock(30, Some(100)), mock(50, None)])?;
+        assert_eq!(
+            aggregate.get_rate_limits_config()?.requests_per_minute,
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_summed_usage_drives_the_limiter() -> Result<()> {
+        // Each key alone is at 30% of a shared budget of 100, together at 60%
+        let shared_budget = RateLimits {
+            requests_per_minute: Some(100),
+            tokens_per_minute: None,
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: None,
+            error_requests_per_minute: None,
+            fraction_of_quota: None,
+            shared_instances: None,
+            ramp_up_seconds: None,
+            force: false,
+            by_model: HashMap::new(),
+        };
+        let mut single = limiter(AggregateProvider::new(vec![mock(30, Some(100))])?)
+            .with_limits(shared_budget.clone());
+        let (proceed, _) = single.check_limits()?;
+        assert!(proceed);
+
+        let aggregate = AggregateProvider::new(vec![mock(30, Some(100)), mock(30, Some(100))])?;
+        let mut combined = limiter(aggregate).with_limits(shared_budget);
+        let (proceed, _) = combined.check_limits()?;
+        assert!(!proceed, "Combined usage should reach critical");
+        assert_eq!(combined.last_percentages().requests, 60);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sums_saturate_near_max() -> Result<()> {
+        let aggregate = AggregateProvider::new(vec![
+            mock(u32::MAX - 1, Some(u32::MAX)),
+            mock(u32::MAX, Some(u32::MAX - 1)),
+        ])?;
+
+        let usage = aggregate.get_rate_limits()?;
+        assert_eq!(usage.requests_used, u32::MAX);
+        let limits = aggregate.get_rate_limits_config()?;
+        assert_eq!(limits.requests_per_minute, Some(u32::MAX));
+
+        let (proceed, _) = limiter(aggregate).check_limits()?;
+        assert!(!proceed, "Saturated usage should reach critical");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pushes_summed_usage() -> Result<()> {
+        use crate::providers::proxy::ProxyProvider;
+        use crate::proxy::ProxyUsage;
+        use std::time::Duration;
+
+        let (first, second) = (Arc::new(ProxyUsage::new()), Arc::new(ProxyUsage::new()));
+        let proxied = |usage: &Arc<ProxyUsage>| -> Box<dyn Provider> {
+            Box::new(ProxyProvider::new(Arc::clone(usage), mock(0, Some(100))))
+        };
+        let aggregate = AggregateProvider::new(vec![proxied(&first), proxied(&second)])?;
+        let mut updates = aggregate.subscribe().expect("Both providers push usage");
+
+        first.record(10, 0);
+        second.record(20, 0);
+        let total = tokio::time::timeout(
+            Duration::from_secs(5),
+            updates.wait_for(|usage| usage.requests_used == 2),
+        )
+        .await??
+        .clone();
+        assert_eq!(total.tokens_used, 30);
+
+        // A provider that is only polled leaves nothing to push
+        let mixed = AggregateProvider::new(vec![proxied(&first), mock(0, Some(100))])?;
+        assert!(mixed.subscribe().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_needs_providers() {
+        assert!(AggregateProvider::new(Vec::new()).is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::config::ApiConfig;
-use crate::providers::config::AnthropicConfig;
+use crate::providers::config::{AnthropicConfig, ANTHROPIC_DEFAULT_RATE_LIMITS};
 use crate::providers::{Provider, RateLimitInfo, RateLimitsConfig};
 use anyhow::Result;
 
@@ -13,6 +13,7 @@ pub struct AnthropicProvider {
     requests_used: u32,
     tokens_used: u32,
     input_tokens_used: u32,
+    output_tokens_used: u32,
 }
 
 impl AnthropicProvider {
@@ -41,12 +42,13 @@ impl AnthropicProvider {
         };
 
         Ok(Self {
-            api_key: api_key.to_string(),
+            api_key: api_key.clone(),
             base_url,
             config: provider_config,
             requests_used: 0,
             tokens_used: 0,
             input_tokens_used: 0,
+            output_tokens_used: 0,
         })
     }
 }
@@ -57,15 +59,20 @@ impl Provider for AnthropicProvider {
             requests_used: self.requests_used,
             tokens_used: self.tokens_used,
             input_tokens_used: self.input_tokens_used,
+            output_tokens_used: self.output_tokens_used,
+            error_requests_used: 0,
         })
     }
 
     fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
-        Ok(RateLimitsConfig {
-            requests_per_minute: Some(10000), // Anthropic's default rate limit
-            tokens_per_minute: Some(100_000), // Anthropic's default token limit
-            input_tokens_per_minute: Some(50000), // Anthropic's default input token limit
-        })
+        Ok(ANTHROPIC_DEFAULT_RATE_LIMITS)
+    }
+
+    fn reset_usage(&mut self) {
+        self.requests_used = 0;
+        self.tokens_used = 0;
+        self.input_tokens_used = 0;
+        self.output_tokens_used = 0;
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
@@ -76,6 +83,7 @@ impl Provider for AnthropicProvider {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::MergeStrategy;
     use crate::providers::config::ProviderConfig;
     use std::collections::HashMap;
 
@@ -86,6 +94,8 @@ mod tests {
             api_key: Some("test_key".to_string()),
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         };
         let provider = AnthropicProvider::new(&config);
         assert!(provider.is_ok());
@@ -103,6 +113,8 @@ mod tests {
             api_key: None,
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         };
         let provider = AnthropicProvider::new(&config);
         assert!(provider.is_err());
@@ -121,6 +133,8 @@ mod tests {
             api_key: Some("test_key".to_string()),
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         };
         let provider = AnthropicProvider::new(&config);
         assert!(provider.is_err());
@@ -137,6 +151,8 @@ mod tests {
             api_key: Some("test_key".to_string()),
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         };
         let provider = AnthropicProvider::new(&config).unwrap();
         let limits = provider.get_rate_limits();
@@ -0,0 +1,136 @@
+use crate::providers::{Provider, RateLimitInfo, RateLimitsConfig};
+use anyhow::Result;
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+
+/// Provider backed by a function returning the current usage and limits
+///
+/// For tests and small apps that can read usage themselves and do not need
+/// a provider type of their own. The function is called once per usage
+/// check; the limits it returns with the usage are the ones checked against.
+pub struct ClosureProvider<F> {
+    read: F,
+    /// Limits returned with the latest usage, until the limiter asks for them
+    limits: Mutex<Option<RateLimitsConfig>>,
+}
+
+impl<F> ClosureProvider<F>
+where
+    F: Fn() -> Result<(RateLimitInfo, RateLimitsConfig)> + Send + Sync + 'static,
+{
+    /// Report the usage and limits returned by `read`
+    #[must_use]
+    pub const fn new(read: F) -> Self {
+        Self {
+            read,
+            limits: Mutex::new(None),
+        }
+    }
+}
+
+impl<F> fmt::Debug for ClosureProvider<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClosureProvider").finish_non_exhaustive()
+    }
+}
+
+impl<F> Provider for ClosureProvider<F>
+where
+    F: Fn() -> Result<(RateLimitInfo, RateLimitsConfig)> + Send + Sync + 'static,
+{
+    fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+        let (usage, limits) = (self.read)()?;
+        *self.limits.lock().unwrap_or_else(PoisonError::into_inner) = Some(limits);
+        Ok(usage)
+    }
+
+    fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
+        // Pair the limits with the usage just read rather than reading again
+        let cached = self
+            .limits
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        cached.map_or_else(|| (self.read)().map(|(_, limits)| limits), Ok)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BackoffConfig, Thresholds};
+    use crate::providers::rate_limiter::{RateLimiter, UsageBand};
+    use anyhow::anyhow;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    fn usage(requests_used: u32) -> RateLimitInfo {
+        RateLimitInfo {
+            requests_used,
+            tokens_used: 0,
+            input_tokens_used: 0,
+            output_tokens_used: 0,
+            error_requests_used: 0,
+        }
+    }
+
+    const LIMITS: RateLimitsConfig = RateLimitsConfig {
+        requests_per_minute: Some(100),
+        tokens_per_minute: None,
+        input_tokens_per_minute: None,
+        output_tokens_per_minute: None,
+    };
+
+    #[test]
+    fn test_limiter_reacts_to_ramping_usage() -> Result<()> {
+        let calls = Arc::new(AtomicU32::new(0));
+        let provider = ClosureProvider::new({
+            let calls = Arc::clone(&calls);
+            // Each check sees 40 more requests than the last
+            move || Ok((usage(calls.fetch_add(1, Ordering::Relaxed) * 40), LIMITS))
+        });
+        let mut limiter = RateLimiter::new(
+            Thresholds::default(),
+            BackoffConfig::default(),
+            provider.boxed(),
+        );
+
+        // 0% and 40% are normal, 80% is a warning, 120% is critical
+        assert!(limiter.check_limits()?.0);
+        assert!(limiter.check_limits()?.0);
+        assert_eq!(limiter.last_band(), UsageBand::Normal);
+        assert!(limiter.check_limits()?.0);
+        assert_eq!(limiter.last_band(), UsageBand::Warning);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed);
+        assert_eq!(limiter.last_band(), UsageBand::Critical);
+        assert_eq!(limiter.remaining().requests, Some(0));
+
+        // The closure is read once per check
+        assert_eq!(calls.load(Ordering::Relaxed), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_closure_errors_fail_the_check() {
+        let provider = ClosureProvider::new(|| Err(anyhow!("usage unavailable")));
+        let mut limiter = RateLimiter::new(
+            Thresholds::default(),
+            BackoffConfig::default(),
+            provider.boxed(),
+        );
+        let err = limiter.check_limits().unwrap_err();
+        assert!(err.to_string().contains("usage unavailable"));
+    }
+
+    #[test]
+    fn test_limits_without_a_prior_read_call_the_closure() -> Result<()> {
+        let provider = ClosureProvider::new(|| Ok((usage(5), LIMITS)));
+        assert_eq!(provider.get_rate_limits_config()?, LIMITS);
+        Ok(())
+    }
+}
@@ -1,3 +1,6 @@
+use super::aggregate::sum_limits;
+use super::RateLimitsConfig;
+use crate::config::ApiConfig;
 use serde::de::{Deserializer, MapAccess, Visitor};
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Serialize, Serializer};
@@ -5,6 +8,7 @@ use std::collections::HashMap;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 use thiserror::Error;
+use tracing::warn;
 
 #[derive(Debug, Error)]
 pub enum ProviderError {
@@ -18,6 +22,7 @@ pub enum ProviderConfig {
     Anthropic(AnthropicConfig),
     OpenAI(OpenAIConfig),
     Mock(MockConfig),
+    Aggregate(AggregateConfig),
 }
 
 impl Display for ProviderConfig {
@@ -26,6 +31,7 @@ impl Display for ProviderConfig {
             Self::Anthropic(_) => write!(f, "anthropic"),
             Self::OpenAI(_) => write!(f, "openai"),
             Self::Mock(_) => write!(f, "mock"),
+            Self::Aggregate(_) => write!(f, "aggregate"),
         }
     }
 }
@@ -51,9 +57,9 @@ pub struct AnthropicConfig {
         serialize_with = "serialize_u32"
     )]
     pub max_tokens: u32,
-    /// Additional model parameters
+    /// Additional model parameters, keeping their TOML/JSON type
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
-    pub parameters: HashMap<String, String>,
+    pub parameters: HashMap<String, serde_json::Value>,
 }
 
 impl Default for AnthropicConfig {
@@ -87,9 +93,9 @@ pub struct OpenAIConfig {
     )]
     pub max_tokens: u32,
 
-    /// Additional parameters
+    /// Additional parameters, keeping their TOML/JSON type
     #[serde(default, serialize_with = "serialize_hashmap")]
-    pub parameters: HashMap<String, String>,
+    pub parameters: HashMap<String, serde_json::Value>,
 }
 
 impl Default for OpenAIConfig {
@@ -131,7 +137,10 @@ where
     serializer.serialize_u32(*value)
 }
 
-fn serialize_hashmap<S>(value: &HashMap<String, String>, serializer: S) -> Result<S::Ok, S::Error>
+fn serialize_hashmap<S>(
+    value: &HashMap<String, serde_json::Value>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
@@ -143,31 +152,152 @@ where
 pub struct MockConfig {
     /// Additional parameters for testing
     #[serde(default)]
-    pub parameters: HashMap<String, String>,
-    /// Simulated requests per minute
-    #[serde(default = "default_mock_requests")]
-    pub requests_per_minute: u32,
+    pub parameters: HashMap<String, serde_json::Value>,
+    /// Simulated requests per minute (unmonitored when unset, always at or
+    /// over the limit when zero)
+    #[serde(default)]
+    pub requests_per_minute: Option<u32>,
     /// Simulated tokens per minute
-    #[serde(default = "default_mock_tokens")]
-    pub tokens_per_minute: u32,
+    #[serde(default)]
+    pub tokens_per_minute: Option<u32>,
     /// Simulated input tokens per minute
-    #[serde(default = "default_mock_input_tokens")]
-    pub input_tokens_per_minute: u32,
-}
-
-const fn default_mock_requests() -> u32 {
-    100
+    #[serde(default)]
+    pub input_tokens_per_minute: Option<u32>,
+    /// Simulated output tokens per minute (unlimited when unset)
+    #[serde(default)]
+    pub output_tokens_per_minute: Option<u32>,
+    /// Simulated requests already made this minute, reported by every check
+    #[serde(default)]
+    pub requests_used: u32,
+    /// Simulated API delay of each usage check, in milliseconds
+    #[serde(default)]
+    pub delay_ms: Option<u64>,
 }
 
-const fn default_mock_tokens() -> u32 {
-    1000
+/// Configuration for a provider that combines several providers into one
+/// budget, e.g. for traffic split across two accounts
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AggregateConfig {
+    /// Providers whose usage and limits are summed
+    #[serde(default)]
+    pub providers: Vec<ApiConfig>,
 }
 
-const fn default_mock_input_tokens() -> u32 {
-    500
-}
+/// Anthropic's documented default rate limits
+pub const ANTHROPIC_DEFAULT_RATE_LIMITS: RateLimitsConfig = RateLimitsConfig {
+    requests_per_minute: Some(10000),
+    tokens_per_minute: Some(100_000),
+    input_tokens_per_minute: Some(50000),
+    output_tokens_per_minute: None, // Only enforced when configured
+};
+
+/// `OpenAI`'s documented default rate limits
+pub const OPENAI_DEFAULT_RATE_LIMITS: RateLimitsConfig = RateLimitsConfig {
+    requests_per_minute: Some(3500),
+    tokens_per_minute: Some(90000),
+    input_tokens_per_minute: Some(45000),
+    output_tokens_per_minute: None, // Only enforced when configured
+};
 
 impl ProviderConfig {
+    /// The keys a provider block of `provider_type` accepts besides `type`
+    /// and `strict_keys`, or `None` for an unknown provider type
+    #[must_use]
+    pub fn known_keys(provider_type: &str) -> Option<&'static [&'static str]> {
+        match provider_type {
+            "anthropic" | "openai" => Some(&["model", "max_tokens", "parameters"]),
+            "mock" => Some(&[
+                "parameters",
+                "requests_per_minute",
+                "tokens_per_minute",
+                "input_tokens_per_minute",
+                "output_tokens_per_minute",
+                "requests_used",
+                "delay_ms",
+            ]),
+            "aggregate" => Some(&["providers"]),
+            _ => None,
+        }
+    }
+
+    /// The default configuration of every provider `--api` can select
+    ///
+    /// Aggregates combine other providers and are only set up in the config.
+    #[must_use]
+    pub fn supported() -> [Self; 3] {
+        [
+            Self::Anthropic(AnthropicConfig::default()),
+            Self::OpenAI(OpenAIConfig::default()),
+            Self::Mock(MockConfig::default()),
+        ]
+    }
+
+    /// The maximum tokens per request, for providers that have one
+    #[must_use]
+    pub const fn max_tokens(&self) -> Option<u32> {
+        match self {
+            Self::Anthropic(config) => Some(config.max_tokens),
+            Self::OpenAI(config) => Some(config.max_tokens),
+            Self::Mock(_) | Self::Aggregate(_) => None,
+        }
+    }
+
+    /// The model requests are made with, for providers that have one
+    #[must_use]
+    pub fn model(&self) -> Option<&str> {
+        match self {
+            Self::Anthropic(config) => Some(&config.model),
+            Self::OpenAI(config) => Some(&config.model),
+            Self::Mock(_) | Self::Aggregate(_) => None,
+        }
+    }
+
+    /// The rate limits the provider applies when none are configured
+    ///
+    /// These are what the provider's `get_rate_limits_config` reports; an
+    /// aggregate's are the sum of its providers'.
+    #[must_use]
+    pub fn default_rate_limits(&self) -> RateLimitsConfig {
+        match self {
+            Self::Anthropic(_) => ANTHROPIC_DEFAULT_RATE_LIMITS,
+            Self::OpenAI(_) => OPENAI_DEFAULT_RATE_LIMITS,
+            Self::Mock(cfg) => RateLimitsConfig {
+                requests_per_minute: cfg.requests_per_minute,
+                tokens_per_minute: cfg.tokens_per_minute,
+                input_tokens_per_minute: cfg.input_tokens_per_minute,
+                output_tokens_per_minute: cfg.output_tokens_per_minute,
+            },
+            Self::Aggregate(cfg) => cfg
+                .providers
+                .iter()
+                .map(|api| api.provider_config.default_rate_limits())
+                .reduce(|total, limits| RateLimitsConfig {
+                    requests_per_minute: sum_limits(
+                        total.requests_per_minute,
+                        limits.requests_per_minute,
+                    ),
+                    tokens_per_minute: sum_limits(
+                        total.tokens_per_minute,
+                        limits.tokens_per_minute,
+                    ),
+                    input_tokens_per_minute: sum_limits(
+                        total.input_tokens_per_minute,
+                        limits.input_tokens_per_minute,
+                    ),
+                    output_tokens_per_minute: sum_limits(
+                        total.output_tokens_per_minute,
+                        limits.output_tokens_per_minute,
+                    ),
+                })
+                .unwrap_or(RateLimitsConfig {
+                    requests_per_minute: None,
+                    tokens_per_minute: None,
+                    input_tokens_per_minute: None,
+                    output_tokens_per_minute: None,
+                }),
+        }
+    }
+
     /// Validates the provider configuration
     ///
     /// # Errors
@@ -198,6 +328,17 @@ impl ProviderConfig {
                 Ok(())
             }
             Self::Mock(_) => Ok(()),
+            Self::Aggregate(config) => {
+                if config.providers.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "An aggregate provider needs at least one provider"
+                    ));
+                }
+                config
+                    .providers
+                    .iter()
+                    .try_for_each(|api| api.provider_config.validate())
+            }
         }
     }
 }
@@ -232,6 +373,10 @@ impl serde::Serialize for ProviderConfig {
                     map.serialize_entry("parameters", &cfg.parameters)?;
                 }
             }
+            Self::Aggregate(cfg) => {
+                map.serialize_entry("type", "aggregate")?;
+                map.serialize_entry("providers", &cfg.providers)?;
+            }
         }
         map.end()
     }
@@ -266,6 +411,26 @@ impl<'de> serde::Deserialize<'de> for ProviderConfig {
                 let provider_type = type_value
                     .as_str()
                     .ok_or_else(|| M::Error::custom("type field is not a string"))?;
+                let strict_keys = values
+                    .remove("strict_keys")
+                    .map(|value| {
+                        value
+                            .as_bool()
+                            .ok_or_else(|| M::Error::custom("strict_keys field is not a boolean"))
+                    })
+                    .transpose()?
+                    .unwrap_or(false);
+                // A misspelled key would otherwise silently fall back to its default
+                if let Some(known) = ProviderConfig::known_keys(provider_type) {
+                    for key in values.keys().filter(|key| !known.contains(&key.as_str())) {
+                        if strict_keys {
+                            return Err(M::Error::unknown_field(key, known));
+                        }
+                        warn!(
+                            "Ignoring unknown key `{key}` in the {provider_type} provider config"
+                        );
+                    }
+                }
                 let obj = serde_json::Value::Object(values);
                 match provider_type {
                     "anthropic" => {
@@ -283,6 +448,11 @@ impl<'de> serde::Deserialize<'de> for ProviderConfig {
                             serde_json::from_value(obj).map_err(M::Error::custom)?;
                         Ok(ProviderConfig::Mock(cfg))
                     }
+                    "aggregate" => {
+                        let cfg: AggregateConfig =
+                            serde_json::from_value(obj).map_err(M::Error::custom)?;
+                        Ok(ProviderConfig::Aggregate(cfg))
+                    }
                     other => Err(M::Error::custom(format!("unknown provider type: {other}"))),
                 }
             }
@@ -322,14 +492,126 @@ mod tests {
     fn test_mock_config() {
         let config = MockConfig {
             parameters: HashMap::new(),
-            requests_per_minute: 100,
-            tokens_per_minute: 1000,
-            input_tokens_per_minute: 500,
+            requests_per_minute: Some(100),
+            tokens_per_minute: Some(1000),
+            input_tokens_per_minute: Some(500),
+            output_tokens_per_minute: None,
+            requests_used: 0,
+            delay_ms: None,
         };
         assert!(config.parameters.is_empty());
-        assert_eq!(config.requests_per_minute, 100);
-        assert_eq!(config.tokens_per_minute, 1000);
-        assert_eq!(config.input_tokens_per_minute, 500);
+        assert_eq!(config.requests_per_minute, Some(100));
+        assert_eq!(config.tokens_per_minute, Some(1000));
+        assert_eq!(config.input_tokens_per_minute, Some(500));
+    }
+
+    #[test]
+    fn test_typed_parameters_round_trip() {
+        let toml_text =
+            "type = \"anthropic\"\nparameters = { top_p = 0.9, stream = false, user = \"ci\" }";
+        let config: ProviderConfig = toml::from_str(toml_text).unwrap();
+        let written = toml::to_string(&config).unwrap();
+        let ProviderConfig::Anthropic(parsed) = toml::from_str(&written).unwrap() else {
+            panic!("Expected an Anthropic provider");
+        };
+        assert_eq!(parsed.parameters["top_p"], serde_json::json!(0.9));
+        assert_eq!(parsed.parameters["stream"], serde_json::json!(false));
+        assert_eq!(parsed.parameters["user"], serde_json::json!("ci"));
+
+        let openai = ProviderConfig::OpenAI(OpenAIConfig {
+            parameters: HashMap::from([
+                ("temperature".to_string(), serde_json::json!(0.7)),
+                ("logprobs".to_string(), serde_json::json!(true)),
+            ]),
+            ..OpenAIConfig::default()
+        });
+        let json = serde_json::to_string(&openai).unwrap();
+        let ProviderConfig::OpenAI(parsed) = serde_json::from_str(&json).unwrap() else {
+            panic!("Expected an OpenAI provider");
+        };
+        assert_eq!(parsed.parameters["temperature"], serde_json::json!(0.7));
+        assert_eq!(parsed.parameters["logprobs"], serde_json::json!(true));
+    }
+
+    #[test]
+    fn test_strict_keys_rejects_misspelled_key() {
+        let err = toml::from_str::<ProviderConfig>(
+            "type = \"anthropic\"\nstrict_keys = true\nmodle = \"claude-3\"",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("unknown field `modle`"), "{err}");
+        assert!(err.contains("`model`"), "{err}");
+    }
+
+    #[test]
+    fn test_unknown_keys_are_ignored_by_default() {
+        let config: ProviderConfig =
+            toml::from_str("type = \"anthropic\"\nmodle = \"claude-3\"").unwrap();
+        assert_eq!(config.model(), Some("claude-2"));
+    }
+
+    #[test]
+    fn test_strict_keys_accepts_known_keys() {
+        let config: ProviderConfig = toml::from_str(
+            "type = \"mock\"\nstrict_keys = true\nrequests_per_minute = 5\ndelay_ms = 10",
+        )
+        .unwrap();
+        let ProviderConfig::Mock(mock) = config else {
+            panic!("Expected a mock provider");
+        };
+        assert_eq!(mock.requests_per_minute, Some(5));
+        assert_eq!(mock.delay_ms, Some(10));
+
+        let err = toml::from_str::<ProviderConfig>("type = \"anthropic\"\nstrict_keys = \"yes\"")
+            .unwrap_err();
+        assert!(err.to_string().contains("strict_keys"), "{err}");
+    }
+
+    #[test]
+    fn test_known_keys_cover_every_field() {
+        // Anthropic leaves out empty parameters
+        let parameters = HashMap::from([("temperature".to_string(), serde_json::json!(0.5))]);
+        let configs = [
+            (
+                "anthropic",
+                serde_json::to_value(AnthropicConfig {
+                    parameters,
+                    ..AnthropicConfig::default()
+                }),
+            ),
+            ("openai", serde_json::to_value(OpenAIConfig::default())),
+            ("mock", serde_json::to_value(MockConfig::default())),
+            (
+                "aggregate",
+                serde_json::to_value(AggregateConfig::default()),
+            ),
+        ];
+        for (provider_type, value) in configs {
+            let known = ProviderConfig::known_keys(provider_type).unwrap();
+            for key in value.unwrap().as_object().unwrap().keys() {
+                assert!(
+                    known.contains(&key.as_str()),
+                    "{provider_type} misses {key}"
+                );
+            }
+        }
+        assert!(ProviderConfig::known_keys("other").is_none());
+    }
+
+    #[test]
+    fn test_supported_providers() {
+        let names: Vec<String> = ProviderConfig::supported()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(names, ["anthropic", "openai", "mock"]);
+        let [anthropic, _, mock] = ProviderConfig::supported();
+        assert_eq!(
+            anthropic.max_tokens(),
+            Some(AnthropicConfig::default().max_tokens)
+        );
+        assert_eq!(mock.max_tokens(), None);
     }
 
     #[test]
@@ -0,0 +1,215 @@
+use crate::providers::{Provider, ProviderCallError, RateLimitInfo, RateLimitsConfig};
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::warn;
+
+/// Provider that reports limits from the first healthy provider in a chain
+///
+/// Calls go to the active provider, initially the first. When it fails, the
+/// chain is re-evaluated in order and the first provider that answers becomes
+/// active, so a recovered primary is preferred over later fallbacks.
+#[derive(Debug)]
+pub struct FallbackProvider {
+    providers: Vec<Box<dyn Provider>>,
+    active: AtomicUsize,
+}
+
+impl FallbackProvider {
+    /// Create a chain from providers in order of preference
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `providers` is empty
+    pub fn new(providers: Vec<Box<dyn Provider>>) -> Result<Self> {
+        if providers.is_empty() {
+            return Err(anyhow!("A provider chain needs at least one provider"));
+        }
+        Ok(Self {
+            providers,
+            active: AtomicUsize::new(0),
+        })
+    }
+
+    /// Index of the provider currently answering calls
+    #[must_use]
+    pub fn active_index(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    fn active(&self) -> &dyn Provider {
+        self.providers[self.active_index()].as_ref()
+    }
+}
+
+impl Provider for FallbackProvider {
+    fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+        let failed = self.active_index();
+        let error = match self.providers[failed].get_rate_limits() {
+            Ok(info) => return Ok(info),
+            Err(e) => e,
+        };
+        warn!("Provider {failed} in the chain failed: {error}");
+
+        for (index, provider) in self.providers.iter().enumerate() {
+            if index == failed {
+                continue;
+            }
+            match provider.get_rate_limits() {
+                Ok(info) => {
+                    warn!("Falling back to provider {index} in the chain");
+                    self.active.store(index, Ordering::Relaxed);
+                    return Ok(info);
+                }
+                Err(e) => warn!("Provider {index} in the chain failed: {e}"),
+            }
+        }
+        Err(error.context("All providers in the chain failed"))
+    }
+
+    fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
+        self.active().get_rate_limits_config()
+    }
+
+    fn take_last_error(&self) -> Option<ProviderCallError> {
+        self.active().take_last_error()
+    }
+
+    fn take_latency(&self) -> Option<Duration> {
+        self.active().take_latency()
+    }
+
+    fn reset_at(&self) -> Option<Instant> {
+        self.active().reset_at()
+    }
+
+    fn reset_usage(&mut self) {
+        for provider in &mut self.providers {
+            provider.reset_usage();
+        }
+    }
+
+    /// Usage pushed by the provider active when subscribing
+    fn subscribe(&self) -> Option<watch::Receiver<RateLimitInfo>> {
+        self.active().subscribe()
+    }
+
+    fn record_pushed(&self, usage: &RateLimitInfo) -> Result<()> {
+        self.active().record_pushed(usage)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    /// Provider whose health can be toggled, reporting `limit` requests per minute
+    #[derive(Debug)]
+    struct FlakyProvider {
+        healthy: AtomicBool,
+        limit: u32,
+    }
+
+    impl FlakyProvider {
+        fn boxed(healthy: bool, limit: u32) -> Box<dyn Provider> {
+            Box::new(Self {
+                healthy: AtomicBool::new(healthy),
+                limit,
+            })
+        }
+    }
+
+    impl Provider for FlakyProvider {
+        fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+            if !self.healthy.load(Ordering::Relaxed) {
+                return Err(anyhow!("provider {} is down", self.limit));
+            }
+            Ok(RateLimitInfo {
+                requests_used: 1,
+                tokens_used: 0,
+                input_tokens_used: 0,
+                output_tokens_used: 0,
+                error_requests_used: 0,
+            })
+        }
+
+        fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
+            Ok(RateLimitsConfig {
+                requests_per_minute: Some(self.limit),
+                tokens_per_minute: None,
+                input_tokens_per_minute: None,
+                output_tokens_per_minute: None,
+            })
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    fn set_healthy(chain: &FallbackProvider, index: usize, healthy: bool) {
+        chain.providers[index]
+            .as_any()
+            .downcast_ref::<FlakyProvider>()
+            .unwrap()
+            .healthy
+            .store(healthy, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_secondary_serves_limits_when_primary_fails() {
+        let chain = FallbackProvider::new(vec![
+            FlakyProvider::boxed(false, 100),
+            FlakyProvider::boxed(true, 200),
+        ])
+        .unwrap();
+
+        assert!(chain.get_rate_limits().is_ok());
+        assert_eq!(chain.active_index(), 1);
+        assert_eq!(
+            chain.get_rate_limits_config().unwrap().requests_per_minute,
+            Some(200)
+        );
+    }
+
+    #[test]
+    fn test_chain_returns_to_recovered_primary() {
+        let chain = FallbackProvider::new(vec![
+            FlakyProvider::boxed(true, 100),
+            FlakyProvider::boxed(true, 200),
+        ])
+        .unwrap();
+        assert!(chain.get_rate_limits().is_ok());
+        assert_eq!(chain.active_index(), 0);
+
+        set_healthy(&chain, 0, false);
+        assert!(chain.get_rate_limits().is_ok());
+        assert_eq!(chain.active_index(), 1);
+
+        // The primary is preferred again once the fallback fails
+        set_healthy(&chain, 0, true);
+        set_healthy(&chain, 1, false);
+        assert!(chain.get_rate_limits().is_ok());
+        assert_eq!(chain.active_index(), 0);
+    }
+
+    #[test]
+    fn test_chain_fails_when_all_providers_fail() {
+        let chain = FallbackProvider::new(vec![
+            FlakyProvider::boxed(false, 100),
+            FlakyProvider::boxed(false, 200),
+        ])
+        .unwrap();
+        let err = chain.get_rate_limits().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("All providers in the chain failed"));
+        assert!(FallbackProvider::new(Vec::new()).is_err());
+    }
+}
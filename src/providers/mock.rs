@@ -2,14 +2,18 @@ use crate::config::ApiConfig;
 use crate::providers::config::MockConfig;
 use crate::providers::{Provider, RateLimitInfo, RateLimitsConfig};
 use anyhow::Result;
+use std::time::Duration;
 
-/// Mock provider for testing
+/// Mock provider with settable in-memory usage
+///
+/// Backs the `mock` API in configs and tests; [`MockProvider::with_limits`]
+/// builds one directly for demos and local modes.
 #[derive(Debug)]
 pub struct MockProvider {
     pub requests_used: u32,
     pub tokens_used: u32,
     pub input_tokens_used: u32,
-    #[allow(dead_code)]
+    pub output_tokens_used: u32,
     config: MockConfig,
 }
 
@@ -27,15 +31,58 @@ impl MockProvider {
         };
 
         Ok(Self {
-            requests_used: 0,
+            requests_used: provider_config.requests_used,
             tokens_used: 0,
             input_tokens_used: 0,
+            output_tokens_used: 0,
             config: provider_config,
         })
     }
 
-    /// Set the usage values for testing
-    pub fn set_usage(&mut self, requests: u32, tokens: u32, input_tokens: u32) {
+    /// Create a mock provider reporting the given per-minute limits
+    ///
+    /// Usage starts at zero; change it with [`MockProvider::set_usage`].
+    ///
+    /// ```
+    /// use strainer::config::{BackoffConfig, Thresholds};
+    /// use strainer::providers::mock::MockProvider;
+    /// use strainer::providers::rate_limiter::RateLimiter;
+    /// use strainer::providers::Provider;
+    ///
+    /// let mut provider = MockProvider::with_limits(100, 10_000, 5_000);
+    /// provider.set_usage(95, 1_000, 500);
+    ///
+    /// let mut limiter = RateLimiter::new(
+    ///     Thresholds::default(),
+    ///     BackoffConfig::default(),
+    ///     provider.boxed(),
+    /// );
+    /// let (proceed, _backoff) = limiter.check_limits()?;
+    /// assert!(!proceed, "95 of 100 requests is critical");
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    #[must_use]
+    pub fn with_limits(
+        requests_per_minute: u32,
+        tokens_per_minute: u32,
+        input_tokens_per_minute: u32,
+    ) -> Self {
+        Self {
+            requests_used: 0,
+            tokens_used: 0,
+            input_tokens_used: 0,
+            output_tokens_used: 0,
+            config: MockConfig {
+                requests_per_minute: Some(requests_per_minute),
+                tokens_per_minute: Some(tokens_per_minute),
+                input_tokens_per_minute: Some(input_tokens_per_minute),
+                ..MockConfig::default()
+            },
+        }
+    }
+
+    /// Set the usage values reported by the next check
+    pub const fn set_usage(&mut self, requests: u32, tokens: u32, input_tokens: u32) {
         self.requests_used = requests;
         self.tokens_used = tokens;
         self.input_tokens_used = input_tokens;
@@ -44,21 +91,39 @@ impl MockProvider {
 
 impl Provider for MockProvider {
     fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+        if let Some(delay) = self.config.delay_ms {
+            std::thread::sleep(Duration::from_millis(delay));
+        }
         Ok(RateLimitInfo {
             requests_used: self.requests_used,
             tokens_used: self.tokens_used,
             input_tokens_used: self.input_tokens_used,
+            output_tokens_used: self.output_tokens_used,
+            error_requests_used: 0,
         })
     }
 
     fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
         Ok(RateLimitsConfig {
-            requests_per_minute: Some(self.config.requests_per_minute),
-            tokens_per_minute: Some(self.config.tokens_per_minute),
-            input_tokens_per_minute: Some(self.config.input_tokens_per_minute),
+            requests_per_minute: self.config.requests_per_minute,
+            tokens_per_minute: self.config.tokens_per_minute,
+            input_tokens_per_minute: self.config.input_tokens_per_minute,
+            output_tokens_per_minute: self.config.output_tokens_per_minute,
         })
     }
 
+    /// The simulated delay of each usage check
+    fn take_latency(&self) -> Option<Duration> {
+        self.config.delay_ms.map(Duration::from_millis)
+    }
+
+    fn reset_usage(&mut self) {
+        self.requests_used = 0;
+        self.tokens_used = 0;
+        self.input_tokens_used = 0;
+        self.output_tokens_used = 0;
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -67,6 +132,7 @@ impl Provider for MockProvider {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::MergeStrategy;
     use crate::providers::config::ProviderConfig;
     use std::collections::HashMap;
 
@@ -77,6 +143,8 @@ mod tests {
             api_key: None,
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         };
         let provider = MockProvider::new(&config).unwrap();
         assert_eq!(provider.requests_used, 0);
@@ -93,6 +161,8 @@ mod tests {
             api_key: Some("test_key".to_string()),
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         };
         let provider = MockProvider::new(&config);
         assert!(provider.is_err());
@@ -109,6 +179,8 @@ mod tests {
             api_key: None,
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         };
         let mut provider = MockProvider::new(&config).unwrap();
         provider.set_usage(10, 100, 50);
@@ -117,6 +189,27 @@ mod tests {
         assert_eq!(provider.input_tokens_used, 50);
     }
 
+    #[test]
+    fn test_mock_provider_with_limits() -> Result<()> {
+        let mut provider = MockProvider::with_limits(60, 6000, 3000);
+        assert_eq!(
+            provider.get_rate_limits_config()?,
+            RateLimitsConfig {
+                requests_per_minute: Some(60),
+                tokens_per_minute: Some(6000),
+                input_tokens_per_minute: Some(3000),
+                output_tokens_per_minute: None,
+            }
+        );
+        assert_eq!(provider.get_rate_limits()?.requests_used, 0);
+
+        provider.set_usage(30, 100, 50);
+        let usage = provider.get_rate_limits()?;
+        assert_eq!(usage.requests_used, 30);
+        assert_eq!(usage.input_tokens_used, 50);
+        Ok(())
+    }
+
     #[test]
     fn test_mock_provider_get_rate_limits() {
         let config = ApiConfig {
@@ -124,6 +217,8 @@ mod tests {
             api_key: None,
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         };
         let mut provider = MockProvider::new(&config).unwrap();
         provider.set_usage(10, 100, 50);
@@ -140,8 +235,30 @@ mod tests {
             api_key: None,
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         };
         let provider = MockProvider::new(&config).unwrap();
         let _: &MockProvider = provider.as_any().downcast_ref().unwrap();
     }
+
+    #[test]
+    fn test_mock_provider_reset_usage() {
+        let config = ApiConfig {
+            provider_config: ProviderConfig::Mock(MockConfig::default()),
+            api_key: None,
+            base_url: None,
+            parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
+        };
+        let mut provider = MockProvider::new(&config).unwrap();
+        provider.set_usage(10, 100, 50);
+        provider.reset_usage();
+
+        let usage = provider.get_rate_limits().unwrap();
+        assert_eq!(usage.requests_used, 0);
+        assert_eq!(usage.tokens_used, 0);
+        assert_eq!(usage.input_tokens_used, 0);
+    }
 }
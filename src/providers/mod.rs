@@ -1,25 +1,175 @@
 use crate::config::ApiConfig;
-use anyhow::Result;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+use tracing::warn;
 
+pub mod aggregate;
 pub mod anthropic;
+pub mod closure;
 pub mod config;
+pub mod fallback;
 pub mod mock;
+pub mod proxy;
 pub mod rate_limiter;
+pub mod replay;
 
 /// Rate limit information returned by providers
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RateLimitInfo {
     pub requests_used: u32,
     pub tokens_used: u32,
     pub input_tokens_used: u32,
+    pub output_tokens_used: u32,
+    /// Requests that failed, counted apart from `requests_used` since
+    /// providers usually only count successful requests against the quota
+    #[serde(default)]
+    pub error_requests_used: u32,
 }
 
 /// Rate limit configuration for providers
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RateLimitsConfig {
     pub requests_per_minute: Option<u32>,
     pub tokens_per_minute: Option<u32>,
     pub input_tokens_per_minute: Option<u32>,
+    pub output_tokens_per_minute: Option<u32>,
+}
+
+/// Classification of a failed provider call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderErrorKind {
+    /// The provider rejected the call for exceeding a rate limit (429)
+    RateLimited,
+    /// The provider failed to handle the call (5xx)
+    ServerError,
+    /// The provider rejected the credentials (401/403)
+    Auth,
+    /// The provider could not be reached
+    Network,
+}
+
+impl ProviderErrorKind {
+    /// Classify an HTTP status code, returning `None` for non-error statuses
+    #[must_use]
+    pub const fn from_status(status: u16) -> Option<Self> {
+        match status {
+            429 => Some(Self::RateLimited),
+            401 | 403 => Some(Self::Auth),
+            500..=599 => Some(Self::ServerError),
+            _ => None,
+        }
+    }
+}
+
+/// A failed call observed by a provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderCallError {
+    pub kind: ProviderErrorKind,
+    /// How long the provider asked us to wait, from its `Retry-After` header
+    pub retry_after: Option<Duration>,
+}
+
+impl ProviderCallError {
+    #[must_use]
+    pub const fn new(kind: ProviderErrorKind) -> Self {
+        Self {
+            kind,
+            retry_after: None,
+        }
+    }
+}
+
+/// Parse a `Retry-After` header value given in seconds
+#[must_use]
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// Parse a rate limit reset given as the time left, e.g. `6m0s` or `20ms`
+/// in `OpenAI`'s `x-ratelimit-reset-tokens`
+#[must_use]
+pub fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let is_number = |c: char| c.is_ascii_digit() || c == '.';
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    let mut total = Duration::ZERO;
+    while !rest.is_empty() {
+        let (number, tail) = rest.split_at(rest.find(|c| !is_number(c))?);
+        let (unit, tail) = tail.split_at(tail.find(is_number).unwrap_or(tail.len()));
+        let seconds_per_unit = match unit {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        let number: f64 = number.parse().ok()?;
+        total += Duration::try_from_secs_f64(number * seconds_per_unit).ok()?;
+        rest = tail;
+    }
+    Some(total)
+}
+
+/// Parse a rate limit reset given as an RFC 3339 time, e.g.
+/// `2024-01-01T00:00:30Z` in Anthropic's `anthropic-ratelimit-tokens-reset`,
+/// into the time left after `now`; a time already passed leaves none
+#[must_use]
+pub fn parse_reset_time(value: &str, now: SystemTime) -> Option<Duration> {
+    let at = parse_rfc3339(value.trim())?;
+    Some(at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+fn parse_rfc3339(value: &str) -> Option<SystemTime> {
+    let (date, time) = value.split_once(['T', 't', ' '])?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+
+    let (time, offset_seconds) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else {
+        let (time, offset) = time.split_at(time.rfind(['+', '-'])?);
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        let seconds = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+        (
+            time,
+            if offset.starts_with('-') {
+                -seconds
+            } else {
+                seconds
+            },
+        )
+    };
+    let mut time = time.splitn(3, ':');
+    let hour: i64 = time.next()?.parse().ok()?;
+    let minute: i64 = time.next()?.parse().ok()?;
+    let second: f64 = time.next()?.parse().ok()?;
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+        || !(0.0..61.0).contains(&second)
+    {
+        return None;
+    }
+
+    let seconds =
+        days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 - offset_seconds;
+    let whole = Duration::from_secs(u64::try_from(seconds).ok()?);
+    UNIX_EPOCH.checked_add(whole + Duration::try_from_secs_f64(second).ok()?)
+}
+
+/// Days from 1970-01-01 to a date in the proleptic Gregorian calendar
+const fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
 }
 
 /// Provider trait for API services
@@ -36,8 +186,114 @@ pub trait Provider: std::fmt::Debug + std::any::Any + Send + Sync {
     /// Returns an error if unable to retrieve rate limit configuration or if the configuration is invalid
     fn get_rate_limits_config(&self) -> Result<RateLimitsConfig>;
 
+    /// Take the most recent failed call observed since the last poll
+    ///
+    /// Providers that make no calls of their own never report errors.
+    fn take_last_error(&self) -> Option<ProviderCallError> {
+        None
+    }
+
+    /// Take the round-trip time of the provider's latest API call since the
+    /// last poll
+    ///
+    /// Providers that make no calls of their own report no latency.
+    fn take_latency(&self) -> Option<Duration> {
+        None
+    }
+
+    /// When the provider's current rate limit window resets, if it said so
+    ///
+    /// Critical usage then backs off until the reset rather than for the
+    /// maximum backoff. Providers that never see the API's rate limit
+    /// headers do not know.
+    fn reset_at(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Clear usage counted locally, called when a new per-minute window starts
+    ///
+    /// Providers whose usage comes from the API have nothing to reset.
+    fn reset_usage(&mut self) {}
+
+    /// Subscribe to usage pushed by the provider as it changes
+    ///
+    /// The receiver holds the latest usage, so updates sent faster than they
+    /// are read collapse into the newest. Providers that only report usage
+    /// when polled return `None`.
+    fn subscribe(&self) -> Option<watch::Receiver<RateLimitInfo>> {
+        None
+    }
+
+    /// Take note of pushed usage that a check used in place of polling
+    ///
+    /// Lets wrappers that see every polled reading, such as a recording, see
+    /// pushed readings too. Other providers ignore it.
+    ///
+    /// # Errors
+    /// Returns an error if the reading cannot be handled
+    fn record_pushed(&self, _usage: &RateLimitInfo) -> Result<()> {
+        Ok(())
+    }
+
     /// Convert to Any for downcasting
     fn as_any(&self) -> &dyn std::any::Any;
+
+    /// Box this provider for use behind `dyn Provider`
+    ///
+    /// Boxing an already boxed provider returns it unchanged.
+    fn boxed(self) -> Box<dyn Provider>
+    where
+        Self: Sized,
+    {
+        Box::new(self)
+    }
+}
+
+/// Forwards every call to the boxed provider, so composites can hold and
+/// wrap `Box<dyn Provider>` like any other provider
+///
+/// `as_any` also forwards, so downcasting sees the inner provider rather than
+/// the box.
+impl Provider for Box<dyn Provider> {
+    fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+        (**self).get_rate_limits()
+    }
+
+    fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
+        (**self).get_rate_limits_config()
+    }
+
+    fn take_last_error(&self) -> Option<ProviderCallError> {
+        (**self).take_last_error()
+    }
+
+    fn take_latency(&self) -> Option<Duration> {
+        (**self).take_latency()
+    }
+
+    fn reset_at(&self) -> Option<Instant> {
+        (**self).reset_at()
+    }
+
+    fn reset_usage(&mut self) {
+        (**self).reset_usage();
+    }
+
+    fn subscribe(&self) -> Option<watch::Receiver<RateLimitInfo>> {
+        (**self).subscribe()
+    }
+
+    fn record_pushed(&self, usage: &RateLimitInfo) -> Result<()> {
+        (**self).record_pushed(usage)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        (**self).as_any()
+    }
+
+    fn boxed(self) -> Box<dyn Provider> {
+        self
+    }
 }
 
 /// Create a new provider based on the configuration
@@ -52,18 +308,66 @@ pub trait Provider: std::fmt::Debug + std::any::Any + Send + Sync {
 pub fn create_provider(config: &ApiConfig) -> Result<Box<dyn Provider>> {
     match &config.provider_config {
         config::ProviderConfig::Anthropic(_) => {
-            Ok(Box::new(anthropic::AnthropicProvider::new(config)?))
+            Ok(anthropic::AnthropicProvider::new(config)?.boxed())
         }
         config::ProviderConfig::OpenAI(_) => {
             Err(anyhow::anyhow!("OpenAI provider not yet implemented"))
         }
-        config::ProviderConfig::Mock(_) => Ok(Box::new(mock::MockProvider::new(config)?)),
+        config::ProviderConfig::Mock(_) => Ok(mock::MockProvider::new(config)?.boxed()),
+        config::ProviderConfig::Aggregate(aggregate) => {
+            let providers = aggregate
+                .providers
+                .iter()
+                .map(create_provider)
+                .collect::<Result<Vec<_>>>()?;
+            Ok(aggregate::AggregateProvider::new(providers)?.boxed())
+        }
+    }
+}
+
+/// Create a provider that falls back through `fallbacks` when `primary` fails
+///
+/// Providers that cannot be constructed are skipped with a warning. Without
+/// fallbacks this is the same as [`create_provider`].
+///
+/// # Errors
+///
+/// Returns an error if no provider in the chain can be constructed
+pub fn create_provider_chain(
+    primary: &ApiConfig,
+    fallbacks: &[ApiConfig],
+) -> Result<Box<dyn Provider>> {
+    if fallbacks.is_empty() {
+        return create_provider(primary);
+    }
+
+    let mut providers = Vec::new();
+    let mut errors = Vec::new();
+    for config in std::iter::once(primary).chain(fallbacks) {
+        match create_provider(config) {
+            Ok(provider) => providers.push(provider),
+            Err(e) => {
+                warn!(
+                    "Skipping {} provider in the chain: {e}",
+                    config.provider_config
+                );
+                errors.push(format!("{}: {e}", config.provider_config));
+            }
+        }
     }
+    if providers.is_empty() {
+        bail!(
+            "No provider in the chain could be created ({})",
+            errors.join("; ")
+        );
+    }
+    Ok(fallback::FallbackProvider::new(providers)?.boxed())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::MergeStrategy;
     use crate::providers::config::{AnthropicConfig, ProviderConfig};
     use std::collections::HashMap;
 
@@ -74,6 +378,8 @@ mod tests {
             api_key: Some("test_key".to_string()),
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         };
         let provider = create_provider(&config);
         assert!(provider.is_ok());
@@ -86,6 +392,8 @@ mod tests {
             api_key: Some("test_key".to_string()),
             base_url: None,
             parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
         };
         let provider = create_provider(&config);
         assert!(provider.is_err());
@@ -95,16 +403,181 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_provider_chain_falls_back_when_primary_fails() {
+        let primary = ApiConfig {
+            provider_config: ProviderConfig::OpenAI(config::OpenAIConfig::default()),
+            api_key: Some("test_key".to_string()),
+            base_url: None,
+            parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
+        };
+        let secondary = ApiConfig {
+            provider_config: ProviderConfig::Mock(config::MockConfig {
+                requests_per_minute: Some(42),
+                ..config::MockConfig::default()
+            }),
+            ..ApiConfig::default()
+        };
+
+        let provider = create_provider_chain(&primary, &[secondary]).unwrap();
+        assert!(provider.get_rate_limits().is_ok());
+        assert_eq!(
+            provider
+                .get_rate_limits_config()
+                .unwrap()
+                .requests_per_minute,
+            Some(42)
+        );
+
+        let err = create_provider_chain(&primary, std::slice::from_ref(&primary)).unwrap_err();
+        assert!(err.to_string().contains("No provider in the chain"));
+    }
+
+    #[test]
+    fn test_provider_chain_without_fallbacks_is_plain_provider() {
+        let primary = ApiConfig {
+            provider_config: ProviderConfig::Mock(config::MockConfig::default()),
+            ..ApiConfig::default()
+        };
+        let provider = create_provider_chain(&primary, &[]).unwrap();
+        assert!(provider
+            .as_any()
+            .downcast_ref::<mock::MockProvider>()
+            .is_some());
+    }
+
+    fn mock_with_requests(requests: u32) -> mock::MockProvider {
+        let config = ApiConfig {
+            provider_config: ProviderConfig::Mock(config::MockConfig::default()),
+            ..ApiConfig::default()
+        };
+        let mut provider = mock::MockProvider::new(&config).unwrap();
+        provider.set_usage(requests, 0, 0);
+        provider
+    }
+
+    #[test]
+    fn test_boxed_provider_forwards_through_nesting() {
+        let inner = mock_with_requests(3).boxed();
+        let mut nested: Box<dyn Provider> = Box::new(inner);
+
+        assert_eq!(nested.get_rate_limits().unwrap().requests_used, 3);
+        assert!(nested.get_rate_limits_config().is_ok());
+        assert!(nested.take_last_error().is_none());
+        assert!(nested
+            .as_any()
+            .downcast_ref::<mock::MockProvider>()
+            .is_some());
+
+        nested.reset_usage();
+        assert_eq!(nested.get_rate_limits().unwrap().requests_used, 0);
+    }
+
+    #[test]
+    fn test_boxing_a_box_keeps_it() {
+        let provider = mock_with_requests(1).boxed();
+        let address = std::ptr::from_ref(provider.as_any()).cast::<()>();
+        let reboxed = provider.boxed();
+        assert_eq!(std::ptr::from_ref(reboxed.as_any()).cast::<()>(), address);
+    }
+
+    #[test]
+    fn test_composites_of_boxed_providers() {
+        let aggregate = aggregate::AggregateProvider::new(vec![
+            mock_with_requests(2).boxed(),
+            mock_with_requests(5).boxed(),
+        ])
+        .unwrap()
+        .boxed();
+        let chain = fallback::FallbackProvider::new(vec![aggregate, mock_with_requests(1).boxed()])
+            .unwrap()
+            .boxed();
+        let outer =
+            aggregate::AggregateProvider::new(vec![chain, mock_with_requests(4).boxed()]).unwrap();
+
+        assert_eq!(outer.get_rate_limits().unwrap().requests_used, 11);
+    }
+
+    #[test]
+    fn test_provider_error_kind_from_status() {
+        assert_eq!(
+            ProviderErrorKind::from_status(429),
+            Some(ProviderErrorKind::RateLimited)
+        );
+        assert_eq!(
+            ProviderErrorKind::from_status(503),
+            Some(ProviderErrorKind::ServerError)
+        );
+        assert_eq!(
+            ProviderErrorKind::from_status(401),
+            Some(ProviderErrorKind::Auth)
+        );
+        assert_eq!(ProviderErrorKind::from_status(200), None);
+        assert_eq!(ProviderErrorKind::from_status(400), None);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("30"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after(" 5 "), Some(Duration::from_secs(5)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+    }
+
     #[test]
     fn test_rate_limit_info_debug() {
         let info = RateLimitInfo {
             requests_used: 10,
             tokens_used: 100,
             input_tokens_used: 50,
+            output_tokens_used: 0,
+            error_requests_used: 0,
         };
         let debug_str = format!("{info:?}");
         assert!(debug_str.contains("requests_used: 10"));
         assert!(debug_str.contains("tokens_used: 100"));
         assert!(debug_str.contains("input_tokens_used: 50"));
     }
+
+    #[test]
+    fn test_parse_reset_duration() {
+        assert_eq!(parse_reset_duration("6m0s"), Some(Duration::from_mins(6)));
+        assert_eq!(
+            parse_reset_duration("1h2m3.5s"),
+            Some(Duration::from_millis(3_723_500))
+        );
+        assert_eq!(
+            parse_reset_duration("20ms"),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(parse_reset_duration(" 2s "), Some(Duration::from_secs(2)));
+        assert_eq!(parse_reset_duration(""), None);
+        assert_eq!(parse_reset_duration("soon"), None);
+        assert_eq!(parse_reset_duration("5"), None);
+    }
+
+    #[test]
+    fn test_parse_reset_time() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        // 2023-11-14T22:13:20Z is 1_700_000_000
+        assert_eq!(
+            parse_reset_time("2023-11-14T22:14:20Z", now),
+            Some(Duration::from_mins(1))
+        );
+        assert_eq!(
+            parse_reset_time("2023-11-14T23:14:20+01:00", now),
+            Some(Duration::from_mins(1))
+        );
+        assert_eq!(
+            parse_reset_time("2023-11-14T22:13:20.250Z", now),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(
+            parse_reset_time("2023-11-14T22:00:00Z", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_reset_time("tomorrow", now), None);
+        assert_eq!(parse_reset_time("2023-13-14T22:00:00Z", now), None);
+    }
 }
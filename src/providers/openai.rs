@@ -1,5 +1,5 @@
 use crate::config::ApiConfig;
-use crate::providers::config::OpenAIConfig;
+use crate::providers::config::{OpenAIConfig, OPENAI_DEFAULT_RATE_LIMITS};
 use crate::providers::{Provider, RateLimitInfo, RateLimitsConfig};
 use anyhow::Result;
 
@@ -11,6 +11,7 @@ pub struct OpenAIProvider {
     requests_used: u32,
     tokens_used: u32,
     input_tokens_used: u32,
+    output_tokens_used: u32,
 }
 
 impl OpenAIProvider {
@@ -35,6 +36,7 @@ impl OpenAIProvider {
             requests_used: 0,
             tokens_used: 0,
             input_tokens_used: 0,
+            output_tokens_used: 0,
         })
     }
 }
@@ -45,15 +47,13 @@ impl Provider for OpenAIProvider {
             requests_used: self.requests_used,
             tokens_used: self.tokens_used,
             input_tokens_used: self.input_tokens_used,
+            output_tokens_used: self.output_tokens_used,
+            error_requests_used: 0,
         })
     }
 
     fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
-        Ok(RateLimitsConfig {
-            requests_per_minute: Some(3500),  // OpenAI's default rate limit
-            tokens_per_minute: Some(90000),   // OpenAI's default token limit
-            input_tokens_per_minute: Some(45000), // OpenAI's default input token limit
-        })
+        Ok(OPENAI_DEFAULT_RATE_LIMITS)
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
@@ -0,0 +1,283 @@
+use crate::config::MissingMetricPolicy;
+use crate::providers::{Provider, ProviderCallError, RateLimitInfo, RateLimitsConfig};
+use crate::proxy::ProxyUsage;
+use anyhow::{bail, Result};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Provider that reports usage counted by strainer's local proxy
+///
+/// Limits are taken from the wrapped provider; usage comes from the requests
+/// the child process sent through the proxy instead of a usage endpoint.
+#[derive(Debug)]
+pub struct ProxyProvider {
+    usage: Arc<ProxyUsage>,
+    inner: Box<dyn Provider>,
+    missing_metric_policy: MissingMetricPolicy,
+}
+
+impl ProxyProvider {
+    /// Create a new proxy-backed provider
+    #[must_use]
+    pub fn new(usage: Arc<ProxyUsage>, inner: Box<dyn Provider>) -> Self {
+        Self {
+            usage,
+            inner,
+            missing_metric_policy: MissingMetricPolicy::default(),
+        }
+    }
+
+    /// Set how token counts left out of successful responses are reported
+    #[must_use]
+    pub const fn with_missing_metric_policy(mut self, policy: MissingMetricPolicy) -> Self {
+        self.missing_metric_policy = policy;
+        self
+    }
+}
+
+impl Provider for ProxyProvider {
+    fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+        let mut usage = self.usage.snapshot();
+        let missing = self.usage.missing_metrics();
+        if !missing.any() {
+            return Ok(usage);
+        }
+        match self.missing_metric_policy {
+            MissingMetricPolicy::Zero => {}
+            MissingMetricPolicy::Error => {
+                let metrics = match (missing.input_tokens, missing.output_tokens) {
+                    (true, true) => "input and output",
+                    (true, false) => "input",
+                    _ => "output",
+                };
+                bail!("A provider response did not report its {metrics} token count");
+            }
+            MissingMetricPolicy::AssumeFull => {
+                // Without a known limit, saturate so any configured limit is reached
+                let limits = self.inner.get_rate_limits_config()?;
+                let full = |used: u32, limit: Option<u32>| used.max(limit.unwrap_or(u32::MAX));
+                if missing.input_tokens {
+                    usage.input_tokens_used =
+                        full(usage.input_tokens_used, limits.input_tokens_per_minute);
+                }
+                if missing.output_tokens {
+                    usage.output_tokens_used =
+                        full(usage.output_tokens_used, limits.output_tokens_per_minute);
+                }
+                usage.tokens_used = full(usage.tokens_used, limits.tokens_per_minute);
+            }
+        }
+        Ok(usage)
+    }
+
+    fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
+        self.inner.get_rate_limits_config()
+    }
+
+    fn take_last_error(&self) -> Option<ProviderCallError> {
+        self.usage.take_last_error()
+    }
+
+    fn take_latency(&self) -> Option<Duration> {
+        self.usage.take_latency()
+    }
+
+    /// The reset proxied responses reported, else the wrapped provider's
+    fn reset_at(&self) -> Option<Instant> {
+        self.usage.reset_at().or_else(|| self.inner.reset_at())
+    }
+
+    fn reset_usage(&mut self) {
+        self.inner.reset_usage();
+    }
+
+    /// Usage as the proxy records each request
+    ///
+    /// Only pushed when missing token counts count as zero; the other
+    /// policies are applied when usage is polled.
+    fn subscribe(&self) -> Option<watch::Receiver<RateLimitInfo>> {
+        (self.missing_metric_policy == MissingMetricPolicy::Zero).then(|| self.usage.subscribe())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ApiConfig, MergeStrategy};
+    use crate::providers::config::{MockConfig, ProviderConfig};
+    use crate::providers::mock::MockProvider;
+    use std::collections::HashMap;
+
+    fn create_mock_provider() -> Box<dyn Provider> {
+        mock_provider_with_limits(Some(1000), Some(500))
+    }
+
+    fn mock_provider_with_limits(
+        tokens_per_minute: Option<u32>,
+        input_tokens_per_minute: Option<u32>,
+    ) -> Box<dyn Provider> {
+        let config = ApiConfig {
+            provider_config: ProviderConfig::Mock(MockConfig {
+                parameters: HashMap::default(),
+                requests_per_minute: Some(100),
+                tokens_per_minute,
+                input_tokens_per_minute,
+                output_tokens_per_minute: None,
+                requests_used: 0,
+                delay_ms: None,
+            }),
+            api_key: None,
+            base_url: None,
+            parameters: HashMap::default(),
+            merge: MergeStrategy::Extend,
+            remove_parameters: Vec::new(),
+        };
+        Box::new(MockProvider::new(&config).unwrap())
+    }
+
+    #[test]
+    fn test_proxy_provider_reports_proxy_usage() {
+        let usage = Arc::new(ProxyUsage::new());
+        let provider = ProxyProvider::new(Arc::clone(&usage), create_mock_provider());
+
+        usage.record(40, 60);
+        let info = provider.get_rate_limits().unwrap();
+        assert_eq!(info.requests_used, 1);
+        assert_eq!(info.tokens_used, 100);
+        assert_eq!(info.input_tokens_used, 40);
+    }
+
+    fn partial_usage() -> Arc<ProxyUsage> {
+        let usage = Arc::new(ProxyUsage::new());
+        usage.record(100, 50);
+        usage.record_reported(Some(40), None);
+        usage
+    }
+
+    #[test]
+    fn test_missing_metric_counts_as_zero() {
+        let provider = ProxyProvider::new(partial_usage(), create_mock_provider());
+        let info = provider.get_rate_limits().unwrap();
+        assert_eq!(info.requests_used, 2);
+        assert_eq!(info.tokens_used, 190);
+        assert_eq!(info.input_tokens_used, 140);
+        assert_eq!(info.output_tokens_used, 50);
+    }
+
+    #[test]
+    fn test_missing_metric_assumed_full() {
+        use crate::config::{BackoffConfig, Thresholds};
+        use crate::providers::rate_limiter::RateLimiter;
+
+        let provider = ProxyProvider::new(partial_usage(), create_mock_provider())
+            .with_missing_metric_policy(MissingMetricPolicy::AssumeFull);
+        let info = provider.get_rate_limits().unwrap();
+        // Reported counts are kept; the total and the missing count are full
+        assert_eq!(info.input_tokens_used, 140);
+        assert_eq!(info.tokens_used, 1000);
+        assert_eq!(info.output_tokens_used, u32::MAX);
+
+        let mut limiter = RateLimiter::new(
+            Thresholds::default(),
+            BackoffConfig::default(),
+            provider.boxed(),
+        );
+        let (proceed, _) = limiter.check_limits().unwrap();
+        assert!(!proceed);
+        assert_eq!(limiter.last_percentages().tokens, 100);
+    }
+
+    #[test]
+    fn test_missing_metric_fails_the_check() {
+        let provider = ProxyProvider::new(partial_usage(), create_mock_provider())
+            .with_missing_metric_policy(MissingMetricPolicy::Error);
+        let err = provider.get_rate_limits().unwrap_err();
+        assert!(err.to_string().contains("output token count"));
+
+        let complete = Arc::new(ProxyUsage::new());
+        complete.record(1, 1);
+        let provider = ProxyProvider::new(complete, create_mock_provider())
+            .with_missing_metric_policy(MissingMetricPolicy::Error);
+        assert!(provider.get_rate_limits().is_ok());
+    }
+
+    #[test]
+    fn test_proxy_provider_pushes_recorded_usage() {
+        let usage = Arc::new(ProxyUsage::new());
+        let provider = ProxyProvider::new(Arc::clone(&usage), create_mock_provider());
+        let mut updates = provider.subscribe().unwrap();
+
+        usage.record(40, 60);
+        assert!(updates.has_changed().unwrap());
+        let info = updates.borrow_and_update().clone();
+        assert_eq!(info.requests_used, 1);
+        assert_eq!(info.tokens_used, 100);
+
+        let provider = ProxyProvider::new(usage, create_mock_provider())
+            .with_missing_metric_policy(MissingMetricPolicy::AssumeFull);
+        assert!(provider.subscribe().is_none());
+    }
+
+    #[test]
+    fn test_proxy_provider_uses_inner_limits() {
+        let provider = ProxyProvider::new(Arc::new(ProxyUsage::new()), create_mock_provider());
+        let limits = provider.get_rate_limits_config().unwrap();
+        assert_eq!(limits.requests_per_minute, Some(100));
+        assert_eq!(limits.tokens_per_minute, Some(1000));
+        assert_eq!(limits.input_tokens_per_minute, Some(500));
+    }
+
+    #[tokio::test]
+    async fn test_estimated_tokens_feed_check_limits() -> Result<()> {
+        use crate::config::{BackoffConfig, Thresholds};
+        use crate::providers::rate_limiter::RateLimiter;
+        use crate::proxy::ProxyServer;
+        use serde_json::json;
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        // A provider whose responses carry no usage block
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "content": [{"type": "text", "text": "Hello, world!"}]
+            })))
+            .mount(&upstream)
+            .await;
+        let proxy = ProxyServer::start_with_client(
+            "127.0.0.1:0",
+            &upstream.uri(),
+            reqwest::Client::new(),
+            true,
+            None,
+        )
+        .await?;
+        reqwest::Client::new()
+            .post(format!("{}/v1/messages", proxy.local_url()))
+            .json(&json!({"messages": [{"role": "user", "content": "Hello, world!"}]}))
+            .send()
+            .await?;
+
+        // 4 tokens of text and 4 of message overhead in, 4 tokens out
+        let info = proxy.usage().snapshot();
+        assert_eq!((info.input_tokens_used, info.output_tokens_used), (8, 4));
+
+        let provider =
+            ProxyProvider::new(proxy.usage(), mock_provider_with_limits(Some(24), Some(16)));
+        let mut limiter = RateLimiter::new(
+            Thresholds::default(),
+            BackoffConfig::default(),
+            Box::new(provider),
+        );
+        limiter.check_limits()?;
+        let percentages = limiter.last_percentages();
+        assert_eq!(percentages.tokens, 50);
+        assert_eq!(percentages.input_tokens, 50);
+        Ok(())
+    }
+}
@@ -1,14 +1,21 @@
-use super::Provider;
-use crate::config::{BackoffConfig, Thresholds};
-use anyhow::Result;
-use std::time::{Duration, Instant};
-use tracing::{info, warn};
+use super::{Provider, ProviderCallError, ProviderErrorKind, RateLimitInfo, RateLimitsConfig};
+use crate::config::{
+    active_window, BackoffConfig, Gating, RateLimits, RoundingMode, ScheduleWindow, Thresholds,
+};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
 
 #[derive(Debug)]
 pub struct UsageStats {
     pub requests_used: u32,
     pub tokens_used: u32,
     pub input_tokens_used: u32,
+    pub output_tokens_used: u32,
+    pub error_requests_used: u32,
     pub last_check: Instant,
 }
 
@@ -18,28 +25,152 @@ impl Default for UsageStats {
             requests_used: 0,
             tokens_used: 0,
             input_tokens_used: 0,
+            output_tokens_used: 0,
+            error_requests_used: 0,
             last_check: Instant::now(),
         }
     }
 }
 
 impl UsageStats {
-    fn new(requests: u32, tokens: u32, input_tokens: u32) -> Self {
+    fn new(info: &RateLimitInfo) -> Self {
         Self {
-            requests_used: requests,
-            tokens_used: tokens,
-            input_tokens_used: input_tokens,
+            requests_used: info.requests_used,
+            tokens_used: info.tokens_used,
+            input_tokens_used: info.input_tokens_used,
+            output_tokens_used: info.output_tokens_used,
+            error_requests_used: info.error_requests_used,
             last_check: Instant::now(),
         }
     }
 }
 
+/// Usage of each limit as a percentage, as computed by the last check
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsagePercentages {
+    pub requests: u32,
+    pub tokens: u32,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Failed requests against `error_requests_per_minute`
+    #[serde(default)]
+    pub error_requests: u32,
+}
+
+impl UsagePercentages {
+    /// The highest of the individual percentages
+    #[must_use]
+    pub fn max(&self) -> u32 {
+        self.requests
+            .max(self.tokens)
+            .max(self.input_tokens)
+            .max(self.output_tokens)
+            .max(self.error_requests)
+    }
+}
+
+/// Headroom left under each limit, as computed by the last check
+///
+/// Each is `limit - used`, zero when usage is over the limit and `None` when
+/// the limit is not set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsageRemaining {
+    pub requests: Option<u32>,
+    pub tokens: Option<u32>,
+    pub input_tokens: Option<u32>,
+    pub output_tokens: Option<u32>,
+    /// Failed requests left under `error_requests_per_minute`
+    pub error_requests: Option<u32>,
+}
+
+/// Where usage stands relative to the warning and critical thresholds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageBand {
+    #[default]
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl std::fmt::Display for UsageBand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Normal => write!(f, "normal"),
+            Self::Warning => write!(f, "warning"),
+            Self::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+/// Weight of the newest sample in the smoothed usage growth rate
+const GROWTH_RATE_SMOOTHING: f64 = 0.5;
+
+/// Length of the window that per-minute limits apply to
+const USAGE_WINDOW: Duration = Duration::from_mins(1);
+
+/// Consecutive checks with slow provider calls before backing off on latency
+const LATENCY_BACKOFF_CHECKS: usize = 3;
+
+/// Share of each limit allowed when a ramp-up starts
+const RAMP_UP_START: f64 = 0.1;
+
+/// Latencies the provider reported over the most recent checks
+#[derive(Debug, Default)]
+struct LatencyTracker {
+    samples: VecDeque<Duration>,
+}
+
+impl LatencyTracker {
+    fn record(&mut self, latency: Duration) {
+        if self.samples.len() == LATENCY_BACKOFF_CHECKS {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    /// Whether each of the last `LATENCY_BACKOFF_CHECKS` latencies exceeded `threshold`
+    fn elevated(&self, threshold: Duration) -> bool {
+        self.samples.len() == LATENCY_BACKOFF_CHECKS
+            && self.samples.iter().all(|&latency| latency > threshold)
+    }
+
+    fn average(&self) -> Option<Duration> {
+        let count = u32::try_from(self.samples.len()).ok().filter(|&n| n > 0)?;
+        Some(self.samples.iter().sum::<Duration>() / count)
+    }
+}
+
 /// `RateLimiter` manages API rate limits with thresholds for warning and critical levels
 #[derive(Debug)]
 pub struct RateLimiter {
     thresholds: Thresholds,
     backoff: BackoffConfig,
     usage: UsageStats,
+    last_percentages: UsagePercentages,
+    last_remaining: UsageRemaining,
+    last_band: UsageBand,
+    limits: Option<RateLimits>,
+    /// Time and peak usage percentage of the previous check
+    last_sample: Option<(Instant, u32)>,
+    /// Exponential moving average of usage growth, in percentage points per second
+    growth_rate: Option<f64>,
+    schedule: Vec<ScheduleWindow>,
+    /// Index of the schedule window currently applied
+    active_window: Option<usize>,
+    /// Thresholds and limits to restore when the active window ends
+    unscheduled: Option<(Thresholds, Option<RateLimits>)>,
+    clock: fn() -> SystemTime,
+    /// Start of the current per-minute window
+    window_start: Instant,
+    /// When usage entered the warning band, while it stays there
+    warning_since: Option<Instant>,
+    /// Whether the sustained warning alert was raised for the current stay
+    warning_escalated: bool,
+    /// Latency of recent provider calls
+    latency: LatencyTracker,
+    /// When the limiter was created, which starts any ramp-up
+    started: Instant,
     provider: Box<dyn Provider>,
 }
 
@@ -55,24 +186,344 @@ impl RateLimiter {
             thresholds,
             backoff,
             usage: UsageStats::default(),
+            last_percentages: UsagePercentages::default(),
+            last_remaining: UsageRemaining::default(),
+            last_band: UsageBand::default(),
+            limits: None,
+            last_sample: None,
+            growth_rate: None,
+            schedule: Vec::new(),
+            active_window: None,
+            unscheduled: None,
+            clock: SystemTime::now,
+            window_start: Instant::now(),
+            warning_since: None,
+            warning_escalated: false,
+            latency: LatencyTracker::default(),
+            started: Instant::now(),
             provider,
         }
     }
 
-    /// Calculate the usage percentage, with proper handling of edge cases
-    #[allow(clippy::cast_possible_truncation, clippy::cast_lossless)]
+    /// Apply configured limits on top of the provider's reported quota
+    ///
+    /// Each effective limit is the stricter of the configured absolute limit
+    /// and the provider's limit scaled by `fraction_of_quota`.
+    #[must_use]
+    pub fn with_limits(mut self, limits: RateLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Switch limits and thresholds by time of day
+    ///
+    /// Before each check the first window covering the current hour is
+    /// applied over the limits and thresholds the limiter was built with;
+    /// outside every window those apply unchanged.
+    #[must_use]
+    pub fn with_schedule(mut self, schedule: Vec<ScheduleWindow>) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// Read the time of day for the schedule from `clock` instead of the system clock
+    #[must_use]
+    pub const fn with_clock(mut self, clock: fn() -> SystemTime) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Apply the schedule window covering the current time, if it changed
+    fn apply_schedule(&mut self) {
+        if self.schedule.is_empty() {
+            return;
+        }
+        let active = active_window(&self.schedule, (self.clock)());
+        if active == self.active_window {
+            return;
+        }
+        self.active_window = active;
+
+        // Start from the unscheduled settings so windows never stack
+        if let Some((thresholds, limits)) = self.unscheduled.take() {
+            self.thresholds = thresholds;
+            self.limits = limits;
+        }
+        let Some(window) = active.map(|index| &self.schedule[index]) else {
+            info!("No schedule window is active, using the base limits");
+            return;
+        };
+        info!("Schedule window {window} is active");
+        self.unscheduled = Some((self.thresholds.clone(), self.limits.clone()));
+        if let Some(thresholds) = &window.thresholds {
+            self.thresholds = thresholds.clone();
+        }
+        if let Some(window_limits) = &window.limits {
+            match &mut self.limits {
+                Some(limits) => limits.merge(window_limits),
+                None => self.limits = Some(window_limits.clone()),
+            }
+        }
+    }
+
+    /// Limits enforced for a quota reported by the provider
+    ///
+    /// Where both are set, the stricter of the configured limit and the
+    /// reported one (scaled by any `fraction_of_quota`) is enforced; where
+    /// only one is, it applies alone. Either way it is then divided among
+    /// any `shared_instances`, and scaled down while a ramp-up is running.
+    /// With `force` set, the reported quota is ignored and only the
+    /// configured limits apply.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn effective_limits(&self, reported: &RateLimitsConfig) -> RateLimitsConfig {
+        let Some(limits) = &self.limits else {
+            return reported.clone();
+        };
+        let reported = if limits.force {
+            &RateLimitsConfig::default()
+        } else {
+            reported
+        };
+        // Each instance sharing the quota gets an equal part, which only a
+        // zero limit (always exceeded) leaves at zero
+        let share = limits.shared_instances.unwrap_or(1).max(1);
+        let ramp = self.ramp_up_share(limits);
+        let limit = |reported, absolute| {
+            Self::effective_limit(reported, absolute, limits.fraction_of_quota)
+                .map(|limit| (limit / share).max(limit.min(1)))
+                .map(|limit| ramp.map_or(limit, |ramp| (f64::from(limit) * ramp).ceil() as u32))
+        };
+        RateLimitsConfig {
+            requests_per_minute: limit(reported.requests_per_minute, limits.requests_per_minute),
+            tokens_per_minute: limit(reported.tokens_per_minute, limits.tokens_per_minute),
+            input_tokens_per_minute: limit(
+                reported.input_tokens_per_minute,
+                limits.input_tokens_per_minute,
+            ),
+            output_tokens_per_minute: limit(
+                reported.output_tokens_per_minute,
+                limits.output_tokens_per_minute,
+            ),
+        }
+    }
+
+    /// Share of each limit allowed while the ramp-up after start is running,
+    /// growing linearly from [`RAMP_UP_START`] to all of it
+    fn ramp_up_share(&self, limits: &RateLimits) -> Option<f64> {
+        let ramp = Duration::from_secs(u64::from(limits.ramp_up_seconds.unwrap_or(0)));
+        let elapsed = self.started.elapsed();
+        (elapsed < ramp).then(|| {
+            RAMP_UP_START + (1.0 - RAMP_UP_START) * elapsed.as_secs_f64() / ramp.as_secs_f64()
+        })
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn effective_limit(
+        reported: Option<u32>,
+        absolute: Option<u32>,
+        fraction: Option<f64>,
+    ) -> Option<u32> {
+        // Never scale a quota down to zero, which would block all usage
+        let scaled = reported.map(|limit| {
+            fraction.map_or(limit, |fraction| {
+                ((f64::from(limit) * fraction).floor() as u32).max(1)
+            })
+        });
+        match (scaled, absolute) {
+            (Some(scaled), Some(absolute)) => Some(scaled.min(absolute)),
+            (scaled, absolute) => scaled.or(absolute),
+        }
+    }
+
+    /// The thresholds this limiter checks usage against
+    #[must_use]
+    pub const fn thresholds(&self) -> &Thresholds {
+        &self.thresholds
+    }
+
+    /// Usage percentages computed by the most recent successful check
+    #[must_use]
+    pub const fn last_percentages(&self) -> UsagePercentages {
+        self.last_percentages
+    }
+
+    /// Headroom left under each limit this minute, as computed by the most
+    /// recent successful check
+    #[must_use]
+    pub const fn remaining(&self) -> UsageRemaining {
+        self.last_remaining
+    }
+
+    /// Band of the usage computed by the most recent successful check
+    #[must_use]
+    pub const fn last_band(&self) -> UsageBand {
+        self.last_band
+    }
+
+    /// Clear usage, the last check's results and the growth forecast
+    ///
+    /// Lets a limiter be reused for a new batch of work without rebuilding
+    /// it around the provider. Usage the provider counts locally is cleared
+    /// and a new per-minute window starts. Configuration, including the
+    /// schedule, is kept.
+    pub fn reset(&mut self) {
+        self.provider.reset_usage();
+        self.window_start = Instant::now();
+        self.usage = UsageStats::default();
+        self.last_percentages = UsagePercentages::default();
+        self.last_remaining = UsageRemaining::default();
+        self.set_band(UsageBand::default());
+        self.last_sample = None;
+        self.growth_rate = None;
+    }
+
+    /// Start a new per-minute window if the current one has ended at `at`
+    ///
+    /// Tells the provider to clear usage it counts locally and returns
+    /// whether a new window started.
+    pub fn roll_usage_window(&mut self, at: Instant) -> bool {
+        if at.saturating_duration_since(self.window_start) < USAGE_WINDOW {
+            return false;
+        }
+        self.provider.reset_usage();
+        self.window_start = at;
+        true
+    }
+
+    /// Record the peak usage of a check and update the smoothed growth rate
+    pub fn record_sample(&mut self, percent: u32, at: Instant) {
+        if let Some((last_at, last_percent)) = self.last_sample {
+            let elapsed = at.saturating_duration_since(last_at).as_secs_f64();
+            if elapsed > 0.0 {
+                let rate = (f64::from(percent) - f64::from(last_percent)) / elapsed;
+                self.growth_rate = Some(self.growth_rate.map_or(rate, |average| {
+                    GROWTH_RATE_SMOOTHING.mul_add(rate - average, average)
+                }));
+            }
+        }
+        self.last_sample = Some((at, percent));
+    }
+
+    /// Projected time until usage reaches the critical threshold
+    ///
+    /// Returns `None` when usage is not growing or is already critical.
+    #[must_use]
+    pub fn time_to_critical(&self) -> Option<Duration> {
+        let (_, percent) = self.last_sample?;
+        let rate = self.growth_rate.filter(|rate| *rate > 0.0)?;
+        let remaining = u32::from(self.thresholds.critical)
+            .checked_sub(percent)
+            .filter(|remaining| *remaining > 0)?;
+        Duration::try_from_secs_f64(f64::from(remaining) / rate).ok()
+    }
+
+    fn log_forecast(&self) {
+        let horizon = Duration::from_secs(u64::from(self.thresholds.forecast_horizon_seconds));
+        if horizon.is_zero() {
+            return;
+        }
+        if let Some(eta) = self.time_to_critical().filter(|eta| *eta <= horizon) {
+            info!(
+                "Usage projected to reach critical threshold ({}%) in {}s",
+                self.thresholds.critical,
+                eta.as_secs()
+            );
+        }
+    }
+
+    /// Calculate the usage percentage, rounded to a whole percent
+    ///
+    /// A limit of zero allows no usage at all: with nothing used it is exactly
+    /// reached, at 100%, and any usage is infinitely far over it. Usage far
+    /// beyond a limit saturates at `u32::MAX` percent.
+    #[allow(clippy::cast_possible_truncation)]
     #[must_use]
-    pub fn calculate_usage_percent(used: u32, limit: u32) -> u32 {
+    pub const fn calculate_usage_percent(used: u32, limit: u32, rounding: RoundingMode) -> u32 {
         if limit == 0 {
-            return 0;
+            return if used == 0 { 100 } else { u32::MAX };
         }
         // Use u64 for intermediate calculation to avoid overflow
-        let used = u64::from(used);
-        let limit = u64::from(limit);
+        let percent = rounding.divide(used as u64 * 100, limit as u64);
+        if percent > u32::MAX as u64 {
+            u32::MAX
+        } else {
+            percent as u32
+        }
+    }
+
+    /// Record the band of the latest check, ending any stay in the warning band
+    fn set_band(&mut self, band: UsageBand) {
+        self.last_band = band;
+        if band != UsageBand::Warning {
+            self.warning_since = None;
+            self.warning_escalated = false;
+        }
+    }
+
+    /// Track a check in the warning band at `now`
+    ///
+    /// Returns whether usage has stayed in the band for at least
+    /// `warning_sustained_seconds`, alerting once when it first has.
+    fn hold_warning(&mut self, now: Instant) -> bool {
+        let since = *self.warning_since.get_or_insert(now);
+        let sustained = self.thresholds.warning_sustained_seconds;
+        if sustained == 0
+            || now.saturating_duration_since(since) < Duration::from_secs(sustained.into())
+        {
+            return false;
+        }
+        if !self.warning_escalated {
+            self.warning_escalated = true;
+            if self.thresholds.pause_on_sustained_warning {
+                error!("Usage has stayed in the warning band for {sustained}s, pausing");
+            } else {
+                error!("Usage has stayed in the warning band for {sustained}s");
+            }
+        }
+        true
+    }
+
+    /// With `strict_usage`, fail on any limit reported above 100%
+    fn check_strict_usage(&self) -> Result<()> {
+        if !self.thresholds.strict_usage {
+            return Ok(());
+        }
+        let usage = &self.last_percentages;
+        let over = [
+            ("requests", usage.requests),
+            ("tokens", usage.tokens),
+            ("input tokens", usage.input_tokens),
+            ("output tokens", usage.output_tokens),
+        ]
+        .into_iter()
+        .find(|&(_, percent)| percent > 100);
+        match over {
+            Some((metric, percent)) => Err(anyhow!(
+                "Provider reports {metric} usage at {percent}% of the limit; \
+                 usage data may be stale or the limit misconfigured"
+            )),
+            None => Ok(()),
+        }
+    }
 
-        // Calculate percentage with high precision
-        let percent = (used * 100) / limit;
-        percent as u32
+    /// Decide how to proceed after the provider reported a failed call
+    fn handle_call_error(&self, error: &ProviderCallError) -> Result<(bool, Duration)> {
+        match error.kind {
+            ProviderErrorKind::RateLimited => {
+                let backoff = error
+                    .retry_after
+                    .unwrap_or_else(|| self.backoff.max_backoff());
+                warn!("Provider rate limited the request, backing off for {backoff:?}");
+                Ok((false, backoff))
+            }
+            ProviderErrorKind::ServerError | ProviderErrorKind::Network => {
+                // Transient failures are retried without touching usage state
+                warn!("Provider call failed ({:?}), retrying", error.kind);
+                Ok((true, self.backoff.min_backoff()))
+            }
+            ProviderErrorKind::Auth => Err(anyhow!("Provider rejected the API credentials")),
+        }
     }
 
     /// Check if any rate limits are exceeded and get appropriate backoff time
@@ -91,83 +542,336 @@ impl RateLimiter {
     /// - Rate limit data is invalid or corrupted
     /// - Provider communication fails
     pub fn check_limits(&mut self) -> Result<(bool, Duration)> {
+        self.check_provider_limits(None)
+    }
+
+    /// Check usage the provider pushed through [`RateLimiter::subscribe`]
+    ///
+    /// Like [`RateLimiter::check_limits`], with `pushed` in place of polling
+    /// the provider for usage.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider cannot report its limits, or if
+    /// `strict_usage` is set and usage is above a limit
+    pub fn check_pushed_limits(&mut self, pushed: &RateLimitInfo) -> Result<(bool, Duration)> {
+        self.check_provider_limits(Some(pushed))
+    }
+
+    /// Subscribe to usage pushed by the provider, if it streams usage
+    #[must_use]
+    pub fn subscribe(&self) -> Option<watch::Receiver<RateLimitInfo>> {
+        self.provider.subscribe()
+    }
+
+    /// Check the provider's limits against `pushed` usage, or its polled
+    /// usage when nothing was pushed
+    fn check_provider_limits(
+        &mut self,
+        pushed: Option<&RateLimitInfo>,
+    ) -> Result<(bool, Duration)> {
+        self.roll_usage_window(Instant::now());
+
+        // A failed provider call takes precedence over usage figures
+        if let Some(error) = self.provider.take_last_error() {
+            self.apply_schedule();
+            return self.handle_call_error(&error);
+        }
+
         // Get current usage and limits from provider
-        let rate_info = self.provider.get_rate_limits()?;
-        let rate_config = self.provider.get_rate_limits_config()?;
+        let (rate_info, rate_config) = if let Some(pushed) = pushed {
+            self.provider.record_pushed(pushed)?;
+            (pushed.clone(), self.provider.get_rate_limits_config()?)
+        } else {
+            (
+                self.provider.get_rate_limits()?,
+                self.provider.get_rate_limits_config()?,
+            )
+        };
+        // A check without a provider call breaks the streak, so a process
+        // paused on latency resumes after its backoff
+        match self.provider.take_latency() {
+            Some(latency) => self.latency.record(latency),
+            None => self.latency = LatencyTracker::default(),
+        }
+        let reset_at = self.provider.reset_at();
+        let (proceed, backoff) = self.check_limits_until(&rate_info, &rate_config, reset_at)?;
+        if proceed && self.latency_elevated() {
+            return Ok((false, self.backoff.max_backoff()));
+        }
+        Ok((proceed, backoff))
+    }
+
+    /// Whether recent provider calls were slow enough to back off before the
+    /// provider starts rate limiting
+    fn latency_elevated(&self) -> bool {
+        let threshold = self.thresholds.latency_backoff_ms;
+        if threshold == 0 || !self.latency.elevated(Duration::from_millis(threshold)) {
+            return false;
+        }
+        warn!(
+            "Provider latency above {threshold}ms for {LATENCY_BACKOFF_CHECKS} consecutive checks, backing off"
+        );
+        true
+    }
+
+    /// Average latency of the last few provider calls, if any were reported
+    #[must_use]
+    pub fn average_latency(&self) -> Option<Duration> {
+        self.latency.average()
+    }
+
+    /// Percentages of the current usage against each limit, zero for unset ones
+    fn usage_percentages(
+        &self,
+        rate_config: &RateLimitsConfig,
+        error_limit: Option<u32>,
+    ) -> UsagePercentages {
+        let percent = |used, limit: Option<u32>| {
+            limit.map_or(0, |limit| {
+                Self::calculate_usage_percent(used, limit, self.thresholds.rounding)
+            })
+        };
+        UsagePercentages {
+            requests: percent(self.usage.requests_used, rate_config.requests_per_minute),
+            tokens: percent(self.usage.tokens_used, rate_config.tokens_per_minute),
+            input_tokens: percent(
+                self.usage.input_tokens_used,
+                rate_config.input_tokens_per_minute,
+            ),
+            output_tokens: percent(
+                self.usage.output_tokens_used,
+                rate_config.output_tokens_per_minute,
+            ),
+            error_requests: percent(self.usage.error_requests_used, error_limit),
+        }
+    }
+
+    /// Record the headroom left under each limit and log it with the
+    /// percentages of the latest check
+    fn record_remaining(&mut self, rate_config: &RateLimitsConfig, error_limit: Option<u32>) {
+        let left = |used: u32, limit: Option<u32>| limit.map(|limit| limit.saturating_sub(used));
+        let remaining = [
+            left(self.usage.requests_used, rate_config.requests_per_minute),
+            left(self.usage.tokens_used, rate_config.tokens_per_minute),
+            left(
+                self.usage.input_tokens_used,
+                rate_config.input_tokens_per_minute,
+            ),
+            left(
+                self.usage.output_tokens_used,
+                rate_config.output_tokens_per_minute,
+            ),
+            left(self.usage.error_requests_used, error_limit),
+        ];
+        self.last_remaining = UsageRemaining {
+            requests: remaining[0],
+            tokens: remaining[1],
+            input_tokens: remaining[2],
+            output_tokens: remaining[3],
+            error_requests: remaining[4],
+        };
+
+        let p = self.last_percentages;
+        let usage = [
+            (
+                p.requests,
+                self.usage.requests_used,
+                rate_config.requests_per_minute,
+            ),
+            (
+                p.tokens,
+                self.usage.tokens_used,
+                rate_config.tokens_per_minute,
+            ),
+            (
+                p.input_tokens,
+                self.usage.input_tokens_used,
+                rate_config.input_tokens_per_minute,
+            ),
+            (
+                p.output_tokens,
+                self.usage.output_tokens_used,
+                rate_config.output_tokens_per_minute,
+            ),
+            (
+                p.error_requests,
+                self.usage.error_requests_used,
+                error_limit,
+            ),
+        ];
+        // Log current usage, with the headroom left under each set limit
+        let status = |index: usize| {
+            let (percent, used, limit) = usage[index];
+            let percent = self.display_percent(percent, used, limit);
+            remaining[index].map_or_else(
+                || format!("{percent}%"),
+                |remaining| format!("{percent}% ({remaining} left)"),
+            )
+        };
+        info!(
+            "Rate limit status - Requests: {}, Tokens: {}, Input Tokens: {}, Output Tokens: {}, Errors: {}",
+            status(0),
+            status(1),
+            status(2),
+            status(3),
+            status(4)
+        );
+    }
+
+    /// Format a usage percentage for logging, with the configured number of
+    /// decimal places
+    ///
+    /// The fractional figure is computed from `used` and `limit` for display
+    /// only; `percent` is the rounded value the thresholds are compared with,
+    /// and is shown as is for whole percents and zero or unset limits.
+    fn display_percent(&self, percent: u32, used: u32, limit: Option<u32>) -> String {
+        let precision = usize::from(self.thresholds.percent_precision);
+        match limit {
+            Some(limit) if precision > 0 && limit > 0 => {
+                format!("{:.precision$}", f64::from(used) * 100.0 / f64::from(limit))
+            }
+            _ => percent.to_string(),
+        }
+    }
+
+    /// Check usage the caller already has, without polling the provider
+    ///
+    /// For callers that read usage themselves, e.g. from the rate limit
+    /// headers of their own API responses. `config` holds the limits as the
+    /// provider reports them; configured limits and the schedule apply as in
+    /// [`RateLimiter::check_limits`], which delegates here.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `strict_usage` is set and usage is above a limit
+    pub fn check_limits_with(
+        &mut self,
+        info: &RateLimitInfo,
+        config: &RateLimitsConfig,
+    ) -> Result<(bool, Duration)> {
+        self.check_limits_until(info, config, None)
+    }
+
+    /// Back off until the provider's limits reset, kept within the backoff
+    /// range, or for the maximum backoff when the reset time is unknown
+    fn critical_backoff(&self, reset_at: Option<Instant>) -> Duration {
+        let now = Instant::now();
+        let Some(until_reset) = reset_at
+            .filter(|reset_at| *reset_at > now)
+            .map(|reset_at| reset_at - now)
+        else {
+            return self.backoff.max_backoff();
+        };
+        let backoff = until_reset
+            .min(self.backoff.max_backoff())
+            .max(self.backoff.min_backoff());
+        info!("Provider limits reset in {until_reset:?}, backing off for {backoff:?}");
+        backoff
+    }
+
+    /// [`RateLimiter::check_limits_with`], backing off at critical until
+    /// `reset_at` when the provider reported it
+    fn check_limits_until(
+        &mut self,
+        info: &RateLimitInfo,
+        config: &RateLimitsConfig,
+        reset_at: Option<Instant>,
+    ) -> Result<(bool, Duration)> {
+        self.apply_schedule();
+        let rate_config = self.effective_limits(config);
+        // Providers never report an error cap, so only a configured one applies
+        let error_limit = self
+            .limits
+            .as_ref()
+            .and_then(|limits| limits.error_requests_per_minute);
 
         // If all limits are None, allow proceeding with minimum backoff
         if rate_config.requests_per_minute.is_none()
             && rate_config.tokens_per_minute.is_none()
             && rate_config.input_tokens_per_minute.is_none()
+            && rate_config.output_tokens_per_minute.is_none()
+            && error_limit.is_none()
         {
-            return Ok((
-                true,
-                Duration::from_secs(u64::from(self.backoff.min_seconds)),
-            ));
+            self.last_remaining = UsageRemaining::default();
+            self.set_band(UsageBand::Normal);
+            return Ok((true, self.backoff.min_backoff()));
         }
 
         // Update internal usage stats
-        self.usage = UsageStats::new(
-            rate_info.requests_used,
-            rate_info.tokens_used,
-            rate_info.input_tokens_used,
-        );
-
-        // Calculate percentages for each limit type
-        let requests_percent = rate_config.requests_per_minute.map_or(0, |limit| {
-            Self::calculate_usage_percent(self.usage.requests_used, limit)
-        });
-
-        let tokens_percent = rate_config.tokens_per_minute.map_or(0, |limit| {
-            Self::calculate_usage_percent(self.usage.tokens_used, limit)
-        });
+        self.usage = UsageStats::new(info);
 
-        let input_tokens_percent = rate_config.input_tokens_per_minute.map_or(0, |limit| {
-            Self::calculate_usage_percent(self.usage.input_tokens_used, limit)
-        });
+        self.last_percentages = self.usage_percentages(&rate_config, error_limit);
+        let UsagePercentages {
+            requests: requests_percent,
+            tokens: tokens_percent,
+            input_tokens: input_tokens_percent,
+            output_tokens: output_tokens_percent,
+            error_requests: error_requests_percent,
+        } = self.last_percentages;
 
-        // Log current usage
-        info!(
-            "Rate limit status - Requests: {}%, Tokens: {}%, Input Tokens: {}%",
-            requests_percent, tokens_percent, input_tokens_percent
-        );
+        self.record_remaining(&rate_config, error_limit);
+        self.check_strict_usage()?;
+        let max_percent = self.last_percentages.max();
+        self.record_sample(max_percent, Instant::now());
+        self.log_forecast();
 
-        // Find the highest usage percentage
-        let max_percent = requests_percent
-            .max(tokens_percent)
-            .max(input_tokens_percent);
+        // Usage that decides whether critical is reached; the early return
+        // above guarantees at least one limit is set. Too many errors are
+        // critical on their own, whatever the gating.
+        let quota_percent = match self.thresholds.gating {
+            Gating::Any => max_percent,
+            Gating::All => [
+                (rate_config.requests_per_minute, requests_percent),
+                (rate_config.tokens_per_minute, tokens_percent),
+                (rate_config.input_tokens_per_minute, input_tokens_percent),
+                (rate_config.output_tokens_per_minute, output_tokens_percent),
+            ]
+            .into_iter()
+            .filter_map(|(limit, percent)| limit.map(|_| percent))
+            .min()
+            .unwrap_or(0),
+        };
+        let critical_percent = quota_percent.max(error_requests_percent);
 
         // Convert thresholds to u32 for comparison
         let critical = u32::from(self.thresholds.critical);
         let warning = u32::from(self.thresholds.warning);
         let resume = u32::from(self.thresholds.resume);
 
-        // Check thresholds in priority order
-        if max_percent >= critical {
+        // Check thresholds in priority order; a warning threshold equal to
+        // the critical one disables the warning band
+        let reached = |percent: u32, threshold: u32| {
+            if self.thresholds.threshold_inclusive {
+                percent >= threshold
+            } else {
+                percent > threshold
+            }
+        };
+        if reached(critical_percent, critical) {
+            if reached(error_requests_percent, critical) {
+                warn!("Failed requests at or above critical threshold ({critical}%), the provider may be down");
+            }
             warn!("Usage at or above critical threshold ({}%)", critical);
-            Ok((
-                false,
-                Duration::from_secs(u64::from(self.backoff.max_seconds)),
-            ))
-        } else if max_percent >= warning {
+            self.set_band(UsageBand::Critical);
+            Ok((false, self.critical_backoff(reset_at)))
+        } else if warning < critical && reached(max_percent, warning) {
             warn!("Usage at or above warning threshold ({}%)", warning);
-            Ok((
-                true,
-                Duration::from_secs(u64::from(self.backoff.min_seconds)),
-            ))
+            self.set_band(UsageBand::Warning);
+            if self.hold_warning(Instant::now()) && self.thresholds.pause_on_sustained_warning {
+                return Ok((false, self.backoff.max_backoff()));
+            }
+            Ok((true, self.backoff.min_backoff()))
         } else if max_percent <= resume {
-            // Reset usage stats when below resume threshold
-            self.usage = UsageStats::default();
-            Ok((
-                true,
-                Duration::from_secs(u64::from(self.backoff.min_seconds)),
-            ))
+            if self.thresholds.reset_on_resume {
+                self.usage = UsageStats::default();
+            }
+            self.set_band(UsageBand::Normal);
+            Ok((true, self.backoff.min_backoff()))
         } else {
             // Normal operation
-            Ok((
-                true,
-                Duration::from_secs(u64::from(self.backoff.min_seconds)),
-            ))
+            self.set_band(UsageBand::Normal);
+            Ok((true, self.backoff.min_backoff()))
         }
     }
 }
@@ -175,9 +879,12 @@ impl RateLimiter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::RateLimits;
-    use crate::providers::{RateLimitInfo, RateLimitsConfig};
+    use crate::config::{HumanDuration, RateLimits};
+    use crate::providers::{ProviderCallError, RateLimitInfo, RateLimitsConfig};
+    use std::collections::HashMap;
     use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Mutex;
+    use std::time::UNIX_EPOCH;
 
     // Basic validation tests
     #[test]
@@ -186,11 +893,19 @@ mod tests {
             requests_per_minute: Some(100),
             tokens_per_minute: Some(1000),
             input_tokens_per_minute: Some(500),
+            output_tokens_per_minute: Some(500),
+            error_requests_per_minute: None,
+            fraction_of_quota: None,
+            shared_instances: None,
+            ramp_up_seconds: None,
+            force: false,
+            by_model: HashMap::new(),
         };
 
         assert!(limits.requests_per_minute.unwrap() > 0);
         assert!(limits.tokens_per_minute.unwrap() > 0);
         assert!(limits.input_tokens_per_minute.unwrap() > 0);
+        assert!(limits.output_tokens_per_minute.unwrap() > 0);
     }
 
     #[test]
@@ -199,6 +914,16 @@ mod tests {
             warning: 30,
             critical: 50,
             resume: 25,
+            forecast_horizon_seconds: 300,
+            threshold_inclusive: true,
+            gating: Gating::Any,
+            rounding: RoundingMode::Floor,
+            strict_usage: false,
+            warning_sustained_seconds: 0,
+            pause_on_sustained_warning: false,
+            reset_on_resume: false,
+            latency_backoff_ms: 0,
+            percent_precision: 0,
         };
 
         assert!(thresholds.warning < thresholds.critical);
@@ -208,8 +933,8 @@ mod tests {
     #[test]
     fn test_backoff_validation() {
         let backoff = BackoffConfig {
-            min_seconds: 1,
-            max_seconds: 5,
+            min_seconds: HumanDuration::from_secs(1),
+            max_seconds: HumanDuration::from_secs(5),
         };
 
         assert!(backoff.min_seconds < backoff.max_seconds);
@@ -220,11 +945,21 @@ mod tests {
             warning: 30,
             critical: 50,
             resume: 25,
+            forecast_horizon_seconds: 300,
+            threshold_inclusive: true,
+            gating: Gating::Any,
+            rounding: RoundingMode::Floor,
+            strict_usage: false,
+            warning_sustained_seconds: 0,
+            pause_on_sustained_warning: false,
+            reset_on_resume: false,
+            latency_backoff_ms: 0,
+            percent_precision: 0,
         };
 
         let backoff = BackoffConfig {
-            min_seconds: 1,
-            max_seconds: 5,
+            min_seconds: HumanDuration::from_secs(1),
+            max_seconds: HumanDuration::from_secs(5),
         };
 
         RateLimiter::new(thresholds, backoff, Box::new(TestMockProvider::new()))
@@ -236,6 +971,7 @@ mod tests {
         assert_eq!(stats.requests_used, 0);
         assert_eq!(stats.tokens_used, 0);
         assert_eq!(stats.input_tokens_used, 0);
+        assert_eq!(stats.output_tokens_used, 0);
     }
 
     #[test]
@@ -244,16 +980,128 @@ mod tests {
         assert_eq!(limiter.thresholds.warning, 30);
         assert_eq!(limiter.thresholds.critical, 50);
         assert_eq!(limiter.thresholds.resume, 25);
-        assert_eq!(limiter.backoff.min_seconds, 1);
-        assert_eq!(limiter.backoff.max_seconds, 5);
+        assert_eq!(limiter.backoff.min_seconds, HumanDuration::from_secs(1));
+        assert_eq!(limiter.backoff.max_seconds, HumanDuration::from_secs(5));
     }
 
     #[test]
     fn test_calculate_usage_percent() {
-        assert_eq!(RateLimiter::calculate_usage_percent(50, 100), 50);
-        assert_eq!(RateLimiter::calculate_usage_percent(0, 100), 0);
-        assert_eq!(RateLimiter::calculate_usage_percent(100, 100), 100);
-        assert_eq!(RateLimiter::calculate_usage_percent(200, 100), 200);
+        assert_eq!(
+            RateLimiter::calculate_usage_percent(50, 100, RoundingMode::Floor),
+            50
+        );
+        assert_eq!(
+            RateLimiter::calculate_usage_percent(0, 100, RoundingMode::Floor),
+            0
+        );
+        assert_eq!(
+            RateLimiter::calculate_usage_percent(100, 100, RoundingMode::Floor),
+            100
+        );
+        assert_eq!(
+            RateLimiter::calculate_usage_percent(200, 100, RoundingMode::Floor),
+            200
+        );
+        assert_eq!(
+            RateLimiter::calculate_usage_percent(0, 0, RoundingMode::Floor),
+            100
+        );
+        assert_eq!(
+            RateLimiter::calculate_usage_percent(1, 0, RoundingMode::Floor),
+            u32::MAX
+        );
+    }
+
+    #[test]
+    fn test_usage_percent_near_max_values() {
+        for mode in [
+            RoundingMode::Floor,
+            RoundingMode::Ceil,
+            RoundingMode::Nearest,
+        ] {
+            let percent = |used, limit| RateLimiter::calculate_usage_percent(used, limit, mode);
+            assert_eq!(percent(u32::MAX, u32::MAX), 100);
+            assert_eq!(percent(u32::MAX / 2, u32::MAX - 1), 50);
+            assert_eq!(percent(0, u32::MAX), 0);
+            // 429496729500% does not fit in a u32 and saturates
+            assert_eq!(percent(u32::MAX, 1), u32::MAX);
+            assert_eq!(percent(u32::MAX, 100), u32::MAX);
+        }
+        assert_eq!(
+            RateLimiter::calculate_usage_percent(u32::MAX - 1, u32::MAX, RoundingMode::Floor),
+            99
+        );
+        assert_eq!(
+            RateLimiter::calculate_usage_percent(u32::MAX - 1, u32::MAX, RoundingMode::Ceil),
+            100
+        );
+    }
+
+    #[test]
+    fn test_effective_limit_near_max() {
+        let limit = |reported, absolute, fraction| {
+            RateLimiter::effective_limit(reported, absolute, fraction)
+        };
+        assert_eq!(limit(Some(u32::MAX), None, Some(1.0)), Some(u32::MAX));
+        assert_eq!(limit(Some(u32::MAX), None, Some(0.5)), Some(u32::MAX / 2));
+        assert_eq!(
+            limit(Some(u32::MAX), Some(u32::MAX - 1), None),
+            Some(u32::MAX - 1)
+        );
+    }
+
+    #[test]
+    fn test_usage_percent_rounding_modes() {
+        let percent = |used, mode| RateLimiter::calculate_usage_percent(used, 1000, mode);
+        assert_eq!(percent(755, RoundingMode::Floor), 75);
+        assert_eq!(percent(755, RoundingMode::Ceil), 76);
+        assert_eq!(percent(755, RoundingMode::Nearest), 76);
+        assert_eq!(percent(754, RoundingMode::Nearest), 75);
+        assert_eq!(percent(751, RoundingMode::Ceil), 76);
+
+        // Whole percentages are the same in every mode
+        for mode in [
+            RoundingMode::Floor,
+            RoundingMode::Ceil,
+            RoundingMode::Nearest,
+        ] {
+            assert_eq!(percent(750, mode), 75);
+            assert_eq!(RateLimiter::calculate_usage_percent(0, 0, mode), 100);
+        }
+    }
+
+    #[test]
+    fn test_display_percent_precision() {
+        let mut limiter = create_test_limiter();
+        assert_eq!(limiter.display_percent(49, 496, Some(1000)), "49");
+
+        limiter.thresholds.percent_precision = 1;
+        assert_eq!(limiter.display_percent(49, 496, Some(1000)), "49.6");
+        assert_eq!(limiter.display_percent(50, 504, Some(1000)), "50.4");
+        assert_eq!(limiter.display_percent(0, 0, Some(30)), "0.0");
+        // Unset and zero limits keep the whole percent
+        assert_eq!(limiter.display_percent(0, 5, None), "0");
+        assert_eq!(
+            limiter.display_percent(u32::MAX, 5, Some(0)),
+            u32::MAX.to_string()
+        );
+    }
+
+    #[test]
+    fn test_ceil_rounding_trips_critical_earlier() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        mock_provider(&limiter).set_limits(Some(1000), None, None);
+        mock_provider(&limiter)
+            .requests_used
+            .store(495, Ordering::Relaxed);
+
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(proceed, "49.5% rounds down to below critical");
+
+        limiter.thresholds.rounding = RoundingMode::Ceil;
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed, "49.5% rounds up to the critical 50%");
+        Ok(())
     }
 
     #[test]
@@ -313,44 +1161,580 @@ mod tests {
     }
 
     #[test]
-    fn test_mixed_usage() -> Result<()> {
+    fn test_last_percentages() -> Result<()> {
         let mut limiter = create_test_limiter();
+        assert_eq!(limiter.last_percentages(), UsagePercentages::default());
 
-        // Test with mixed usage levels
-        {
-            let mock_provider = limiter
-                .provider
-                .as_any()
-                .downcast_ref::<TestMockProvider>()
-                .unwrap();
-            mock_provider.requests_used.store(10, Ordering::Relaxed); // Below warning
-            mock_provider.tokens_used.store(400, Ordering::Relaxed); // Above warning
-            mock_provider
-                .input_tokens_used
-                .store(600, Ordering::Relaxed); // Above critical
-        }
+        let provider = mock_provider(&limiter);
+        provider.requests_used.store(20, Ordering::Relaxed);
+        provider.tokens_used.store(100, Ordering::Relaxed);
+        provider.input_tokens_used.store(200, Ordering::Relaxed);
+        limiter.check_limits()?;
 
-        let (proceed, _) = limiter.check_limits()?;
-        assert!(
-            !proceed,
-            "Should not proceed when any metric is above critical"
+        let percentages = limiter.last_percentages();
+        assert_eq!(percentages.requests, 20);
+        assert_eq!(percentages.tokens, 10);
+        assert_eq!(percentages.input_tokens, 40);
+        assert_eq!(percentages.max(), 40);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remaining_is_limit_minus_used() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        assert_eq!(limiter.remaining(), UsageRemaining::default());
+
+        let provider = mock_provider(&limiter);
+        provider.requests_used.store(20, Ordering::Relaxed);
+        provider.tokens_used.store(100, Ordering::Relaxed);
+        provider.input_tokens_used.store(200, Ordering::Relaxed);
+        limiter.check_limits()?;
+        // Limits are 100 requests, 1000 tokens and 500 input tokens
+        assert_eq!(
+            limiter.remaining(),
+            UsageRemaining {
+                requests: Some(100 - 20),
+                tokens: Some(1000 - 100),
+                input_tokens: Some(500 - 200),
+                output_tokens: None,
+                error_requests: None,
+            }
+        );
+
+        // Usage over a limit leaves nothing rather than wrapping around
+        mock_provider(&limiter)
+            .requests_used
+            .store(150, Ordering::Relaxed);
+        limiter.check_limits()?;
+        let remaining = limiter.remaining();
+        assert_eq!(remaining.requests, Some(0));
+        assert_eq!(
+            (remaining.tokens, remaining.input_tokens),
+            (Some(900), Some(300))
         );
 
+        limiter.reset();
+        assert_eq!(limiter.remaining(), UsageRemaining::default());
         Ok(())
     }
 
     #[test]
-    fn test_no_limits() -> Result<()> {
-        let mut limiter = create_test_limiter();
+    fn test_remaining_covers_output_tokens_and_errors() -> Result<()> {
+        let mut limiter = create_test_limiter().with_limits(RateLimits {
+            error_requests_per_minute: Some(10),
+            ..RateLimits::default()
+        });
+        let provider = mock_provider(&limiter);
+        provider.output_tokens_used.store(50, Ordering::Relaxed);
+        provider.limits.lock().unwrap().output_tokens_per_minute = Some(200);
+        limiter.check_limits()?;
 
-        // Test with no limits set
-        {
-            let mock_provider = limiter
-                .provider
-                .as_any()
+        let remaining = limiter.remaining();
+        assert_eq!(remaining.output_tokens, Some(150));
+        assert_eq!(remaining.error_requests, Some(10));
+        Ok(())
+    }
+
+    #[test]
+    fn test_remaining_uses_effective_limits() -> Result<()> {
+        let mut limiter = create_test_limiter().with_limits(RateLimits {
+            requests_per_minute: Some(40),
+            tokens_per_minute: None,
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: None,
+            error_requests_per_minute: None,
+            fraction_of_quota: None,
+            shared_instances: None,
+            ramp_up_seconds: None,
+            force: false,
+            by_model: HashMap::new(),
+        });
+        let provider = mock_provider(&limiter);
+        provider.requests_used.store(10, Ordering::Relaxed);
+        *provider.limits.lock().unwrap() = RateLimitsConfig {
+            requests_per_minute: Some(100),
+            tokens_per_minute: None,
+            input_tokens_per_minute: Some(500),
+            output_tokens_per_minute: None,
+        };
+        limiter.check_limits()?;
+        // The configured 40 requests is stricter than the reported 100, and
+        // an unset token limit leaves unlimited headroom
+        let remaining = limiter.remaining();
+        assert_eq!(remaining.requests, Some(30));
+        assert_eq!(remaining.tokens, None);
+        assert_eq!(remaining.input_tokens, Some(500));
+        Ok(())
+    }
+
+    #[test]
+    fn test_exactly_critical_is_reached_when_inclusive() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        assert!(limiter.thresholds().threshold_inclusive);
+        // 50 of 100 requests is exactly the 50% critical threshold
+        mock_provider(&limiter)
+            .requests_used
+            .store(50, Ordering::Relaxed);
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(!proceed, "Usage at critical should pause when inclusive");
+        assert_eq!(backoff, Duration::from_secs(5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_exactly_critical_is_below_when_exclusive() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        limiter.thresholds.threshold_inclusive = false;
+        let provider = mock_provider(&limiter);
+        provider.requests_used.store(50, Ordering::Relaxed);
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(proceed, "Usage at critical should not pause when exclusive");
+        assert_eq!(backoff, Duration::from_secs(1));
+
+        // 50.9% still rounds down to 50%, so only 51 requests trips critical
+        mock_provider(&limiter)
+            .requests_used
+            .store(51, Ordering::Relaxed);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed, "Usage above critical should pause");
+        Ok(())
+    }
+
+    #[test]
+    fn test_all_gating_ignores_single_metric_spike() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        limiter.thresholds.gating = Gating::All;
+        let provider = mock_provider(&limiter);
+        provider.requests_used.store(95, Ordering::Relaxed);
+        provider.tokens_used.store(100, Ordering::Relaxed);
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(proceed, "Requests alone should not pause under all gating");
+        assert_eq!(backoff, Duration::from_secs(1));
+
+        limiter.thresholds.gating = Gating::Any;
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed, "Requests alone should pause under any gating");
+        Ok(())
+    }
+
+    #[test]
+    fn test_all_gating_pauses_when_every_limit_is_critical() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        limiter.thresholds.gating = Gating::All;
+        let provider = mock_provider(&limiter);
+        provider.requests_used.store(95, Ordering::Relaxed);
+        provider.tokens_used.store(600, Ordering::Relaxed);
+        provider.input_tokens_used.store(300, Ordering::Relaxed);
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(!proceed, "Every limit at critical should pause");
+        assert_eq!(backoff, Duration::from_secs(5));
+
+        // Output tokens have no limit, so their low usage does not count
+        assert_eq!(limiter.last_percentages().output_tokens, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_to_critical_steady_ramp() {
+        let mut limiter = create_test_limiter();
+        let start = Instant::now();
+        assert_eq!(limiter.time_to_critical(), None);
+
+        // 5 percentage points per second towards a critical threshold of 50%
+        for (second, percent) in [(0, 10), (1, 15), (2, 20), (3, 25), (4, 30)] {
+            limiter.record_sample(percent, start + Duration::from_secs(second));
+        }
+
+        // 20 points left at 5 points per second
+        assert_eq!(limiter.time_to_critical(), Some(Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn test_time_to_critical_smooths_bursts() {
+        let mut limiter = create_test_limiter();
+        let start = Instant::now();
+        limiter.record_sample(10, start);
+        limiter.record_sample(12, start + Duration::from_secs(1));
+        limiter.record_sample(30, start + Duration::from_secs(2));
+
+        // The burst (18/s) is averaged with the earlier 2/s growth
+        let eta = limiter.time_to_critical().unwrap();
+        assert_eq!(eta, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_time_to_critical_none_when_not_growing() {
+        let mut limiter = create_test_limiter();
+        let start = Instant::now();
+        limiter.record_sample(40, start);
+        limiter.record_sample(30, start + Duration::from_secs(1));
+        assert_eq!(limiter.time_to_critical(), None);
+
+        // Already at critical there is nothing to forecast
+        limiter.record_sample(45, start + Duration::from_secs(2));
+        limiter.record_sample(60, start + Duration::from_secs(3));
+        assert_eq!(limiter.time_to_critical(), None);
+    }
+
+    #[test]
+    fn test_model_limits_apply_to_active_model() -> Result<()> {
+        let limits: RateLimits = toml::from_str(
+            r#"
+requests_per_minute = 1000
+
+[by_model."claude-3-opus"]
+requests_per_minute = 100
+
+[by_model."claude-3-haiku"]
+requests_per_minute = 4000
+"#,
+        )?;
+        // 200 requests are critical against opus's 100 rpm only
+        for (model, percent) in [
+            (Some("claude-3-opus"), 200),
+            (Some("claude-3-haiku"), 5),
+            (Some("claude-2"), 20),
+            (None, 20),
+        ] {
+            let mut limiter = create_test_limiter().with_limits(limits.for_model(model));
+            let provider = mock_provider(&limiter);
+            provider.set_limits(None, None, None);
+            provider.requests_used.store(200, Ordering::Relaxed);
+            let (proceed, _) = limiter.check_limits()?;
+            assert_eq!(proceed, percent < 50, "{model:?}");
+            assert_eq!(limiter.last_percentages().requests, percent, "{model:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_instances_divide_limits() -> Result<()> {
+        let limits = RateLimits {
+            requests_per_minute: Some(1000),
+            tokens_per_minute: None,
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: None,
+            error_requests_per_minute: None,
+            fraction_of_quota: None,
+            shared_instances: Some(4),
+            ramp_up_seconds: None,
+            force: false,
+            by_model: HashMap::new(),
+        };
+        let mut limiter = create_test_limiter().with_limits(limits);
+        limiter.thresholds.warning = 90;
+        limiter.thresholds.critical = 100;
+        let provider = mock_provider(&limiter);
+        provider.set_limits(None, None, None);
+
+        let effective = limiter.effective_limits(&provider.get_rate_limits_config()?);
+        assert_eq!(effective.requests_per_minute, Some(250));
+
+        provider.requests_used.store(249, Ordering::Relaxed);
+        assert!(limiter.check_limits()?.0);
+
+        // A quarter of the 1000 rpm quota is this instance's whole share
+        mock_provider(&limiter)
+            .requests_used
+            .store(250, Ordering::Relaxed);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed, "Should trip critical at 250 of 1000 rpm");
+        assert_eq!(limiter.last_percentages().requests, 100);
+
+        // Shares are never rounded down to zero, and zero stays zero
+        for (configured, share) in [(3, 1), (0, 0)] {
+            let limiter = create_test_limiter().with_limits(RateLimits {
+                requests_per_minute: Some(configured),
+                shared_instances: Some(4),
+                ..RateLimits::default()
+            });
+            let effective = limiter.effective_limits(&RateLimitsConfig {
+                requests_per_minute: None,
+                tokens_per_minute: None,
+                input_tokens_per_minute: None,
+                output_tokens_per_minute: None,
+            });
+            assert_eq!(effective.requests_per_minute, Some(share));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_ramp_up_lowers_limits_early() -> Result<()> {
+        let limits = RateLimits {
+            requests_per_minute: Some(1000),
+            tokens_per_minute: None,
+            ramp_up_seconds: Some(60),
+            ..RateLimits::default()
+        };
+        let mut limiter = create_test_limiter().with_limits(limits);
+        let provider = mock_provider(&limiter);
+        provider.set_limits(None, None, None);
+
+        // 400 requests are 40% of the full limit, below critical (50%), but
+        // the ramp starts at a tenth of it
+        provider.requests_used.store(400, Ordering::Relaxed);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed, "Should trip the ramped-down limit");
+        assert!(limiter.last_percentages().requests > 100);
+
+        // Halfway through, 55% of the limit is allowed
+        limiter.started = Instant::now().checked_sub(Duration::from_secs(30)).unwrap();
+        let effective = limiter.effective_limits(&RateLimitsConfig {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: None,
+        });
+        let halfway = effective.requests_per_minute.unwrap();
+        assert!((550..555).contains(&halfway), "{halfway}");
+
+        limiter.started = Instant::now().checked_sub(Duration::from_mins(1)).unwrap();
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(proceed, "Should allow 40% once the ramp is over");
+        assert_eq!(limiter.last_percentages().requests, 40);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fraction_of_quota_halves_reported_limit() -> Result<()> {
+        let limits = RateLimits {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: None,
+            error_requests_per_minute: None,
+            fraction_of_quota: Some(0.5),
+            shared_instances: None,
+            ramp_up_seconds: None,
+            force: false,
+            by_model: HashMap::new(),
+        };
+        let mut limiter = create_test_limiter().with_limits(limits);
+        let provider = mock_provider(&limiter);
+        provider.set_limits(Some(1000), None, None);
+
+        let effective = limiter.effective_limits(&provider.get_rate_limits_config()?);
+        assert_eq!(effective.requests_per_minute, Some(500));
+
+        // 240 of the provider's 1000 rpm is 48% of the effective 500
+        provider.requests_used.store(240, Ordering::Relaxed);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(proceed);
+        assert_eq!(limiter.last_percentages().requests, 48);
+
+        // 250 of 1000 rpm is only 25% of the quota but trips critical at 50%
+        mock_provider(&limiter)
+            .requests_used
+            .store(250, Ordering::Relaxed);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed, "Should trip critical at half the provider quota");
+        assert_eq!(limiter.last_percentages().requests, 50);
+        Ok(())
+    }
+
+    #[test]
+    fn test_force_limits_ignores_reported_limits() -> Result<()> {
+        let limits = RateLimits {
+            requests_per_minute: Some(100),
+            tokens_per_minute: None,
+            force: true,
+            ..RateLimits::default()
+        };
+        let mut limiter = create_test_limiter().with_limits(limits);
+        let provider = mock_provider(&limiter);
+        // A generous request quota and a tight token one
+        provider.set_limits(Some(10_000), Some(1000), None);
+
+        let effective = limiter.effective_limits(&provider.get_rate_limits_config()?);
+        assert_eq!(effective.requests_per_minute, Some(100));
+        assert_eq!(effective.tokens_per_minute, None);
+
+        // 60 requests are under 1% of the reported quota, but 60% of the
+        // forced limit
+        provider.requests_used.store(60, Ordering::Relaxed);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed, "Should trip critical at the forced limit");
+        assert_eq!(limiter.last_percentages().requests, 60);
+
+        // The reported token limit no longer applies at all
+        let provider = mock_provider(&limiter);
+        provider.requests_used.store(10, Ordering::Relaxed);
+        provider.tokens_used.store(900, Ordering::Relaxed);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(proceed, "Should ignore the reported token limit");
+        assert_eq!(limiter.last_percentages().tokens, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_configured_limits_fill_gaps_in_reported_limits() -> Result<()> {
+        let limits = RateLimits {
+            requests_per_minute: Some(500),
+            tokens_per_minute: None,
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: Some(40),
+            error_requests_per_minute: None,
+            fraction_of_quota: None,
+            shared_instances: None,
+            ramp_up_seconds: None,
+            force: false,
+            by_model: HashMap::new(),
+        };
+        let mut limiter = create_test_limiter().with_limits(limits);
+        let provider = mock_provider(&limiter);
+
+        let effective = limiter.effective_limits(&provider.get_rate_limits_config()?);
+        assert_eq!(
+            effective,
+            RateLimitsConfig {
+                requests_per_minute: Some(100),
+                tokens_per_minute: Some(1000),
+                input_tokens_per_minute: Some(500),
+                output_tokens_per_minute: Some(40),
+            },
+            "The stricter limit should apply and either side fill the gaps"
+        );
+
+        // The provider's 100 is stricter than the configured 500
+        provider.requests_used.store(80, Ordering::Relaxed);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed);
+        assert_eq!(limiter.last_percentages().requests, 80);
+        Ok(())
+    }
+
+    #[test]
+    fn test_configured_limit_below_reported_limit_trips_critical() -> Result<()> {
+        let limits = RateLimits {
+            requests_per_minute: Some(10),
+            tokens_per_minute: None,
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: None,
+            error_requests_per_minute: None,
+            fraction_of_quota: None,
+            shared_instances: None,
+            ramp_up_seconds: None,
+            force: false,
+            by_model: HashMap::new(),
+        };
+        let mut limiter = create_test_limiter().with_limits(limits);
+        let provider = mock_provider(&limiter);
+        provider.set_limits(Some(10_000), Some(1_000_000), None);
+        provider.requests_used.store(5, Ordering::Relaxed);
+
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(
+            !proceed,
+            "5 of the configured 10 requests is critical despite the provider's 10000"
+        );
+        assert_eq!(limiter.last_percentages().requests, 50);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stricter_of_absolute_and_fraction_wins() {
+        let reported = RateLimitsConfig {
+            requests_per_minute: Some(1000),
+            tokens_per_minute: Some(1000),
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: Some(3),
+        };
+        let limits = RateLimits {
+            requests_per_minute: Some(300),
+            tokens_per_minute: Some(800),
+            input_tokens_per_minute: Some(200),
+            output_tokens_per_minute: None,
+            error_requests_per_minute: None,
+            fraction_of_quota: Some(0.5),
+            shared_instances: None,
+            ramp_up_seconds: None,
+            force: false,
+            by_model: HashMap::new(),
+        };
+        let limiter = create_test_limiter().with_limits(limits);
+
+        let effective = limiter.effective_limits(&reported);
+        assert_eq!(effective.requests_per_minute, Some(300));
+        assert_eq!(effective.tokens_per_minute, Some(500));
+        assert_eq!(effective.input_tokens_per_minute, Some(200));
+        assert_eq!(effective.output_tokens_per_minute, Some(1));
+    }
+
+    #[test]
+    fn test_output_token_limit_trips_critical() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        let provider = mock_provider(&limiter);
+        provider.limits.lock().unwrap().output_tokens_per_minute = Some(200);
+        provider.requests_used.store(1, Ordering::Relaxed);
+        provider.tokens_used.store(10, Ordering::Relaxed);
+        provider.input_tokens_used.store(5, Ordering::Relaxed);
+        provider.output_tokens_used.store(150, Ordering::Relaxed);
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(!proceed, "Output tokens alone should trip critical");
+        assert_eq!(backoff, Duration::from_secs(5));
+        assert_eq!(limiter.last_percentages().output_tokens, 75);
+        assert_eq!(limiter.last_percentages().input_tokens, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_token_limit_unset_is_ignored() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        mock_provider(&limiter)
+            .output_tokens_used
+            .store(1_000_000, Ordering::Relaxed);
+
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(proceed, "Output tokens without a limit should not pause");
+        assert_eq!(limiter.last_percentages().output_tokens, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mixed_usage() -> Result<()> {
+        let mut limiter = create_test_limiter();
+
+        // Test with mixed usage levels
+        {
+            let mock_provider = limiter
+                .provider
+                .as_any()
+                .downcast_ref::<TestMockProvider>()
+                .unwrap();
+            mock_provider.requests_used.store(10, Ordering::Relaxed); // Below warning
+            mock_provider.tokens_used.store(400, Ordering::Relaxed); // Above warning
+            mock_provider
+                .input_tokens_used
+                .store(600, Ordering::Relaxed); // Above critical
+        }
+
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(
+            !proceed,
+            "Should not proceed when any metric is above critical"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_limits() -> Result<()> {
+        let mut limiter = create_test_limiter();
+
+        // Test with no limits set
+        {
+            let mock_provider = limiter
+                .provider
+                .as_any()
                 .downcast_ref::<TestMockProvider>()
                 .unwrap();
-            mock_provider.set_limits(Some(0), Some(0), Some(0)); // Set all limits to 0 to disable them
+            mock_provider.set_limits(None, None, None);
             mock_provider.requests_used.store(1000, Ordering::Relaxed);
             mock_provider.tokens_used.store(10000, Ordering::Relaxed);
             mock_provider
@@ -364,6 +1748,36 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sub_second_backoff() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        limiter.backoff.min_seconds = HumanDuration::from_millis(200);
+        limiter.backoff.max_seconds = HumanDuration::from_millis(750);
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(proceed);
+        assert_eq!(backoff, Duration::from_millis(200));
+
+        mock_provider(&limiter)
+            .requests_used
+            .store(95, Ordering::Relaxed);
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(!proceed);
+        assert_eq!(backoff, Duration::from_millis(750));
+        Ok(())
+    }
+
+    #[test]
+    fn test_zero_limit_is_always_exceeded() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        mock_provider(&limiter).set_limits(Some(0), None, None);
+
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed, "A zero limit should block even without usage");
+        assert_eq!(limiter.last_percentages().requests, 100);
+        Ok(())
+    }
+
     #[test]
     fn test_resume_threshold() -> Result<()> {
         let mut limiter = create_test_limiter();
@@ -405,14 +1819,861 @@ mod tests {
         Ok(())
     }
 
+    fn mock_provider(limiter: &RateLimiter) -> &TestMockProvider {
+        limiter
+            .provider
+            .as_any()
+            .downcast_ref::<TestMockProvider>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_last_band_follows_thresholds() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        let mut bands = Vec::new();
+        for requests in [10, 40, 60, 10] {
+            mock_provider(&limiter)
+                .requests_used
+                .store(requests, Ordering::Relaxed);
+            limiter.check_limits()?;
+            bands.push(limiter.last_band());
+        }
+        assert_eq!(
+            bands,
+            [
+                UsageBand::Normal,
+                UsageBand::Warning,
+                UsageBand::Critical,
+                UsageBand::Normal
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_equal_thresholds_skip_warning_band() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        limiter.thresholds.warning = 50;
+        limiter.thresholds.warning_sustained_seconds = 1;
+        let mut bands = Vec::new();
+        for requests in [10, 49, 50, 10] {
+            mock_provider(&limiter)
+                .requests_used
+                .store(requests, Ordering::Relaxed);
+            let (proceed, _) = limiter.check_limits()?;
+            assert_eq!(proceed, requests < 50, "{requests} requests");
+            assert!(limiter.warning_since.is_none());
+            bands.push(limiter.last_band());
+        }
+        assert_eq!(
+            bands,
+            [
+                UsageBand::Normal,
+                UsageBand::Normal,
+                UsageBand::Critical,
+                UsageBand::Normal
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_equal_thresholds_under_all_gating_never_warn() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        limiter.thresholds.warning = 50;
+        limiter.thresholds.gating = Gating::All;
+        mock_provider(&limiter)
+            .requests_used
+            .store(95, Ordering::Relaxed);
+
+        // One critical metric neither pauses nor warns
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(proceed);
+        assert_eq!(backoff, Duration::from_secs(1));
+        assert_eq!(limiter.last_band(), UsageBand::Normal);
+        Ok(())
+    }
+
+    /// 03:00 UTC
+    fn off_peak_clock() -> SystemTime {
+        UNIX_EPOCH + Duration::from_hours(3)
+    }
+
+    /// 12:00 UTC
+    fn peak_clock() -> SystemTime {
+        UNIX_EPOCH + Duration::from_hours(12)
+    }
+
+    fn test_schedule() -> Vec<ScheduleWindow> {
+        vec![
+            ScheduleWindow {
+                start_hour: 22,
+                end_hour: 8,
+                limits: None,
+                thresholds: Some(Thresholds {
+                    warning: 60,
+                    critical: 90,
+                    resume: 50,
+                    ..create_test_limiter().thresholds
+                }),
+            },
+            ScheduleWindow {
+                start_hour: 8,
+                end_hour: 20,
+                limits: Some(RateLimits {
+                    requests_per_minute: Some(50),
+                    tokens_per_minute: None,
+                    input_tokens_per_minute: None,
+                    output_tokens_per_minute: None,
+                    error_requests_per_minute: None,
+                    fraction_of_quota: None,
+                    shared_instances: None,
+                    ramp_up_seconds: None,
+                    force: false,
+                    by_model: HashMap::new(),
+                }),
+                thresholds: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_schedule_switches_limits_by_hour() -> Result<()> {
+        let mut limiter = create_test_limiter()
+            .with_schedule(test_schedule())
+            .with_clock(off_peak_clock);
+        mock_provider(&limiter)
+            .requests_used
+            .store(40, Ordering::Relaxed);
+
+        // Off-peak loosens the thresholds, so 40% is still normal
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(proceed);
+        assert_eq!(limiter.last_band(), UsageBand::Normal);
+
+        // Peak lowers the request limit to 50, putting 40 requests at 80%
+        limiter = limiter.with_clock(peak_clock);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed);
+        assert_eq!(limiter.last_percentages().requests, 80);
+        assert_eq!(limiter.thresholds().critical, 50);
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedule_restores_base_settings_outside_windows() -> Result<()> {
+        fn evening_clock() -> SystemTime {
+            UNIX_EPOCH + Duration::from_hours(21)
+        }
+
+        let mut limiter = create_test_limiter()
+            .with_limits(RateLimits {
+                requests_per_minute: Some(80),
+                tokens_per_minute: Some(1000),
+                input_tokens_per_minute: None,
+                output_tokens_per_minute: None,
+                error_requests_per_minute: None,
+                fraction_of_quota: None,
+                shared_instances: None,
+                ramp_up_seconds: None,
+                force: false,
+                by_model: HashMap::new(),
+            })
+            .with_schedule(test_schedule())
+            .with_clock(peak_clock);
+        limiter.check_limits()?;
+        let limits = limiter.limits.clone().unwrap();
+        assert_eq!(limits.requests_per_minute, Some(50));
+        assert_eq!(limits.tokens_per_minute, Some(1000));
+
+        limiter = limiter.with_clock(off_peak_clock);
+        limiter.check_limits()?;
+        assert_eq!(
+            limiter.limits.clone().unwrap().requests_per_minute,
+            Some(80)
+        );
+        assert_eq!(limiter.thresholds().critical, 90);
+
+        limiter = limiter.with_clock(evening_clock);
+        limiter.check_limits()?;
+        assert_eq!(
+            limiter.limits.clone().unwrap().requests_per_minute,
+            Some(80)
+        );
+        assert_eq!(limiter.thresholds().critical, 50);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_limits_with_external_usage() -> Result<()> {
+        // The provider reports no usage, so every band below comes from the
+        // figures passed in
+        let mut limiter = create_test_limiter();
+        let limits = RateLimitsConfig {
+            requests_per_minute: Some(200),
+            tokens_per_minute: None,
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: None,
+        };
+        let requests = |requests_used| RateLimitInfo {
+            requests_used,
+            tokens_used: 0,
+            input_tokens_used: 0,
+            output_tokens_used: 0,
+            error_requests_used: 0,
+        };
+
+        let (proceed, backoff) = limiter.check_limits_with(&requests(20), &limits)?;
+        assert!(proceed);
+        assert_eq!(backoff, Duration::from_secs(1));
+        assert_eq!(limiter.last_band(), UsageBand::Normal);
+        assert_eq!(limiter.last_percentages().requests, 10);
+
+        assert!(limiter.check_limits_with(&requests(80), &limits)?.0);
+        assert_eq!(limiter.last_band(), UsageBand::Warning);
+
+        let (proceed, backoff) = limiter.check_limits_with(&requests(120), &limits)?;
+        assert!(!proceed);
+        assert_eq!(backoff, Duration::from_secs(5));
+        assert_eq!(limiter.last_band(), UsageBand::Critical);
+        assert_eq!(limiter.usage.requests_used, 120);
+        Ok(())
+    }
+
+    fn error_limited(error_requests_per_minute: Option<u32>, gating: Gating) -> RateLimiter {
+        let mut limiter = create_test_limiter().with_limits(RateLimits {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: None,
+            error_requests_per_minute,
+            fraction_of_quota: None,
+            shared_instances: None,
+            ramp_up_seconds: None,
+            force: false,
+            by_model: HashMap::new(),
+        });
+        limiter.thresholds.gating = gating;
+        limiter
+    }
+
+    const fn requests_with_errors(requests_used: u32, error_requests_used: u32) -> RateLimitInfo {
+        RateLimitInfo {
+            requests_used,
+            tokens_used: 0,
+            input_tokens_used: 0,
+            output_tokens_used: 0,
+            error_requests_used,
+        }
+    }
+
+    const REQUEST_QUOTA: RateLimitsConfig = RateLimitsConfig {
+        requests_per_minute: Some(100),
+        tokens_per_minute: Some(1000),
+        input_tokens_per_minute: None,
+        output_tokens_per_minute: None,
+    };
+
+    #[test]
+    fn test_error_rate_trips_critical_independently() -> Result<()> {
+        let mut limiter = error_limited(Some(10), Gating::Any);
+
+        // Successful requests are well within quota, errors are not
+        let (proceed, backoff) =
+            limiter.check_limits_with(&requests_with_errors(5, 6), &REQUEST_QUOTA)?;
+        assert!(!proceed, "Errors alone should trip critical");
+        assert_eq!(backoff, Duration::from_secs(5));
+        assert_eq!(limiter.last_percentages().requests, 5);
+        assert_eq!(limiter.last_percentages().error_requests, 60);
+        assert_eq!(limiter.last_band(), UsageBand::Critical);
+
+        // And the other way round, errors low while requests are critical
+        let (proceed, _) =
+            limiter.check_limits_with(&requests_with_errors(60, 1), &REQUEST_QUOTA)?;
+        assert!(!proceed);
+        assert_eq!(limiter.last_percentages().error_requests, 10);
+
+        let (proceed, _) =
+            limiter.check_limits_with(&requests_with_errors(5, 1), &REQUEST_QUOTA)?;
+        assert!(proceed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_rate_is_critical_under_all_gating() -> Result<()> {
+        // Gating on every quota limit does not hold back an outage
+        let mut limiter = error_limited(Some(10), Gating::All);
+        let (proceed, _) =
+            limiter.check_limits_with(&requests_with_errors(60, 6), &REQUEST_QUOTA)?;
+        assert!(!proceed);
+
+        // Without the errors, requests alone are not enough under `all`
+        let (proceed, _) =
+            limiter.check_limits_with(&requests_with_errors(60, 0), &REQUEST_QUOTA)?;
+        assert!(proceed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_rate_without_cap_is_ignored() -> Result<()> {
+        let mut limiter = error_limited(None, Gating::Any);
+        let (proceed, _) =
+            limiter.check_limits_with(&requests_with_errors(5, 1000), &REQUEST_QUOTA)?;
+        assert!(proceed);
+        assert_eq!(limiter.last_percentages().error_requests, 0);
+
+        // An error cap applies even when the provider reports no limits
+        let mut limiter = error_limited(Some(10), Gating::Any);
+        let unlimited = RateLimitsConfig {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            ..REQUEST_QUOTA
+        };
+        let (proceed, _) = limiter.check_limits_with(&requests_with_errors(0, 5), &unlimited)?;
+        assert!(!proceed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_limits_with_applies_configured_limits() -> Result<()> {
+        let mut limiter = create_test_limiter().with_limits(RateLimits {
+            requests_per_minute: Some(100),
+            ..RateLimits::default()
+        });
+        let info = RateLimitInfo {
+            requests_used: 60,
+            tokens_used: 0,
+            input_tokens_used: 0,
+            output_tokens_used: 0,
+            error_requests_used: 0,
+        };
+        let reported = RateLimitsConfig {
+            requests_per_minute: Some(1000),
+            tokens_per_minute: None,
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: None,
+        };
+
+        // 6% of the reported limit, but 60% of the stricter configured one
+        assert!(!limiter.check_limits_with(&info, &reported)?.0);
+        assert_eq!(limiter.last_percentages().requests, 60);
+
+        let unlimited = RateLimitsConfig {
+            requests_per_minute: None,
+            ..reported
+        };
+        let mut limiter = create_test_limiter();
+        assert!(limiter.check_limits_with(&info, &unlimited)?.0);
+        assert_eq!(limiter.last_band(), UsageBand::Normal);
+        Ok(())
+    }
+
+    #[test]
+    fn test_usage_kept_at_resume_threshold() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        mock_provider(&limiter)
+            .requests_used
+            .store(20, Ordering::Relaxed);
+
+        assert!(limiter.check_limits()?.0);
+        assert_eq!(limiter.usage.requests_used, 20);
+        assert_eq!(limiter.last_percentages().requests, 20);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reset_on_resume_zeroes_usage_until_next_check() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        limiter.thresholds.reset_on_resume = true;
+        mock_provider(&limiter)
+            .requests_used
+            .store(20, Ordering::Relaxed);
+
+        assert!(limiter.check_limits()?.0);
+        assert_eq!(limiter.usage.requests_used, 0);
+        assert_eq!(limiter.last_percentages().requests, 20);
+
+        // The provider's real figures come back on the next check
+        mock_provider(&limiter)
+            .requests_used
+            .store(40, Ordering::Relaxed);
+        assert!(limiter.check_limits()?.0);
+        assert_eq!(limiter.usage.requests_used, 40);
+        assert_eq!(limiter.last_band(), UsageBand::Warning);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reset_clears_accumulated_state() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        let start = Instant::now();
+        limiter.record_sample(10, start);
+        limiter.record_sample(20, start + Duration::from_secs(10));
+        mock_provider(&limiter)
+            .requests_used
+            .store(45, Ordering::Relaxed);
+        limiter.check_limits()?;
+        assert_eq!(limiter.usage.requests_used, 45);
+        assert_eq!(limiter.last_band(), UsageBand::Warning);
+        assert!(limiter.time_to_critical().is_some());
+
+        limiter.reset();
+        assert_eq!(limiter.usage.requests_used, 0);
+        assert_eq!(limiter.last_percentages(), UsagePercentages::default());
+        assert_eq!(limiter.last_band(), UsageBand::Normal);
+        assert!(limiter.last_sample.is_none());
+        assert!(limiter.time_to_critical().is_none());
+
+        // The provider is kept and the next check starts afresh
+        mock_provider(&limiter)
+            .requests_used
+            .store(5, Ordering::Relaxed);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(proceed);
+        assert_eq!(limiter.last_percentages().requests, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sustained_warning_escalates() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        limiter.thresholds.warning_sustained_seconds = 30;
+        mock_provider(&limiter)
+            .requests_used
+            .store(40, Ordering::Relaxed);
+        assert!(limiter.check_limits()?.0);
+        let since = limiter.warning_since.unwrap();
+
+        // Staying in the band keeps the original entry time
+        assert!(!limiter.hold_warning(since + Duration::from_secs(29)));
+        assert!(limiter.hold_warning(since + Duration::from_secs(31)));
+        assert!(limiter.warning_escalated);
+        assert_eq!(limiter.warning_since, Some(since));
+
+        // Without pause_on_sustained_warning the process keeps running
+        assert!(limiter.check_limits()?.0);
+
+        // Leaving the band starts the next stay afresh
+        mock_provider(&limiter)
+            .requests_used
+            .store(10, Ordering::Relaxed);
+        limiter.check_limits()?;
+        assert!(limiter.warning_since.is_none());
+        assert!(!limiter.warning_escalated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sustained_warning_can_pause() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        limiter.thresholds.warning_sustained_seconds = 30;
+        limiter.thresholds.pause_on_sustained_warning = true;
+        mock_provider(&limiter)
+            .requests_used
+            .store(40, Ordering::Relaxed);
+        assert!(limiter.check_limits()?.0);
+
+        limiter.warning_since = Instant::now().checked_sub(Duration::from_secs(31));
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(!proceed);
+        assert_eq!(backoff, limiter.backoff.max_backoff());
+        assert_eq!(limiter.last_band(), UsageBand::Warning);
+
+        // Disabled by default, however long the band is held
+        limiter.thresholds.warning_sustained_seconds = 0;
+        assert!(limiter.check_limits()?.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_usage_fails_above_limit() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        mock_provider(&limiter)
+            .requests_used
+            .store(150, Ordering::Relaxed);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed);
+
+        limiter.thresholds.strict_usage = true;
+        let err = limiter.check_limits().unwrap_err();
+        assert!(err.to_string().contains("requests usage at 150%"));
+
+        // Exactly at the limit is still a normal critical reading
+        mock_provider(&limiter)
+            .requests_used
+            .store(100, Ordering::Relaxed);
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(!proceed);
+        Ok(())
+    }
+
+    /// Provider that counts each poll as a request, like a local counter would
+    #[derive(Debug, Default)]
+    struct CountingProvider {
+        requests: AtomicU32,
+    }
+
+    impl Provider for CountingProvider {
+        fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+            Ok(RateLimitInfo {
+                requests_used: self.requests.fetch_add(1, Ordering::Relaxed) + 1,
+                tokens_used: 0,
+                input_tokens_used: 0,
+                output_tokens_used: 0,
+                error_requests_used: 0,
+            })
+        }
+
+        fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
+            Ok(RateLimitsConfig {
+                requests_per_minute: Some(10),
+                tokens_per_minute: None,
+                input_tokens_per_minute: None,
+                output_tokens_per_minute: None,
+            })
+        }
+
+        fn reset_usage(&mut self) {
+            *self.requests.get_mut() = 0;
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    #[test]
+    fn test_local_usage_resets_each_window() -> Result<()> {
+        let mut limiter = RateLimiter::new(
+            create_test_limiter().thresholds,
+            create_test_limiter().backoff,
+            Box::new(CountingProvider::default()),
+        );
+        let start = Instant::now();
+        limiter.window_start = start;
+        for _ in 0..3 {
+            limiter.check_limits()?;
+        }
+        assert_eq!(limiter.last_percentages().requests, 30);
+
+        assert!(!limiter.roll_usage_window(start + Duration::from_secs(30)));
+        limiter.check_limits()?;
+        assert_eq!(limiter.last_percentages().requests, 40);
+
+        assert!(limiter.roll_usage_window(start + Duration::from_secs(61)));
+        limiter.check_limits()?;
+        assert_eq!(limiter.last_percentages().requests, 10);
+        // The next window is measured from the rollover
+        assert!(!limiter.roll_usage_window(start + Duration::from_secs(100)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rate_limited_error_honors_retry_after() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        mock_provider(&limiter).set_last_error(429, Some("7"));
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(!proceed, "Should pause when rate limited");
+        assert_eq!(backoff, Duration::from_secs(7));
+
+        // The error is consumed, so the next check uses usage again
+        let (proceed, _) = limiter.check_limits()?;
+        assert!(proceed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rate_limited_error_without_retry_after() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        mock_provider(&limiter).set_last_error(429, None);
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(!proceed);
+        assert_eq!(backoff, Duration::from_secs(5));
+        Ok(())
+    }
+
+    /// Backoff at critical usage when the provider reports `reset_at`
+    fn critical_backoff_with_reset(reset_at: Option<Instant>) -> Result<Duration> {
+        let mut limiter = create_test_limiter();
+        let provider = mock_provider(&limiter);
+        provider.requests_used.store(60, Ordering::Relaxed);
+        *provider.reset_at.lock().unwrap() = reset_at;
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(!proceed, "Should pause at critical usage");
+        Ok(backoff)
+    }
+
+    #[test]
+    fn test_critical_backoff_waits_until_reset() -> Result<()> {
+        let backoff = critical_backoff_with_reset(Some(Instant::now() + Duration::from_secs(3)))?;
+        assert!(backoff <= Duration::from_secs(3));
+        assert!(backoff > Duration::from_millis(2900), "{backoff:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_critical_backoff_clamps_reset_to_range() -> Result<()> {
+        let far = critical_backoff_with_reset(Some(Instant::now() + Duration::from_mins(1)))?;
+        assert_eq!(far, Duration::from_secs(5));
+
+        let near = critical_backoff_with_reset(Some(Instant::now() + Duration::from_millis(200)))?;
+        assert_eq!(near, Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_critical_backoff_without_reset_uses_max() -> Result<()> {
+        assert_eq!(critical_backoff_with_reset(None)?, Duration::from_secs(5));
+
+        let past = Instant::now().checked_sub(Duration::from_secs(1));
+        assert_eq!(critical_backoff_with_reset(past)?, Duration::from_secs(5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_server_error_retries_without_updating_usage() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        let provider = mock_provider(&limiter);
+        provider.requests_used.store(60, Ordering::Relaxed);
+        provider.set_last_error(503, Some("30"));
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(proceed, "Server errors should not pause the process");
+        assert_eq!(backoff, Duration::from_secs(1));
+        assert_eq!(limiter.usage.requests_used, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_network_error_retries() -> Result<()> {
+        let mut limiter = create_test_limiter();
+        *mock_provider(&limiter).last_error.lock().unwrap() =
+            Some(ProviderCallError::new(ProviderErrorKind::Network));
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(proceed);
+        assert_eq!(backoff, Duration::from_secs(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_auth_error_is_fatal() {
+        let mut limiter = create_test_limiter();
+        mock_provider(&limiter).set_last_error(401, None);
+
+        let result = limiter.check_limits();
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("credentials"));
+    }
+
+    fn latency_limiter(delay_ms: Option<u64>, latency_backoff_ms: u64) -> RateLimiter {
+        let api = crate::config::ApiConfig {
+            provider_config: crate::providers::config::ProviderConfig::Mock(
+                crate::providers::config::MockConfig {
+                    delay_ms,
+                    ..Default::default()
+                },
+            ),
+            ..Default::default()
+        };
+        let provider = crate::providers::create_provider(&api).unwrap();
+        let thresholds = Thresholds {
+            latency_backoff_ms,
+            percent_precision: 0,
+            ..create_test_limiter().thresholds
+        };
+        RateLimiter::new(thresholds, BackoffConfig::default(), provider)
+    }
+
+    #[test]
+    fn test_latency_backoff_after_consecutive_slow_checks() -> Result<()> {
+        let mut limiter = latency_limiter(Some(20), 5);
+        assert!(limiter.check_limits()?.0);
+        assert!(limiter.check_limits()?.0);
+
+        let (proceed, backoff) = limiter.check_limits()?;
+        assert!(!proceed);
+        assert_eq!(backoff, BackoffConfig::default().max_backoff());
+        assert!(limiter.average_latency().unwrap() >= Duration::from_millis(20));
+        Ok(())
+    }
+
+    #[test]
+    fn test_latency_backoff_needs_slow_polls_and_a_threshold() -> Result<()> {
+        // Fast polls never back off
+        let mut limiter = latency_limiter(None, 1000);
+        for _ in 0..=LATENCY_BACKOFF_CHECKS {
+            assert!(limiter.check_limits()?.0);
+        }
+
+        // Slow polls are ignored while the threshold is 0
+        let mut limiter = latency_limiter(Some(5), 0);
+        for _ in 0..=LATENCY_BACKOFF_CHECKS {
+            assert!(limiter.check_limits()?.0);
+        }
+        assert!(limiter.average_latency().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_without_a_call_breaks_the_latency_streak() -> Result<()> {
+        use crate::providers::proxy::ProxyProvider;
+        use crate::proxy::ProxyUsage;
+        use std::sync::Arc;
+
+        let usage = Arc::new(ProxyUsage::new());
+        let provider = ProxyProvider::new(Arc::clone(&usage), latency_limiter(None, 0).provider);
+        let thresholds = Thresholds {
+            latency_backoff_ms: 5,
+            ..create_test_limiter().thresholds
+        };
+        let mut limiter = RateLimiter::new(thresholds, BackoffConfig::default(), provider.boxed());
+
+        let slow_check = |limiter: &mut RateLimiter| {
+            usage.record_latency(Duration::from_millis(20));
+            limiter.check_limits().map(|(proceed, _)| proceed)
+        };
+        assert!(slow_check(&mut limiter)?);
+        assert!(slow_check(&mut limiter)?);
+        assert!(!slow_check(&mut limiter)?);
+
+        // Nothing went through the proxy while paused
+        assert!(limiter.check_limits()?.0);
+        assert_eq!(limiter.average_latency(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_latency_tracker_needs_a_full_slow_streak() {
+        let threshold = Duration::from_millis(100);
+        let slow = Duration::from_millis(150);
+        let mut tracker = LatencyTracker::default();
+        assert_eq!(tracker.average(), None);
+
+        tracker.record(slow);
+        tracker.record(slow);
+        assert!(!tracker.elevated(threshold));
+        tracker.record(slow);
+        assert!(tracker.elevated(threshold));
+
+        // A single fast poll breaks the streak until it rolls out
+        tracker.record(Duration::from_millis(30));
+        assert!(!tracker.elevated(threshold));
+        assert_eq!(tracker.average(), Some(Duration::from_millis(110)));
+        tracker.record(slow);
+        tracker.record(slow);
+        assert!(!tracker.elevated(threshold));
+        tracker.record(slow);
+        assert!(tracker.elevated(threshold));
+    }
+
+    fn requests(requests_used: u32) -> RateLimitInfo {
+        RateLimitInfo {
+            requests_used,
+            tokens_used: 0,
+            input_tokens_used: 0,
+            output_tokens_used: 0,
+            error_requests_used: 0,
+        }
+    }
+
+    fn streaming_limiter() -> (RateLimiter, watch::Sender<RateLimitInfo>) {
+        let (sender, _) = watch::channel(requests(0));
+        let provider = StreamingProvider {
+            sender: sender.clone(),
+        };
+        let limiter = RateLimiter::new(
+            create_test_limiter().thresholds,
+            BackoffConfig::default(),
+            Box::new(provider),
+        );
+        (limiter, sender)
+    }
+
+    #[tokio::test]
+    async fn test_pushed_usage_drives_checks() -> Result<()> {
+        let (mut limiter, sender) = streaming_limiter();
+        let mut updates = limiter.subscribe().expect("provider streams usage");
+
+        // Polling the provider would fail, so every check uses pushed usage
+        for (used, expect_proceed) in [(10, true), (95, false), (20, true)] {
+            sender.send(requests(used))?;
+            updates.changed().await?;
+            let pushed = updates.borrow_and_update().clone();
+            let (proceed, _) = limiter.check_pushed_limits(&pushed)?;
+            assert_eq!(proceed, expect_proceed, "{used} requests");
+        }
+        assert!(limiter.check_limits().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pushed_usage_keeps_latest_update() -> Result<()> {
+        let (mut limiter, sender) = streaming_limiter();
+        let mut updates = limiter.subscribe().expect("provider streams usage");
+
+        for used in [90, 95, 5] {
+            sender.send(requests(used))?;
+        }
+        updates.changed().await?;
+        let pushed = updates.borrow_and_update().clone();
+        assert_eq!(pushed.requests_used, 5);
+        assert!(limiter.check_pushed_limits(&pushed)?.0);
+        assert!(!updates.has_changed()?);
+
+        // Dropping the provider's sender closes the stream
+        drop(sender);
+        drop(limiter);
+        assert!(updates.changed().await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_polled_provider_has_no_stream() {
+        assert!(create_test_limiter().subscribe().is_none());
+    }
+
+    /// Pushes usage through a channel and cannot be polled
+    #[derive(Debug)]
+    struct StreamingProvider {
+        sender: watch::Sender<RateLimitInfo>,
+    }
+
+    impl Provider for StreamingProvider {
+        fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+            anyhow::bail!("StreamingProvider only pushes usage")
+        }
+
+        fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
+            Ok(RateLimitsConfig {
+                requests_per_minute: Some(100),
+                tokens_per_minute: None,
+                input_tokens_per_minute: None,
+                output_tokens_per_minute: None,
+            })
+        }
+
+        fn subscribe(&self) -> Option<watch::Receiver<RateLimitInfo>> {
+            Some(self.sender.subscribe())
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
     #[derive(Debug)]
     struct TestMockProvider {
         requests_used: AtomicU32,
         tokens_used: AtomicU32,
         input_tokens_used: AtomicU32,
-        requests_limit: AtomicU32,
-        tokens_limit: AtomicU32,
-        input_tokens_limit: AtomicU32,
+        output_tokens_used: AtomicU32,
+        limits: Mutex<RateLimitsConfig>,
+        last_error: Mutex<Option<ProviderCallError>>,
+        reset_at: Mutex<Option<Instant>>,
     }
 
     impl TestMockProvider {
@@ -421,27 +2682,36 @@ mod tests {
                 requests_used: AtomicU32::new(0),
                 tokens_used: AtomicU32::new(0),
                 input_tokens_used: AtomicU32::new(0),
-                requests_limit: AtomicU32::new(100),
-                tokens_limit: AtomicU32::new(1000),
-                input_tokens_limit: AtomicU32::new(500),
+                output_tokens_used: AtomicU32::new(0),
+                limits: Mutex::new(RateLimitsConfig {
+                    requests_per_minute: Some(100),
+                    tokens_per_minute: Some(1000),
+                    input_tokens_per_minute: Some(500),
+                    output_tokens_per_minute: None,
+                }),
+                last_error: Mutex::new(None),
+                reset_at: Mutex::new(None),
             }
         }
 
+        fn set_last_error(&self, status: u16, retry_after: Option<&str>) {
+            let kind = ProviderErrorKind::from_status(status).unwrap();
+            *self.last_error.lock().unwrap() = Some(ProviderCallError {
+                kind,
+                retry_after: retry_after.and_then(crate::providers::parse_retry_after),
+            });
+        }
+
         fn set_limits(
             &self,
             requests: Option<u32>,
             tokens: Option<u32>,
             input_tokens: Option<u32>,
         ) {
-            if let Some(r) = requests {
-                self.requests_limit.store(r, Ordering::Relaxed);
-            }
-            if let Some(t) = tokens {
-                self.tokens_limit.store(t, Ordering::Relaxed);
-            }
-            if let Some(i) = input_tokens {
-                self.input_tokens_limit.store(i, Ordering::Relaxed);
-            }
+            let mut limits = self.limits.lock().unwrap();
+            limits.requests_per_minute = requests;
+            limits.tokens_per_minute = tokens;
+            limits.input_tokens_per_minute = input_tokens;
         }
     }
 
@@ -451,23 +2721,21 @@ mod tests {
                 requests_used: self.requests_used.load(Ordering::Relaxed),
                 tokens_used: self.tokens_used.load(Ordering::Relaxed),
                 input_tokens_used: self.input_tokens_used.load(Ordering::Relaxed),
+                output_tokens_used: self.output_tokens_used.load(Ordering::Relaxed),
+                error_requests_used: 0,
             })
         }
 
         fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
-            let requests = self.requests_limit.load(Ordering::Relaxed);
-            let tokens = self.tokens_limit.load(Ordering::Relaxed);
-            let input_tokens = self.input_tokens_limit.load(Ordering::Relaxed);
+            Ok(self.limits.lock().unwrap().clone())
+        }
 
-            Ok(RateLimitsConfig {
-                requests_per_minute: if requests > 0 { Some(requests) } else { None },
-                tokens_per_minute: if tokens > 0 { Some(tokens) } else { None },
-                input_tokens_per_minute: if input_tokens > 0 {
-                    Some(input_tokens)
-                } else {
-                    None
-                },
-            })
+        fn take_last_error(&self) -> Option<ProviderCallError> {
+            self.last_error.lock().unwrap().take()
+        }
+
+        fn reset_at(&self) -> Option<Instant> {
+            *self.reset_at.lock().unwrap()
         }
 
         fn as_any(&self) -> &dyn std::any::Any {
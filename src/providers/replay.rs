@@ -0,0 +1,402 @@
+use crate::config::{BackoffConfig, RateLimits, Thresholds};
+use crate::providers::rate_limiter::{RateLimiter, UsagePercentages};
+use crate::providers::{Provider, ProviderCallError, RateLimitInfo, RateLimitsConfig};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+/// A usage reading seen during a run, one per line of a `run --record` file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub usage: RateLimitInfo,
+    /// Limits reported by the provider, before configured limits are applied
+    pub limits: RateLimitsConfig,
+}
+
+/// Provider that appends every usage reading of another provider to a file
+#[derive(Debug)]
+pub struct RecordingProvider {
+    inner: Box<dyn Provider>,
+    output: Mutex<File>,
+}
+
+impl RecordingProvider {
+    /// Start recording to `path`, replacing any existing recording
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be created
+    pub fn new(path: &Path, inner: Box<dyn Provider>) -> Result<Self> {
+        let output = File::create(path)
+            .with_context(|| format!("Failed to create recording {}", path.display()))?;
+        Ok(Self {
+            inner,
+            output: Mutex::new(output),
+        })
+    }
+
+    /// Append a reading of `usage` to the recording
+    #[allow(clippy::cast_possible_truncation)]
+    fn record(&self, usage: &RateLimitInfo) -> Result<()> {
+        let record = UsageRecord {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or(Duration::ZERO)
+                .as_millis() as u64,
+            usage: usage.clone(),
+            limits: self.inner.get_rate_limits_config()?,
+        };
+        let line = serde_json::to_string(&record)?;
+        writeln!(
+            self.output.lock().unwrap_or_else(PoisonError::into_inner),
+            "{line}"
+        )
+        .context("Failed to write usage recording")
+    }
+}
+
+impl Provider for RecordingProvider {
+    fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+        let usage = self.inner.get_rate_limits()?;
+        self.record(&usage)?;
+        Ok(usage)
+    }
+
+    fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
+        self.inner.get_rate_limits_config()
+    }
+
+    fn take_last_error(&self) -> Option<ProviderCallError> {
+        self.inner.take_last_error()
+    }
+
+    fn take_latency(&self) -> Option<Duration> {
+        self.inner.take_latency()
+    }
+
+    fn reset_at(&self) -> Option<Instant> {
+        self.inner.reset_at()
+    }
+
+    fn reset_usage(&mut self) {
+        self.inner.reset_usage();
+    }
+
+    fn subscribe(&self) -> Option<watch::Receiver<RateLimitInfo>> {
+        self.inner.subscribe()
+    }
+
+    /// Pushed readings are recorded like polled ones, so a replay, which
+    /// polls every reading, reaches the same decisions
+    fn record_pushed(&self, usage: &RateLimitInfo) -> Result<()> {
+        self.record(usage)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Provider that plays back recorded readings in order
+///
+/// Each call to `get_rate_limits` moves to the next reading, whose limits are
+/// then reported by `get_rate_limits_config`, matching the order in which
+/// [`RateLimiter::check_limits`] queries a provider.
+#[derive(Debug)]
+pub struct ReplayProvider {
+    records: Mutex<VecDeque<UsageRecord>>,
+    current: Mutex<Option<RateLimitsConfig>>,
+}
+
+impl ReplayProvider {
+    #[must_use]
+    pub fn new(records: Vec<UsageRecord>) -> Self {
+        Self {
+            records: Mutex::new(records.into()),
+            current: Mutex::new(None),
+        }
+    }
+}
+
+impl Provider for ReplayProvider {
+    fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+        let record = self
+            .records
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop_front()
+            .ok_or_else(|| anyhow!("No recorded readings left to replay"))?;
+        *self.current.lock().unwrap_or_else(PoisonError::into_inner) = Some(record.limits);
+        Ok(record.usage)
+    }
+
+    fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
+        self.current
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .ok_or_else(|| anyhow!("No recorded reading has been replayed yet"))
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Read a recording written by `run --record`
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or a line is not a valid record
+pub fn read_records(path: &Path) -> Result<Vec<UsageRecord>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read recording {}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).with_context(|| {
+                format!("Invalid record on line {} of {}", index + 1, path.display())
+            })
+        })
+        .collect()
+}
+
+/// The limiter's decision for one replayed reading
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayDecision {
+    pub timestamp_ms: u64,
+    pub percentages: UsagePercentages,
+    pub proceed: bool,
+    pub backoff: Duration,
+}
+
+/// Feed recorded readings through a limiter and collect its decisions
+///
+/// # Errors
+///
+/// Returns an error if the limiter fails to evaluate a reading
+pub fn replay(
+    records: &[UsageRecord],
+    thresholds: Thresholds,
+    backoff: BackoffConfig,
+    limits: RateLimits,
+) -> Result<Vec<ReplayDecision>> {
+    let provider = ReplayProvider::new(records.to_vec());
+    let mut limiter = RateLimiter::new(thresholds, backoff, Box::new(provider)).with_limits(limits);
+
+    records
+        .iter()
+        .map(|record| {
+            let (proceed, backoff) = limiter.check_limits()?;
+            Ok(ReplayDecision {
+                timestamp_ms: record.timestamp_ms,
+                percentages: limiter.last_percentages(),
+                proceed,
+                backoff,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HumanDuration;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    /// Provider returning a fixed sequence of readings, like a live run would see
+    #[derive(Debug)]
+    struct ScriptedProvider {
+        readings: Mutex<VecDeque<u32>>,
+        reset_at: Option<Instant>,
+    }
+
+    impl Provider for ScriptedProvider {
+        fn get_rate_limits(&self) -> Result<RateLimitInfo> {
+            Ok(usage(self.readings.lock().unwrap().pop_front().unwrap()))
+        }
+
+        fn get_rate_limits_config(&self) -> Result<RateLimitsConfig> {
+            Ok(RateLimitsConfig {
+                requests_per_minute: Some(100),
+                tokens_per_minute: Some(1000),
+                input_tokens_per_minute: None,
+                output_tokens_per_minute: None,
+            })
+        }
+
+        fn reset_at(&self) -> Option<Instant> {
+            self.reset_at
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    const READINGS: [u32; 6] = [10, 60, 85, 95, 40, 5];
+
+    fn usage(requests_used: u32) -> RateLimitInfo {
+        RateLimitInfo {
+            requests_used,
+            tokens_used: requests_used * 10,
+            input_tokens_used: 0,
+            output_tokens_used: 0,
+            error_requests_used: 0,
+        }
+    }
+
+    fn thresholds() -> Thresholds {
+        Thresholds {
+            warning: 80,
+            critical: 90,
+            resume: 50,
+            ..Thresholds::default()
+        }
+    }
+
+    /// Enforce the recorded provider limits as reported
+    fn provider_limits() -> RateLimits {
+        RateLimits {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            input_tokens_per_minute: None,
+            output_tokens_per_minute: None,
+            error_requests_per_minute: None,
+            fraction_of_quota: None,
+            shared_instances: None,
+            ramp_up_seconds: None,
+            force: false,
+            by_model: HashMap::new(),
+        }
+    }
+
+    fn backoff() -> BackoffConfig {
+        BackoffConfig {
+            min_seconds: HumanDuration::from_secs(1),
+            max_seconds: HumanDuration::from_secs(30),
+        }
+    }
+
+    #[test]
+    fn test_replay_reproduces_recorded_decisions() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("usage.jsonl");
+
+        let scripted = ScriptedProvider {
+            readings: Mutex::new(READINGS.into()),
+            reset_at: None,
+        };
+        let recording = RecordingProvider::new(&path, Box::new(scripted)).unwrap();
+        let mut limiter = RateLimiter::new(thresholds(), backoff(), Box::new(recording))
+            .with_limits(provider_limits());
+        let live: Vec<(UsagePercentages, bool, Duration)> = READINGS
+            .iter()
+            .map(|_| {
+                let (proceed, backoff) = limiter.check_limits().unwrap();
+                (limiter.last_percentages(), proceed, backoff)
+            })
+            .collect();
+
+        let records = read_records(&path).unwrap();
+        assert_eq!(records.len(), READINGS.len());
+        let replayed: Vec<(UsagePercentages, bool, Duration)> =
+            replay(&records, thresholds(), backoff(), provider_limits())
+                .unwrap()
+                .into_iter()
+                .map(|decision| (decision.percentages, decision.proceed, decision.backoff))
+                .collect();
+
+        assert_eq!(replayed, live);
+        assert!(!live[3].1, "95% should have paused the live run");
+    }
+
+    #[test]
+    fn test_replay_reproduces_pushed_decisions() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("usage.jsonl");
+
+        let scripted = ScriptedProvider {
+            readings: Mutex::new(VecDeque::from([10, 40])),
+            reset_at: None,
+        };
+        let recording = RecordingProvider::new(&path, Box::new(scripted)).unwrap();
+        let mut limiter = RateLimiter::new(thresholds(), backoff(), Box::new(recording))
+            .with_limits(provider_limits());
+        let live = vec![
+            limiter.check_limits().unwrap(),
+            limiter.check_pushed_limits(&usage(95)).unwrap(),
+            limiter.check_limits().unwrap(),
+        ];
+
+        let records = read_records(&path).unwrap();
+        let recorded: Vec<u32> = records.iter().map(|r| r.usage.requests_used).collect();
+        assert_eq!(recorded, [10, 95, 40]);
+        let replayed: Vec<(bool, Duration)> =
+            replay(&records, thresholds(), backoff(), provider_limits())
+                .unwrap()
+                .into_iter()
+                .map(|decision| (decision.proceed, decision.backoff))
+                .collect();
+        assert_eq!(replayed, live);
+        assert!(!live[1].0, "The pushed 95% should have paused the live run");
+    }
+
+    #[test]
+    fn test_recording_provider_forwards_reset_at() {
+        let dir = TempDir::new().unwrap();
+        let reset_at = Instant::now() + Duration::from_secs(20);
+        let scripted = ScriptedProvider {
+            readings: Mutex::new(READINGS.into()),
+            reset_at: Some(reset_at),
+        };
+        let recording =
+            RecordingProvider::new(&dir.path().join("usage.jsonl"), Box::new(scripted)).unwrap();
+        assert_eq!(recording.reset_at(), Some(reset_at));
+
+        // Critical usage backs off until the reset rather than the maximum
+        let mut limiter = RateLimiter::new(thresholds(), backoff(), Box::new(recording))
+            .with_limits(provider_limits());
+        let backoffs: Vec<Duration> = READINGS
+            .iter()
+            .map(|_| limiter.check_limits().unwrap())
+            .filter(|(proceed, _)| !proceed)
+            .map(|(_, backoff)| backoff)
+            .collect();
+        assert!(!backoffs.is_empty());
+        assert!(
+            backoffs
+                .iter()
+                .all(|backoff| *backoff <= Duration::from_secs(20)),
+            "{backoffs:?}"
+        );
+    }
+
+    #[test]
+    fn test_replay_provider_runs_out_of_readings() {
+        let provider = ReplayProvider::new(Vec::new());
+        assert!(provider.get_rate_limits_config().is_err());
+        assert!(provider.get_rate_limits().is_err());
+    }
+
+    #[test]
+    fn test_read_records_reports_bad_line() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("usage.jsonl");
+        std::fs::write(&path, "\nnot json\n").unwrap();
+
+        let err = read_records(&path).unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+    }
+}
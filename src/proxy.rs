@@ -0,0 +1,1264 @@
+use crate::gate::ACCEPT_RETRY_DELAY;
+use crate::providers::{
+    parse_reset_duration, parse_reset_time, parse_retry_after, ProviderCallError,
+    ProviderErrorKind, RateLimitInfo,
+};
+use crate::tokens::estimate_body_tokens;
+use anyhow::{anyhow, Context, Result};
+use rand_core::{OsRng, RngCore};
+use reqwest::{Client, Method, Response, Url};
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Length of the window over which proxied usage is accumulated
+const USAGE_WINDOW: Duration = Duration::from_mins(1);
+
+/// Maximum size of a request head we are willing to buffer
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Headers that describe a single hop and must not be forwarded
+const HOP_HEADERS: [&str; 6] = [
+    "connection",
+    "content-length",
+    "host",
+    "proxy-connection",
+    "transfer-encoding",
+    "accept-encoding",
+];
+
+/// Headers in which providers report when a rate limit window resets, as a
+/// time (Anthropic) or the time left (`OpenAI`)
+const RESET_HEADERS: [&str; 6] = [
+    "anthropic-ratelimit-requests-reset",
+    "anthropic-ratelimit-tokens-reset",
+    "anthropic-ratelimit-input-tokens-reset",
+    "anthropic-ratelimit-output-tokens-reset",
+    "x-ratelimit-reset-requests",
+    "x-ratelimit-reset-tokens",
+];
+
+/// Token counts that successful responses left out during the current window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MissingMetrics {
+    pub input_tokens: bool,
+    pub output_tokens: bool,
+}
+
+impl MissingMetrics {
+    /// Whether any response left out a token count
+    #[must_use]
+    pub const fn any(&self) -> bool {
+        self.input_tokens || self.output_tokens
+    }
+}
+
+/// Request and token counts observed by the proxy during the current window
+#[derive(Debug)]
+pub struct ProxyUsage {
+    window: Mutex<(Instant, RateLimitInfo, MissingMetrics)>,
+    last_error: Mutex<Option<ProviderCallError>>,
+    /// When the provider last said its limits reset
+    reset_at: Mutex<Option<Instant>>,
+    /// Round-trip time of the latest upstream call not yet taken
+    latency: Mutex<Option<Duration>>,
+    /// Usage of the current window, sent each time a request is recorded
+    updates: watch::Sender<RateLimitInfo>,
+}
+
+impl Default for ProxyUsage {
+    fn default() -> Self {
+        Self {
+            window: Mutex::new((Instant::now(), empty_usage(), MissingMetrics::default())),
+            last_error: Mutex::new(None),
+            reset_at: Mutex::new(None),
+            latency: Mutex::new(None),
+            updates: watch::Sender::new(empty_usage()),
+        }
+    }
+}
+
+impl ProxyUsage {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a single proxied request and the tokens it consumed
+    pub fn record(&self, input_tokens: u32, output_tokens: u32) {
+        self.record_reported(Some(input_tokens), Some(output_tokens));
+    }
+
+    /// Record a single proxied request whose response may have left out a
+    /// token count
+    ///
+    /// Missing counts add nothing to usage but are remembered for the rest of
+    /// the window, see [`ProxyUsage::missing_metrics`].
+    pub fn record_reported(&self, input_tokens: Option<u32>, output_tokens: Option<u32>) {
+        let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+        Self::roll(&mut window);
+        let (_, info, missing) = &mut *window;
+        let (input, output) = (input_tokens.unwrap_or(0), output_tokens.unwrap_or(0));
+        info.requests_used = info.requests_used.saturating_add(1);
+        info.tokens_used = info
+            .tokens_used
+            .saturating_add(input.saturating_add(output));
+        info.input_tokens_used = info.input_tokens_used.saturating_add(input);
+        info.output_tokens_used = info.output_tokens_used.saturating_add(output);
+        missing.input_tokens |= input_tokens.is_none();
+        missing.output_tokens |= output_tokens.is_none();
+        self.updates.send_replace(info.clone());
+        drop(window);
+    }
+
+    /// Record a failed upstream call, counting it as an error request
+    pub fn record_error(&self, error: ProviderCallError) {
+        let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+        Self::roll(&mut window);
+        window.1.error_requests_used = window.1.error_requests_used.saturating_add(1);
+        *self
+            .last_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(error);
+        self.updates.send_replace(window.1.clone());
+        drop(window);
+    }
+
+    /// Subscribe to the usage of the current window, updated as each
+    /// request is recorded
+    ///
+    /// A window that ends is not announced; the next recorded request
+    /// starts the new one.
+    #[must_use]
+    pub fn subscribe(&self) -> watch::Receiver<RateLimitInfo> {
+        self.updates.subscribe()
+    }
+
+    /// Take the most recent failed upstream call, if any
+    #[must_use]
+    pub fn take_last_error(&self) -> Option<ProviderCallError> {
+        self.last_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    /// Record the round-trip time of an upstream call, replacing any not yet
+    /// taken
+    pub fn record_latency(&self, latency: Duration) {
+        *self.latency.lock().unwrap_or_else(PoisonError::into_inner) = Some(latency);
+    }
+
+    /// Take the round-trip time of the latest upstream call, if any
+    #[must_use]
+    pub fn take_latency(&self) -> Option<Duration> {
+        self.latency
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    /// Record that the provider's limits reset `after` from now, replacing
+    /// any earlier report
+    pub fn record_reset(&self, after: Duration) {
+        *self.reset_at.lock().unwrap_or_else(PoisonError::into_inner) =
+            Some(Instant::now() + after);
+    }
+
+    /// When the provider's limits reset, unless that has already passed
+    #[must_use]
+    pub fn reset_at(&self) -> Option<Instant> {
+        self.reset_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .filter(|reset_at| *reset_at > Instant::now())
+    }
+
+    /// Get the usage accumulated in the current window
+    #[must_use]
+    pub fn snapshot(&self) -> RateLimitInfo {
+        let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+        Self::roll(&mut window);
+        window.1.clone()
+    }
+
+    /// Get the token counts left out by responses in the current window
+    #[must_use]
+    pub fn missing_metrics(&self) -> MissingMetrics {
+        let mut window = self.window.lock().unwrap_or_else(PoisonError::into_inner);
+        Self::roll(&mut window);
+        window.2
+    }
+
+    fn roll(window: &mut (Instant, RateLimitInfo, MissingMetrics)) {
+        if elapsed_saturating(window.0, USAGE_WINDOW) >= USAGE_WINDOW {
+            *window = (Instant::now(), empty_usage(), MissingMetrics::default());
+        }
+    }
+}
+
+/// Time elapsed since `since`, clamped to the range `[0, cap]`
+///
+/// A timestamp in the future yields zero and a very old one never exceeds
+/// the window.
+fn elapsed_saturating(since: Instant, cap: Duration) -> Duration {
+    Instant::now().saturating_duration_since(since).min(cap)
+}
+
+const fn empty_usage() -> RateLimitInfo {
+    RateLimitInfo {
+        requests_used: 0,
+        tokens_used: 0,
+        input_tokens_used: 0,
+        output_tokens_used: 0,
+        error_requests_used: 0,
+    }
+}
+
+/// Local HTTP proxy that forwards requests to the provider and counts usage
+///
+/// Requests are forwarded to the upstream URL with their path appended to
+/// its base path. Responses are passed on as they arrive, and their usage is
+/// read once they end: from the `usage` block of a JSON body, or of the last
+/// event reporting it in a streamed (`text/event-stream`) response.
+/// With token estimation on, successful responses without a `usage` block
+/// count the estimated tokens of the request and response text instead.
+#[derive(Debug)]
+pub struct ProxyServer {
+    addr: SocketAddr,
+    usage: Arc<ProxyUsage>,
+    handle: JoinHandle<()>,
+}
+
+impl ProxyServer {
+    /// Start the proxy listening on `listen` and forwarding to `upstream`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The upstream URL is invalid
+    /// - The listen address cannot be bound
+    pub async fn start(listen: &str, upstream: &str) -> Result<Self> {
+        Self::start_with_client(listen, upstream, Client::new(), false, None).await
+    }
+
+    /// Start the proxy, forwarding requests upstream with `client`
+    ///
+    /// `estimate_tokens` estimates the tokens of responses that do not report
+    /// their usage. With a `request_id_header`, each forwarded request gets
+    /// that header set to a new random identifier, replacing any the client sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The upstream URL is invalid
+    /// - The listen address cannot be bound
+    pub async fn start_with_client(
+        listen: &str,
+        upstream: &str,
+        client: Client,
+        estimate_tokens: bool,
+        request_id_header: Option<String>,
+    ) -> Result<Self> {
+        let upstream =
+            Url::parse(upstream).with_context(|| format!("Invalid upstream URL: {upstream}"))?;
+        let listener = TcpListener::bind(listen)
+            .await
+            .with_context(|| format!("Failed to bind proxy listener on {listen}"))?;
+        let addr = listener.local_addr()?;
+        let usage = Arc::new(ProxyUsage::new());
+
+        let handle = tokio::spawn({
+            let usage = Arc::clone(&usage);
+            async move {
+                loop {
+                    let (stream, peer) = match listener.accept().await {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            warn!("Proxy failed to accept a connection: {e}");
+                            tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                            continue;
+                        }
+                    };
+                    let client = client.clone();
+                    let upstream = upstream.clone();
+                    let usage = Arc::clone(&usage);
+                    let forward = Forward {
+                        estimate_tokens,
+                        request_id_header: request_id_header.clone(),
+                    };
+                    tokio::spawn(async move {
+                        let result =
+                            handle_connection(stream, &client, &upstream, &usage, &forward).await;
+                        if let Err(e) = result {
+                            warn!("Proxy connection from {peer} failed: {e}");
+                        }
+                    });
+                }
+            }
+        });
+
+        debug!("Proxy listening on {addr}");
+        Ok(Self {
+            addr,
+            usage,
+            handle,
+        })
+    }
+
+    /// The address the proxy is listening on
+    #[must_use]
+    pub const fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The base URL a child process should use to reach the proxy
+    #[must_use]
+    pub fn local_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Shared handle to the usage counted by this proxy
+    #[must_use]
+    pub fn usage(&self) -> Arc<ProxyUsage> {
+        Arc::clone(&self.usage)
+    }
+}
+
+impl Drop for ProxyServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// How requests are forwarded upstream
+struct Forward {
+    estimate_tokens: bool,
+    request_id_header: Option<String>,
+}
+
+struct ProxyRequest {
+    method: Method,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    client: &Client,
+    upstream: &Url,
+    usage: &ProxyUsage,
+    forward: &Forward,
+) -> Result<()> {
+    let request = read_request(&mut stream).await?;
+    let estimated_input = forward
+        .estimate_tokens
+        .then(|| estimate_body_tokens(&request.body));
+    let url = upstream_url(upstream, &request.path);
+    debug!("Proxying {} {url}", request.method);
+
+    let request_id_header = forward.request_id_header.as_deref();
+    let mut builder = client.request(request.method, url);
+    for (name, value) in &request.headers {
+        let replaced = request_id_header.is_some_and(|header| name.eq_ignore_ascii_case(header));
+        if !replaced && !HOP_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+            builder = builder.header(name, value);
+        }
+    }
+    if let Some(header) = request_id_header {
+        let request_id = format!("{:016x}", OsRng.next_u64());
+        debug!("Forwarding with {header}: {request_id}");
+        builder = builder.header(header, request_id);
+    }
+
+    let sent = Instant::now();
+    let mut response = match builder.body(request.body).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            usage.record_error(ProviderCallError::new(ProviderErrorKind::Network));
+            let message = format!("Upstream request failed: {e}");
+            write_response(&mut stream, 502, "Bad Gateway", &[], message.as_bytes()).await?;
+            return Err(anyhow!(message));
+        }
+    };
+
+    let status = response.status();
+    let error_kind = ProviderErrorKind::from_status(status.as_u16());
+    if let Some(kind) = error_kind {
+        let retry_after = response
+            .headers()
+            .get("retry-after")
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        usage.record_error(ProviderCallError { kind, retry_after });
+    }
+    let headers: Vec<(String, String)> = response
+        .headers()
+        .iter()
+        .filter(|(name, _)| !HOP_HEADERS.contains(&name.as_str()))
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    if let Some(after) = reset_from_headers(&headers, SystemTime::now()) {
+        usage.record_reset(after);
+    }
+    let event_stream = headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("content-type") && value.starts_with("text/event-stream")
+    });
+    write_head(
+        &mut stream,
+        status.as_u16(),
+        status.canonical_reason().unwrap_or(""),
+        &headers,
+        response.content_length(),
+    )
+    .await?;
+    let mut body = Vec::new();
+    let forwarded = forward_body(&mut response, &mut stream, &mut body).await;
+    usage.record_latency(sent.elapsed());
+
+    // Only successful requests count against the rate limit; failed ones
+    // were counted as errors above
+    if status.is_success() {
+        let mut tokens = if event_stream {
+            reported_usage_from_events(&body)
+        } else {
+            reported_usage_from_body(&body)
+        };
+        if let Some(input_tokens) = estimated_input {
+            if tokens.0.unwrap_or(0) == 0 && tokens.1.unwrap_or(0) == 0 {
+                tokens = (Some(input_tokens), Some(estimate_body_tokens(&body)));
+                debug!("Response reported no usage, estimated {tokens:?} tokens");
+            }
+        }
+        usage.record_reported(tokens.0, tokens.1);
+    }
+    forwarded
+}
+
+/// Copy the response body to the client as it arrives, so streamed responses
+/// are not held back, keeping a copy in `body` to read usage from
+async fn forward_body(
+    response: &mut Response,
+    stream: &mut TcpStream,
+    body: &mut Vec<u8>,
+) -> Result<()> {
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        stream.write_all(&chunk).await?;
+        stream.flush().await?;
+    }
+    Ok(())
+}
+
+/// The upstream URL for a request `path`, under the upstream's base path
+///
+/// SDKs append their endpoint to the base URL they are given, some with the
+/// API version (`/v1/messages`) and some without (`/chat/completions`), so
+/// the base path is only added when `path` does not already start with it.
+fn upstream_url(upstream: &Url, path: &str) -> Url {
+    let (path, query) = path
+        .split_once('?')
+        .map_or((path, None), |(p, q)| (p, Some(q)));
+    let base = upstream.path().trim_end_matches('/');
+    let under_base = path
+        .strip_prefix(base)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+    let mut url = upstream.clone();
+    if under_base {
+        url.set_path(path);
+    } else {
+        url.set_path(&format!("{base}{path}"));
+    }
+    url.set_query(query);
+    url
+}
+
+/// Time until the latest rate limit reset reported in response `headers`
+fn reset_from_headers(headers: &[(String, String)], now: SystemTime) -> Option<Duration> {
+    headers
+        .iter()
+        .filter(|(name, _)| RESET_HEADERS.contains(&name.to_ascii_lowercase().as_str()))
+        .filter_map(|(_, value)| {
+            parse_reset_duration(value).or_else(|| parse_reset_time(value, now))
+        })
+        .max()
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<ProxyRequest> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_HEAD_SIZE {
+            return Err(anyhow!("Request head too large"));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(anyhow!("Connection closed before request was complete"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = std::str::from_utf8(&buf[..head_end])?;
+    let mut lines = head.split("\r\n");
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let method = parts
+        .next()
+        .ok_or_else(|| anyhow!("Missing request method"))?;
+    let method = Method::from_bytes(method.as_bytes())?;
+    let path = parts
+        .next()
+        .ok_or_else(|| anyhow!("Missing request path"))?
+        .to_string();
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect();
+
+    let chunked = headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("transfer-encoding")
+            && value.to_ascii_lowercase().contains("chunked")
+    });
+    let mut body = buf[head_end + 4..].to_vec();
+    if chunked {
+        body = read_chunked_body(stream, body).await?;
+    } else {
+        let content_length = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .map_or(Ok(0), |(_, value)| value.parse::<usize>())?;
+        fill(stream, &mut body, content_length).await?;
+        body.truncate(content_length);
+    }
+
+    Ok(ProxyRequest {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+/// Decode a `Transfer-Encoding: chunked` request body, of which `pending`
+/// has already been read
+async fn read_chunked_body(stream: &mut TcpStream, mut pending: Vec<u8>) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(stream, &mut pending).await?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .with_context(|| format!("Invalid chunk size: {size}"))?;
+        if size == 0 {
+            // Skip any trailers up to the blank line that ends the body
+            while !read_line(stream, &mut pending).await?.is_empty() {}
+            return Ok(body);
+        }
+        fill(stream, &mut pending, size + 2).await?;
+        body.extend_from_slice(&pending[..size]);
+        pending.drain(..size + 2);
+    }
+}
+
+/// Take the next CRLF-terminated line from `pending`, reading more as needed
+async fn read_line(stream: &mut TcpStream, pending: &mut Vec<u8>) -> Result<String> {
+    loop {
+        if let Some(pos) = pending.windows(2).position(|w| w == b"\r\n") {
+            let line = String::from_utf8_lossy(&pending[..pos]).into_owned();
+            pending.drain(..pos + 2);
+            return Ok(line);
+        }
+        if pending.len() > MAX_HEAD_SIZE {
+            return Err(anyhow!("Chunk header too large"));
+        }
+        let len = pending.len();
+        fill(stream, pending, len + 1).await?;
+    }
+}
+
+/// Read from `stream` until `buf` holds at least `len` bytes
+async fn fill(stream: &mut TcpStream, buf: &mut Vec<u8>, len: usize) -> Result<()> {
+    let mut chunk = [0u8; 4096];
+    while buf.len() < len {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(anyhow!("Connection closed before body was complete"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    Ok(())
+}
+
+/// Write a response head; without a `content_length` the body ends when the
+/// connection is closed
+async fn write_head(
+    stream: &mut TcpStream,
+    status: u16,
+    reason: &str,
+    headers: &[(String, String)],
+    content_length: Option<u64>,
+) -> Result<()> {
+    let mut head = format!("HTTP/1.1 {status} {reason}\r\n");
+    for (name, value) in headers {
+        write!(head, "{name}: {value}\r\n")?;
+    }
+    if let Some(len) = content_length {
+        write!(head, "content-length: {len}\r\n")?;
+    }
+    head.push_str("connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    Ok(())
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: u16,
+    reason: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+) -> Result<()> {
+    write_head(stream, status, reason, headers, Some(body.len() as u64)).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Extract (input, output) token counts from a provider response body
+///
+/// Understands both Anthropic (`input_tokens`/`output_tokens`) and `OpenAI`
+/// (`prompt_tokens`/`completion_tokens`) usage blocks. Bodies without a
+/// usage block count as zero tokens.
+#[must_use]
+pub fn usage_from_body(body: &[u8]) -> (u32, u32) {
+    let (input, output) = reported_usage_from_body(body);
+    (input.unwrap_or(0), output.unwrap_or(0))
+}
+
+/// Extract the (input, output) token counts a response body reports
+///
+/// Like [`usage_from_body`], but a count the body leaves out is `None`.
+#[must_use]
+pub fn reported_usage_from_body(body: &[u8]) -> (Option<u32>, Option<u32>) {
+    serde_json::from_slice::<serde_json::Value>(body)
+        .map_or((None, None), |value| reported_usage(&value["usage"]))
+}
+
+/// Extract the (input, output) token counts a server-sent event stream reports
+///
+/// Each `data:` event is read like a response body, also looking under
+/// `message` where Anthropic's `message_start` event reports its usage. A
+/// count reported by a later event replaces an earlier one, so the usage of
+/// the final event is taken.
+#[must_use]
+pub fn reported_usage_from_events(body: &[u8]) -> (Option<u32>, Option<u32>) {
+    String::from_utf8_lossy(body)
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .filter_map(|data| serde_json::from_str::<serde_json::Value>(data.trim()).ok())
+        .fold((None, None), |(input, output), event| {
+            let usage = if event["usage"].is_object() {
+                &event["usage"]
+            } else {
+                &event["message"]["usage"]
+            };
+            let (reported_input, reported_output) = reported_usage(usage);
+            (reported_input.or(input), reported_output.or(output))
+        })
+}
+
+/// The (input, output) token counts of a `usage` block
+fn reported_usage(usage: &serde_json::Value) -> (Option<u32>, Option<u32>) {
+    let count = |primary: &str, fallback: &str| {
+        usage[primary]
+            .as_u64()
+            .or_else(|| usage[fallback].as_u64())
+            .map(|n| u32::try_from(n).unwrap_or(u32::MAX))
+    };
+    (
+        count("input_tokens", "prompt_tokens"),
+        count("output_tokens", "completion_tokens"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[test]
+    fn test_usage_from_body() {
+        let anthropic = json!({"usage": {"input_tokens": 12, "output_tokens": 30}});
+        assert_eq!(usage_from_body(anthropic.to_string().as_bytes()), (12, 30));
+
+        let openai = json!({"usage": {"prompt_tokens": 7, "completion_tokens": 5}});
+        assert_eq!(usage_from_body(openai.to_string().as_bytes()), (7, 5));
+
+        assert_eq!(usage_from_body(b"not json"), (0, 0));
+        assert_eq!(usage_from_body(b"{}"), (0, 0));
+    }
+
+    #[test]
+    fn test_reported_usage_from_body() {
+        let partial = json!({"usage": {"input_tokens": 12}});
+        assert_eq!(
+            reported_usage_from_body(partial.to_string().as_bytes()),
+            (Some(12), None)
+        );
+        assert_eq!(usage_from_body(partial.to_string().as_bytes()), (12, 0));
+        assert_eq!(reported_usage_from_body(b"{}"), (None, None));
+    }
+
+    #[test]
+    fn test_reported_usage_from_events() {
+        let anthropic = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":25,\"output_tokens\":1}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"delta\":{\"text\":\"Hi\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":15}}\n\n",
+        );
+        assert_eq!(
+            reported_usage_from_events(anthropic.as_bytes()),
+            (Some(25), Some(15))
+        );
+
+        let openai = concat!(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}],\"usage\":null}\r\n\r\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":7,\"completion_tokens\":3}}\r\n\r\n",
+            "data: [DONE]\r\n\r\n",
+        );
+        assert_eq!(
+            reported_usage_from_events(openai.as_bytes()),
+            (Some(7), Some(3))
+        );
+        assert_eq!(
+            reported_usage_from_events(b"data: [DONE]\n\n"),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_proxy_usage_tracks_missing_metrics() {
+        let usage = ProxyUsage::new();
+        usage.record(10, 20);
+        assert!(!usage.missing_metrics().any());
+
+        usage.record_reported(Some(5), None);
+        let info = usage.snapshot();
+        assert_eq!((info.requests_used, info.tokens_used), (2, 35));
+        assert_eq!(
+            usage.missing_metrics(),
+            MissingMetrics {
+                input_tokens: false,
+                output_tokens: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_elapsed_saturating_future_instant() {
+        let window = Duration::from_mins(1);
+        let future = Instant::now() + Duration::from_hours(1);
+        assert_eq!(elapsed_saturating(future, window), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_elapsed_saturating_caps_at_window() {
+        let window = Duration::from_millis(10);
+        let past = Instant::now();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(elapsed_saturating(past, window), window);
+    }
+
+    #[test]
+    fn test_proxy_usage_record() {
+        let usage = ProxyUsage::new();
+        usage.record(10, 20);
+        usage.record(5, 0);
+        let info = usage.snapshot();
+        assert_eq!(info.requests_used, 2);
+        assert_eq!(info.tokens_used, 35);
+        assert_eq!(info.input_tokens_used, 15);
+        assert_eq!(info.output_tokens_used, 20);
+    }
+
+    #[test]
+    fn test_proxy_usage_saturates_near_max() {
+        let usage = ProxyUsage::new();
+        usage.record(u32::MAX - 1, u32::MAX);
+        usage.record(u32::MAX, 1);
+        let info = usage.snapshot();
+        assert_eq!(info.requests_used, 2);
+        assert_eq!(info.tokens_used, u32::MAX);
+        assert_eq!(info.input_tokens_used, u32::MAX);
+        assert_eq!(info.output_tokens_used, u32::MAX);
+
+        let huge = json!({"usage": {"input_tokens": u64::MAX, "output_tokens": 5_000_000_000_u64}});
+        assert_eq!(
+            usage_from_body(huge.to_string().as_bytes()),
+            (u32::MAX, u32::MAX)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_proxy_counts_upstream_calls() -> Result<()> {
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .and(header("x-api-key", "test-key"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "msg_1",
+                "usage": {"input_tokens": 10, "output_tokens": 25}
+            })))
+            .expect(2)
+            .mount(&upstream)
+            .await;
+
+        let proxy = ProxyServer::start("127.0.0.1:0", &upstream.uri()).await?;
+        let client = Client::new();
+        for _ in 0..2 {
+            let response = client
+                .post(format!("{}/v1/messages", proxy.local_url()))
+                .header("x-api-key", "test-key")
+                .json(&json!({"model": "claude-2", "max_tokens": 10}))
+                .send()
+                .await?;
+            assert!(response.status().is_success());
+            let body: serde_json::Value = response.json().await?;
+            assert_eq!(body["id"], "msg_1");
+        }
+
+        let info = proxy.usage().snapshot();
+        assert_eq!(info.requests_used, 2);
+        assert_eq!(info.tokens_used, 70);
+        assert_eq!(info.input_tokens_used, 20);
+        assert_eq!(info.output_tokens_used, 50);
+        Ok(())
+    }
+
+    #[test]
+    fn test_upstream_url_keeps_base_path() -> Result<()> {
+        let openai = Url::parse("https://api.openai.com/v1")?;
+        assert_eq!(
+            upstream_url(&openai, "/chat/completions").as_str(),
+            "https://api.openai.com/v1/chat/completions"
+        );
+        assert_eq!(
+            upstream_url(&openai, "/v1/chat/completions?x=1").as_str(),
+            "https://api.openai.com/v1/chat/completions?x=1"
+        );
+        assert_eq!(
+            upstream_url(&openai, "/v1beta/models").as_str(),
+            "https://api.openai.com/v1/v1beta/models"
+        );
+
+        let root = Url::parse("http://127.0.0.1:8080")?;
+        assert_eq!(
+            upstream_url(&root, "/v1/messages").as_str(),
+            "http://127.0.0.1:8080/v1/messages"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_forwards_under_upstream_base_path() -> Result<()> {
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "usage": {"prompt_tokens": 3, "completion_tokens": 4}
+            })))
+            .expect(1)
+            .mount(&upstream)
+            .await;
+
+        // The `OpenAI` SDK appends the endpoint to a base URL ending in `/v1`
+        let base = format!("{}/v1", upstream.uri());
+        let proxy = ProxyServer::start("127.0.0.1:0", &base).await?;
+        let response = Client::new()
+            .post(format!("{}/chat/completions", proxy.local_url()))
+            .json(&json!({"model": "gpt-4"}))
+            .send()
+            .await?;
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(proxy.usage().snapshot().tokens_used, 7);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_counts_streamed_usage() -> Result<()> {
+        let upstream = MockServer::start().await;
+        let events = concat!(
+            "event: message_start\n",
+            "data: {\"message\":{\"usage\":{\"input_tokens\":12,\"output_tokens\":1}}}\n\n",
+            "event: message_delta\n",
+            "data: {\"usage\":{\"output_tokens\":30}}\n\n",
+        );
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(events, "text/event-stream"))
+            .mount(&upstream)
+            .await;
+
+        let proxy = ProxyServer::start("127.0.0.1:0", &upstream.uri()).await?;
+        let response = Client::new()
+            .post(format!("{}/v1/messages", proxy.local_url()))
+            .json(&json!({"stream": true}))
+            .send()
+            .await?;
+        assert_eq!(response.text().await?, events);
+
+        let info = proxy.usage().snapshot();
+        assert_eq!((info.input_tokens_used, info.output_tokens_used), (12, 30));
+        assert!(!proxy.usage().missing_metrics().any());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_passes_on_stream_before_it_ends() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let upstream = format!("http://{}", listener.local_addr()?);
+        let (finish, finished) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            read_request(&mut stream).await?;
+            stream
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\
+                      transfer-encoding: chunked\r\n\r\n6\r\ndata: \r\n",
+                )
+                .await?;
+            let _ = finished.await;
+            stream.write_all(b"4\r\n{}\n\n\r\n0\r\n\r\n").await?;
+            anyhow::Ok(())
+        });
+
+        let proxy = ProxyServer::start("127.0.0.1:0", &upstream).await?;
+        let mut response = Client::new()
+            .post(format!("{}/v1/messages", proxy.local_url()))
+            .send()
+            .await?;
+        let first = tokio::time::timeout(Duration::from_secs(5), response.chunk())
+            .await
+            .expect("the first event should arrive before the stream ends")?;
+        assert_eq!(first.as_deref(), Some(&b"data: "[..]));
+        finish.send(()).unwrap();
+        assert_eq!(response.text().await?, "{}\n\n");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_decodes_chunked_request_body() -> Result<()> {
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(wiremock::matchers::body_string("{\"model\":\"gpt-4\"}"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(1)
+            .mount(&upstream)
+            .await;
+
+        let proxy = ProxyServer::start("127.0.0.1:0", &upstream.uri()).await?;
+        let mut stream = TcpStream::connect(proxy.local_addr()).await?;
+        stream
+            .write_all(
+                b"POST /v1/chat/completions HTTP/1.1\r\nhost: localhost\r\n\
+                  transfer-encoding: chunked\r\n\r\n\
+                  8\r\n{\"model\"\r\n9;ext=1\r\n:\"gpt-4\"}\r\n0\r\nx-trailer: 1\r\n\r\n",
+            )
+            .await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_reset_from_headers_takes_latest() {
+        let now = std::time::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let headers = vec![
+            ("content-type".to_string(), "application/json".to_string()),
+            ("x-ratelimit-reset-requests".to_string(), "20ms".to_string()),
+            ("x-ratelimit-reset-tokens".to_string(), "6m0s".to_string()),
+            (
+                "anthropic-ratelimit-tokens-reset".to_string(),
+                "2023-11-14T22:14:20Z".to_string(),
+            ),
+        ];
+        assert_eq!(
+            reset_from_headers(&headers, now),
+            Some(Duration::from_mins(6))
+        );
+        assert_eq!(reset_from_headers(&headers[..1], now), None);
+    }
+
+    #[test]
+    fn test_proxy_usage_reset_expires() {
+        let usage = ProxyUsage::default();
+        assert!(usage.reset_at().is_none());
+        usage.record_reset(Duration::from_mins(1));
+        assert!(usage.reset_at().is_some_and(|at| at > Instant::now()));
+        usage.record_reset(Duration::ZERO);
+        assert!(usage.reset_at().is_none(), "A passed reset is not reported");
+    }
+
+    #[tokio::test]
+    async fn test_proxy_records_reset_headers() -> Result<()> {
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("x-ratelimit-reset-tokens", "30s")
+                    .set_body_json(json!({"usage": {"prompt_tokens": 1, "completion_tokens": 1}})),
+            )
+            .mount(&upstream)
+            .await;
+
+        let proxy = ProxyServer::start("127.0.0.1:0", &upstream.uri()).await?;
+        Client::new()
+            .post(format!("{}/v1/chat/completions", proxy.local_url()))
+            .json(&json!({"model": "gpt-4"}))
+            .send()
+            .await?;
+
+        let reset_at = proxy.usage().reset_at().expect("reset should be recorded");
+        let remaining = reset_at - Instant::now();
+        assert!(remaining <= Duration::from_secs(30));
+        assert!(remaining > Duration::from_secs(25), "{remaining:?}");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_times_upstream_round_trip() -> Result<()> {
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_delay(Duration::from_millis(50))
+                    .set_body_json(json!({})),
+            )
+            .mount(&upstream)
+            .await;
+
+        let proxy = ProxyServer::start("127.0.0.1:0", &upstream.uri()).await?;
+        assert_eq!(proxy.usage().take_latency(), None);
+        Client::new()
+            .post(format!("{}/v1/messages", proxy.local_url()))
+            .json(&json!({}))
+            .send()
+            .await?;
+
+        let latency = proxy
+            .usage()
+            .take_latency()
+            .expect("latency should be recorded");
+        assert!(latency >= Duration::from_millis(50), "{latency:?}");
+        assert_eq!(proxy.usage().take_latency(), None, "Latency is taken once");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_sets_request_id_per_call() -> Result<()> {
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .mount(&upstream)
+            .await;
+
+        let proxy = ProxyServer::start_with_client(
+            "127.0.0.1:0",
+            &upstream.uri(),
+            Client::new(),
+            false,
+            Some("X-Request-ID".to_string()),
+        )
+        .await?;
+        for _ in 0..2 {
+            Client::new()
+                .post(format!("{}/v1/messages", proxy.local_url()))
+                .header("x-request-id", "from-the-child")
+                .send()
+                .await?;
+        }
+
+        let requests = upstream.received_requests().await.unwrap();
+        let ids: Vec<_> = requests
+            .iter()
+            .map(|request| {
+                let ids: Vec<_> = request
+                    .headers
+                    .iter()
+                    .filter(|(name, _)| name.as_str().eq_ignore_ascii_case("x-request-id"))
+                    .flat_map(|(_, values)| values.iter().map(|value| value.as_str().to_string()))
+                    .collect();
+                assert_eq!(ids.len(), 1, "The client's ID should be replaced: {ids:?}");
+                ids[0].clone()
+            })
+            .collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.iter().all(|id| id.len() == 16), "{ids:?}");
+        assert_ne!(ids[0], ids[1], "Each call should get its own ID");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_forwards_error_status() -> Result<()> {
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(
+                ResponseTemplate::new(429)
+                    .insert_header("retry-after", "12")
+                    .set_body_string("rate limited"),
+            )
+            .mount(&upstream)
+            .await;
+
+        let proxy = ProxyServer::start("127.0.0.1:0", &upstream.uri()).await?;
+        let response = Client::new()
+            .post(format!("{}/v1/messages", proxy.local_url()))
+            .body("{}")
+            .send()
+            .await?;
+        assert_eq!(response.status().as_u16(), 429);
+        assert_eq!(response.text().await?, "rate limited");
+
+        let usage = proxy.usage();
+        let info = usage.snapshot();
+        assert_eq!(info.requests_used, 0);
+        assert_eq!(info.error_requests_used, 1);
+
+        let error = usage.take_last_error().unwrap();
+        assert_eq!(error.kind, ProviderErrorKind::RateLimited);
+        assert_eq!(error.retry_after, Some(Duration::from_secs(12)));
+        assert!(usage.take_last_error().is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_waits_for_slow_upstream_beyond_request_timeout() -> Result<()> {
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+            .mount(&upstream)
+            .await;
+
+        let client = crate::config::NetworkConfig {
+            request_timeout_seconds: 1,
+            ..Default::default()
+        }
+        .forwarding_client()?;
+        let proxy =
+            ProxyServer::start_with_client("127.0.0.1:0", &upstream.uri(), client, false, None)
+                .await?;
+        let response = Client::new()
+            .post(format!("{}/v1/messages", proxy.local_url()))
+            .send()
+            .await?;
+        assert_eq!(response.status().as_u16(), 200);
+
+        let usage = proxy.usage();
+        assert_eq!(usage.snapshot().requests_used, 1);
+        assert!(usage.take_last_error().is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_marks_partial_success_responses() -> Result<()> {
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/messages"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"usage": {"output_tokens": 9}})),
+            )
+            .mount(&upstream)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/denied"))
+            .respond_with(ResponseTemplate::new(429))
+            .mount(&upstream)
+            .await;
+
+        let proxy = ProxyServer::start("127.0.0.1:0", &upstream.uri()).await?;
+        Client::new()
+            .post(format!("{}/v1/denied", proxy.local_url()))
+            .send()
+            .await?;
+        // Error responses carry no usage and are not expected to
+        assert!(!proxy.usage().missing_metrics().any());
+
+        Client::new()
+            .post(format!("{}/v1/messages", proxy.local_url()))
+            .send()
+            .await?;
+        let usage = proxy.usage();
+        assert_eq!(usage.snapshot().output_tokens_used, 9);
+        assert!(usage.missing_metrics().input_tokens);
+        assert!(!usage.missing_metrics().output_tokens);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_rate_limited_requests_not_counted() -> Result<()> {
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(429))
+            .expect(3)
+            .mount(&upstream)
+            .await;
+
+        let proxy = ProxyServer::start("127.0.0.1:0", &upstream.uri()).await?;
+        for _ in 0..3 {
+            Client::new()
+                .post(format!("{}/v1/messages", proxy.local_url()))
+                .send()
+                .await?;
+        }
+
+        let info = proxy.usage().snapshot();
+        assert_eq!(info.requests_used, 0);
+        assert_eq!(info.error_requests_used, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_proxy_server_error_not_counted() -> Result<()> {
+        let upstream = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&upstream)
+            .await;
+
+        let proxy = ProxyServer::start("127.0.0.1:0", &upstream.uri()).await?;
+        let response = Client::new()
+            .post(format!("{}/v1/messages", proxy.local_url()))
+            .send()
+            .await?;
+        assert_eq!(response.status().as_u16(), 503);
+
+        let usage = proxy.usage();
+        assert_eq!(usage.snapshot().requests_used, 0);
+        assert_eq!(usage.snapshot().error_requests_used, 1);
+        assert_eq!(
+            usage.take_last_error().map(|e| e.kind),
+            Some(ProviderErrorKind::ServerError)
+        );
+        Ok(())
+    }
+}
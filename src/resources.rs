@@ -0,0 +1,236 @@
+use crate::process::stat_fields;
+use nix::unistd::{sysconf, Pid, SysconfVar};
+use std::fmt;
+use std::time::Instant;
+
+/// Clock ticks per second used by procfs when `sysconf` cannot report it
+const DEFAULT_TICKS_PER_SECOND: u64 = 100;
+
+/// Memory and CPU ceilings for a child process
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// Resident memory in bytes
+    pub max_memory: Option<u64>,
+    /// CPU time as a percentage of one core, e.g. 150 for one and a half cores
+    pub max_cpu_percent: Option<f64>,
+}
+
+impl ResourceLimits {
+    /// Whether no ceiling is set
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.max_memory.is_none() && self.max_cpu_percent.is_none()
+    }
+
+    /// The first ceiling `usage` is above, if any
+    #[must_use]
+    pub fn exceeded_by(&self, usage: &ResourceUsage) -> Option<ResourceExcess> {
+        if let Some(limit) = self.max_memory.filter(|&limit| usage.memory_bytes > limit) {
+            return Some(ResourceExcess::Memory {
+                used: usage.memory_bytes,
+                limit,
+            });
+        }
+        match (usage.cpu_percent, self.max_cpu_percent) {
+            (Some(used), Some(limit)) if used > limit => Some(ResourceExcess::Cpu { used, limit }),
+            _ => None,
+        }
+    }
+}
+
+/// Memory and CPU use of a process
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResourceUsage {
+    /// Resident memory in bytes
+    pub memory_bytes: u64,
+    /// CPU use since the previous sample as a percentage of one core, `None`
+    /// for the first sample
+    pub cpu_percent: Option<f64>,
+}
+
+/// A resource ceiling a process is above
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResourceExcess {
+    /// Resident memory in bytes
+    Memory { used: u64, limit: u64 },
+    /// CPU use as a percentage of one core
+    Cpu { used: f64, limit: f64 },
+}
+
+impl fmt::Display for ResourceExcess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Memory { used, limit } => {
+                write!(f, "memory at {used} bytes exceeds the limit of {limit}")
+            }
+            Self::Cpu { used, limit } => {
+                write!(f, "CPU at {used:.0}% exceeds the limit of {limit}%")
+            }
+        }
+    }
+}
+
+/// Samples a process's memory and CPU use from procfs
+///
+/// Only Linux has procfs; elsewhere [`ResourceMonitor::sample`] returns `None`
+/// and no ceiling is enforced.
+#[derive(Debug)]
+pub struct ResourceMonitor {
+    pid: Pid,
+    limits: ResourceLimits,
+    ticks_per_second: u64,
+    /// When the CPU time was last read and the total at that point, in ticks
+    last_cpu: Option<(Instant, u64)>,
+}
+
+impl ResourceMonitor {
+    #[must_use]
+    pub fn new(pid: i32, limits: ResourceLimits) -> Self {
+        let ticks_per_second = sysconf(SysconfVar::CLK_TCK)
+            .ok()
+            .flatten()
+            .and_then(|ticks| u64::try_from(ticks).ok())
+            .filter(|&ticks| ticks > 0)
+            .unwrap_or(DEFAULT_TICKS_PER_SECOND);
+        Self {
+            pid: Pid::from_raw(pid),
+            limits,
+            ticks_per_second,
+            last_cpu: None,
+        }
+    }
+
+    /// The ceilings this monitor checks
+    #[must_use]
+    pub const fn limits(&self) -> &ResourceLimits {
+        &self.limits
+    }
+
+    /// Read the process's current memory and its CPU use since the last sample
+    ///
+    /// Returns `None` if the process is gone or procfs is unavailable.
+    pub fn sample(&mut self) -> Option<ResourceUsage> {
+        let status = std::fs::read_to_string(format!("/proc/{}/status", self.pid)).ok()?;
+        // Kernel threads and zombies have no resident set
+        let memory_bytes = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|rss| rss.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+            .map_or(0, |kib| kib * 1024);
+
+        // utime and stime are the 14th and 15th fields, counting the pid and
+        // command name that stat_fields skips
+        let stat = stat_fields(self.pid)?;
+        let ticks = stat.get(11)?.parse::<u64>().ok()? + stat.get(12)?.parse::<u64>().ok()?;
+        let now = Instant::now();
+        let cpu_percent = self.last_cpu.and_then(|(at, last_ticks)| {
+            let elapsed = now.duration_since(at).as_secs_f64();
+            #[allow(clippy::cast_precision_loss)]
+            let used = ticks.saturating_sub(last_ticks) as f64 / self.ticks_per_second as f64;
+            (elapsed > 0.0).then(|| used / elapsed * 100.0)
+        });
+        self.last_cpu = Some((now, ticks));
+
+        Some(ResourceUsage {
+            memory_bytes,
+            cpu_percent,
+        })
+    }
+
+    /// Sample the process and report the first ceiling it is above
+    pub fn check(&mut self) -> Option<ResourceExcess> {
+        if self.limits.is_empty() {
+            return None;
+        }
+        let usage = self.sample()?;
+        self.limits.exceeded_by(&usage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_report_first_excess() {
+        let limits = ResourceLimits {
+            max_memory: Some(1000),
+            max_cpu_percent: Some(50.0),
+        };
+        let usage = |memory_bytes, cpu_percent| ResourceUsage {
+            memory_bytes,
+            cpu_percent,
+        };
+        assert_eq!(limits.exceeded_by(&usage(1000, Some(50.0))), None);
+        assert_eq!(
+            limits.exceeded_by(&usage(1001, Some(80.0))),
+            Some(ResourceExcess::Memory {
+                used: 1001,
+                limit: 1000
+            })
+        );
+        let excess = limits.exceeded_by(&usage(10, Some(80.0))).unwrap();
+        assert_eq!(excess.to_string(), "CPU at 80% exceeds the limit of 50%");
+        // CPU needs two samples
+        assert_eq!(limits.exceeded_by(&usage(10, None)), None);
+        assert!(ResourceLimits::default().is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_memory_hungry_child_exceeds_limit() {
+        use crate::process::ProcessController;
+        use std::time::Duration;
+
+        // The shell holds 64 MiB read from the pipe before it sleeps
+        let command = [
+            "sh".to_string(),
+            "-c".to_string(),
+            "x=$(head -c 67108864 /dev/zero | tr '\\0' a); sleep 10".to_string(),
+        ];
+        let (controller, mut child) = ProcessController::from_command(&command).unwrap();
+        let mut monitor = ResourceMonitor::new(
+            controller.pid(),
+            ResourceLimits {
+                max_memory: Some(32 * 1024 * 1024),
+                max_cpu_percent: None,
+            },
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let excess = loop {
+            if let Some(excess) = monitor.check() {
+                break excess;
+            }
+            assert!(Instant::now() < deadline, "memory never exceeded the limit");
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        assert!(
+            matches!(excess, ResourceExcess::Memory { used, .. } if used > 32 * 1024 * 1024),
+            "{excess}"
+        );
+
+        controller.terminate().unwrap();
+        let _ = child.wait();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_cpu_use_is_measured_between_samples() {
+        let mut monitor = ResourceMonitor::new(
+            std::process::id().try_into().unwrap(),
+            ResourceLimits::default(),
+        );
+        assert_eq!(monitor.sample().unwrap().cpu_percent, None);
+
+        // Spin for a while so this process uses most of a core
+        let start = Instant::now();
+        let mut spins = 0u64;
+        while start.elapsed() < std::time::Duration::from_millis(300) {
+            spins = std::hint::black_box(spins + 1);
+        }
+        let usage = monitor.sample().unwrap();
+        assert!(usage.cpu_percent.unwrap() > 10.0, "{usage:?}");
+        assert!(usage.memory_bytes > 0);
+    }
+}
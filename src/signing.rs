@@ -0,0 +1,226 @@
+use anyhow::{anyhow, bail, Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand_core::OsRng;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Environment variable, named after the env prefix, holding the
+/// hex-encoded public key used to verify configs
+pub const PUBLIC_KEY_ENV: &str = "CONFIG_PUBLIC_KEY";
+
+/// Path of the detached signature for a config file (`strainer.toml.sig`)
+#[must_use]
+pub fn signature_path(config_path: &Path) -> PathBuf {
+    let mut path = config_path.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+/// Generate a new signing key
+#[must_use]
+pub fn generate_signing_key() -> SigningKey {
+    SigningKey::generate(&mut OsRng)
+}
+
+/// Read a hex-encoded signing key from a file
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or does not hold a valid key
+pub fn read_signing_key(path: &Path) -> Result<SigningKey> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read signing key {}", path.display()))?;
+    let bytes = decode_fixed::<32>(contents.trim())
+        .with_context(|| format!("Invalid signing key in {}", path.display()))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Write a signing key to a new file as hex, readable only by the owner
+///
+/// The file is created with those permissions, so the key is never readable
+/// by anyone else, even briefly.
+///
+/// # Errors
+///
+/// Returns an error if the file already exists or cannot be written
+pub fn write_signing_key(path: &Path, key: &SigningKey) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", hex::encode(key.to_bytes())))
+        .with_context(|| format!("Failed to write signing key {}", path.display()))
+}
+
+/// Parse a hex-encoded public key
+///
+/// # Errors
+///
+/// Returns an error if the key is not 32 hex-encoded bytes or not a valid point
+pub fn parse_public_key(key: &str) -> Result<VerifyingKey> {
+    let bytes = decode_fixed::<32>(key.trim()).context("Invalid config public key")?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| anyhow!("Invalid config public key: {e}"))
+}
+
+/// Hex-encoded public key matching a signing key
+#[must_use]
+pub fn public_key_hex(key: &SigningKey) -> String {
+    hex::encode(key.verifying_key().to_bytes())
+}
+
+/// Sign a config file, writing the detached signature next to it
+///
+/// Returns the path of the written signature.
+///
+/// # Errors
+///
+/// Returns an error if the config cannot be read or the signature cannot be written
+pub fn sign_config(config_path: &Path, key: &SigningKey) -> Result<PathBuf> {
+    let contents = std::fs::read(config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let signature = key.sign(&contents);
+    let sig_path = signature_path(config_path);
+    std::fs::write(
+        &sig_path,
+        format!("{}\n", hex::encode(signature.to_bytes())),
+    )
+    .with_context(|| format!("Failed to write {}", sig_path.display()))?;
+    Ok(sig_path)
+}
+
+/// Verify a config file against its detached signature
+///
+/// # Errors
+///
+/// Returns an error if the signature is missing, malformed, or does not match
+/// the file contents
+pub fn verify_config(config_path: &Path, key: &VerifyingKey) -> Result<()> {
+    let sig_path = signature_path(config_path);
+    if !sig_path.exists() {
+        bail!(
+            "Config {} is not signed: {} is missing",
+            config_path.display(),
+            sig_path.display()
+        );
+    }
+    let contents = std::fs::read(config_path)
+        .with_context(|| format!("Failed to read {}", config_path.display()))?;
+    let encoded = std::fs::read_to_string(&sig_path)
+        .with_context(|| format!("Failed to read {}", sig_path.display()))?;
+    let signature = decode_fixed::<64>(encoded.trim())
+        .map(|bytes| Signature::from_bytes(&bytes))
+        .with_context(|| format!("Malformed signature in {}", sig_path.display()))?;
+    key.verify(&contents, &signature).map_err(|_| {
+        anyhow!(
+            "Signature verification failed for {}: the file may have been modified",
+            config_path.display()
+        )
+    })
+}
+
+fn decode_fixed<const N: usize>(encoded: &str) -> Result<[u8; N]> {
+    let bytes = hex::decode(encoded)?;
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| anyhow!("expected {N} bytes, got {}", bytes.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_config(dir: &TempDir) -> PathBuf {
+        let path = dir.path().join("strainer.toml");
+        std::fs::write(&path, "[limits]\nrequests_per_minute = 10\n").unwrap();
+        path
+    }
+
+    #[test]
+    fn test_signature_path_appends_extension() {
+        assert_eq!(
+            signature_path(Path::new("/etc/strainer.toml")),
+            PathBuf::from("/etc/strainer.toml.sig")
+        );
+    }
+
+    #[test]
+    fn test_verify_valid_signature() {
+        let dir = TempDir::new().unwrap();
+        let config = write_config(&dir);
+        let key = generate_signing_key();
+
+        let sig_path = sign_config(&config, &key).unwrap();
+        assert!(sig_path.exists());
+        verify_config(&config, &key.verifying_key()).unwrap();
+    }
+
+    #[test]
+    fn test_verify_tampered_config_fails() {
+        let dir = TempDir::new().unwrap();
+        let config = write_config(&dir);
+        let key = generate_signing_key();
+        sign_config(&config, &key).unwrap();
+
+        std::fs::write(&config, "[limits]\nrequests_per_minute = 10000\n").unwrap();
+        let err = verify_config(&config, &key.verifying_key()).unwrap_err();
+        assert!(err.to_string().contains("verification failed"));
+    }
+
+    #[test]
+    fn test_verify_missing_signature_fails() {
+        let dir = TempDir::new().unwrap();
+        let config = write_config(&dir);
+        let key = generate_signing_key();
+
+        let err = verify_config(&config, &key.verifying_key()).unwrap_err();
+        assert!(err.to_string().contains("not signed"));
+    }
+
+    #[test]
+    fn test_verify_with_wrong_key_fails() {
+        let dir = TempDir::new().unwrap();
+        let config = write_config(&dir);
+        sign_config(&config, &generate_signing_key()).unwrap();
+
+        let other = generate_signing_key();
+        assert!(verify_config(&config, &other.verifying_key()).is_err());
+    }
+
+    #[test]
+    fn test_key_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let key_path = dir.path().join("strainer.key");
+        let key = generate_signing_key();
+
+        write_signing_key(&key_path, &key).unwrap();
+        let loaded = read_signing_key(&key_path).unwrap();
+        assert_eq!(loaded.to_bytes(), key.to_bytes());
+
+        let public = parse_public_key(&public_key_hex(&key)).unwrap();
+        assert_eq!(public, key.verifying_key());
+        assert!(parse_public_key("not-hex").is_err());
+        assert!(parse_public_key("abcd").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signing_key_is_private_and_never_replaced() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let key_path = dir.path().join("strainer.key");
+        write_signing_key(&key_path, &generate_signing_key()).unwrap();
+        let mode = std::fs::metadata(&key_path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600, "{mode:o}");
+
+        let before = std::fs::read_to_string(&key_path).unwrap();
+        assert!(write_signing_key(&key_path, &generate_signing_key()).is_err());
+        assert_eq!(std::fs::read_to_string(&key_path).unwrap(), before);
+    }
+}
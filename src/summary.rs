@@ -0,0 +1,188 @@
+use crate::providers::rate_limiter::UsagePercentages;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Statistics about one `run`, written as JSON by `run --summary-file`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunSummary {
+    /// Name of the primary provider, e.g. `anthropic`
+    pub provider: String,
+    /// Milliseconds since the Unix epoch
+    pub started_at_ms: u64,
+    /// Milliseconds since the Unix epoch
+    pub ended_at_ms: u64,
+    /// Exit code of the process, `None` if it never started or was killed by a signal
+    pub exit_code: Option<i32>,
+    /// Number of times the process was paused
+    pub pause_count: u32,
+    /// Total time the process spent paused
+    pub paused_ms: u64,
+    /// Highest usage seen for each limit
+    pub peak: UsagePercentages,
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis() as u64
+}
+
+impl RunSummary {
+    /// Start a summary for a run that begins now
+    #[must_use]
+    pub fn new(provider: impl Into<String>) -> Self {
+        let started_at_ms = now_ms();
+        Self {
+            provider: provider.into(),
+            started_at_ms,
+            ended_at_ms: started_at_ms,
+            exit_code: None,
+            pause_count: 0,
+            paused_ms: 0,
+            peak: UsagePercentages::default(),
+        }
+    }
+
+    /// Fold a usage check into the peak percentages
+    pub fn observe(&mut self, percentages: UsagePercentages) {
+        self.peak.requests = self.peak.requests.max(percentages.requests);
+        self.peak.tokens = self.peak.tokens.max(percentages.tokens);
+        self.peak.input_tokens = self.peak.input_tokens.max(percentages.input_tokens);
+        self.peak.output_tokens = self.peak.output_tokens.max(percentages.output_tokens);
+        self.peak.error_requests = self.peak.error_requests.max(percentages.error_requests);
+    }
+
+    /// Count a pause that lasted `duration`
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn record_pause(&mut self, duration: Duration) {
+        self.pause_count += 1;
+        self.paused_ms += duration.as_millis() as u64;
+    }
+
+    /// Mark the run as ended now
+    pub fn finish(&mut self) {
+        self.ended_at_ms = now_ms();
+    }
+
+    /// Wall-clock time from the start of the run to its end
+    #[must_use]
+    pub const fn runtime(&self) -> Duration {
+        Duration::from_millis(self.ended_at_ms.saturating_sub(self.started_at_ms))
+    }
+
+    /// Write the summary as JSON to `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("Failed to write run summary {}", path.display()))
+    }
+}
+
+/// Render the runs of `strainer bench` as a table, one row per
+/// requests-per-minute limit
+#[must_use]
+pub fn bench_table(results: &[(u32, RunSummary)]) -> String {
+    let header = format!(
+        "{:>12}  {:>9}  {:>6}  {:>9}  {:>4}\n",
+        "requests/min", "runtime", "pauses", "paused", "exit"
+    );
+    let rows = results.iter().map(|(limit, summary)| {
+        let exit = summary
+            .exit_code
+            .map_or_else(|| "-".to_string(), |code| code.to_string());
+        format!(
+            "{limit:>12}  {:>8.1}s  {:>6}  {:>8.1}s  {exit:>4}\n",
+            summary.runtime().as_secs_f64(),
+            summary.pause_count,
+            Duration::from_millis(summary.paused_ms).as_secs_f64(),
+        )
+    });
+    std::iter::once(header).chain(rows).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_summary_tracks_peaks_and_pauses() {
+        let mut summary = RunSummary::new("mock");
+        summary.observe(UsagePercentages {
+            requests: 40,
+            tokens: 90,
+            input_tokens: 10,
+            output_tokens: 0,
+            error_requests: 0,
+        });
+        summary.observe(UsagePercentages {
+            requests: 70,
+            tokens: 20,
+            input_tokens: 5,
+            output_tokens: 0,
+            error_requests: 0,
+        });
+        summary.record_pause(Duration::from_millis(1500));
+        summary.record_pause(Duration::from_millis(500));
+
+        assert_eq!(
+            summary.peak,
+            UsagePercentages {
+                requests: 70,
+                tokens: 90,
+                input_tokens: 10,
+                output_tokens: 0,
+                error_requests: 0,
+            }
+        );
+        assert_eq!(summary.pause_count, 2);
+        assert_eq!(summary.paused_ms, 2000);
+    }
+
+    #[test]
+    fn test_bench_table_has_a_row_per_limit() {
+        let mut slow = RunSummary::new("mock");
+        slow.ended_at_ms = slow.started_at_ms + 2500;
+        slow.record_pause(Duration::from_millis(1200));
+        slow.exit_code = Some(0);
+        let mut fast = RunSummary::new("mock");
+        fast.ended_at_ms = fast.started_at_ms + 1000;
+
+        let table = bench_table(&[(30, slow), (1000, fast)]);
+        let rows: Vec<Vec<&str>> = table
+            .lines()
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                vec!["requests/min", "runtime", "pauses", "paused", "exit"],
+                vec!["30", "2.5s", "1", "1.2s", "0"],
+                vec!["1000", "1.0s", "0", "0.0s", "-"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_summary_round_trips_through_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("summary.json");
+        let mut summary = RunSummary::new("anthropic");
+        summary.exit_code = Some(3);
+        summary.finish();
+        summary.write(&path).unwrap();
+
+        let read: RunSummary =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(read, summary);
+        assert!(read.ended_at_ms >= read.started_at_ms);
+    }
+}
@@ -24,6 +24,8 @@ impl MockProvider {
                 requests_used: 0,
                 tokens_used: 0,
                 input_tokens_used: 0,
+                output_tokens_used: 0,
+                error_requests_used: 0,
             }))),
         })
     }
@@ -67,6 +69,8 @@ impl Provider for MockProvider {
                 requests_used: 0,
                 tokens_used: 0,
                 input_tokens_used: 0,
+                output_tokens_used: 0,
+                error_requests_used: 0,
             }))
     }
 
@@ -79,6 +83,7 @@ impl Provider for MockProvider {
             requests_per_minute: Some(100),
             tokens_per_minute: Some(1000),
             input_tokens_per_minute: Some(500),
+            output_tokens_per_minute: None,
         })
     }
 
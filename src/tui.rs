@@ -0,0 +1,244 @@
+use crate::config::Thresholds;
+use crate::providers::rate_limiter::UsagePercentages;
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::widgets::{Block, Borders, Gauge, Paragraph, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Number of checks kept for the usage sparkline
+const HISTORY_LEN: usize = 120;
+
+/// Everything the dashboard renders
+#[derive(Debug, Clone)]
+pub struct DashboardState {
+    pub title: String,
+    pub thresholds: Thresholds,
+    pub percentages: UsagePercentages,
+    pub paused: bool,
+    history: VecDeque<u64>,
+}
+
+impl DashboardState {
+    #[must_use]
+    pub fn new(title: String, thresholds: Thresholds) -> Self {
+        Self {
+            title,
+            thresholds,
+            percentages: UsagePercentages::default(),
+            paused: false,
+            history: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+
+    /// Record the result of a limit check
+    pub fn update(&mut self, percentages: UsagePercentages, paused: bool) {
+        self.percentages = percentages;
+        self.paused = paused;
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(u64::from(percentages.max()));
+    }
+
+    /// Highest usage percentage of each recorded check, oldest first
+    #[must_use]
+    pub const fn history(&self) -> &VecDeque<u64> {
+        &self.history
+    }
+
+    fn color_for(&self, percent: u32) -> Color {
+        if percent >= u32::from(self.thresholds.critical) {
+            Color::Red
+        } else if percent >= u32::from(self.thresholds.warning) {
+            Color::Yellow
+        } else {
+            Color::Green
+        }
+    }
+
+    fn gauge<'a>(&self, label: &'a str, percent: u32) -> Gauge<'a> {
+        Gauge::default()
+            .block(Block::default().borders(Borders::ALL).title(label))
+            .gauge_style(Style::default().fg(self.color_for(percent)))
+            .ratio(f64::from(percent.min(100)) / 100.0)
+            .label(format!("{percent}%"))
+    }
+}
+
+/// Draw the usage gauges, history sparkline and status line
+pub fn render(frame: &mut Frame, state: &DashboardState) {
+    let [requests, tokens, input_tokens, output_tokens, history, status] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Min(4),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let percentages = state.percentages;
+    frame.render_widget(state.gauge("Requests", percentages.requests), requests);
+    frame.render_widget(state.gauge("Tokens", percentages.tokens), tokens);
+    frame.render_widget(
+        state.gauge("Input tokens", percentages.input_tokens),
+        input_tokens,
+    );
+    frame.render_widget(
+        state.gauge("Output tokens", percentages.output_tokens),
+        output_tokens,
+    );
+
+    let sparkline = Sparkline::default()
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("{} - peak usage", state.title)),
+        )
+        .data(state.history().iter().copied())
+        .max(100)
+        .style(Style::default().fg(state.color_for(percentages.max())));
+    frame.render_widget(sparkline, history);
+
+    let status_text = if state.paused {
+        "PAUSED - waiting for usage to drop (q to quit)"
+    } else {
+        "RUNNING (q to quit)"
+    };
+    frame.render_widget(Paragraph::new(status_text), status);
+}
+
+/// Live terminal dashboard that replaces log output during a run
+///
+/// The terminal is switched to raw mode on the alternate screen and restored
+/// when the dashboard is dropped.
+#[derive(Debug)]
+pub struct Dashboard {
+    terminal: DefaultTerminal,
+    state: DashboardState,
+}
+
+impl Dashboard {
+    /// Take over the terminal and start the dashboard
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the terminal cannot be put into raw mode
+    pub fn start(title: String, thresholds: Thresholds) -> Result<Self> {
+        Ok(Self {
+            terminal: ratatui::try_init()?,
+            state: DashboardState::new(title, thresholds),
+        })
+    }
+
+    /// Record a limit check and redraw
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if drawing to the terminal fails
+    pub fn update(&mut self, percentages: UsagePercentages, paused: bool) -> Result<()> {
+        self.state.update(percentages, paused);
+        self.terminal.draw(|frame| render(frame, &self.state))?;
+        Ok(())
+    }
+
+    /// Check, without blocking, whether the user asked to quit
+    ///
+    /// Raw mode swallows Ctrl-C, so it is handled here alongside `q` and Esc.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if terminal events cannot be read
+    pub fn quit_requested(&self) -> Result<bool> {
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                let ctrl_c =
+                    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+                if ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn render_to_string(state: &DashboardState) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(60, 24)).unwrap();
+        terminal.draw(|frame| render(frame, state)).unwrap();
+        terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(ratatui::buffer::Cell::symbol)
+            .collect()
+    }
+
+    #[test]
+    fn test_dashboard_state_history_is_bounded() {
+        let mut state = DashboardState::new("test".to_string(), Thresholds::default());
+        for i in 0..(HISTORY_LEN + 10) {
+            let percent = u32::try_from(i % 100).unwrap();
+            state.update(
+                UsagePercentages {
+                    requests: percent,
+                    ..UsagePercentages::default()
+                },
+                false,
+            );
+        }
+        assert_eq!(state.history().len(), HISTORY_LEN);
+        assert_eq!(state.history().back(), Some(&29));
+    }
+
+    #[test]
+    fn test_render_shows_gauges_and_status() {
+        let mut state = DashboardState::new("sleep 10".to_string(), Thresholds::default());
+        state.update(
+            UsagePercentages {
+                requests: 95,
+                tokens: 40,
+                input_tokens: 10,
+                output_tokens: 20,
+                error_requests: 0,
+            },
+            true,
+        );
+
+        let screen = render_to_string(&state);
+        assert!(screen.contains("Requests"));
+        assert!(screen.contains("95%"));
+        assert!(screen.contains("Input tokens"));
+        assert!(screen.contains("Output tokens"));
+        assert!(screen.contains("sleep 10 - peak usage"));
+        assert!(screen.contains("PAUSED"));
+    }
+
+    #[test]
+    fn test_gauge_colors_follow_thresholds() {
+        let state = DashboardState::new("test".to_string(), Thresholds::default());
+        assert_eq!(state.color_for(10), Color::Green);
+        assert_eq!(state.color_for(80), Color::Yellow);
+        assert_eq!(state.color_for(90), Color::Red);
+    }
+}
@@ -88,6 +88,49 @@ fn test_init_with_env_vars() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_init_dry_run_prints_config_without_writing() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let config_path = temp_dir.path().join("strainer").join("config.toml");
+
+    let mut cmd = Command::cargo_bin("strainer")?;
+    cmd.arg("init")
+        .arg("--no-prompt")
+        .arg("--dry-run")
+        .arg("--config")
+        .arg(config_path.as_os_str())
+        .env_remove("STRAINER_PROVIDER");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(r#"type = "anthropic""#));
+
+    assert!(!config_path.exists());
+    assert!(!config_path.parent().unwrap().exists());
+    Ok(())
+}
+
+#[test]
+fn test_init_dry_run_ignores_existing_config() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let config_path = temp_dir.path().join("config.toml");
+    fs::write(&config_path, "# existing config")?;
+
+    let mut cmd = Command::cargo_bin("strainer")?;
+    cmd.arg("init")
+        .arg("--no-prompt")
+        .arg("--stdout")
+        .arg("--config")
+        .arg(config_path.as_os_str());
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("[api]"));
+
+    assert_eq!(fs::read_to_string(config_path)?, "# existing config");
+    Ok(())
+}
+
 #[test]
 fn test_init_command_help() -> anyhow::Result<()> {
     let mut cmd = Command::cargo_bin("strainer")?;
@@ -3,6 +3,10 @@ use std::env;
 use std::fs;
 use std::os::unix::fs::PermissionsExt;
 use std::time::Duration;
+use strainer::audit::{AuditAction, AuditRecord};
+use strainer::config::HumanDuration;
+use strainer::providers::config::{MockConfig, ProviderConfig};
+use strainer::providers::rate_limiter::UsageBand;
 use tempfile::tempdir;
 use tokio::process::Command as TokioCommand;
 
@@ -29,6 +33,11 @@ async fn run_strainer_command(
     args: &[&str],
     test_dir: &tempfile::TempDir,
 ) -> Result<std::process::Output> {
+    Ok(strainer_command(args, test_dir).output().await?)
+}
+
+// Helper function to build the command so tests can adjust its environment
+fn strainer_command(args: &[&str], test_dir: &tempfile::TempDir) -> TokioCommand {
     let binary_path = env::var("CARGO_BIN_EXE_strainer").unwrap_or_else(|_| {
         // Fallback for running tests directly without cargo
         let generic_path = env::current_dir().unwrap().join("target/debug/strainer");
@@ -42,7 +51,9 @@ async fn run_strainer_command(
             generic_path.display().to_string()
         } else {
             panic!(
-                "Could not find strainer binary in either {generic_path:?} or {platform_path:?}"
+                "Could not find strainer binary in either {} or {}",
+                generic_path.display(),
+                platform_path.display()
             );
         }
     });
@@ -53,7 +64,7 @@ async fn run_strainer_command(
     cmd.current_dir(test_dir.path());
     cmd.stderr(std::process::Stdio::piped());
     cmd.stdout(std::process::Stdio::piped());
-    Ok(cmd.output().await?)
+    cmd
 }
 
 fn spawn_strainer_command(
@@ -73,7 +84,9 @@ fn spawn_strainer_command(
             generic_path.display().to_string()
         } else {
             panic!(
-                "Could not find strainer binary in either {generic_path:?} or {platform_path:?}"
+                "Could not find strainer binary in either {} or {}",
+                generic_path.display(),
+                platform_path.display()
             );
         }
     });
@@ -223,6 +236,40 @@ async fn test_run_command_process_control() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_watch_reads_pid_from_pid_file() -> Result<()> {
+    let test_dir = tempdir()?;
+    let mut child = TokioCommand::new("sleep").arg("30").spawn()?;
+    let pid = child.id().expect("Failed to get process ID");
+    let pid_file = test_dir.path().join("app.pid");
+    fs::write(&pid_file, format!("{pid}\n"))?;
+
+    let watch = [
+        "watch",
+        "--pidfile",
+        "app.pid",
+        "--api",
+        "mock",
+        "--api-key",
+        "test",
+    ];
+    let output = run_strainer_command(&watch, &test_dir).await?;
+    assert!(output.status.success(), "watch failed: {output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&format!("Process {pid} is running")));
+
+    // Once the process is gone, the PID in the file no longer counts
+    child.kill().await?;
+    child.wait().await?;
+    let output = run_strainer_command(&watch, &test_dir).await?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not running"));
+
+    fs::write(&pid_file, "not a pid")?;
+    let output = run_strainer_command(&watch, &test_dir).await?;
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid PID"));
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_watch_command() -> anyhow::Result<()> {
     let test_dir = tempdir()?;
@@ -271,3 +318,1295 @@ async fn test_watch_command() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_run_command_allow_missing_config() -> Result<()> {
+    let test_dir = tempdir()?;
+    fs::write(
+        test_dir.path().join("strainer.toml"),
+        "this is [not valid toml",
+    )?;
+
+    let output = run_strainer_command(&["run", "--api", "mock", "--", "true"], &test_dir).await?;
+    assert!(
+        !output.status.success(),
+        "Expected a broken config to be fatal without the flag"
+    );
+
+    let output = run_strainer_command(
+        &[
+            "run",
+            "--allow-missing-config",
+            "--api",
+            "mock",
+            "--",
+            "true",
+        ],
+        &test_dir,
+    )
+    .await?;
+    assert!(
+        output.status.success(),
+        "Expected run to proceed with --allow-missing-config: {output:?}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sign_config_and_verify_on_run() -> Result<()> {
+    let test_dir = tempdir()?;
+    let config_path = test_dir.path().join("strainer.toml");
+    let mut config = strainer::Config::default();
+    config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+    let contents = toml::to_string(&config)?;
+    fs::write(&config_path, &contents)?;
+
+    let output = run_strainer_command(
+        &["sign-config", "--key", "strainer.key", "--generate-key"],
+        &test_dir,
+    )
+    .await?;
+    assert!(output.status.success(), "sign-config failed: {output:?}");
+    assert!(test_dir.path().join("strainer.toml.sig").exists());
+    let stdout = String::from_utf8(output.stdout)?;
+    let public_key = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Public key: "))
+        .expect("sign-config should print the public key")
+        .to_string();
+
+    let run_args = [
+        "run",
+        "--public-key",
+        public_key.as_str(),
+        "--api",
+        "mock",
+        "--",
+        "true",
+    ];
+    let output = run_strainer_command(&run_args, &test_dir).await?;
+    assert!(
+        output.status.success(),
+        "Expected a correctly signed config to run: {output:?}"
+    );
+
+    fs::write(
+        &config_path,
+        contents.replace("warning = 80", "warning = 85"),
+    )?;
+    let output = run_strainer_command(&run_args, &test_dir).await?;
+    assert!(
+        !output.status.success(),
+        "Expected a tampered config to fail"
+    );
+    assert!(String::from_utf8(output.stderr)?.contains("verification failed"));
+
+    let mut no_verify_args = vec!["run", "--no-verify"];
+    no_verify_args.extend_from_slice(&run_args[1..]);
+    let output = run_strainer_command(&no_verify_args, &test_dir).await?;
+    assert!(
+        output.status.success(),
+        "Expected --no-verify to skip verification: {output:?}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_signed_config_requires_signed_includes() -> Result<()> {
+    let test_dir = tempdir()?;
+    let mut config = strainer::Config::default();
+    config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+    fs::write(
+        test_dir.path().join("strainer.toml"),
+        format!("include = [\"limits.toml\"]\n{}", toml::to_string(&config)?),
+    )?;
+    fs::write(
+        test_dir.path().join("limits.toml"),
+        "[limits]\nrequests_per_minute = 1000\n",
+    )?;
+
+    let output = run_strainer_command(
+        &["sign-config", "--key", "strainer.key", "--generate-key"],
+        &test_dir,
+    )
+    .await?;
+    assert!(output.status.success(), "sign-config failed: {output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    let public_key = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Public key: "))
+        .expect("sign-config should print the public key")
+        .to_string();
+
+    let run_args = [
+        "run",
+        "--public-key",
+        public_key.as_str(),
+        "--api",
+        "mock",
+        "--",
+        "true",
+    ];
+    let output = run_strainer_command(&run_args, &test_dir).await?;
+    assert!(
+        !output.status.success(),
+        "Expected an unsigned include to fail"
+    );
+    assert!(String::from_utf8(output.stderr)?.contains("limits.toml is not signed"));
+
+    let output = run_strainer_command(
+        &["sign-config", "limits.toml", "--key", "strainer.key"],
+        &test_dir,
+    )
+    .await?;
+    assert!(output.status.success(), "sign-config failed: {output:?}");
+    let output = run_strainer_command(&run_args, &test_dir).await?;
+    assert!(
+        output.status.success(),
+        "Expected signed includes to run: {output:?}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_id_in_json_logs() -> Result<()> {
+    let test_dir = tempdir()?;
+
+    let output = strainer_command(
+        &[
+            "--log-format",
+            "json",
+            "--run-id",
+            "orchestrator-42",
+            "run",
+            "--api",
+            "mock",
+            "--api-key",
+            "test",
+            "--",
+            "true",
+        ],
+        &test_dir,
+    )
+    // Other tests set RUST_LOG to silence the binary
+    .env_remove("RUST_LOG")
+    .output()
+    .await?;
+    assert!(output.status.success(), "run failed: {output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    assert!(!events.is_empty(), "Expected JSON log events: {stdout}");
+    for event in &events {
+        assert_eq!(event["span"]["run_id"], "orchestrator-42", "{event}");
+    }
+
+    // Without --run-id a random one is generated
+    let output = strainer_command(
+        &[
+            "--log-format",
+            "json",
+            "run",
+            "--api",
+            "mock",
+            "--api-key",
+            "test",
+            "--",
+            "true",
+        ],
+        &test_dir,
+    )
+    .env_remove("RUST_LOG")
+    .output()
+    .await?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("\"run_id\":\""), "{stdout}");
+
+    // Events from loading the config and from commands that run no process
+    // carry it too
+    fs::write(test_dir.path().join("usage.jsonl"), "")?;
+    let output = strainer_command(
+        &[
+            "--log-format",
+            "json",
+            "--run-id",
+            "replay-7",
+            "--config",
+            "missing.toml",
+            "--allow-missing-config",
+            "replay",
+            "usage.jsonl",
+        ],
+        &test_dir,
+    )
+    .env_remove("RUST_LOG")
+    .output()
+    .await?;
+    assert!(output.status.success(), "replay failed: {output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    let warning = stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|event| {
+            event["fields"]["message"]
+                .as_str()
+                .is_some_and(|message| message.contains("using defaults"))
+        })
+        .unwrap_or_else(|| panic!("Expected the missing config warning: {stdout}"));
+    assert_eq!(warning["span"]["run_id"], "replay-7", "{warning}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_config_check_only_does_not_spawn() -> Result<()> {
+    let test_dir = tempdir()?;
+
+    let output = run_strainer_command(
+        &[
+            "run",
+            "--config-check-only",
+            "--api",
+            "mock",
+            "--api-key",
+            "test",
+            "--",
+            "touch",
+            "spawned",
+        ],
+        &test_dir,
+    )
+    .await?;
+    assert!(output.status.success(), "check failed: {output:?}");
+    assert!(String::from_utf8(output.stdout)?.contains("config OK"));
+    assert!(
+        !test_dir.path().join("spawned").exists(),
+        "The command must not be run"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_named_commands() -> Result<()> {
+    let test_dir = tempdir()?;
+    let mut config = strainer::Config::default();
+    config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+    for name in ["first", "second"] {
+        config.commands.insert(
+            name.to_string(),
+            strainer::config::NamedCommand {
+                command: vec!["touch".to_string(), format!("{name}.done")],
+                limits: None,
+                thresholds: None,
+                backoff: None,
+            },
+        );
+    }
+    fs::write(
+        test_dir.path().join("strainer.toml"),
+        toml::to_string(&config)?,
+    )?;
+
+    for name in ["first", "second"] {
+        let output = run_strainer_command(&["run-named", name], &test_dir).await?;
+        assert!(output.status.success(), "{name} failed: {output:?}");
+        assert!(test_dir.path().join(format!("{name}.done")).exists());
+    }
+
+    let output = run_strainer_command(&["run-named", "third"], &test_dir).await?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("available commands: first, second"),
+        "{stderr}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_detach_writes_pid_file_and_stops() -> Result<()> {
+    let test_dir = tempdir()?;
+    let pid_file = test_dir.path().join("strainer.pid");
+    let log_file = test_dir.path().join("strainer.log");
+
+    let output = tokio::time::timeout(
+        Duration::from_secs(10),
+        run_strainer_command(
+            &[
+                "run",
+                "--detach",
+                "--pid-file",
+                pid_file.to_str().unwrap(),
+                "--log-file",
+                log_file.to_str().unwrap(),
+                "--api",
+                "mock",
+                "--api-key",
+                "test",
+                "--",
+                "sleep",
+                "30",
+            ],
+            &test_dir,
+        ),
+    )
+    .await??;
+    assert!(output.status.success(), "detach failed: {output:?}");
+
+    let pid: i32 = fs::read_to_string(&pid_file)?.trim().parse()?;
+
+    // Wait until the daemon has started its command before stopping it
+    for _ in 0..100 {
+        if fs::read_to_string(&log_file).is_ok_and(|log| log.contains("Started process")) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), None)?;
+
+    let output = run_strainer_command(
+        &["stop", "--pid-file", pid_file.to_str().unwrap()],
+        &test_dir,
+    )
+    .await?;
+    assert!(output.status.success(), "stop failed: {output:?}");
+
+    // The daemon removes its PID file once it has shut down
+    for _ in 0..100 {
+        if !pid_file.exists() {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("detached strainer did not stop");
+}
+
+#[tokio::test]
+async fn test_providers_lists_defaults() -> Result<()> {
+    let test_dir = tempdir()?;
+    let output = run_strainer_command(&["providers"], &test_dir).await?;
+    assert!(output.status.success(), "providers failed: {output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    for expected in [
+        "anthropic\n  base_url: https://api.anthropic.com/v1",
+        "openai\n  base_url: https://api.openai.com/v1",
+        "mock\n  base_url: -",
+    ] {
+        assert!(stdout.contains(expected), "{stdout}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_record_and_replay_usage() -> Result<()> {
+    let test_dir = tempdir()?;
+    let recording = test_dir.path().join("usage.jsonl");
+    let mut config = strainer::Config::default();
+    config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+    fs::write(
+        test_dir.path().join("strainer.toml"),
+        toml::to_string(&config)?,
+    )?;
+
+    let output = run_strainer_command(
+        &[
+            "run",
+            "--record",
+            recording.to_str().unwrap(),
+            "--api",
+            "mock",
+            "--api-key",
+            "test",
+            "--",
+            "sleep",
+            "2",
+        ],
+        &test_dir,
+    )
+    .await?;
+    assert!(output.status.success(), "run failed: {output:?}");
+    let readings = fs::read_to_string(&recording)?.lines().count();
+    assert!(readings > 0, "Expected the run to record usage readings");
+
+    let output = run_strainer_command(&["replay", recording.to_str().unwrap()], &test_dir).await?;
+    assert!(output.status.success(), "replay failed: {output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("-> proceed"), "{stdout}");
+    assert!(
+        stdout.contains(&format!("Replayed {readings} readings")),
+        "{stdout}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_writes_summary_file_on_failure() -> Result<()> {
+    let test_dir = tempdir()?;
+    let summary_path = test_dir.path().join("summary.json");
+    let mut config = strainer::Config::default();
+    config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+    fs::write(
+        test_dir.path().join("strainer.toml"),
+        toml::to_string(&config)?,
+    )?;
+
+    let output = run_strainer_command(
+        &[
+            "run",
+            "--summary-file",
+            summary_path.to_str().unwrap(),
+            "--api",
+            "mock",
+            "--api-key",
+            "test",
+            "--",
+            "sh",
+            "-c",
+            "sleep 1; exit 3",
+        ],
+        &test_dir,
+    )
+    .await?;
+    assert!(!output.status.success(), "run should fail: {output:?}");
+
+    let summary: strainer::summary::RunSummary =
+        serde_json::from_str(&fs::read_to_string(&summary_path)?)?;
+    assert_eq!(summary.exit_code, Some(3));
+    assert_eq!(summary.provider, "mock");
+    assert!(summary.ended_at_ms >= summary.started_at_ms);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bench_sweeps_requests_per_minute() -> Result<()> {
+    let test_dir = tempdir()?;
+    let mut config = strainer::Config::default();
+    config.api.provider_config = ProviderConfig::Mock(MockConfig {
+        requests_used: 50,
+        ..MockConfig::default()
+    });
+    config.backoff.min_seconds = HumanDuration::from_millis(100);
+    config.backoff.max_seconds = HumanDuration::from_millis(200);
+    let config_path = test_dir.path().join("strainer.toml");
+    fs::write(&config_path, toml::to_string(&config)?)?;
+
+    // The mock reports 50 requests, five times the lower limit, so that run
+    // pauses; at half the higher limit the other never does
+    let output = run_strainer_command(
+        &[
+            "--config",
+            config_path.to_str().unwrap(),
+            "--log-level",
+            "error",
+            "bench",
+            "--requests-per-minute",
+            "10,100",
+            "--",
+            "sleep",
+            "1",
+        ],
+        &test_dir,
+    )
+    .await?;
+    assert!(output.status.success(), "bench failed: {output:?}");
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let rows: Vec<Vec<&str>> = stdout
+        .lines()
+        .skip_while(|line| !line.contains("requests/min"))
+        .skip(1)
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(rows.len(), 2, "{stdout}");
+    let pauses = |row: &[&str]| row[2].parse::<u32>().unwrap();
+    assert_eq!((rows[0][0], rows[1][0]), ("10", "100"));
+    assert!(pauses(&rows[0]) > 0, "{stdout}");
+    assert_eq!(pauses(&rows[1]), 0, "{stdout}");
+    assert_eq!((rows[0][4], rows[1][4]), ("0", "0"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_monitor_logs_band_until_interrupted() -> Result<()> {
+    let test_dir = tempdir()?;
+    let mut config = strainer::Config::default();
+    // The mock has used its whole request quota, so usage is critical from
+    // the start
+    config.api.provider_config = ProviderConfig::Mock(MockConfig {
+        requests_per_minute: Some(100),
+        requests_used: 100,
+        ..MockConfig::default()
+    });
+    let config_path = test_dir.path().join("strainer.toml");
+    fs::write(&config_path, toml::to_string(&config)?)?;
+
+    let child = strainer_command(
+        &[
+            "--config",
+            config_path.to_str().unwrap(),
+            "monitor",
+            "--interval",
+            "1",
+        ],
+        &test_dir,
+    )
+    .spawn()?;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    #[allow(clippy::cast_possible_wrap)]
+    let pid = nix::unistd::Pid::from_raw(child.id().unwrap() as i32);
+    nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGINT)?;
+
+    let output = tokio::time::timeout(Duration::from_secs(10), child.wait_with_output()).await??;
+    assert!(output.status.success(), "monitor failed: {output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Usage band is critical"), "{stdout}");
+    assert_eq!(stdout.matches("Usage band").count(), 1, "{stdout}");
+    assert!(stdout.contains("Stopped monitoring"), "{stdout}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_on_critical_command_runs_once() -> Result<()> {
+    let test_dir = tempdir()?;
+    let mut config = strainer::Config::default();
+    // The mock has used its whole request quota, so the command pauses and
+    // resumes repeatedly while it runs
+    config.api.provider_config = ProviderConfig::Mock(MockConfig {
+        requests_per_minute: Some(100),
+        requests_used: 100,
+        ..MockConfig::default()
+    });
+    config.backoff.min_seconds = HumanDuration::from_millis(100);
+    config.backoff.max_seconds = HumanDuration::from_millis(200);
+    config.process.on_critical_command = Some(vec!["touch".to_string(), "fallback".to_string()]);
+    let config_path = test_dir.path().join("strainer.toml");
+    fs::write(&config_path, toml::to_string(&config)?)?;
+
+    let args = [
+        "--config",
+        config_path.to_str().unwrap(),
+        "run",
+        "--api",
+        "mock",
+        "--api-key",
+        "test",
+        "--",
+        "sleep",
+        "1",
+    ];
+    let output = run_strainer_command(&args, &test_dir).await?;
+    assert!(output.status.success(), "run failed: {output:?}");
+    assert!(test_dir.path().join("fallback").exists());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert_eq!(
+        stdout.matches("starting fallback command touch").count(),
+        1,
+        "{stdout}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_on_pause_exit_terminates_with_tempfail() -> Result<()> {
+    let test_dir = tempdir()?;
+    let mut config = strainer::Config::default();
+    // The mock has used its whole request quota, so usage is critical from
+    // the first check
+    config.api.provider_config = ProviderConfig::Mock(MockConfig {
+        requests_per_minute: Some(100),
+        requests_used: 100,
+        ..MockConfig::default()
+    });
+    let config_path = test_dir.path().join("strainer.toml");
+    fs::write(&config_path, toml::to_string(&config)?)?;
+
+    let args = [
+        "--config",
+        config_path.to_str().unwrap(),
+        "run",
+        "--api",
+        "mock",
+        "--api-key",
+        "test",
+        "--on-pause-exit",
+        "--",
+        "sh",
+        "-c",
+        "sleep 2 && touch done",
+    ];
+    let output = run_strainer_command(&args, &test_dir).await?;
+    assert_eq!(output.status.code(), Some(75), "{output:?}");
+    // The orphaned `sleep` holds the output open until it ends, so by now
+    // the command would have finished had it not been terminated
+    assert!(!test_dir.path().join("done").exists());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(
+        stdout.contains("terminating process instead of pausing"),
+        "{stdout}"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_on_pause_exit_ignored_without_pause_on_critical() -> Result<()> {
+    let test_dir = tempdir()?;
+    let mut config = strainer::Config::default();
+    config.api.provider_config = ProviderConfig::Mock(MockConfig {
+        requests_per_minute: Some(100),
+        requests_used: 100,
+        ..MockConfig::default()
+    });
+    config.process.pause_on_critical = false;
+    let config_path = test_dir.path().join("strainer.toml");
+    fs::write(&config_path, toml::to_string(&config)?)?;
+
+    let args = [
+        "--config",
+        config_path.to_str().unwrap(),
+        "run",
+        "--api",
+        "mock",
+        "--api-key",
+        "test",
+        "--on-pause-exit",
+        "--",
+        "sh",
+        "-c",
+        "sleep 1 && touch done",
+    ];
+    let output = run_strainer_command(&args, &test_dir).await?;
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+    assert!(test_dir.path().join("done").exists());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_percent_precision_logs_decimals() -> Result<()> {
+    let test_dir = tempdir()?;
+    let mut config = strainer::Config::default();
+    config.api.provider_config = ProviderConfig::Mock(MockConfig {
+        requests_per_minute: Some(100),
+        ..MockConfig::default()
+    });
+    config.thresholds.percent_precision = 1;
+    let config_path = test_dir.path().join("strainer.toml");
+    fs::write(&config_path, toml::to_string(&config)?)?;
+
+    let args = [
+        "--config",
+        config_path.to_str().unwrap(),
+        "run",
+        "--api",
+        "mock",
+        "--api-key",
+        "test",
+        "--",
+        "sleep",
+        "0.5",
+    ];
+    let output = run_strainer_command(&args, &test_dir).await?;
+    assert!(output.status.success(), "run failed: {output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Requests: 0.0% ("), "{stdout}");
+    Ok(())
+}
+
+/// Start `run` on a command that takes a second to exit after SIGTERM, then
+/// interrupt strainer and return whether the command finished its cleanup
+async fn interrupt_slow_exit(extra_args: &[&str]) -> Result<bool> {
+    let test_dir = tempdir()?;
+    let script = "trap 'sleep 1; touch flushed; exit 0' TERM; touch started; \
+                  while :; do sleep 0.1; done";
+    let mut args = vec!["run", "--api", "mock", "--api-key", "test"];
+    args.extend_from_slice(extra_args);
+    args.extend_from_slice(&["--", "sh", "-c", script]);
+    let child = strainer_command(&args, &test_dir).spawn()?;
+
+    let started = test_dir.path().join("started");
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !started.exists() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await?;
+    #[allow(clippy::cast_possible_wrap)]
+    let pid = nix::unistd::Pid::from_raw(child.id().unwrap() as i32);
+    nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGINT)?;
+
+    let output = tokio::time::timeout(Duration::from_secs(10), child.wait_with_output()).await??;
+    assert!(output.status.success(), "run failed: {output:?}");
+    Ok(test_dir.path().join("flushed").exists())
+}
+
+#[tokio::test]
+async fn test_kill_timeout_lets_command_clean_up_on_shutdown() -> Result<()> {
+    let started = std::time::Instant::now();
+    assert!(interrupt_slow_exit(&["--kill-timeout", "10s"]).await?);
+    // Strainer stops waiting as soon as the command exits
+    assert!(started.elapsed() < Duration::from_secs(8));
+
+    // The default timeout kills the command before its cleanup is done
+    assert!(!interrupt_slow_exit(&[]).await?);
+    Ok(())
+}
+
+/// Run `sh -c script` under `config` with an audit log, returning its records
+async fn audited_run(config: &strainer::Config, script: &str) -> Result<Vec<AuditRecord>> {
+    let test_dir = tempdir()?;
+    let config_path = test_dir.path().join("strainer.toml");
+    fs::write(&config_path, toml::to_string(config)?)?;
+    let args = [
+        "--config",
+        config_path.to_str().unwrap(),
+        "run",
+        "--api",
+        "mock",
+        "--api-key",
+        "test",
+        "--audit-log",
+        "audit.jsonl",
+        "--",
+        "sh",
+        "-c",
+        script,
+    ];
+    let output = run_strainer_command(&args, &test_dir).await?;
+    assert!(output.status.success(), "run failed: {output:?}");
+
+    let contents = fs::read_to_string(test_dir.path().join("audit.jsonl"))?;
+    contents
+        .lines()
+        .map(|line| serde_json::from_str(line).map_err(|e| anyhow::anyhow!("{e}: {line}")))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_audit_log_records_each_decision() -> Result<()> {
+    let mut config = strainer::Config::default();
+    config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+    let records = audited_run(&config, "sleep 1").await?;
+    assert!(!records.is_empty());
+    assert!(records
+        .iter()
+        .all(|record| record.action == AuditAction::Proceed && record.band == UsageBand::Normal));
+
+    // Once the mock has used its whole request quota, every check pauses and
+    // resumes
+    config.api.provider_config = ProviderConfig::Mock(MockConfig {
+        requests_per_minute: Some(100),
+        requests_used: 100,
+        ..MockConfig::default()
+    });
+    config.backoff.min_seconds = HumanDuration::from_millis(100);
+    config.backoff.max_seconds = HumanDuration::from_millis(300);
+    let records = audited_run(&config, "sleep 1").await?;
+    assert!(records.len() >= 2, "{records:?}");
+    for pair in records.chunks(2) {
+        assert_eq!(pair[0].action, AuditAction::Pause, "{records:?}");
+        assert_eq!(pair[0].band, UsageBand::Critical);
+        assert_eq!(pair[0].backoff_ms, 300);
+        assert_eq!(pair[0].percentages.requests, 100);
+        if let Some(resume) = pair.get(1) {
+            assert_eq!(resume.action, AuditAction::Resume, "{records:?}");
+            assert!(resume.timestamp_ms >= pair[0].timestamp_ms + 300);
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_control_file_pauses_and_resumes_command() -> Result<()> {
+    let test_dir = tempdir()?;
+    let args = [
+        "run",
+        "--api",
+        "mock",
+        "--api-key",
+        "test",
+        "--control-file",
+        "control",
+        "--audit-log",
+        "audit.jsonl",
+        "--",
+        "sh",
+        "-c",
+        "while :; do echo tick >> ticks; sleep 0.1; done",
+    ];
+    let child = strainer_command(&args, &test_dir).spawn()?;
+    let ticks_path = test_dir.path().join("ticks");
+    let ticks = || fs::read_to_string(&ticks_path).map_or(0, |t| t.lines().count());
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while ticks() == 0 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await?;
+
+    // Usage is checked every second, so give strainer time to see the file
+    fs::write(test_dir.path().join("control"), "pause\n")?;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let paused = ticks();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(ticks(), paused, "command kept running while paused");
+
+    fs::write(test_dir.path().join("control"), "resume\n")?;
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(ticks() > paused, "command did not resume");
+
+    #[allow(clippy::cast_possible_wrap)]
+    let pid = nix::unistd::Pid::from_raw(child.id().unwrap() as i32);
+    nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGINT)?;
+    let output = tokio::time::timeout(Duration::from_secs(10), child.wait_with_output()).await??;
+    assert!(output.status.success(), "run failed: {output:?}");
+
+    // The pause held over several checks is audited once
+    let audit = fs::read_to_string(test_dir.path().join("audit.jsonl"))?;
+    let actions: Vec<AuditAction> = audit
+        .lines()
+        .map(|line| Ok(serde_json::from_str::<AuditRecord>(line)?.action))
+        .collect::<Result<_>>()?;
+    let count = |action| actions.iter().filter(|&&a| a == action).count();
+    assert_eq!(count(AuditAction::Pause), 1, "{actions:?}");
+    assert_eq!(count(AuditAction::Resume), 1, "{actions:?}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_exit_codes() -> Result<()> {
+    let test_dir = tempdir()?;
+    let not_executable = test_dir.path().join("not_executable.sh");
+    fs::write(&not_executable, "#!/bin/sh\n")?;
+    fs::set_permissions(&not_executable, fs::Permissions::from_mode(0o644))?;
+    let not_executable = not_executable.display().to_string();
+
+    let mock = ["run", "--api", "mock", "--api-key", "test"];
+    let scenarios: [(&[&str], &[&str], i32); 5] = [
+        (&mock, &["--", "true"], 0),
+        (&mock, &["--", "sh", "-c", "exit 3"], 1),
+        (
+            &["run", "--api", "mock", "--api-key", "keyring:no-account"],
+            &["--", "true"],
+            2,
+        ),
+        (&mock, &["--", &not_executable], 126),
+        (&mock, &["--", "strainer-test-missing-command"], 127),
+    ];
+    for (base, extra, expected) in scenarios {
+        let args: Vec<&str> = base.iter().chain(extra).copied().collect();
+        let output = run_strainer_command(&args, &test_dir).await?;
+        assert_eq!(output.status.code(), Some(expected), "{args:?}: {output:?}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_config_loaded_from_url() -> Result<()> {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let mut config = strainer::Config::default();
+    config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+    config.limits.requests_per_minute = Some(42);
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/strainer.toml"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(toml::to_string(&config)?))
+        .mount(&server)
+        .await;
+
+    let test_dir = tempdir()?;
+    let url = format!("{}/strainer.toml", server.uri());
+    let args = ["--config", &url, "config", "export", "--format", "env"];
+    let output = run_strainer_command(&args, &test_dir).await?;
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("STRAINER_REQUESTS_PER_MINUTE"), "{stdout}");
+    assert!(stdout.contains("42"), "{stdout}");
+
+    // A config that cannot be fetched is a config error naming the URL
+    let missing = format!("{}/missing.toml", server.uri());
+    let args = ["--config", &missing, "run", "--api", "mock", "--", "true"];
+    let output = run_strainer_command(&args, &test_dir).await?;
+    assert_eq!(output.status.code(), Some(2), "{output:?}");
+    assert!(String::from_utf8(output.stderr)?.contains(&missing));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_with_pty() -> Result<()> {
+    let test_dir = tempdir()?;
+    let is_tty = "if [ -t 1 ]; then echo tty; else echo no tty; exit 1; fi";
+    let base = ["run", "--api", "mock", "--api-key", "test"];
+
+    let args: Vec<&str> = base
+        .iter()
+        .chain(&["--pty", "--", "sh", "-c", is_tty])
+        .copied()
+        .collect();
+    let output = run_strainer_command(&args, &test_dir).await?;
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8(output.stdout)?.contains("tty\n"));
+
+    let args: Vec<&str> = base
+        .iter()
+        .chain(&["--", "sh", "-c", is_tty])
+        .copied()
+        .collect();
+    let output = run_strainer_command(&args, &test_dir).await?;
+    assert!(!output.status.success(), "{output:?}");
+    assert!(String::from_utf8(output.stdout)?.contains("no tty"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_stdio_modes_route_output() -> Result<()> {
+    let test_dir = tempdir()?;
+    let script = "echo to-stdout; echo to-stderr >&2";
+    for (flag, forwarded) in [
+        ("--inherit-stdio", true),
+        ("--pipe-stdio", true),
+        ("--null-stdio", false),
+    ] {
+        let args = [
+            "--log-level",
+            "error",
+            "run",
+            "--api",
+            "mock",
+            "--api-key",
+            "test",
+            flag,
+            "--",
+            "sh",
+            "-c",
+            script,
+        ];
+        let output = run_strainer_command(&args, &test_dir).await?;
+        assert!(output.status.success(), "{flag}: {output:?}");
+        let stdout = String::from_utf8(output.stdout)?;
+        let stderr = String::from_utf8(output.stderr)?;
+        assert_eq!(stdout.contains("to-stdout"), forwarded, "{flag}: {stdout}");
+        assert_eq!(stderr.contains("to-stderr"), forwarded, "{flag}: {stderr}");
+        assert!(!stdout.contains("to-stderr"), "{flag}: {stdout}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pipe_stdio_drains_output_while_paused() -> Result<()> {
+    let test_dir = tempdir()?;
+    let summary_path = test_dir.path().join("summary.json");
+    let mut config = strainer::Config::default();
+    // The mock has used its whole request quota, so the command is paused
+    // repeatedly
+    config.api.provider_config = ProviderConfig::Mock(MockConfig {
+        requests_per_minute: Some(100),
+        requests_used: 100,
+        ..MockConfig::default()
+    });
+    config.backoff.min_seconds = HumanDuration::from_millis(100);
+    config.backoff.max_seconds = HumanDuration::from_millis(200);
+    fs::write(
+        test_dir.path().join("strainer.toml"),
+        toml::to_string(&config)?,
+    )?;
+
+    // Each write is larger than a pipe holds
+    let script = "for i in 1 2 3 4 5 6 7 8 9 10; do \
+                  head -c 200000 /dev/zero | tr '\\0' x; sleep 0.1; done";
+    let output = run_strainer_command(
+        &[
+            "--log-level",
+            "error",
+            "run",
+            "--pipe-stdio",
+            "--summary-file",
+            summary_path.to_str().unwrap(),
+            "--api",
+            "mock",
+            "--api-key",
+            "test",
+            "--",
+            "sh",
+            "-c",
+            script,
+        ],
+        &test_dir,
+    )
+    .await?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout.len(), 2_000_000);
+    assert!(output.stdout.iter().all(|&byte| byte == b'x'));
+
+    let summary: strainer::summary::RunSummary =
+        serde_json::from_str(&fs::read_to_string(&summary_path)?)?;
+    assert!(summary.pause_count > 0, "{summary:?}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pipe_stdio_forwards_bytes_unchanged() -> Result<()> {
+    let test_dir = tempdir()?;
+    // Invalid UTF-8, a NUL, a line far longer than the relay's buffer and
+    // output that does not end with a newline
+    let mut payload = vec![0xff, 0xfe, b'\n', 0xc3, 0x28, 0x00, 0x80, b'\n'];
+    payload.extend((0..100_000u32).map(|i| b'a' + (i % 26) as u8));
+    payload.extend_from_slice(b"\n\xe2\x82 no newline");
+    fs::write(test_dir.path().join("payload"), &payload)?;
+
+    let output = run_strainer_command(
+        &[
+            "--log-level",
+            "error",
+            "run",
+            "--pipe-stdio",
+            "--api",
+            "mock",
+            "--api-key",
+            "test",
+            "--",
+            "sh",
+            "-c",
+            "cat payload; cat payload >&2",
+        ],
+        &test_dir,
+    )
+    .await?;
+    assert!(output.status.success(), "run failed: {output:?}");
+    assert!(output.stdout == payload, "stdout was altered");
+    assert!(output.stderr == payload, "stderr was altered");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_diff_config_prints_changed_fields() -> Result<()> {
+    let test_dir = tempdir()?;
+    let mut config = strainer::Config::default();
+    config.api.provider_config = ProviderConfig::Mock(MockConfig::default());
+    config.api.api_key = Some("sk-secret".to_string());
+    config.limits.requests_per_minute = Some(30);
+    let left = test_dir.path().join("a.toml");
+    fs::write(&left, toml::to_string(&config)?)?;
+    config.limits.requests_per_minute = Some(90);
+    let right = test_dir.path().join("b.toml");
+    fs::write(&right, toml::to_string(&config)?)?;
+
+    let args = [
+        "diff-config",
+        left.to_str().unwrap(),
+        right.to_str().unwrap(),
+    ];
+    let output = run_strainer_command(&args, &test_dir).await?;
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "limits.requests_per_minute: 30 -> 90\n"
+    );
+
+    let args = [
+        "diff-config",
+        left.to_str().unwrap(),
+        left.to_str().unwrap(),
+    ];
+    let output = run_strainer_command(&args, &test_dir).await?;
+    assert_eq!(String::from_utf8(output.stdout)?, "No differences\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_key_lock_blocks_second_run() -> Result<()> {
+    use strainer::lock::RunLock;
+
+    let test_dir = tempdir()?;
+    let api_key = format!("test-key-lock-{}", std::process::id());
+    let lock_path = RunLock::path_for(&api_key);
+    let run = |key_lock: &'static str, command: &'static str| {
+        let api_key = api_key.clone();
+        strainer_command(
+            &[
+                "run",
+                "--api",
+                "mock",
+                "--api-key",
+                &api_key,
+                "--key-lock",
+                key_lock,
+                "--",
+                "sh",
+                "-c",
+                command,
+            ],
+            &test_dir,
+        )
+    };
+
+    let mut first = run("fail", "sleep 2").spawn()?;
+    let first_pid = first.id();
+    for _ in 0..100 {
+        if first_pid.is_some() && RunLock::holder(&lock_path) == first_pid {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(RunLock::holder(&lock_path), first_pid);
+
+    let second = run("fail", "true").output().await?;
+    assert_eq!(second.status.code(), Some(75), "{second:?}");
+    assert!(String::from_utf8_lossy(&second.stderr).contains("API key is locked"));
+
+    // A waiting run starts once the first one has finished
+    let third = run("wait", "true").output().await?;
+    assert!(third.status.success(), "{third:?}");
+    // The lock is released just before the first run exits
+    let first = tokio::time::timeout(Duration::from_secs(5), first.wait())
+        .await
+        .map_err(|_| anyhow::anyhow!("first run should have finished"))??;
+    assert!(first.success());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_logs_target_without_api_key() -> Result<()> {
+    let test_dir = tempdir()?;
+
+    let output = strainer_command(
+        &[
+            "run",
+            "--config-check-only",
+            "--api",
+            "anthropic",
+            "--model",
+            "claude-target-test",
+            "--api-key",
+            "sk-target-secret",
+            "--api-base-url",
+            "http://127.0.0.1:9/v1",
+            "--",
+            "true",
+        ],
+        &test_dir,
+    )
+    .env_remove("RUST_LOG")
+    .output()
+    .await?;
+    assert!(output.status.success(), "check failed: {output:?}");
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(
+        stdout
+            .contains("provider=anthropic model=claude-target-test base_url=http://127.0.0.1:9/v1"),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("sk-target-secret"), "{stdout}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_routes_select_provider_per_command() -> Result<()> {
+    let test_dir = tempdir()?;
+    fs::write(
+        test_dir.path().join("strainer.toml"),
+        r#"
+        [api]
+        type = "anthropic"
+        api_key = "base-key"
+
+        [limits]
+        [thresholds]
+        [backoff]
+        [process]
+        [logging]
+        level = "info"
+        format = "text"
+
+        [[routes]]
+        program = "true"
+        api = { type = "anthropic", api_key = "k", model = "claude-route", base_url = "http://127.0.0.1:9/v1" }
+
+        [[routes]]
+        pattern = "openai-tool"
+        api = { type = "mock", base_url = "http://mock-route.invalid" }
+        "#,
+    )?;
+    let target = |flags: &'static [&'static str], command: &'static [&'static str]| {
+        let mut args = vec!["--config", "strainer.toml", "run", "--config-check-only"];
+        args.extend_from_slice(flags);
+        args.push("--");
+        args.extend_from_slice(command);
+        let mut cmd = strainer_command(&args, &test_dir);
+        cmd.env_remove("RUST_LOG");
+        async move {
+            let output = cmd.output().await?;
+            assert!(output.status.success(), "check failed: {output:?}");
+            anyhow::Ok(String::from_utf8(output.stdout)?)
+        }
+    };
+
+    let stdout = target(&[], &["true"]).await?;
+    assert!(
+        stdout.contains("provider=anthropic model=claude-route"),
+        "{stdout}"
+    );
+    let stdout = target(&[], &["sh", "-c", "echo openai-tool"]).await?;
+    assert!(
+        stdout.contains("provider=mock model=- base_url=http://mock-route.invalid"),
+        "{stdout}"
+    );
+    let stdout = target(&[], &["false"]).await?;
+    assert!(stdout.contains("provider=anthropic"), "{stdout}");
+    assert!(!stdout.contains("-route"), "{stdout}");
+
+    // A provider chosen on the command line wins over a matching route
+    let stdout = target(&["--api", "mock", "--api-key", "test"], &["true"]).await?;
+    assert!(stdout.contains("provider=mock"), "{stdout}");
+    assert!(!stdout.contains("-route"), "{stdout}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_config_set_edits_file_in_place() -> Result<()> {
+    let test_dir = tempdir()?;
+    let config = strainer::config::Config {
+        api: strainer::config::ApiConfig {
+            provider_config: ProviderConfig::Mock(MockConfig::default()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let contents = format!(
+        "# Limits for the shared account\n{}",
+        toml::to_string(&config)?
+    );
+    let config_path = test_dir.path().join("strainer.toml");
+    fs::write(&config_path, contents)?;
+
+    let output = run_strainer_command(
+        &["config", "set", "limits.requests_per_minute", "120"],
+        &test_dir,
+    )
+    .await?;
+    assert!(output.status.success(), "config set failed: {output:?}");
+
+    let edited = fs::read_to_string(&config_path)?;
+    assert!(
+        edited.starts_with("# Limits for the shared account\n"),
+        "{edited}"
+    );
+    assert!(edited.contains("requests_per_minute = 120"), "{edited}");
+
+    let output = run_strainer_command(
+        &["config", "set", "limits.request_per_minute", "120"],
+        &test_dir,
+    )
+    .await?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Unknown config key"), "{stderr}");
+    assert_eq!(fs::read_to_string(&config_path)?, edited);
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::env;
+use strainer::config::Config;
+
+#[allow(dead_code)]
+mod common;
+use common::EnvGuard;
+
+// Each test uses its own variables: tests in this file run in parallel and
+// `STRAINER_*` values would leak into the other config tests' `from_env`.
+
+#[test]
+fn test_from_env_rejects_zero_max_tokens() {
+    let _env_guard = EnvGuard::new(vec!["STRAINER_MAX_TOKENS"]);
+    env::set_var("STRAINER_MAX_TOKENS", "0");
+
+    let err = Config::builder().from_env().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "STRAINER_MAX_TOKENS must be greater than 0"
+    );
+}
+
+#[test]
+fn test_from_env_rejects_zero_shared_instances() {
+    let _env_guard = EnvGuard::new(vec!["ZEROSHARE_SHARED_INSTANCES"]);
+    env::set_var("ZEROSHARE_SHARED_INSTANCES", "0");
+
+    let err = Config::builder()
+        .from_env_with_prefix("ZEROSHARE_")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "ZEROSHARE_SHARED_INSTANCES must be greater than 0"
+    );
+}
+
+#[test]
+fn test_from_env_parses_rates() {
+    let _env_guard = EnvGuard::new(vec![
+        "RATE_PROVIDER_TYPE",
+        "RATE_TOKENS_PER_MINUTE",
+        "RATE_REQUESTS_PER_MINUTE",
+    ]);
+    env::set_var("RATE_PROVIDER_TYPE", "mock");
+    env::set_var("RATE_TOKENS_PER_MINUTE", "50k");
+    env::set_var("RATE_REQUESTS_PER_MINUTE", "100/min");
+
+    let config = Config::builder()
+        .from_env_with_prefix("RATE_")
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(config.limits.tokens_per_minute, Some(50_000));
+    assert_eq!(config.limits.requests_per_minute, Some(100));
+
+    // An invalid rate is an error rather than no limit
+    env::set_var("RATE_TOKENS_PER_MINUTE", "lots");
+    let err = Config::builder().from_env_with_prefix("RATE_").unwrap_err();
+    assert!(
+        format!("{err:#}").starts_with("Invalid RATE_TOKENS_PER_MINUTE: Invalid rate 'lots'"),
+        "{err:#}"
+    );
+}
+
+#[test]
+fn test_from_env_names_misordered_thresholds() {
+    let _env_guard = EnvGuard::new(vec!["ORDER_WARNING_THRESHOLD", "ORDER_RESUME_THRESHOLD"]);
+    env::set_var("ORDER_WARNING_THRESHOLD", "95");
+
+    let err = Config::builder()
+        .from_env_with_prefix("ORDER_")
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Warning threshold 95 must not exceed critical threshold 90 (set by ORDER_WARNING_THRESHOLD)"
+    );
+
+    env::set_var("ORDER_WARNING_THRESHOLD", "40");
+    env::set_var("ORDER_RESUME_THRESHOLD", "40");
+    let err = Config::builder()
+        .from_env_with_prefix("ORDER_")
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .ends_with("(set by ORDER_WARNING_THRESHOLD, ORDER_RESUME_THRESHOLD)"),
+        "{err}"
+    );
+
+    env::set_var("ORDER_RESUME_THRESHOLD", "10");
+    assert!(Config::builder().from_env_with_prefix("ORDER_").is_ok());
+}
@@ -0,0 +1,153 @@
+use anyhow::Result;
+use std::env;
+use strainer::config::{Config, Gating, KeyLock, MissingMetricPolicy, RoundingMode, StdioMode};
+use strainer::providers::config::{AnthropicConfig, ProviderConfig};
+
+#[allow(dead_code)]
+mod common;
+use common::EnvGuard;
+
+/// Every variable `to_env_vars(true)` exports
+const EXPORTED_VARS: [&str; 39] = [
+    "STRAINER_PROVIDER_TYPE",
+    "STRAINER_MODEL",
+    "STRAINER_MAX_TOKENS",
+    "STRAINER_API_KEY",
+    "STRAINER_BASE_URL",
+    "STRAINER_REQUESTS_PER_MINUTE",
+    "STRAINER_TOKENS_PER_MINUTE",
+    "STRAINER_INPUT_TOKENS_PER_MINUTE",
+    "STRAINER_OUTPUT_TOKENS_PER_MINUTE",
+    "STRAINER_ERROR_REQUESTS_PER_MINUTE",
+    "STRAINER_FRACTION_OF_QUOTA",
+    "STRAINER_SHARED_INSTANCES",
+    "STRAINER_RAMP_UP_SECONDS",
+    "STRAINER_FORCE_LIMITS",
+    "STRAINER_WARNING_THRESHOLD",
+    "STRAINER_CRITICAL_THRESHOLD",
+    "STRAINER_RESUME_THRESHOLD",
+    "STRAINER_FORECAST_HORIZON",
+    "STRAINER_THRESHOLD_INCLUSIVE",
+    "STRAINER_GATING",
+    "STRAINER_ROUNDING",
+    "STRAINER_STRICT_USAGE",
+    "STRAINER_WARNING_SUSTAINED_SECONDS",
+    "STRAINER_PAUSE_ON_SUSTAINED_WARNING",
+    "STRAINER_RESET_ON_RESUME",
+    "STRAINER_LATENCY_BACKOFF_MS",
+    "STRAINER_PERCENT_PRECISION",
+    "STRAINER_PAUSE_ON_WARNING",
+    "STRAINER_PAUSE_ON_CRITICAL",
+    "STRAINER_WARMUP_SECONDS",
+    "STRAINER_POST_RESUME_GRACE_SECONDS",
+    "STRAINER_PAUSE_SIGNAL",
+    "STRAINER_MAX_MEMORY",
+    "STRAINER_MAX_CPU_PERCENT",
+    "STRAINER_KEY_LOCK",
+    "STRAINER_STDIO",
+    "STRAINER_PROXY",
+    "STRAINER_PROXY_ESTIMATE_TOKENS",
+    "STRAINER_MISSING_METRIC_POLICY",
+];
+
+#[test]
+fn test_env_export_round_trips_through_from_env() -> Result<()> {
+    let mut config = Config::builder()
+        .with_provider_config(ProviderConfig::Anthropic(AnthropicConfig::default()))
+        .with_model("claude-3-opus".to_string())
+        .with_max_tokens(4096)
+        .with_api_key("round-trip-key".to_string())
+        .with_base_url("https://proxy.example.com/v1".to_string())
+        .with_requests_per_minute(120)
+        .with_tokens_per_minute(90_000)
+        .with_input_tokens_per_minute(40_000)
+        .with_output_tokens_per_minute(8_000)
+        .with_error_requests_per_minute(12)
+        .with_fraction_of_quota(0.75)
+        .with_shared_instances(3)
+        .with_ramp_up_seconds(90)
+        .with_force_limits(true)
+        .with_warning_threshold(60)
+        .with_critical_threshold(85)
+        .with_resume_threshold(40)
+        .with_pause_on_warning(true)
+        .with_pause_on_critical(false)
+        .with_warmup_seconds(20)
+        .with_post_resume_grace_seconds(4)
+        .with_pause_signal("SIGUSR1".to_string())
+        .with_max_memory(1 << 30)
+        .with_max_cpu_percent(150.0)
+        .with_key_lock(KeyLock::Wait)
+        .with_stdio(StdioMode::Pipe)
+        .with_proxy(true)
+        .with_estimate_tokens(true)
+        .with_missing_metric_policy(MissingMetricPolicy::AssumeFull)
+        .with_rounding(RoundingMode::Ceil)
+        .with_strict_usage(true)
+        .with_warning_sustained_seconds(45)
+        .with_pause_on_sustained_warning(true)
+        .with_reset_on_resume(true)
+        .with_latency_backoff_ms(750)
+        .with_percent_precision(1)
+        .build()?;
+    config.thresholds.gating = Gating::All;
+
+    let vars = config.to_env_vars(true);
+    let _env_guard = EnvGuard::new(EXPORTED_VARS.to_vec());
+    for (name, value) in &vars {
+        env::set_var(name, value);
+    }
+
+    let restored = Config::builder().from_env()?.build()?;
+
+    assert_eq!(restored.to_env_vars(true), vars);
+    assert_eq!(restored.api.api_key, Some("round-trip-key".to_string()));
+    match &restored.api.provider_config {
+        ProviderConfig::Anthropic(cfg) => {
+            assert_eq!(cfg.model, "claude-3-opus");
+            assert_eq!(cfg.max_tokens, 4096);
+        }
+        _ => panic!("Expected Anthropic provider"),
+    }
+    assert_eq!(restored.limits.requests_per_minute, Some(120));
+    assert_eq!(restored.limits.output_tokens_per_minute, Some(8_000));
+    assert_eq!(restored.limits.error_requests_per_minute, Some(12));
+    assert_eq!(restored.limits.shared_instances, Some(3));
+    assert_eq!(restored.limits.ramp_up_seconds, Some(90));
+    assert!(restored.limits.force);
+    assert_eq!(restored.thresholds.critical, 85);
+    assert_eq!(restored.thresholds.gating, Gating::All);
+    assert_eq!(restored.thresholds.rounding, RoundingMode::Ceil);
+    assert!(restored.thresholds.strict_usage);
+    assert_eq!(restored.thresholds.warning_sustained_seconds, 45);
+    assert!(restored.thresholds.pause_on_sustained_warning);
+    assert!(restored.thresholds.reset_on_resume);
+    assert_eq!(restored.thresholds.latency_backoff_ms, 750);
+    assert_eq!(restored.thresholds.percent_precision, 1);
+    assert!(restored.process.pause_on_warning);
+    assert!(!restored.process.pause_on_critical);
+    assert_eq!(restored.process.warmup_seconds, 20);
+    assert_eq!(restored.process.post_resume_grace_seconds, 4);
+    assert_eq!(restored.process.pause_signal.as_deref(), Some("SIGUSR1"));
+    assert_eq!(restored.process.max_memory, Some(1 << 30));
+    assert_eq!(restored.process.max_cpu_percent, Some(150.0));
+    assert_eq!(restored.process.key_lock, KeyLock::Wait);
+    assert_eq!(restored.process.stdio, StdioMode::Pipe);
+    assert!(restored.proxy.enabled);
+    assert!(restored.proxy.estimate_tokens);
+    assert_eq!(
+        restored.proxy.missing_metric_policy,
+        MissingMetricPolicy::AssumeFull
+    );
+    Ok(())
+}
+
+#[test]
+fn test_env_export_omits_api_key_by_default() {
+    let mut config = Config::default();
+    config.api.api_key = Some("hidden".to_string());
+
+    let exports = config.to_env_exports(false);
+    assert!(!exports.contains("hidden"));
+    assert!(!exports.contains("STRAINER_API_KEY"));
+}
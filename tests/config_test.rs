@@ -2,7 +2,7 @@ use anyhow::Result;
 use std::collections::HashMap;
 use std::env;
 use std::fs;
-use strainer::config::Config;
+use strainer::config::{Config, HumanDuration, DEFAULT_ENV_PREFIX};
 use strainer::init::{initialize_config, InitOptions};
 use strainer::providers::config::{OpenAIConfig, ProviderConfig};
 use tempfile::tempdir;
@@ -79,8 +79,8 @@ fn test_config_from_file() -> Result<()> {
     assert_eq!(config.thresholds.warning, 80);
     assert_eq!(config.thresholds.critical, 90);
     assert_eq!(config.thresholds.resume, 70);
-    assert_eq!(config.backoff.min_seconds, 1);
-    assert_eq!(config.backoff.max_seconds, 60);
+    assert_eq!(config.backoff.min_seconds, HumanDuration::from_secs(1));
+    assert_eq!(config.backoff.max_seconds, HumanDuration::from_secs(60));
     assert!(!config.process.pause_on_warning);
     assert!(config.process.pause_on_critical);
     assert_eq!(config.logging.level, "info");
@@ -90,6 +90,34 @@ fn test_config_from_file() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_config_from_env_with_custom_prefix() -> Result<()> {
+    let _env_guard = EnvGuard::new(vec![
+        "MYAPP_API_KEY",
+        "MYAPP_PROVIDER_TYPE",
+        "MYAPP_REQUESTS_PER_MINUTE",
+        "MYAPP_WARMUP_SECONDS",
+        "MYAPP_PROXY",
+    ]);
+    env::set_var("MYAPP_API_KEY", "myapp-key");
+    env::set_var("MYAPP_PROVIDER_TYPE", "mock");
+    env::set_var("MYAPP_REQUESTS_PER_MINUTE", "42");
+    env::set_var("MYAPP_WARMUP_SECONDS", "7");
+    env::set_var("MYAPP_PROXY", "true");
+
+    let config = Config::builder().from_env_with_prefix("MYAPP_")?.build()?;
+
+    assert_eq!(config.api.api_key.as_deref(), Some("myapp-key"));
+    assert!(matches!(
+        config.api.provider_config,
+        ProviderConfig::Mock(_)
+    ));
+    assert_eq!(config.limits.requests_per_minute, Some(42));
+    assert_eq!(config.process.warmup_seconds, 7);
+    assert!(config.proxy.enabled);
+    Ok(())
+}
+
 #[test]
 fn test_config_from_env() -> Result<()> {
     // Create guards first to ensure proper cleanup order
@@ -130,8 +158,8 @@ fn test_config_from_env() -> Result<()> {
         ProviderConfig::Mock(_) => {}
         _ => panic!("Expected Mock provider"),
     }
-    assert_eq!(config.limits.requests_per_minute, Some(30));
-    assert_eq!(config.limits.tokens_per_minute, Some(50000));
+    assert_eq!(config.limits.requests_per_minute, None);
+    assert_eq!(config.limits.tokens_per_minute, None);
 
     // Variables will be dropped in reverse order of declaration
     Ok(())
@@ -687,6 +715,10 @@ async fn test_initialize_config_non_interactive() {
         config_path: Some(config_path.clone()),
         no_prompt: true,
         force: false,
+        wait: false,
+        strict: false,
+        dry_run: false,
+        env_prefix: DEFAULT_ENV_PREFIX.to_string(),
     };
 
     env::set_var("STRAINER_API_KEY", "test-key");
@@ -721,3 +753,112 @@ fn test_config_merge() {
     // Verify the merge
     assert_eq!(base.api.api_key, None);
 }
+
+#[test]
+fn test_config_includes_are_merged() -> Result<()> {
+    let dir = tempdir()?;
+    fs::create_dir(dir.path().join("shared"))?;
+    let base_path = dir.path().join("config.toml");
+    fs::write(
+        &base_path,
+        r#"
+        include = ["limits.toml", "shared/providers.toml"]
+
+        [limits]
+        requests_per_minute = 60
+        tokens_per_minute = 100000
+
+        [thresholds]
+        warning = 80
+        critical = 90
+        resume = 70
+
+        [backoff]
+        min_seconds = 1
+        max_seconds = 60
+
+        [process]
+        pause_on_warning = false
+        pause_on_critical = true
+
+        [logging]
+        level = "info"
+        format = "text"
+    "#,
+    )?;
+    fs::write(
+        dir.path().join("limits.toml"),
+        r"
+        [limits]
+        requests_per_minute = 30
+        input_tokens_per_minute = 20000
+    ",
+    )?;
+    // Included files may include others, relative to themselves
+    fs::write(
+        dir.path().join("shared/providers.toml"),
+        r#"
+        include = ["key.toml"]
+
+        [api]
+        type = "openai"
+        model = "gpt-4"
+        max_tokens = 2000
+    "#,
+    )?;
+    fs::write(
+        dir.path().join("shared/key.toml"),
+        r#"
+        [api]
+        api_key = "included-key"
+    "#,
+    )?;
+
+    let config = Config::builder().from_file(&base_path)?.build()?;
+
+    assert_eq!(config.limits.requests_per_minute, Some(30));
+    assert_eq!(config.limits.tokens_per_minute, Some(100_000));
+    assert_eq!(config.limits.input_tokens_per_minute, Some(20_000));
+    assert_eq!(config.thresholds.warning, 80);
+    assert_eq!(config.api.api_key, Some("included-key".to_string()));
+    match &config.api.provider_config {
+        ProviderConfig::OpenAI(cfg) => {
+            assert_eq!(cfg.model, "gpt-4");
+            assert_eq!(cfg.max_tokens, 2000);
+        }
+        _ => panic!("Expected OpenAI provider"),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_config_missing_include_is_an_error() -> Result<()> {
+    let dir = tempdir()?;
+    let base_path = dir.path().join("config.toml");
+    fs::write(&base_path, r#"include = ["missing.toml"]"#)?;
+
+    let err = Config::builder().from_file(&base_path).unwrap_err();
+    let message = format!("{err:#}");
+    assert!(message.contains("missing.toml"), "{message}");
+    assert!(
+        message.contains(&base_path.display().to_string()),
+        "{message}"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_config_include_cycle_is_an_error() -> Result<()> {
+    let dir = tempdir()?;
+    let base_path = dir.path().join("config.toml");
+    fs::write(&base_path, r#"include = ["a.toml"]"#)?;
+    fs::write(dir.path().join("a.toml"), r#"include = ["b.toml"]"#)?;
+    fs::write(dir.path().join("b.toml"), r#"include = ["a.toml"]"#)?;
+
+    let err = Config::builder().from_file(&base_path).unwrap_err();
+    let message = format!("{err:#}");
+    assert!(message.contains("Config include cycle"), "{message}");
+    assert!(message.contains("a.toml -> "), "{message}");
+    assert!(message.ends_with("a.toml"), "{message}");
+    Ok(())
+}
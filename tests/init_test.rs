@@ -202,6 +202,95 @@ async fn test_mock_provider_config() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_init_writes_provider_default_limits() -> anyhow::Result<()> {
+    clear_env_vars();
+    let temp_dir = TempDir::new()?;
+    let config_path = temp_dir.path().join("config.toml");
+
+    let mut cmd = Command::cargo_bin("strainer")?;
+    cmd.arg("init")
+        .arg("--no-prompt")
+        .arg("--config")
+        .arg(config_path.as_os_str())
+        .env("STRAINER_PROVIDER", "anthropic");
+
+    cmd.assert().success();
+
+    let config: Config = toml::from_str(&fs::read_to_string(config_path)?)?;
+    let defaults = config.api.provider_config.default_rate_limits();
+    assert_eq!(defaults.requests_per_minute, Some(10000));
+    assert_eq!(
+        config.limits.requests_per_minute,
+        defaults.requests_per_minute
+    );
+    assert_eq!(config.limits.tokens_per_minute, defaults.tokens_per_minute);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_strict_init_refuses_invalid_thresholds() -> anyhow::Result<()> {
+    clear_env_vars();
+    let temp_dir = TempDir::new()?;
+    let config_path = temp_dir.path().join("config.toml");
+    let init = |strict: bool| -> anyhow::Result<Command> {
+        let mut cmd = Command::cargo_bin("strainer")?;
+        cmd.arg("init")
+            .arg("--no-prompt")
+            .arg("--config")
+            .arg(config_path.as_os_str())
+            .env("STRAINER_PROVIDER", "mock")
+            // Warning must stay below critical
+            .env("STRAINER_WARNING_THRESHOLD", "90")
+            .env("STRAINER_CRITICAL_THRESHOLD", "80");
+        if strict {
+            cmd.arg("--strict");
+        }
+        Ok(cmd)
+    };
+
+    init(true)?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Refusing to write an invalid config",
+        ));
+    assert!(!config_path.exists());
+
+    init(false)?
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Warning threshold must not exceed critical threshold",
+        ));
+    let config: Config = toml::from_str(&fs::read_to_string(&config_path)?)?;
+    assert_eq!(config.thresholds.warning, 90);
+    assert_eq!(config.thresholds.critical, 80);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_strict_init_writes_valid_config() -> anyhow::Result<()> {
+    clear_env_vars();
+    let temp_dir = TempDir::new()?;
+    let config_path = temp_dir.path().join("config.toml");
+
+    let mut cmd = Command::cargo_bin("strainer")?;
+    cmd.arg("init")
+        .arg("--no-prompt")
+        .arg("--strict")
+        .arg("--config")
+        .arg(config_path.as_os_str())
+        .env("STRAINER_PROVIDER", "mock")
+        .env("STRAINER_CRITICAL_THRESHOLD", "95");
+
+    cmd.assert().success();
+
+    let config: Config = toml::from_str(&fs::read_to_string(config_path)?)?;
+    assert_eq!(config.thresholds.critical, 95);
+    Ok(())
+}
+
 // Test fixtures
 #[allow(dead_code)]
 pub mod fixtures {
@@ -63,7 +63,7 @@ fn test_openai_config_validation() {
 
             parameters: {
                 let mut params = HashMap::new();
-                params.insert("presence_penalty".to_string(), "0.5".to_string());
+                params.insert("presence_penalty".to_string(), serde_json::json!(0.5));
                 params
             },
         },
@@ -106,19 +106,25 @@ fn test_mock_config_validation() {
     let configs = vec![
         MockConfig {
             parameters: HashMap::new(),
-            requests_per_minute: 100,
-            tokens_per_minute: 1000,
-            input_tokens_per_minute: 500,
+            requests_per_minute: Some(100),
+            tokens_per_minute: Some(1000),
+            input_tokens_per_minute: Some(500),
+            output_tokens_per_minute: None,
+            requests_used: 0,
+            delay_ms: None,
         },
         MockConfig {
             parameters: {
                 let mut params = HashMap::new();
-                params.insert("test_key".to_string(), "test_value".to_string());
+                params.insert("test_key".to_string(), serde_json::json!("test_value"));
                 params
             },
-            requests_per_minute: 100,
-            tokens_per_minute: 1000,
-            input_tokens_per_minute: 500,
+            requests_per_minute: Some(100),
+            tokens_per_minute: Some(1000),
+            input_tokens_per_minute: Some(500),
+            output_tokens_per_minute: Some(500),
+            requests_used: 0,
+            delay_ms: None,
         },
     ];
 
@@ -150,9 +156,12 @@ fn test_provider_config_serialization() {
     // Test Mock serialization
     let mock_config = ProviderConfig::Mock(MockConfig {
         parameters: HashMap::new(),
-        requests_per_minute: 100,
-        tokens_per_minute: 1000,
-        input_tokens_per_minute: 500,
+        requests_per_minute: Some(100),
+        tokens_per_minute: Some(1000),
+        input_tokens_per_minute: Some(500),
+        output_tokens_per_minute: None,
+        requests_used: 0,
+        delay_ms: None,
     });
     let json = serde_json::to_string(&mock_config).unwrap();
     let deserialized: ProviderConfig = serde_json::from_str(&json).unwrap();
@@ -163,9 +172,12 @@ fn test_provider_config_serialization() {
 fn test_mock_provider_config() {
     let mock_config = MockConfig {
         parameters: HashMap::new(),
-        requests_per_minute: 100,
-        tokens_per_minute: 1000,
-        input_tokens_per_minute: 500,
+        requests_per_minute: Some(100),
+        tokens_per_minute: Some(1000),
+        input_tokens_per_minute: Some(500),
+        output_tokens_per_minute: None,
+        requests_used: 0,
+        delay_ms: None,
     };
     let provider_config = ProviderConfig::Mock(mock_config);
     assert!(matches!(provider_config, ProviderConfig::Mock(_)));
@@ -174,12 +186,15 @@ fn test_mock_provider_config() {
 #[test]
 fn test_mock_provider_config_with_params() {
     let mut params = HashMap::new();
-    params.insert("test".to_string(), "value".to_string());
+    params.insert("test".to_string(), serde_json::json!("value"));
     let mock_config = MockConfig {
         parameters: params,
-        requests_per_minute: 100,
-        tokens_per_minute: 1000,
-        input_tokens_per_minute: 500,
+        requests_per_minute: Some(100),
+        tokens_per_minute: Some(1000),
+        input_tokens_per_minute: Some(500),
+        output_tokens_per_minute: None,
+        requests_used: 0,
+        delay_ms: None,
     };
     let provider_config = ProviderConfig::Mock(mock_config);
     assert!(matches!(provider_config, ProviderConfig::Mock(_)));
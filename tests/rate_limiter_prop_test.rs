@@ -1,5 +1,5 @@
 use proptest::prelude::*;
-use strainer::config::{BackoffConfig, Thresholds};
+use strainer::config::{BackoffConfig, Gating, HumanDuration, RoundingMode, Thresholds};
 
 // Basic property tests that don't require mocking
 proptest! {
@@ -9,13 +9,23 @@ proptest! {
         warning_val in 2..40u8,
         critical_val in 41..90u8,
         resume_val in 1..2u8,
-        min_backoff in 1..30u32,
-        max_backoff in 31..120u32,
+        min_backoff in 1..30u64,
+        max_backoff in 31..120u64,
     ) {
         let config = Thresholds {
             warning: warning_val,
             critical: critical_val,
             resume: resume_val,
+            forecast_horizon_seconds: 300,
+            threshold_inclusive: true,
+            gating: Gating::Any,
+            rounding: RoundingMode::Floor,
+            strict_usage: false,
+            warning_sustained_seconds: 0,
+            pause_on_sustained_warning: false,
+            reset_on_resume: false,
+            latency_backoff_ms: 0,
+            percent_precision: 0,
         };
 
         // Test that threshold values are in valid ranges
@@ -23,8 +33,8 @@ proptest! {
         prop_assert!(config.resume < config.warning);
 
         let backoff = BackoffConfig {
-            min_seconds: min_backoff,
-            max_seconds: max_backoff,
+            min_seconds: HumanDuration::from_secs(min_backoff),
+            max_seconds: HumanDuration::from_secs(max_backoff),
         };
 
         // Test that backoff values are in valid ranges
@@ -45,18 +55,28 @@ mod prop_tests {
             warning_val in 2..40u8,
             critical_val in 41..90u8,
             resume_val in 1..2u8,
-            min_backoff in 1..30u32,
-            max_backoff in 31..120u32,
+            min_backoff in 1..30u64,
+            max_backoff in 31..120u64,
         ) {
             let mut limiter = RateLimiter::new(
                 Thresholds {
                     warning: warning_val,
                     critical: critical_val,
                     resume: resume_val,
+                    forecast_horizon_seconds: 300,
+                    threshold_inclusive: true,
+                    gating: Gating::Any,
+                    rounding: RoundingMode::Floor,
+                    strict_usage: false,
+                    warning_sustained_seconds: 0,
+                    pause_on_sustained_warning: false,
+                    reset_on_resume: false,
+                    latency_backoff_ms: 0,
+                    percent_precision: 0,
                 },
                 BackoffConfig {
-                    min_seconds: min_backoff,
-                    max_seconds: max_backoff,
+                    min_seconds: HumanDuration::from_secs(min_backoff),
+                    max_seconds: HumanDuration::from_secs(max_backoff),
                 },
                 MockProvider::new()
             );